@@ -0,0 +1,46 @@
+//! How long building tanks takes for large generated programs.
+//!
+//! Run with `cargo bench --bench populate`. There's no benchmark framework among the
+//! dependencies, so this times a few repetitions itself and reports the fastest.
+
+use std::{hint::black_box, time::Instant};
+
+use pufferfish::{
+    font::Font,
+    generate::Generator,
+    parser::{TankOrder, parse_names, populate_tanks},
+};
+
+const RUNS: usize = 10;
+
+fn bench(label: &str, program: &str) {
+    let names = parse_names(program).unwrap();
+    let best = (0..RUNS)
+        .map(|_| {
+            let names = names.clone();
+            let start = Instant::now();
+            black_box(populate_tanks(names, &Font::STANDARD, TankOrder::Source).unwrap());
+            start.elapsed()
+        })
+        .min()
+        .unwrap();
+    let per_tank = best / names.len() as u32;
+    println!(
+        "{label:24} {:>6} tanks  {best:>10.2?}  {per_tank:>8.2?}/tank",
+        names.len()
+    );
+}
+
+fn main() {
+    for (label, tanks, len, apostrophes) in [
+        ("short names", 10_000, (1, 4), false),
+        ("long names", 10_000, (16, 32), false),
+        ("long swizzled names", 10_000, (16, 32), true),
+    ] {
+        let program = Generator::new(tanks, Some(0))
+            .name_len(len.0, len.1)
+            .apostrophes(apostrophes)
+            .program();
+        bench(label, &program);
+    }
+}
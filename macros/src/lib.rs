@@ -0,0 +1,68 @@
+//! Embedding pufferfish programs at compile time.
+//!
+//! `puffer!("ab cd")` parses the program and builds its tanks while the calling crate
+//! compiles, reporting anything the interpreter would reject as a compile error, and expands
+//! to a `pufferfish::embed::EmbeddedProgram` constant expression.
+//!
+//! This is a crate of its own because the macro runs the library's parser, and the library
+//! can't depend on a crate that depends on it. Crates using the macro depend on both.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use pufferfish::{builder::ProgramBuilder, io::MockIo, parser::parse_pragmas};
+use quote::quote;
+use syn::{LitStr, parse_macro_input};
+
+#[proc_macro]
+pub fn puffer(input: TokenStream) -> TokenStream {
+    let code = parse_macro_input!(input as LitStr);
+    expand(&code.value())
+        .unwrap_or_else(|message| syn::Error::new(code.span(), message).to_compile_error())
+        .into()
+}
+
+fn expand(code: &str) -> Result<TokenStream2, String> {
+    // Programs that declare they need extension calls get them, as there's no other way to
+    // ask for them here.
+    let (pragmas, _) = parse_pragmas(code).map_err(|err| err.to_string())?;
+    let extensions = pragmas.metadata.extensions;
+    let program = ProgramBuilder::new(code)
+        .io(MockIo::default())
+        .extensions(extensions)
+        .build()
+        .map_err(|err| err.to_string())?;
+    let tanks = program.aquarium().iter().map(|tank| {
+        let name = tank.name();
+        let cells = tank.grid().as_array();
+        quote! { (#name, ::pufferfish::cells::Cells::new([#(#cells),*])) }
+    });
+    Ok(quote! {
+        ::pufferfish::embed::EmbeddedProgram::new(#code, &[#(#tanks),*], #extensions)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let tokens = expand("#!order source\ncd ab").unwrap().to_string();
+        assert!(tokens.starts_with(":: pufferfish :: embed :: EmbeddedProgram :: new ("));
+        let cd = tokens.find("\"cd\"").unwrap();
+        assert!(cd < tokens.find("\"ab\"").unwrap());
+        assert!(
+            tokens.contains("Cells :: new ([1usize , 0usize , 0usize , 0usize , 1usize , 2usize")
+        );
+        assert!(tokens.ends_with("] , false)"));
+
+        let tokens = expand("#!requires extensions\nab").unwrap().to_string();
+        assert!(tokens.ends_with("] , true)"));
+
+        assert_eq!(expand("'ab").unwrap_err(), "invalid name found: 'ab");
+        assert_eq!(
+            expand("#!font comic\nab").unwrap_err(),
+            "no font named comic"
+        );
+    }
+}
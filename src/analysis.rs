@@ -0,0 +1,232 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{
+    cells::Cells,
+    io::Io,
+    parser::is_name,
+    program::{Program, Tank},
+    stats::{OPCODES, opcode},
+};
+
+/// How a word list partitions into interchangeable names.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CollisionReport {
+    /// Groups of words with identical digit grids and call letters, which behave identically
+    /// wherever they appear.
+    pub semantic: Vec<Vec<String>>,
+    /// Groups of words with identical digit grids but different call letters, which only differ
+    /// when their tank executes a call.
+    pub grid_only: Vec<Vec<String>>,
+    /// Words whose digit grid no other word shares.
+    pub unique: Vec<String>,
+    /// Words that are not valid names.
+    pub invalid: Vec<String>,
+}
+
+/// Groups `words` by the digit grid (mod 10) their tanks decode to.
+///
+/// Words are lowercased first and duplicates are ignored. Groups and the words within them are
+/// sorted, so the report is stable for a given input.
+pub fn find_collisions<'a>(words: impl IntoIterator<Item = &'a str>) -> CollisionReport {
+    let words: BTreeSet<String> = words.into_iter().map(str::to_lowercase).collect();
+    let mut report = CollisionReport::default();
+    let mut by_grid: HashMap<Cells, Vec<Tank>> = HashMap::new();
+    for word in words {
+        if !is_name(&word) {
+            report.invalid.push(word);
+            continue;
+        }
+        let tank = Tank::from_name(&word).expect("valid names always build a tank");
+        by_grid.entry(tank.digits()).or_default().push(tank);
+    }
+    for tanks in by_grid.into_values() {
+        if tanks.len() == 1 {
+            report.unique.push(tanks[0].name().to_string());
+            continue;
+        }
+        let mut by_letter: HashMap<char, Vec<String>> = HashMap::new();
+        for tank in &tanks {
+            by_letter
+                .entry(tank.call_letter())
+                .or_default()
+                .push(tank.name().to_string());
+        }
+        if by_letter.len() > 1 {
+            report
+                .grid_only
+                .push(tanks.iter().map(|t| t.name().to_string()).collect());
+        }
+        report
+            .semantic
+            .extend(by_letter.into_values().filter(|group| group.len() > 1));
+    }
+    report.semantic.sort();
+    report.grid_only.sort();
+    report.unique.sort();
+    report
+}
+
+/// A word list indexed by the letters in each word, for finding the words that share a tank.
+///
+/// Glyphs are summed in any order, so words made of the same letters build the same grid. They
+/// only differ in their call letter, which is whichever letter comes first. An apostrophe
+/// swizzles the letters before it, so words with apostrophes aren't indexed.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AnagramIndex {
+    by_letters: HashMap<Vec<u8>, Vec<String>>,
+}
+
+impl AnagramIndex {
+    /// Indexes the valid names among `words` without apostrophes, lowercased. Each group of
+    /// anagrams is sorted and has no duplicates.
+    pub fn new<'a>(words: impl IntoIterator<Item = &'a str>) -> Self {
+        let words: BTreeSet<String> = words.into_iter().map(str::to_lowercase).collect();
+        let mut by_letters: HashMap<Vec<u8>, Vec<String>> = HashMap::new();
+        for word in words {
+            if let Some(key) = letters(&word) {
+                by_letters.entry(key).or_default().push(word);
+            }
+        }
+        Self { by_letters }
+    }
+
+    /// Every indexed word made of the same letters as `word`, which is among them if it was
+    /// indexed. Empty if `word` has apostrophes or isn't a name.
+    pub fn anagrams(&self, word: &str) -> &[String] {
+        letters(&word.to_lowercase())
+            .and_then(|key| self.by_letters.get(&key))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Every group of more than one anagram, sorted.
+    pub fn groups(&self) -> Vec<&[String]> {
+        let mut groups: Vec<_> = self
+            .by_letters
+            .values()
+            .filter(|group| group.len() > 1)
+            .map(Vec::as_slice)
+            .collect();
+        groups.sort();
+        groups
+    }
+}
+
+/// The letters of `word` in sorted order, if it's a name without apostrophes.
+fn letters(word: &str) -> Option<Vec<u8>> {
+    if !is_name(word) || word.contains('\'') {
+        return None;
+    }
+    let mut letters = word.as_bytes().to_vec();
+    letters.sort_unstable();
+    Some(letters)
+}
+
+/// What a tank is made of and what it can do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TankUsage {
+    pub name: String,
+    pub position: (usize, usize),
+    pub letters: usize,
+    pub apostrophes: usize,
+    /// How many cells hold each digit, as the instruction decoder takes them mod 10.
+    pub digits: [usize; 10],
+    /// The instructions among the tank's cells, in [`OPCODES`] order. The pointer can arrive
+    /// at any cell, so these are the instructions the tank can ever execute.
+    pub instructions: Vec<&'static str>,
+    pub call_letter: char,
+}
+
+impl TankUsage {
+    /// Whether the tank has a call cell, and so whether its call letter ever matters.
+    pub fn calls(&self) -> bool {
+        self.instructions.contains(&"call")
+    }
+}
+
+/// Describes every tank in `program`'s aquarium, in row-major order. Extension instructions
+/// are only counted if the program has extensions enabled.
+pub fn tank_usage<I: Io>(program: &Program<I>) -> Vec<TankUsage> {
+    program
+        .aquarium()
+        .indexed_iter()
+        .map(|(position, tank)| {
+            let mut digits = [0; 10];
+            let mut seen = [false; 16];
+            for (_, sum) in tank.cells() {
+                digits[sum % 10] += 1;
+                seen[opcode(sum, program.extensions)] = true;
+            }
+            TankUsage {
+                name: tank.name().to_string(),
+                position,
+                letters: tank
+                    .name()
+                    .chars()
+                    .filter(char::is_ascii_alphabetic)
+                    .count(),
+                apostrophes: tank.name().chars().filter(|&c| c == '\'').count(),
+                digits,
+                instructions: (0..seen.len())
+                    .filter(|&opcode| seen[opcode])
+                    .map(|opcode| OPCODES[opcode])
+                    .collect(),
+                call_letter: tank.call_letter(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_find_collisions() {
+        // Letter sums commute, so anagrams that start with the same letter fully collide.
+        let report = find_collisions(["stop", "spot", "tops", "post", "fish", "Don't", "no'pe'"]);
+        assert_eq!(
+            report.semantic,
+            vec![vec![String::from("spot"), String::from("stop")]]
+        );
+        assert_eq!(
+            report.grid_only,
+            vec![["post", "spot", "stop", "tops"].map(String::from).to_vec()]
+        );
+        assert_eq!(report.unique, ["don't", "fish"].map(String::from));
+        assert_eq!(report.invalid, [String::from("no'pe'")]);
+    }
+
+    #[test]
+    fn test_anagrams() {
+        let index = AnagramIndex::new(["stop", "Spot", "tops", "post", "pots", "fish", "don't"]);
+        assert_eq!(
+            index.anagrams("opts"),
+            ["post", "pots", "spot", "stop", "tops"]
+        );
+        let grid = Tank::from_name("stop").unwrap().digits();
+        for word in index.anagrams("STOP") {
+            assert_eq!(Tank::from_name(word).unwrap().digits(), grid);
+        }
+        assert_eq!(index.anagrams("fish"), ["fish"]);
+        assert!(index.anagrams("don't").is_empty());
+        assert!(index.anagrams("bird").is_empty());
+        assert_eq!(index.groups().len(), 1);
+    }
+
+    #[test]
+    fn test_tank_usage() {
+        let program = Program::with_io("ab don't", crate::io::MockIo::default()).unwrap();
+        let usage = tank_usage(&program);
+        assert_eq!(usage.len(), 2);
+        let ab = &usage[0];
+        assert_eq!((ab.name.as_str(), ab.position), ("ab", (0, 0)));
+        assert_eq!((ab.letters, ab.apostrophes), (2, 0));
+        assert_eq!(ab.digits, [7, 5, 8, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(ab.instructions, ["blank", "down", "up"]);
+        assert!(!ab.calls());
+        let dont = &usage[1];
+        assert_eq!((dont.letters, dont.apostrophes), (4, 1));
+        assert_eq!(dont.instructions, ["blank", "down", "up", "right", "left"]);
+        assert_eq!(dont.call_letter, 'd');
+    }
+}
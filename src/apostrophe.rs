@@ -0,0 +1,76 @@
+//! Where apostrophes can go in a word, and what each placement does to its tank.
+//!
+//! An apostrophe swizzles everything drawn before it, so the same letters can build quite
+//! different grids depending on where the apostrophes fall. An apostrophe can only go between
+//! two letters, and never next to another, so a word of `n` letters has `n - 1` places for
+//! them and each place takes at most one.
+
+use itertools::Itertools;
+
+use crate::{parser::ParseError, program::Tank};
+
+/// A word with apostrophes added, and how its tank differs from the word's without them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placement {
+    pub name: String,
+    /// The letters each apostrophe follows, counting from 1.
+    pub after: Vec<usize>,
+    pub tank: Tank,
+    /// How many cells decode to a different digit than they do without apostrophes.
+    pub changed: usize,
+}
+
+/// Every way of adding between one and `max_apostrophes` apostrophes to `word`, fewest first
+/// and then in order of where they go. The word may already contain apostrophes; they're
+/// ignored, and placed afresh like the rest.
+pub fn placements(word: &str, max_apostrophes: usize) -> Result<Vec<Placement>, crate::Error> {
+    let letters: String = word.chars().filter(|&c| c != '\'').collect();
+    if !letters.chars().all(|c| c.is_ascii_lowercase()) || letters.is_empty() {
+        return Err(ParseError::InvalidName(word.to_string()).into());
+    }
+    let base: Tank = letters.parse()?;
+    let gaps = letters.len() - 1;
+    let mut placements = Vec::new();
+    for count in 1..=max_apostrophes.min(gaps) {
+        for after in (1..=gaps).combinations(count) {
+            let mut name = String::with_capacity(letters.len() + count);
+            for (i, letter) in letters.chars().enumerate() {
+                if i > 0 && after.contains(&i) {
+                    name.push('\'');
+                }
+                name.push(letter);
+            }
+            let tank: Tank = name.parse()?;
+            placements.push(Placement {
+                changed: tank.hamming(&base),
+                name,
+                after,
+                tank,
+            });
+        }
+    }
+    Ok(placements)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_placements() {
+        let found = placements("abc", 5).unwrap();
+        let names: Vec<_> = found.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["a'bc", "ab'c", "a'b'c"]);
+        assert_eq!(found[2].after, [1, 2]);
+        let base: Tank = "abc".parse().unwrap();
+        for placement in &found {
+            assert_eq!(placement.tank, placement.name.parse().unwrap());
+            assert_eq!(placement.changed, placement.tank.hamming(&base));
+        }
+        assert_eq!(placements("do'nt", 1).unwrap().len(), 3);
+        assert!(placements("a", 2).unwrap().is_empty());
+        assert!(placements("abc", 0).unwrap().is_empty());
+        assert!(placements("Ab", 1).is_err());
+        assert!(placements("''", 1).is_err());
+    }
+}
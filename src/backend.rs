@@ -0,0 +1,172 @@
+//! The interface tooling uses to drive an execution engine.
+//!
+//! Tracing, statistics and equivalence checking only need to step a program and look at its
+//! state, so they are written against [`Backend`] rather than [`Program`] itself. The
+//! tree-walking interpreter in [`crate::program`] is the only engine so far; another engine
+//! implementing the trait works with the same tooling, and can be checked against the
+//! interpreter by running both in lockstep and comparing them with [`state_difference`].
+
+use grid::Grid;
+
+use crate::{
+    io::Io,
+    program::{
+        Direction, HaltReason, InstructionPointer, Program, REGISTERS, RuntimeError, Status, Tank,
+    },
+};
+
+pub trait Backend {
+    /// Executes a single instruction. Once the program has stopped this does nothing.
+    fn step(&mut self) -> Result<Status, RuntimeError>;
+
+    /// Steps until the program stops or reaches its step limit.
+    fn run(&mut self) -> Result<Status, RuntimeError> {
+        loop {
+            if self.status() == Status::Running
+                && self.step_limit().is_some_and(|limit| self.steps() >= limit)
+            {
+                return Ok(Status::Halted(HaltReason::StepLimit));
+            }
+            match self.step()? {
+                Status::Running => {}
+                status => return Ok(status),
+            }
+        }
+    }
+
+    fn status(&self) -> Status;
+
+    fn steps(&self) -> u64;
+
+    fn step_limit(&self) -> Option<u64>;
+
+    fn aquarium(&self) -> &Grid<Tank>;
+
+    /// The row and column of the current tank.
+    fn ftp(&self) -> (usize, usize);
+
+    fn ip(&self) -> InstructionPointer;
+
+    fn direction(&self) -> Direction;
+
+    fn stack(&self) -> &[isize];
+
+    fn registers(&self) -> &[isize; REGISTERS];
+
+    /// Whether raw cell values 10 to 15 run as extension instructions.
+    fn extensions(&self) -> bool;
+
+    /// How many more non-blank instructions a failed tunnel will skip.
+    fn skipping(&self) -> usize;
+
+    /// The glyph sum in the cell the instruction pointer is on.
+    fn current_cell(&self) -> usize {
+        let ip = self.ip();
+        self.aquarium()[self.ftp()].grid()[(ip.row(), ip.col())]
+    }
+}
+
+impl<I: Io> Backend for Program<I> {
+    fn step(&mut self) -> Result<Status, RuntimeError> {
+        Program::step(self)
+    }
+
+    fn run(&mut self) -> Result<Status, RuntimeError> {
+        Program::run(self)
+    }
+
+    fn status(&self) -> Status {
+        Program::status(self)
+    }
+
+    fn steps(&self) -> u64 {
+        Program::steps(self)
+    }
+
+    fn step_limit(&self) -> Option<u64> {
+        Program::step_limit(self)
+    }
+
+    fn aquarium(&self) -> &Grid<Tank> {
+        Program::aquarium(self)
+    }
+
+    fn ftp(&self) -> (usize, usize) {
+        Program::ftp(self)
+    }
+
+    fn ip(&self) -> InstructionPointer {
+        Program::ip(self)
+    }
+
+    fn direction(&self) -> Direction {
+        Program::direction(self)
+    }
+
+    fn stack(&self) -> &[isize] {
+        Program::stack(self)
+    }
+
+    fn registers(&self) -> &[isize; REGISTERS] {
+        Program::registers(self)
+    }
+
+    fn extensions(&self) -> bool {
+        self.extensions
+    }
+
+    fn skipping(&self) -> usize {
+        self.trampoline
+    }
+}
+
+/// How two backends' states differ, if they do: their pointers, then their stacks, then their
+/// registers. I/O is left to the caller, since backends needn't share an I/O type.
+pub fn state_difference(a: &impl Backend, b: &impl Backend) -> Option<String> {
+    if (a.ftp(), a.ip(), a.direction()) != (b.ftp(), b.ip(), b.direction()) {
+        Some(format!(
+            "pointers at tank {:?} cell {},{} and tank {:?} cell {},{}",
+            a.ftp(),
+            a.ip().row(),
+            a.ip().col(),
+            b.ftp(),
+            b.ip().row(),
+            b.ip().col()
+        ))
+    } else if a.stack() != b.stack() {
+        Some(format!("stacks {:?} and {:?}", a.stack(), b.stack()))
+    } else if a.registers() != b.registers() {
+        Some(String::from("registers differ"))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{builder::ProgramBuilder, io::MockIo};
+
+    fn run_dyn(backend: &mut dyn Backend) -> Result<Status, RuntimeError> {
+        backend.run()
+    }
+
+    #[test]
+    fn test_program_backend() {
+        let mut program = ProgramBuilder::new("ab cd")
+            .io(MockIo::default())
+            .step_limit(Some(7))
+            .build()
+            .unwrap();
+        assert_eq!(Backend::current_cell(&program), 1);
+        assert!(matches!(
+            run_dyn(&mut program),
+            Ok(Status::Halted(HaltReason::StepLimit))
+        ));
+        assert_eq!(Backend::steps(&program), 7);
+
+        let fresh = Program::with_io("ab cd", MockIo::default()).unwrap();
+        assert!(state_difference(&program, &fresh).is_some());
+        assert_eq!(state_difference(&fresh, &fresh), None);
+    }
+}
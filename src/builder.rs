@@ -0,0 +1,449 @@
+use thiserror::Error;
+
+use crate::{
+    dialect::{CycleOrder, Dialect, EdgeMode, SkipMode, TunnelMode, ZeroMode},
+    font::Font,
+    io::{Io, StdIo},
+    parser::{TankOrder, parse_names, parse_pragmas, populate_tanks},
+    program::{Program, Tank, tighter},
+    sandbox::Sandbox,
+};
+
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum BuildError {
+    #[error("no tank named {0}")]
+    UnknownTank(String),
+    #[error("the program has no names, so there are no tanks to run")]
+    NoTanks,
+    #[error("no font named {0}")]
+    UnknownFont(String),
+    #[error("there is no spec version {0}")]
+    UnknownSpec(u32),
+    #[error("the program requires extension calls, which aren't enabled")]
+    NeedsExtensions,
+}
+
+/// Configures a [`Program`] before it starts running.
+pub struct ProgramBuilder<I = StdIo> {
+    code: String,
+    io: I,
+    extensions: bool,
+    args: Vec<Vec<u8>>,
+    stack: Vec<isize>,
+    stack_capacity: usize,
+    accumulators: Vec<(String, usize)>,
+    step_limit: Option<u64>,
+    output_limit: Option<u64>,
+    sandbox: Sandbox,
+    seed: Option<u64>,
+    font: Option<&'static Font>,
+    spec: Option<u32>,
+    edges: Option<EdgeMode>,
+    zero: Option<ZeroMode>,
+    skip: Option<SkipMode>,
+    tunnel: Option<TunnelMode>,
+    cycle: Option<CycleOrder>,
+    order: Option<TankOrder>,
+    /// Tanks built ahead of time by [`crate::embed`], used instead of the code's names.
+    tanks: Option<Vec<Tank>>,
+}
+
+impl ProgramBuilder {
+    pub fn new(code: impl Into<String>) -> Self {
+        Self {
+            code: code.into(),
+            io: StdIo::default(),
+            extensions: false,
+            args: Vec::new(),
+            stack: Vec::new(),
+            stack_capacity: 0,
+            accumulators: Vec::new(),
+            step_limit: None,
+            output_limit: None,
+            sandbox: Sandbox::default(),
+            seed: None,
+            font: None,
+            spec: None,
+            edges: None,
+            zero: None,
+            skip: None,
+            tunnel: None,
+            cycle: None,
+            order: None,
+            tanks: None,
+        }
+    }
+}
+
+impl<I: Io> ProgramBuilder<I> {
+    /// Sets the I/O the program's calls read from and write to.
+    pub fn io<J: Io>(self, io: J) -> ProgramBuilder<J> {
+        ProgramBuilder {
+            code: self.code,
+            io,
+            extensions: self.extensions,
+            args: self.args,
+            stack: self.stack,
+            stack_capacity: self.stack_capacity,
+            accumulators: self.accumulators,
+            step_limit: self.step_limit,
+            output_limit: self.output_limit,
+            sandbox: self.sandbox,
+            seed: self.seed,
+            font: self.font,
+            spec: self.spec,
+            edges: self.edges,
+            zero: self.zero,
+            skip: self.skip,
+            tunnel: self.tunnel,
+            cycle: self.cycle,
+            order: self.order,
+            tanks: self.tanks,
+        }
+    }
+
+    /// Enables the extension calls and instructions that are not part of the core language.
+    ///
+    /// With extensions on, cells whose glyph sum is 10 to 15 run an extension instruction
+    /// instead of the digit they would otherwise decode to.
+    pub fn extensions(mut self, enabled: bool) -> Self {
+        self.extensions = enabled;
+        self
+    }
+
+    /// Adds a command-line argument for the program.
+    ///
+    /// Arguments are pushed onto the stack before the first step so that popping yields the
+    /// first argument's bytes in order, then `-1`, then the next argument, and so on.
+    pub fn arg(mut self, arg: impl Into<Vec<u8>>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn args<A: Into<Vec<u8>>>(mut self, args: impl IntoIterator<Item = A>) -> Self {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Sets the initial stack, listed bottom to top. Arguments are pushed above it.
+    pub fn stack(mut self, stack: impl IntoIterator<Item = isize>) -> Self {
+        self.stack = stack.into_iter().collect();
+        self
+    }
+
+    /// Reserves room for at least `capacity` stack entries up front, so a program whose stack
+    /// stays within it never reallocates while running.
+    pub fn stack_capacity(mut self, capacity: usize) -> Self {
+        self.stack_capacity = capacity;
+        self
+    }
+
+    /// Sets the initial accumulator of the named tank.
+    pub fn accumulator(mut self, tank: impl Into<String>, value: usize) -> Self {
+        self.accumulators.push((tank.into(), value));
+        self
+    }
+
+    /// Draws the program's names with `font`, overriding any `#!font` pragma in the code.
+    pub fn font(mut self, font: &'static Font) -> Self {
+        self.font = Some(font);
+        self
+    }
+
+    /// Starts from the rules of a spec version, overriding any `#!spec` pragma in the code.
+    /// Other dialect settings, from pragmas or the builder, still apply on top.
+    pub fn spec(mut self, version: u32) -> Self {
+        self.spec = Some(version);
+        self
+    }
+
+    /// Sets what happens at tank edges, overriding any `#!edges` pragma in the code.
+    pub fn edges(mut self, edges: EdgeMode) -> Self {
+        self.edges = Some(edges);
+        self
+    }
+
+    /// Sets what blank cells do, overriding any `#!zero` pragma in the code.
+    pub fn zero(mut self, zero: ZeroMode) -> Self {
+        self.zero = Some(zero);
+        self
+    }
+
+    /// Sets how many instructions a failed tunnel skips, overriding any `#!skip` pragma.
+    pub fn skip(mut self, skip: SkipMode) -> Self {
+        self.skip = Some(skip);
+        self
+    }
+
+    /// Sets whether tunnels pop their condition, overriding any `#!tunnel` pragma.
+    pub fn tunnel(mut self, tunnel: TunnelMode) -> Self {
+        self.tunnel = Some(tunnel);
+        self
+    }
+
+    /// Sets the operations the cycle instruction rotates through, overriding any `#!cycle`
+    /// pragma.
+    pub fn cycle(mut self, cycle: CycleOrder) -> Self {
+        self.cycle = Some(cycle);
+        self
+    }
+
+    /// Sets the order tanks fill the aquarium in, overriding any `#!order` pragma.
+    pub fn order(mut self, order: TankOrder) -> Self {
+        self.order = Some(order);
+        self
+    }
+
+    /// Limits how many steps [`Program::run`] may execute.
+    pub fn step_limit(mut self, limit: Option<u64>) -> Self {
+        self.step_limit = limit;
+        self
+    }
+
+    /// Limits how many bytes of output the program may write before halting. The sandbox's
+    /// output quota caps the same count, with a halt reason of its own.
+    pub fn output_limit(mut self, limit: Option<u64>) -> Self {
+        self.output_limit = limit;
+        self
+    }
+
+    /// Restricts the calls the program may make and the resources it may use.
+    pub fn sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    /// Seeds the random directions `y` calls choose, making runs that use them reproducible.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Uses `tanks`, already built from the code's names with its `#!font` and `#!order`
+    /// pragmas, rather than parsing the names again.
+    pub(crate) fn prebuilt(mut self, tanks: Vec<Tank>) -> Self {
+        self.tanks = Some(tanks);
+        self
+    }
+
+    pub fn build(self) -> Result<Program<I>, crate::Error> {
+        let (pragmas, code) = parse_pragmas(&self.code)?;
+        if pragmas.metadata.extensions && !self.extensions {
+            return Err(BuildError::NeedsExtensions.into());
+        }
+        // Prebuilt tanks were drawn with the pragma's font, so an override can't apply.
+        let font = match (self.font.filter(|_| self.tanks.is_none()), pragmas.font) {
+            (Some(font), _) => font,
+            (None, Some(name)) => Font::by_name(&name).ok_or(BuildError::UnknownFont(name))?,
+            (None, None) => &Font::STANDARD,
+        };
+        let tanks = match self.tanks {
+            Some(tanks) => tanks,
+            None => {
+                let names = parse_names(code)?;
+                let order = self.order.or(pragmas.order).unwrap_or_default();
+                populate_tanks(names, font, order)?
+            }
+        };
+        if tanks.is_empty() {
+            return Err(BuildError::NoTanks.into());
+        }
+        let mut program = Program::build_aquarium(tanks, self.io);
+        program.font = font;
+        let spec = self.spec.or(pragmas.spec).unwrap_or(Dialect::CURRENT_SPEC);
+        let mut dialect = Dialect::spec(spec).ok_or(BuildError::UnknownSpec(spec))?;
+        if let Some(edges) = self.edges.or(pragmas.edges) {
+            dialect.edges = edges;
+        }
+        if let Some(zero) = self.zero.or(pragmas.zero) {
+            dialect.zero = zero;
+        }
+        if let Some(skip) = self.skip.or(pragmas.skip) {
+            dialect.skip = skip;
+        }
+        if let Some(tunnel) = self.tunnel.or(pragmas.tunnel) {
+            dialect.tunnel = tunnel;
+        }
+        if let Some(cycle) = self.cycle.or(pragmas.cycle) {
+            dialect.cycle = cycle;
+        }
+        program.dialect = dialect;
+        program.extensions = self.extensions;
+        program.step_limit = tighter(self.step_limit, self.sandbox.limits.max_steps);
+        program.output_limit = tighter(self.output_limit, self.sandbox.limits.max_output);
+        program.sandbox = self.sandbox;
+        program.seed = self.seed;
+        for (name, value) in self.accumulators {
+            let tank = program
+                .tank_by_name_mut(&name)
+                .ok_or(BuildError::UnknownTank(name))?;
+            tank.acc = value;
+        }
+        program.stack = self.stack;
+        for arg in self.args.iter().rev() {
+            program.stack.push(-1);
+            program.stack.extend(arg.iter().rev().map(|&b| b as isize));
+        }
+        program
+            .stack
+            .reserve(self.stack_capacity.saturating_sub(program.stack.len()));
+        Ok(program)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{io::MockIo, runner::Limits};
+
+    #[test]
+    fn test_args_are_pushed_in_pop_order() {
+        let program = ProgramBuilder::new("ab")
+            .io(MockIo::default())
+            .arg("hi")
+            .arg("x")
+            .build()
+            .unwrap();
+        assert_eq!(
+            program.stack(),
+            [-1, b'x' as isize, -1, b'i' as isize, b'h' as isize]
+        );
+    }
+
+    #[test]
+    fn test_initial_stack_and_accumulators() {
+        let mut program = ProgramBuilder::new("ab cd")
+            .io(MockIo::default())
+            .stack([1, -2])
+            .accumulator("cd", 5)
+            .arg("z")
+            .build()
+            .unwrap();
+        assert_eq!(program.stack(), [1, -2, -1, b'z' as isize]);
+        assert_eq!(program.tank_by_name_mut("cd").unwrap().acc, 5);
+        assert_eq!(program.tank_by_name_mut("ab").unwrap().acc, 0);
+
+        let err = ProgramBuilder::new("ab")
+            .io(MockIo::default())
+            .accumulator("zz", 1)
+            .build()
+            .err()
+            .unwrap();
+        assert!(matches!(err, crate::Error::Build(BuildError::UnknownTank(name)) if name == "zz"));
+    }
+
+    #[test]
+    fn test_stack_capacity() {
+        let program = ProgramBuilder::new("ab")
+            .io(MockIo::default())
+            .stack([1, 2])
+            .stack_capacity(64)
+            .build()
+            .unwrap();
+        assert!(program.stack.capacity() >= 64);
+        assert_eq!(program.stack(), [1, 2]);
+    }
+
+    #[test]
+    fn test_font_selection() {
+        let font_of =
+            |builder: ProgramBuilder| builder.io(MockIo::default()).build().unwrap().font();
+        assert_eq!(font_of(ProgramBuilder::new("ab")), &Font::STANDARD);
+        assert_eq!(
+            font_of(ProgramBuilder::new("#!font serif\nab")),
+            &Font::SERIF
+        );
+        assert_eq!(
+            font_of(ProgramBuilder::new("#!font serif\nab").font(&Font::BOLD)),
+            &Font::BOLD
+        );
+        let err = ProgramBuilder::new("#!font comic\nab")
+            .io(MockIo::default())
+            .build()
+            .err()
+            .unwrap();
+        assert!(
+            matches!(err, crate::Error::Build(BuildError::UnknownFont(name)) if name == "comic")
+        );
+    }
+
+    #[test]
+    fn test_sandbox_limits() {
+        let sandbox = Sandbox::new().limits(Limits::steps(50).output(10));
+        let program = ProgramBuilder::new("ab")
+            .io(MockIo::default())
+            .step_limit(Some(100))
+            .sandbox(sandbox)
+            .build()
+            .unwrap();
+        assert_eq!(
+            (program.step_limit(), program.output_limit()),
+            (Some(50), Some(10))
+        );
+        let program = ProgramBuilder::new("ab")
+            .io(MockIo::default())
+            .step_limit(Some(20))
+            .sandbox(sandbox)
+            .build()
+            .unwrap();
+        assert_eq!(program.step_limit(), Some(20));
+    }
+
+    #[test]
+    fn test_tank_order() {
+        let names = |builder: ProgramBuilder| {
+            let program = builder.io(MockIo::default()).build().unwrap();
+            program
+                .aquarium()
+                .iter()
+                .map(|tank| tank.name().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(ProgramBuilder::new("cd ab ef")), ["ab", "cd", "ef"]);
+        assert_eq!(
+            names(ProgramBuilder::new("#!order source\ncd ab ef")),
+            ["cd", "ab", "ef"]
+        );
+        assert_eq!(
+            names(ProgramBuilder::new("#!order source\ncd ab").order(TankOrder::Sorted)),
+            ["ab", "cd"]
+        );
+    }
+
+    #[test]
+    fn test_dialect_precedence() {
+        let dialect_of =
+            |builder: ProgramBuilder| builder.io(MockIo::default()).build().unwrap().dialect();
+        assert_eq!(dialect_of(ProgramBuilder::new("ab")), Dialect::default());
+        let dialect = dialect_of(ProgramBuilder::new("#!spec 0\n#!edges hop\nab"));
+        assert_eq!(dialect.zero, ZeroMode::Halt);
+        assert_eq!(dialect.edges, EdgeMode::Hop);
+        let dialect = dialect_of(
+            ProgramBuilder::new("#!spec 0\n#!zero quota=3\nab")
+                .spec(1)
+                .edges(EdgeMode::Bounce),
+        );
+        assert_eq!(dialect.zero, ZeroMode::Quota(3));
+        assert_eq!(dialect.edges, EdgeMode::Bounce);
+        let err = ProgramBuilder::new("#!spec 9\nab")
+            .io(MockIo::default())
+            .build()
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            crate::Error::Build(BuildError::UnknownSpec(9))
+        ));
+    }
+
+    #[test]
+    fn test_requires_extensions() {
+        let builder = || ProgramBuilder::new("#!requires extensions\nab").io(MockIo::default());
+        let err = builder().build().err().unwrap();
+        assert!(matches!(
+            err,
+            crate::Error::Build(BuildError::NeedsExtensions)
+        ));
+        assert!(builder().extensions(true).build().is_ok());
+    }
+}
@@ -0,0 +1,141 @@
+//! Rewriting programs into a canonical form, so that equivalent programs read the same.
+
+use std::collections::HashMap;
+
+use crate::{
+    builder::ProgramBuilder,
+    cells::Cells,
+    io::MockIo,
+    parser::{TankOrder, is_name, parse_pragmas},
+    program::Tank,
+};
+
+/// Rewrites programs as their pragma lines followed by one name per tank in layout order,
+/// separated by single spaces, renaming each tank to the shortest dictionary word that builds
+/// an equivalent tank.
+///
+/// Tanks are equivalent when they share a call letter and digit grid, like the semantic
+/// collisions of [`crate::analysis::find_collisions`]. With extensions enabled the raw glyph
+/// sums must match too, since extension instructions and the `v` call can see them. Unless
+/// the program's `#!order` pragma lays tanks out in source order, the aquarium is laid out in
+/// sorted name order, so a word is only used if the names still sort in layout order; a tank
+/// with no usable word keeps its name.
+#[derive(Debug, Clone, Default)]
+pub struct Canonicalizer {
+    dictionary: Vec<String>,
+    extensions: bool,
+}
+
+impl Canonicalizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The words tanks may be renamed to. Invalid names are ignored and the rest lowercased.
+    pub fn dictionary<S: AsRef<str>>(mut self, words: impl IntoIterator<Item = S>) -> Self {
+        let mut words: Vec<String> = words
+            .into_iter()
+            .map(|w| w.as_ref().to_lowercase())
+            .filter(|w| is_name(w))
+            .collect();
+        words.sort_by(|a, b| (a.len(), a).cmp(&(b.len(), b)));
+        words.dedup();
+        self.dictionary = words;
+        self
+    }
+
+    pub fn extensions(mut self, enabled: bool) -> Self {
+        self.extensions = enabled;
+        self
+    }
+
+    fn key(&self, tank: &Tank) -> (char, Cells) {
+        let grid = if self.extensions {
+            *tank.grid()
+        } else {
+            tank.digits()
+        };
+        (tank.call_letter(), grid)
+    }
+
+    pub fn canonicalize(&self, code: &str) -> Result<String, crate::Error> {
+        let program = ProgramBuilder::new(code).io(MockIo::default()).build()?;
+        let (pragmas, body) = parse_pragmas(code)?;
+        let sorted = pragmas.order.unwrap_or_default() == TankOrder::Sorted;
+        let header = &code[..code.len() - body.len()];
+
+        let font = program.font();
+        let mut words: HashMap<(char, Cells), Vec<&str>> = HashMap::new();
+        for word in &self.dictionary {
+            let tank = Tank::from_name_with_font(word, font)?;
+            words.entry(self.key(&tank)).or_default().push(word);
+        }
+        // Each tank's options, best first, ending with its own name so there is always a way
+        // to keep the layout.
+        let options: Vec<Vec<&str>> = program
+            .aquarium()
+            .iter()
+            .map(|tank| {
+                let mut options = words.get(&self.key(tank)).cloned().unwrap_or_default();
+                options.retain(|&word| word != tank.name());
+                options.push(tank.name());
+                options
+            })
+            .collect();
+        let mut names = Vec::with_capacity(options.len());
+        assert!(
+            choose(&options, sorted, &mut names),
+            "the original names fit"
+        );
+        Ok(format!("{header}{}\n", names.join(" ")))
+    }
+}
+
+/// Picks the first option for each remaining tank that is not already taken and, if `sorted`,
+/// keeps the names in increasing order, backtracking when a choice leaves a later tank with
+/// nothing to pick.
+fn choose<'a>(options: &[Vec<&'a str>], sorted: bool, names: &mut Vec<&'a str>) -> bool {
+    let Some((first, rest)) = options.split_first() else {
+        return true;
+    };
+    for &option in first {
+        if names.contains(&option) || sorted && names.last().is_some_and(|&last| last > option) {
+            continue;
+        }
+        names.push(option);
+        if choose(rest, sorted, names) {
+            return true;
+        }
+        names.pop();
+    }
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_canonicalize() {
+        let canonicalizer = Canonicalizer::new().dictionary(["spot", "stop", "Fish", "xyz"]);
+        // "stop" and "spot" build the same tank, and "spot" sorts first.
+        assert_eq!(
+            canonicalizer.canonicalize("stop,  and chips").unwrap(),
+            "and chips spot\n"
+        );
+        assert_eq!(
+            canonicalizer
+                .canonicalize("#!edges bounce\npots stop")
+                .unwrap(),
+            "#!edges bounce\npots spot\n"
+        );
+        // Renaming "stop" to "spot" would move it before "sq".
+        assert_eq!(canonicalizer.canonicalize("stop sq").unwrap(), "sq stop\n");
+        assert_eq!(
+            canonicalizer
+                .canonicalize("#!order source\nstop sq")
+                .unwrap(),
+            "#!order source\nspot sq\n"
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! Fixed-size storage for a tank's glyph sums.
+//!
+//! Every tank is five rows of four cells, so its sums live inline in a `[usize; 20]` rather
+//! than behind a heap allocation. Code that wants a [`Grid`] can still convert to one.
+//!
+//! Cells add and subtract cell by cell, saturating rather than wrapping, so tools that combine
+//! grids, like the solver building a name letter by letter, all agree on the arithmetic.
+
+use std::ops::{Add, AddAssign, Index, Rem, Sub, SubAssign};
+
+use grid::Grid;
+
+pub const ROWS: usize = 5;
+pub const COLS: usize = 4;
+pub const LEN: usize = ROWS * COLS;
+
+/// A tank's glyph sums, in row-major order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Cells([usize; LEN]);
+
+impl Cells {
+    pub const fn new(cells: [usize; LEN]) -> Self {
+        Self(cells)
+    }
+
+    pub fn rows(&self) -> usize {
+        ROWS
+    }
+
+    pub fn cols(&self) -> usize {
+        COLS
+    }
+
+    /// The sums as one flat array, in row-major order.
+    pub fn as_array(&self) -> &[usize; LEN] {
+        &self.0
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> Option<usize> {
+        (row < ROWS && col < COLS).then(|| self.0[row * COLS + col])
+    }
+
+    pub(crate) fn get_mut(&mut self, row: usize, col: usize) -> Option<&mut usize> {
+        (row < ROWS && col < COLS).then(|| &mut self.0[row * COLS + col])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().copied()
+    }
+
+    pub fn iter_rows(&self) -> impl Iterator<Item = impl Iterator<Item = usize> + '_> + '_ {
+        self.0.chunks_exact(COLS).map(|row| row.iter().copied())
+    }
+
+    /// Every cell with its row and column.
+    pub fn indexed_iter(&self) -> impl Iterator<Item = ((usize, usize), usize)> + '_ {
+        self.iter()
+            .enumerate()
+            .map(|(i, x)| ((i / COLS, i % COLS), x))
+    }
+
+    pub fn map(&self, f: impl Fn(usize) -> usize) -> Self {
+        Self(self.0.map(f))
+    }
+
+    /// Every cell as the instruction decoder sees it, mod 10.
+    pub fn digits(&self) -> Self {
+        *self % 10
+    }
+
+    /// How many cells differ between the two.
+    pub fn hamming(&self, other: &Self) -> usize {
+        self.0.iter().zip(&other.0).filter(|(a, b)| a != b).count()
+    }
+
+    /// The cells of a five by four grid, or `None` if it's another shape.
+    pub fn from_grid(grid: &Grid<usize>) -> Option<Self> {
+        if (grid.rows(), grid.cols()) != (ROWS, COLS) {
+            return None;
+        }
+        let mut cells = [0; LEN];
+        for (cell, &x) in cells.iter_mut().zip(grid.iter()) {
+            *cell = x;
+        }
+        Some(Self(cells))
+    }
+
+    pub fn to_grid(&self) -> Grid<usize> {
+        Grid::from_vec(self.iter().collect(), COLS)
+    }
+}
+
+impl Index<(usize, usize)> for Cells {
+    type Output = usize;
+
+    fn index(&self, (row, col): (usize, usize)) -> &Self::Output {
+        assert!(
+            row < ROWS && col < COLS,
+            "cell {row},{col} is outside the tank"
+        );
+        &self.0[row * COLS + col]
+    }
+}
+
+impl Add for Cells {
+    type Output = Self;
+
+    fn add(mut self, rhs: Self) -> Self::Output {
+        self += rhs;
+        self
+    }
+}
+
+/// Adds cell by cell, saturating at `usize::MAX`.
+impl AddAssign for Cells {
+    fn add_assign(&mut self, rhs: Self) {
+        for (x, y) in self.0.iter_mut().zip(rhs.0) {
+            *x = x.saturating_add(y);
+        }
+    }
+}
+
+impl Sub for Cells {
+    type Output = Self;
+
+    fn sub(mut self, rhs: Self) -> Self::Output {
+        self -= rhs;
+        self
+    }
+}
+
+/// Subtracts cell by cell, saturating at 0.
+impl SubAssign for Cells {
+    fn sub_assign(&mut self, rhs: Self) {
+        for (x, y) in self.0.iter_mut().zip(rhs.0) {
+            *x = x.saturating_sub(y);
+        }
+    }
+}
+
+/// Every cell mod `modulus`.
+impl Rem<usize> for Cells {
+    type Output = Self;
+
+    fn rem(self, modulus: usize) -> Self::Output {
+        self.map(|x| x % modulus)
+    }
+}
+
+impl From<Cells> for Grid<usize> {
+    fn from(cells: Cells) -> Self {
+        cells.to_grid()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cells() {
+        let mut cells = Cells::new(std::array::from_fn(|i| i));
+        assert_eq!(cells[(1, 2)], 6);
+        assert_eq!(cells.get(4, 3), Some(19));
+        assert_eq!(cells.get(0, 4), None);
+        *cells.get_mut(0, 0).unwrap() = 200;
+        assert_eq!(
+            cells.iter_rows().nth(1).unwrap().collect::<Vec<_>>(),
+            [4, 5, 6, 7]
+        );
+        assert_eq!(cells.indexed_iter().nth(5), Some(((1, 1), 5)));
+
+        let grid = cells.to_grid();
+        assert_eq!(grid[(0, 0)], 200);
+        assert_eq!(Cells::from_grid(&grid), Some(cells));
+        assert_eq!(Cells::from_grid(&Grid::new(4, 5)), None);
+        let big = Grid::init(5, 4, 300);
+        assert_eq!(Cells::from_grid(&big).unwrap()[(2, 1)], 300);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let a = Cells::new(std::array::from_fn(|i| 250 + i / 4));
+        let b = Cells::new([3; LEN]);
+        assert_eq!((a + b).as_array()[0], 253);
+        assert_eq!((a + b).as_array()[19], 257);
+        assert_eq!(a + b - b, a);
+        let max = Cells::new([usize::MAX; LEN]);
+        assert_eq!(max + b, max);
+        assert_eq!((b - a), Cells::default());
+        assert_eq!((a % 7).as_array()[..5], [5, 5, 5, 5, 6]);
+        assert_eq!(a.digits().as_array()[4], 1);
+        assert_eq!(a.hamming(&a), 0);
+        assert_eq!(a.hamming(&(a - b)), LEN);
+        // Adding ten to every cell changes its sum but not its digit.
+        let c = Cells::new(std::array::from_fn(|i| i));
+        assert_eq!(c.digits().hamming(&(c + Cells::new([10; LEN])).digits()), 0);
+    }
+}
@@ -0,0 +1,234 @@
+//! Save states taken automatically every so many steps, for going back to part way through a
+//! long run.
+//!
+//! A [`Checkpointer`] watches a run and hands a [save state](crate::state) to its
+//! [`CheckpointStore`] every `every` steps, keeping only the latest `keep` and discarding the
+//! oldest as new ones arrive. Checkpoints are numbered by how many intervals the run had
+//! taken, so checkpoint 3 of a run checkpointed every 1000 steps is the state after step
+//! 3000. [`MemoryStore`] keeps them in memory, and [`DirStore`] as files in a directory, which
+//! `pufferfish resume` can continue from.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io,
+};
+
+use crate::{io::Io, program::Program, state};
+
+/// Somewhere to keep checkpoints.
+pub trait CheckpointStore {
+    fn save(&mut self, number: u64, state: &str) -> io::Result<()>;
+
+    /// Drops a checkpoint that has fallen out of the ring.
+    fn discard(&mut self, number: u64) -> io::Result<()>;
+}
+
+/// Checkpoints held in memory.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryStore {
+    states: BTreeMap<u64, String>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The save state of checkpoint `number`, if it's still kept.
+    pub fn get(&self, number: u64) -> Option<&str> {
+        self.states.get(&number).map(String::as_str)
+    }
+
+    /// The numbers of the kept checkpoints, oldest first.
+    pub fn numbers(&self) -> impl Iterator<Item = u64> + '_ {
+        self.states.keys().copied()
+    }
+}
+
+impl CheckpointStore for MemoryStore {
+    fn save(&mut self, number: u64, state: &str) -> io::Result<()> {
+        self.states.insert(number, state.to_string());
+        Ok(())
+    }
+
+    fn discard(&mut self, number: u64) -> io::Result<()> {
+        self.states.remove(&number);
+        Ok(())
+    }
+}
+
+/// Checkpoints written to a directory, as `checkpoint-000003.state` and so on.
+#[cfg(feature = "host-tools")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirStore {
+    dir: std::path::PathBuf,
+}
+
+#[cfg(feature = "host-tools")]
+impl DirStore {
+    /// Stores checkpoints in `dir`, creating it if need be.
+    pub fn new(dir: impl Into<std::path::PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// Where checkpoint `number` is kept.
+    pub fn path(&self, number: u64) -> std::path::PathBuf {
+        self.dir.join(format!("checkpoint-{number:06}.state"))
+    }
+
+    /// The numbers of the checkpoints in the directory, oldest first.
+    pub fn numbers(&self) -> io::Result<Vec<u64>> {
+        let mut numbers = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let name = entry?.file_name();
+            let number = name
+                .to_str()
+                .and_then(|name| name.strip_prefix("checkpoint-"))
+                .and_then(|name| name.strip_suffix(".state"))
+                .and_then(|number| number.parse::<u64>().ok());
+            numbers.extend(number);
+        }
+        numbers.sort_unstable();
+        Ok(numbers)
+    }
+
+    /// The save state of checkpoint `number`.
+    pub fn load(&self, number: u64) -> io::Result<String> {
+        std::fs::read_to_string(self.path(number))
+    }
+}
+
+#[cfg(feature = "host-tools")]
+impl CheckpointStore for DirStore {
+    fn save(&mut self, number: u64, state: &str) -> io::Result<()> {
+        std::fs::write(self.path(number), state)
+    }
+
+    fn discard(&mut self, number: u64) -> io::Result<()> {
+        match std::fs::remove_file(self.path(number)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Takes a checkpoint every so many steps of a run.
+#[derive(Debug, Clone)]
+pub struct Checkpointer<S> {
+    every: u64,
+    keep: usize,
+    store: S,
+    /// The checkpoints taken and still kept, oldest first.
+    kept: VecDeque<u64>,
+}
+
+impl<S: CheckpointStore> Checkpointer<S> {
+    /// Checkpoints every `every` steps into `store`, keeping the latest 10.
+    pub fn new(every: u64, store: S) -> Self {
+        Self {
+            every: every.max(1),
+            keep: 10,
+            store,
+            kept: VecDeque::new(),
+        }
+    }
+
+    /// How many of the latest checkpoints to keep.
+    pub fn keep(mut self, keep: usize) -> Self {
+        self.keep = keep.max(1);
+        self
+    }
+
+    /// Counts `numbers`, checkpoints already in the store, as kept ahead of any taken here,
+    /// oldest first, so they're discarded in turn as new ones arrive. This is for carrying on
+    /// a run into the directory it was resumed from.
+    pub fn existing(mut self, numbers: impl IntoIterator<Item = u64>) -> Self {
+        self.kept = numbers.into_iter().chain(self.kept).collect();
+        self
+    }
+
+    /// Call after every step. Takes a checkpoint if the run has just finished an interval,
+    /// and returns its number.
+    pub fn after_step<I: Io>(&mut self, program: &Program<I>) -> io::Result<Option<u64>> {
+        let steps = program.steps();
+        if steps == 0 || !steps.is_multiple_of(self.every) {
+            return Ok(None);
+        }
+        let number = steps / self.every;
+        if self.kept.back() == Some(&number) {
+            return Ok(None);
+        }
+        self.store.save(number, &state::save(program))?;
+        self.kept.push_back(number);
+        while self.kept.len() > self.keep {
+            let oldest = self.kept.pop_front().unwrap();
+            self.store.discard(oldest)?;
+        }
+        Ok(Some(number))
+    }
+
+    /// The numbers of the checkpoints taken and still kept, oldest first.
+    pub fn kept(&self) -> impl Iterator<Item = u64> + '_ {
+        self.kept.iter().copied()
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn into_store(self) -> S {
+        self.store
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::MockIo;
+
+    #[test]
+    fn test_checkpoints() {
+        let mut program = Program::with_io("ab", MockIo::default()).unwrap();
+        let mut checkpointer = Checkpointer::new(3, MemoryStore::new()).keep(2);
+        for _ in 0..10 {
+            program.step().unwrap();
+            checkpointer.after_step(&program).unwrap();
+        }
+        assert_eq!(checkpointer.kept().collect::<Vec<_>>(), [2, 3]);
+        let store = checkpointer.into_store();
+        assert_eq!(store.numbers().collect::<Vec<_>>(), [2, 3]);
+        assert_eq!(store.get(1), None);
+        let resumed = state::load(store.get(2).unwrap(), MockIo::default()).unwrap();
+        assert_eq!(resumed.steps(), 6);
+
+        // Carrying on from checkpoint 2 discards it in turn, like one taken here.
+        let mut program = resumed;
+        let mut checkpointer = Checkpointer::new(3, store).keep(2).existing([2]);
+        for _ in 0..6 {
+            program.step().unwrap();
+            checkpointer.after_step(&program).unwrap();
+        }
+        assert_eq!(checkpointer.kept().collect::<Vec<_>>(), [3, 4]);
+        assert_eq!(checkpointer.store().numbers().collect::<Vec<_>>(), [3, 4]);
+    }
+
+    #[cfg(feature = "host-tools")]
+    #[test]
+    fn test_dir_store() {
+        let mut program = Program::with_io("ab", MockIo::default()).unwrap();
+        let dir =
+            std::env::temp_dir().join(format!("pufferfish-checkpoint-{}", std::process::id()));
+        let mut checkpointer = Checkpointer::new(4, DirStore::new(&dir).unwrap()).keep(1);
+        for _ in 0..10 {
+            program.step().unwrap();
+            checkpointer.after_step(&program).unwrap();
+        }
+        let store = checkpointer.store();
+        assert_eq!(store.numbers().unwrap(), [2]);
+        let resumed = state::load(&store.load(2).unwrap(), MockIo::default()).unwrap();
+        assert_eq!(resumed.steps(), 8);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,470 @@
+//! Conditions on a program's state, for conditional breakpoints.
+//!
+//! A condition is a small expression such as `stack.len > 3 && top < 0 && tank == "w'ind"`.
+//! It can read these variables:
+//!
+//! - `stack.len`, and `top`, the value on top of the stack.
+//! - `acc`, the current tank's accumulator, and `cell`, the glyph sum under the pointer.
+//! - `steps`, and `row` and `col`, the pointer's cell in its tank.
+//! - `r0` to `r15`, the registers.
+//! - `tank`, the current tank's name, and `direction`, a name like `"down-left"`.
+//!
+//! They combine with integers, double-quoted strings, `+` and `-`, the comparisons `==`,
+//! `!=`, `<`, `<=`, `>` and `>=`, and `!`, `&&` and `||`, with parentheses for grouping.
+//! Expressions are type checked as they're parsed. When there's no value to read, as with
+//! `top` on an empty stack, every comparison using it is false.
+
+use std::fmt;
+
+use thiserror::Error;
+
+use crate::{
+    io::Io,
+    program::{Program, REGISTERS},
+};
+
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+#[error("{message} at byte {at}")]
+pub struct ConditionError {
+    pub message: String,
+    /// Where in the condition the error is.
+    pub at: usize,
+}
+
+/// A parsed condition. It displays as the text it was parsed from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Self, ConditionError> {
+        let mut parser = Parser {
+            source,
+            pos: 0,
+            operators: 0,
+        };
+        let (expr, ty) = parser.or()?;
+        parser.skip_whitespace();
+        if parser.pos < source.len() {
+            return Err(parser.error("expected an operator"));
+        }
+        if ty != Type::Bool {
+            return Err(ConditionError {
+                message: format!("the condition is {ty}, not a boolean"),
+                at: 0,
+            });
+        }
+        Ok(Self {
+            source: source.to_string(),
+            expr,
+        })
+    }
+
+    /// Whether the condition holds for `program` as it is now.
+    pub fn holds<I: Io>(&self, program: &Program<I>) -> bool {
+        matches!(self.expr.eval(program), Some(Value::Bool(true)))
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Bool,
+    Int,
+    Str,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Type::Bool => "a boolean",
+            Type::Int => "an integer",
+            Type::Str => "a string",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    Bool(bool),
+    Int(i64),
+    Str(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Var {
+    StackLen,
+    Top,
+    Acc,
+    Cell,
+    Steps,
+    Row,
+    Col,
+    Register(usize),
+    Tank,
+    Direction,
+}
+
+impl Var {
+    fn by_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "stack.len" => Var::StackLen,
+            "top" => Var::Top,
+            "acc" => Var::Acc,
+            "cell" => Var::Cell,
+            "steps" => Var::Steps,
+            "row" => Var::Row,
+            "col" => Var::Col,
+            "tank" => Var::Tank,
+            "direction" => Var::Direction,
+            _ => {
+                let digits = name.strip_prefix('r')?;
+                let register: usize = digits.parse().ok()?;
+                if register >= REGISTERS || register.to_string() != digits {
+                    return None;
+                }
+                Var::Register(register)
+            }
+        })
+    }
+
+    fn ty(self) -> Type {
+        match self {
+            Var::Tank | Var::Direction => Type::Str,
+            _ => Type::Int,
+        }
+    }
+
+    fn read<I: Io>(self, program: &Program<I>) -> Option<Value> {
+        let int = |x: usize| Value::Int(x as i64);
+        let tank = &program.aquarium()[program.ftp()];
+        let ip = program.ip();
+        Some(match self {
+            Var::StackLen => int(program.stack().len()),
+            Var::Top => Value::Int(*program.stack().last()? as i64),
+            Var::Acc => int(tank.acc()),
+            Var::Cell => int(tank.grid()[(ip.row(), ip.col())]),
+            Var::Steps => Value::Int(program.steps() as i64),
+            Var::Row => int(ip.row()),
+            Var::Col => int(ip.col()),
+            Var::Register(register) => Value::Int(program.registers()[register] as i64),
+            Var::Tank => Value::Str(tank.name().to_string()),
+            Var::Direction => Value::Str(program.direction().name().to_string()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Value(Value),
+    Var(Var),
+    Neg(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Compare(Op, Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// The expression's value, or `None` if a variable in it has no value.
+    fn eval<I: Io>(&self, program: &Program<I>) -> Option<Value> {
+        let int = |expr: &Expr| match expr.eval(program)? {
+            Value::Int(x) => Some(x),
+            _ => None,
+        };
+        let truth = |expr: &Expr| matches!(expr.eval(program), Some(Value::Bool(true)));
+        Some(match self {
+            Expr::Value(value) => value.clone(),
+            Expr::Var(var) => var.read(program)?,
+            Expr::Neg(x) => Value::Int(int(x)?.wrapping_neg()),
+            Expr::Add(a, b) => Value::Int(int(a)?.wrapping_add(int(b)?)),
+            Expr::Sub(a, b) => Value::Int(int(a)?.wrapping_sub(int(b)?)),
+            Expr::Compare(op, a, b) => {
+                let ordering = match (a.eval(program), b.eval(program)) {
+                    (Some(Value::Int(a)), Some(Value::Int(b))) => a.cmp(&b),
+                    (Some(Value::Str(a)), Some(Value::Str(b))) => a.cmp(&b),
+                    _ => return Some(Value::Bool(false)),
+                };
+                Value::Bool(match op {
+                    Op::Eq => ordering.is_eq(),
+                    Op::Ne => ordering.is_ne(),
+                    Op::Lt => ordering.is_lt(),
+                    Op::Le => ordering.is_le(),
+                    Op::Gt => ordering.is_gt(),
+                    Op::Ge => ordering.is_ge(),
+                })
+            }
+            Expr::Not(x) => Value::Bool(!truth(x)),
+            Expr::And(a, b) => Value::Bool(truth(a) && truth(b)),
+            Expr::Or(a, b) => Value::Bool(truth(a) || truth(b)),
+        })
+    }
+}
+
+/// How many operators and parentheses a condition may have. The parser recurses into each
+/// `!`, `-` and parenthesis, and the expression it builds is as deep as its operators, so
+/// without a limit a long enough run of `!` or `&&` would overflow the stack.
+const MAX_OPERATORS: usize = 256;
+
+struct Parser<'a> {
+    source: &'a str,
+    pos: usize,
+    operators: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: impl Into<String>) -> ConditionError {
+        ConditionError {
+            message: message.into(),
+            at: self.pos,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.source[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Consumes `token` if it comes next.
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        let found = self.source[self.pos..].starts_with(token);
+        if found {
+            self.pos += token.len();
+        }
+        found
+    }
+
+    /// Parses an operand with `parse` and checks it has type `ty`.
+    /// Counts an operator or parenthesis, failing if there are too many.
+    fn count_operator(&mut self) -> Result<(), ConditionError> {
+        if self.operators == MAX_OPERATORS {
+            return Err(self.error("too many operators"));
+        }
+        self.operators += 1;
+        Ok(())
+    }
+
+    fn operand(
+        &mut self,
+        parse: fn(&mut Self) -> Result<(Expr, Type), ConditionError>,
+        ty: Type,
+    ) -> Result<Expr, ConditionError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let (expr, found) = parse(self)?;
+        if found != ty {
+            return Err(ConditionError {
+                message: format!("expected {ty}, found {found}"),
+                at: start,
+            });
+        }
+        Ok(expr)
+    }
+
+    fn or(&mut self) -> Result<(Expr, Type), ConditionError> {
+        let (mut expr, ty) = self.and()?;
+        if ty != Type::Bool {
+            return Ok((expr, ty));
+        }
+        while self.eat("||") {
+            self.count_operator()?;
+            let rhs = self.operand(Self::and, Type::Bool)?;
+            expr = Expr::Or(Box::new(expr), Box::new(rhs));
+        }
+        Ok((expr, Type::Bool))
+    }
+
+    fn and(&mut self) -> Result<(Expr, Type), ConditionError> {
+        let (mut expr, ty) = self.not()?;
+        if ty != Type::Bool {
+            return Ok((expr, ty));
+        }
+        while self.eat("&&") {
+            self.count_operator()?;
+            let rhs = self.operand(Self::not, Type::Bool)?;
+            expr = Expr::And(Box::new(expr), Box::new(rhs));
+        }
+        Ok((expr, Type::Bool))
+    }
+
+    fn not(&mut self) -> Result<(Expr, Type), ConditionError> {
+        self.skip_whitespace();
+        let rest = &self.source[self.pos..];
+        if rest.starts_with('!') && !rest.starts_with("!=") {
+            self.count_operator()?;
+            self.pos += 1;
+            let expr = self.operand(Self::not, Type::Bool)?;
+            return Ok((Expr::Not(Box::new(expr)), Type::Bool));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<(Expr, Type), ConditionError> {
+        self.skip_whitespace();
+        let (lhs, ty) = self.sum()?;
+        // Longer operators first, so `<=` isn't read as `<`.
+        let ops = [
+            ("==", Op::Eq),
+            ("!=", Op::Ne),
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+        ];
+        let Some(&(_, op)) = ops.iter().find(|(token, _)| self.eat(token)) else {
+            return Ok((lhs, ty));
+        };
+        if ty == Type::Bool || ty == Type::Str && !matches!(op, Op::Eq | Op::Ne) {
+            return Err(self.error(format!("can't compare {ty} that way")));
+        }
+        self.count_operator()?;
+        let rhs = self.operand(Self::sum, ty)?;
+        Ok((Expr::Compare(op, Box::new(lhs), Box::new(rhs)), Type::Bool))
+    }
+
+    fn sum(&mut self) -> Result<(Expr, Type), ConditionError> {
+        let (mut expr, ty) = self.unary()?;
+        if ty != Type::Int {
+            return Ok((expr, ty));
+        }
+        loop {
+            if self.eat("+") {
+                self.count_operator()?;
+                let rhs = self.operand(Self::unary, Type::Int)?;
+                expr = Expr::Add(Box::new(expr), Box::new(rhs));
+            } else if self.eat("-") {
+                self.count_operator()?;
+                let rhs = self.operand(Self::unary, Type::Int)?;
+                expr = Expr::Sub(Box::new(expr), Box::new(rhs));
+            } else {
+                return Ok((expr, Type::Int));
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<(Expr, Type), ConditionError> {
+        if self.eat("-") {
+            self.count_operator()?;
+            let expr = self.operand(Self::unary, Type::Int)?;
+            return Ok((Expr::Neg(Box::new(expr)), Type::Int));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<(Expr, Type), ConditionError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let rest = &self.source[start..];
+        if self.eat("(") {
+            self.count_operator()?;
+            let inner = self.or()?;
+            if !self.eat(")") {
+                return Err(self.error("expected ')'"));
+            }
+            return Ok(inner);
+        }
+        if let Some(text) = rest.strip_prefix('"') {
+            // Names have no quotes or backslashes, so strings need no escapes.
+            let end = text
+                .find('"')
+                .ok_or_else(|| self.error("unterminated string"))?;
+            self.pos += end + 2;
+            let value = Value::Str(text[..end].to_string());
+            return Ok((Expr::Value(value), Type::Str));
+        }
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '_'))
+            .unwrap_or(rest.len());
+        let word = &rest[..len];
+        if word.is_empty() {
+            return Err(self.error("expected a value"));
+        }
+        if word.starts_with(|c: char| c.is_ascii_digit()) {
+            let value = word
+                .parse()
+                .map_err(|_| self.error(format!("{word:?} is not an integer")))?;
+            self.pos += len;
+            return Ok((Expr::Value(Value::Int(value)), Type::Int));
+        }
+        let var = Var::by_name(word).ok_or_else(|| self.error(format!("no variable {word:?}")))?;
+        self.pos += len;
+        Ok((Expr::Var(var), var.ty()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::MockIo;
+
+    #[test]
+    fn test_holds() {
+        let mut program = Program::with_io("w'ind", MockIo::default()).unwrap();
+        let holds =
+            |program: &Program<MockIo>, source| Condition::parse(source).unwrap().holds(program);
+        let condition = r#"stack.len > 3 && top < 0 && tank == "w'ind""#;
+        assert!(!holds(&program, condition));
+        // With an empty stack, `top` has no value and comparisons with it are false.
+        assert!(!holds(&program, "top == 0 || top != 0"));
+        assert!(holds(&program, "!(top == 0)"));
+
+        program.stack_mut().extend([1, 2, 3, -4]);
+        assert!(holds(&program, condition));
+        assert!(holds(&program, "top + stack.len - -1 == 1 && r15 == 0"));
+        assert!(holds(&program, r#"direction != "up" || 1 >= 2"#));
+        assert_eq!(Condition::parse(condition).unwrap().to_string(), condition);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |source| Condition::parse(source).unwrap_err().to_string();
+        assert_eq!(error("top >"), "expected a value at byte 5");
+        assert_eq!(
+            error("stack.size > 1"),
+            "no variable \"stack.size\" at byte 0"
+        );
+        assert_eq!(
+            error(r#"tank == 3"#),
+            "expected a string, found an integer at byte 8"
+        );
+        assert_eq!(
+            error(r#"tank < "a""#),
+            "can't compare a string that way at byte 6"
+        );
+        assert_eq!(
+            error("acc + 1"),
+            "the condition is an integer, not a boolean at byte 0"
+        );
+        assert_eq!(error("(acc == 1"), "expected ')' at byte 9");
+        assert_eq!(error("acc == 1 acc"), "expected an operator at byte 9");
+        assert_eq!(error("r16 == 0"), "no variable \"r16\" at byte 0");
+        assert_eq!(
+            error(&("!".repeat(200_000) + "(steps == 0)")),
+            "too many operators at byte 256"
+        );
+        assert!(Condition::parse(&("-".repeat(200_000) + "1 == 0")).is_err());
+        assert!(Condition::parse(&"(".repeat(200_000)).is_err());
+        assert!(Condition::parse(&vec!["steps == 0"; 200_000].join(" && ")).is_err());
+        assert!(Condition::parse(&("!".repeat(254) + "(steps == 0)")).is_ok());
+    }
+}
@@ -0,0 +1,320 @@
+use std::{
+    fmt::Write,
+    fs::{read, read_dir, read_to_string},
+    path::Path,
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    builder::ProgramBuilder,
+    json,
+    program::HaltReason,
+    runner::{Ending, Limits, run_captured},
+};
+
+/// A program to run as part of a corpus.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Case {
+    pub name: String,
+    pub code: String,
+    pub input: Vec<u8>,
+    /// If set, the run must produce exactly this output to pass.
+    pub expected: Option<Vec<u8>>,
+}
+
+/// Loads every `NAME.puff` in `dir` as a case, sorted by name.
+///
+/// `NAME.in`, if present, is used as the program's input, and `NAME.out` as its expected
+/// output.
+pub fn load_dir(dir: &Path) -> Result<Vec<Case>, crate::Error> {
+    let mut cases = Vec::new();
+    for entry in read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "puff") {
+            continue;
+        }
+        let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        let input_path = path.with_extension("in");
+        let expected_path = path.with_extension("out");
+        cases.push(Case {
+            name: name.to_string(),
+            code: read_to_string(&path)?,
+            input: if input_path.exists() {
+                read(input_path)?
+            } else {
+                Vec::new()
+            },
+            expected: expected_path
+                .exists()
+                .then(|| read(expected_path))
+                .transpose()?,
+        });
+    }
+    cases.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(cases)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Passed,
+    /// The program ran but hit its step limit or produced the wrong output.
+    Failed(String),
+    /// The program could not be built, hit a runtime error or crashed the interpreter.
+    Error(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct CaseResult {
+    pub name: String,
+    pub verdict: Verdict,
+    pub steps: u64,
+    pub output: Vec<u8>,
+    pub time: Duration,
+}
+
+/// Runs corpus cases in parallel under a shared set of limits.
+#[derive(Debug, Clone)]
+pub struct CorpusRunner {
+    limits: Limits,
+    jobs: usize,
+    extensions: bool,
+}
+
+impl Default for CorpusRunner {
+    fn default() -> Self {
+        Self {
+            limits: Limits::default(),
+            jobs: thread::available_parallelism().map_or(1, |n| n.get()),
+            extensions: false,
+        }
+    }
+}
+
+impl CorpusRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits applied to each case separately.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    pub fn extensions(mut self, enabled: bool) -> Self {
+        self.extensions = enabled;
+        self
+    }
+
+    /// Runs every case, returning results in the same order as `cases`.
+    pub fn run(&self, cases: &[Case]) -> Vec<CaseResult> {
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new(Vec::with_capacity(cases.len()));
+        thread::scope(|scope| {
+            for _ in 0..self.jobs.min(cases.len()) {
+                scope.spawn(|| {
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(case) = cases.get(i) else {
+                            break;
+                        };
+                        let result = self.run_case(case);
+                        results.lock().unwrap().push((i, result));
+                    }
+                });
+            }
+        });
+        let mut results = results.into_inner().unwrap();
+        results.sort_by_key(|&(i, _)| i);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    fn run_case(&self, case: &Case) -> CaseResult {
+        let start = Instant::now();
+        let builder = ProgramBuilder::new(case.code.as_str()).extensions(self.extensions);
+        let (verdict, steps, output) = match run_captured(builder, case.input.clone(), self.limits)
+        {
+            Err(err) => (Verdict::Error(format!("build error: {err}")), 0, Vec::new()),
+            Ok(outcome) => {
+                let verdict = match &outcome.ending {
+                    Ending::Halted(HaltReason::StepLimit) => {
+                        Verdict::Failed(format!("step limit of {} reached", outcome.steps))
+                    }
+                    Ending::Halted(_) => match &case.expected {
+                        Some(expected) if *expected != outcome.output => {
+                            Verdict::Failed(String::from("output differs from expected"))
+                        }
+                        _ => Verdict::Passed,
+                    },
+                    Ending::Error(err) => Verdict::Error(format!("runtime error: {err}")),
+                    Ending::Panic(message) => Verdict::Error(format!("panic: {message}")),
+                };
+                (verdict, outcome.steps, outcome.output)
+            }
+        };
+        CaseResult {
+            name: case.name.clone(),
+            verdict,
+            steps,
+            output,
+            time: start.elapsed(),
+        }
+    }
+}
+
+/// Renders results as a JUnit XML report with a single test suite.
+pub fn junit_xml(suite: &str, results: &[CaseResult]) -> String {
+    let count = |f: fn(&Verdict) -> bool| results.iter().filter(|r| f(&r.verdict)).count();
+    let failures = count(|v| matches!(v, Verdict::Failed(_)));
+    let errors = count(|v| matches!(v, Verdict::Error(_)));
+    let time: Duration = results.iter().map(|r| r.time).sum();
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    writeln!(
+        out,
+        "<testsuite name=\"{}\" tests=\"{}\" failures=\"{failures}\" errors=\"{errors}\" time=\"{:.3}\">",
+        xml_escape(suite),
+        results.len(),
+        time.as_secs_f64()
+    )
+    .unwrap();
+    for result in results {
+        write!(
+            out,
+            "  <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\"",
+            xml_escape(&result.name),
+            xml_escape(suite),
+            result.time.as_secs_f64()
+        )
+        .unwrap();
+        match &result.verdict {
+            Verdict::Passed => out.push_str("/>\n"),
+            Verdict::Failed(message) => writeln!(
+                out,
+                ">\n    <failure message=\"{}\"/>\n  </testcase>",
+                xml_escape(message)
+            )
+            .unwrap(),
+            Verdict::Error(message) => writeln!(
+                out,
+                ">\n    <error message=\"{}\"/>\n  </testcase>",
+                xml_escape(message)
+            )
+            .unwrap(),
+        }
+    }
+    out.push_str("</testsuite>\n");
+    out
+}
+
+/// Renders results as a JSON object with totals and one entry per case.
+pub fn json_report(results: &[CaseResult]) -> String {
+    let mut out = String::new();
+    let count = |f: fn(&Verdict) -> bool| results.iter().filter(|r| f(&r.verdict)).count();
+    writeln!(
+        out,
+        "{{\"tests\":{},\"passed\":{},\"failed\":{},\"errors\":{},\"cases\":[",
+        results.len(),
+        count(|v| *v == Verdict::Passed),
+        count(|v| matches!(v, Verdict::Failed(_))),
+        count(|v| matches!(v, Verdict::Error(_))),
+    )
+    .unwrap();
+    for (i, result) in results.iter().enumerate() {
+        let (status, message) = match &result.verdict {
+            Verdict::Passed => ("passed", None),
+            Verdict::Failed(message) => ("failed", Some(message)),
+            Verdict::Error(message) => ("error", Some(message)),
+        };
+        write!(
+            out,
+            "{{\"name\":{},\"status\":\"{status}\",\"message\":{},\"steps\":{},\"time\":{:.6},\"output\":{}}}",
+            json::string(&result.name),
+            message.map_or_else(|| String::from("null"), |m| json::string(m)),
+            result.steps,
+            result.time.as_secs_f64(),
+            json::string(&String::from_utf8_lossy(&result.output)),
+        )
+        .unwrap();
+        out.push_str(if i + 1 < results.len() { ",\n" } else { "\n" });
+    }
+    out.push_str("]}\n");
+    out
+}
+
+fn xml_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            // Control characters other than whitespace are not allowed in XML 1.0.
+            c if c < ' ' && !matches!(c, '\t' | '\n' | '\r') => out.push('\u{fffd}'),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn case(name: &str, code: &str, expected: Option<&[u8]>) -> Case {
+        Case {
+            name: name.to_string(),
+            code: code.to_string(),
+            input: Vec::new(),
+            expected: expected.map(<[u8]>::to_vec),
+        }
+    }
+
+    #[test]
+    fn test_verdicts() {
+        let cases = [
+            case("exits", "ebbbbbbbbb", Some(b"")),
+            case("wrong", "ebbbbbbbbb", Some(b"x")),
+            case("loops", "ab", None),
+            case("dupe", "a a", None),
+        ];
+        let results = CorpusRunner::new()
+            .limits(Limits::steps(100))
+            .jobs(3)
+            .run(&cases);
+        let names: Vec<_> = results.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["exits", "wrong", "loops", "dupe"]);
+        assert_eq!(results[0].verdict, Verdict::Passed);
+        assert!(matches!(results[1].verdict, Verdict::Failed(_)));
+        assert!(matches!(results[2].verdict, Verdict::Failed(_)));
+        assert!(matches!(results[3].verdict, Verdict::Error(_)));
+
+        let xml = junit_xml("corpus", &results);
+        assert!(xml.contains("tests=\"4\" failures=\"2\" errors=\"1\""));
+        assert!(xml.contains("<testcase name=\"exits\" classname=\"corpus\""));
+        let json = json_report(&results);
+        assert!(json.starts_with("{\"tests\":4,\"passed\":1,\"failed\":2,\"errors\":1,"));
+        assert!(json.contains("{\"name\":\"loops\",\"status\":\"failed\""));
+    }
+
+    #[test]
+    fn test_xml_escape() {
+        assert_eq!(
+            xml_escape("<a & 'b'>\0"),
+            "&lt;a &amp; &apos;b&apos;&gt;\u{fffd}"
+        );
+    }
+}
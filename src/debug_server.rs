@@ -0,0 +1,480 @@
+//! Driving a [`Debugger`] remotely, over JSON-RPC 2.0.
+//!
+//! Requests and responses are one JSON object per line, so any transport that carries lines
+//! works: the CLI serves them over TCP, and tests feed them from strings. Positions are
+//! `[row, col]` pairs. The methods are:
+//!
+//! - `state`: where the program is and what's on its stack and in its registers.
+//! - `step`, with an optional `count` of up to a million: runs that many instructions,
+//!   ignoring breakpoints.
+//! - `continue`, with an optional `maxSteps` of up to a million, the default: runs to the next
+//!   breakpoint.
+//! - `setBreakpoint`, with `tank` and `cell` positions and an optional `condition` in the
+//!   language of [`crate::condition`]: returns the new breakpoint's `id`. The `tank` can also
+//!   be given by name.
+//! - `removeBreakpoint`, with an `id`, and `breakpoints`, which lists them.
+//! - `output`: everything the program has written so far.
+//! - `timeline`, with optional `end` and `rows`: the recorded steps ending at step `end`, or
+//!   the latest, as a list and drawn as text. The server has to be recording a timeline.
+//! - `minimap`, with an optional `trail` length: the aquarium drawn one character per tank,
+//!   with a trail of recent tanks if the server is recording a timeline.
+//! - `stack`: how many values the next step will pop or move, and the stack drawn as text
+//!   with each value decoded.
+//!
+//! Runtime errors come back as error responses with code 1, and asking for a timeline that
+//! isn't being recorded with code 2.
+
+use std::io::{self, BufRead, Write};
+
+use crate::{
+    condition::Condition,
+    debugger::{Debugger, Location, Stop},
+    io::{Io, MockIo},
+    json::{self, Value},
+    program::{Program, Status},
+    render::{render_minimap, render_stack},
+};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const RUNTIME_ERROR: i64 = 1;
+const NOT_RECORDING: i64 = 2;
+
+/// The most instructions one `step` or `continue` request may run, so one request can't tie
+/// the server up for long.
+const MAX_STEP_COUNT: u64 = 1_000_000;
+
+type Failure = (i64, String);
+
+/// Answers one request, or returns `None` if it's a notification, which gets no response.
+pub fn handle(debugger: &mut Debugger<MockIo>, line: &str) -> Option<String> {
+    let request = match json::parse(line) {
+        Ok(request) => request,
+        Err(message) => return Some(response(&Value::Null, Err((PARSE_ERROR, message)))),
+    };
+    let id = request.get("id").cloned();
+    let result = match request.get("method").and_then(Value::as_str) {
+        Some(method) if request.get("jsonrpc").and_then(Value::as_str) == Some("2.0") => {
+            call(debugger, method, request.get("params"))
+        }
+        _ => Err((INVALID_REQUEST, String::from("not a JSON-RPC 2.0 request"))),
+    };
+    match id {
+        Some(id) => Some(response(&id, result)),
+        None if matches!(result, Err((INVALID_REQUEST, _))) => Some(response(&Value::Null, result)),
+        None => None,
+    }
+}
+
+/// Answers requests from `reader` on `writer` until `reader` runs out.
+pub fn serve(
+    debugger: &mut Debugger<MockIo>,
+    reader: impl BufRead,
+    mut writer: impl Write,
+) -> io::Result<()> {
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = handle(debugger, &line) {
+            writeln!(writer, "{response}")?;
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+fn response(id: &Value, result: Result<Value, Failure>) -> String {
+    let outcome = match result {
+        Ok(result) => ("result", result),
+        Err((code, message)) => (
+            "error",
+            object([
+                ("code", Value::Int(code)),
+                ("message", Value::String(message)),
+            ]),
+        ),
+    };
+    object([
+        ("jsonrpc", Value::String(String::from("2.0"))),
+        ("id", id.clone()),
+        outcome,
+    ])
+    .to_string()
+}
+
+fn call(
+    debugger: &mut Debugger<MockIo>,
+    method: &str,
+    params: Option<&Value>,
+) -> Result<Value, Failure> {
+    let param = |key| params.and_then(|params| params.get(key));
+    match method {
+        "state" => Ok(state(debugger)),
+        "step" => {
+            for _ in 0..steps(param("count"), "count")?.unwrap_or(1) {
+                if debugger.step().map_err(runtime)? != Status::Running {
+                    break;
+                }
+            }
+            Ok(state(debugger))
+        }
+        "continue" => {
+            let stop = debugger
+                .resume(Some(
+                    steps(param("maxSteps"), "maxSteps")?.unwrap_or(MAX_STEP_COUNT),
+                ))
+                .map_err(runtime)?;
+            let (kind, breakpoint) = match stop {
+                Stop::Breakpoint(id) => ("breakpoint", Value::Int(id.into())),
+                Stop::Stopped(_) => ("stopped", Value::Null),
+                Stop::Budget => ("budget", Value::Null),
+                Stop::Paused => ("paused", Value::Null),
+            };
+            Ok(object([
+                ("stop", Value::String(String::from(kind))),
+                ("breakpoint", breakpoint),
+                ("state", state(debugger)),
+            ]))
+        }
+        "setBreakpoint" => {
+            let location = Location {
+                tank: match param("tank") {
+                    Some(Value::String(name)) => {
+                        let program = debugger.program();
+                        program
+                            .name_id(name)
+                            .map(|id| program.position(id))
+                            .ok_or_else(|| invalid_params(format!("no tank called {name:?}")))?
+                    }
+                    tank => position(tank, "tank")?,
+                },
+                cell: position(param("cell"), "cell")?,
+            };
+            let condition = match param("condition") {
+                None | Some(Value::Null) => None,
+                Some(Value::String(source)) => Some(
+                    Condition::parse(source)
+                        .map_err(|e| invalid_params(format!("invalid condition: {e}")))?,
+                ),
+                Some(_) => return Err(invalid_params(String::from("condition must be a string"))),
+            };
+            let id = debugger
+                .add_breakpoint(location, condition)
+                .ok_or_else(|| {
+                    invalid_params(String::from("the breakpoint is outside the aquarium"))
+                })?;
+            Ok(object([("id", Value::Int(id.into()))]))
+        }
+        "removeBreakpoint" => {
+            let id = param("id")
+                .and_then(Value::as_int)
+                .and_then(|id| u32::try_from(id).ok())
+                .ok_or_else(|| invalid_params(String::from("id must be a breakpoint id")))?;
+            Ok(Value::Bool(debugger.remove_breakpoint(id)))
+        }
+        "breakpoints" => Ok(Value::Array(
+            debugger
+                .breakpoints()
+                .iter()
+                .map(|(&id, breakpoint)| {
+                    let condition = breakpoint.condition.as_ref();
+                    object([
+                        ("id", Value::Int(id.into())),
+                        ("tank", pair(breakpoint.location.tank)),
+                        ("cell", pair(breakpoint.location.cell)),
+                        (
+                            "condition",
+                            condition.map_or(Value::Null, |c| Value::String(c.to_string())),
+                        ),
+                    ])
+                })
+                .collect(),
+        )),
+        "output" => Ok(Value::String(
+            String::from_utf8_lossy(debugger.program().io().output()).into_owned(),
+        )),
+        "timeline" => {
+            let timeline = debugger
+                .timeline()
+                .ok_or_else(|| (NOT_RECORDING, String::from("no timeline is being recorded")))?;
+            let end = count(param("end"), "end")?;
+            let rows = count(param("rows"), "rows")?.map_or(20, |rows| rows as usize);
+            let entries = timeline
+                .window(end, rows)
+                .map(|entry| {
+                    object([
+                        ("step", Value::Int(entry.step as i64)),
+                        ("opcode", Value::String(entry.opcode_name().to_string())),
+                        ("tank", pair(entry.tank)),
+                        ("depth", Value::Int(entry.depth as i64)),
+                    ])
+                })
+                .collect();
+            Ok(object([
+                ("entries", Value::Array(entries)),
+                ("text", Value::String(timeline.render(end, rows))),
+            ]))
+        }
+        "minimap" => {
+            let len = count(param("trail"), "trail")?.map_or(4, |len| len as usize);
+            let trail = debugger
+                .timeline()
+                .map_or_else(Vec::new, |timeline| timeline.trail(len));
+            Ok(Value::String(render_minimap(debugger.program(), &trail)))
+        }
+        "stack" => Ok(object([
+            ("consumed", Value::Int(debugger.program().consumed() as i64)),
+            ("text", Value::String(render_stack(debugger.program()))),
+        ])),
+        _ => Err((METHOD_NOT_FOUND, format!("no method named {method:?}"))),
+    }
+}
+
+fn state(debugger: &Debugger<MockIo>) -> Value {
+    program_state(debugger.program())
+}
+
+/// The `state` method's result for `program`, which snapshots reuse.
+pub(crate) fn program_state<I: Io>(program: &Program<I>) -> Value {
+    let ints =
+        |values: &[isize]| Value::Array(values.iter().map(|&x| Value::Int(x as i64)).collect());
+    object([
+        ("status", Value::String(status_name(program.status()))),
+        ("steps", Value::Int(program.steps() as i64)),
+        ("tank", pair(program.ftp())),
+        (
+            "name",
+            Value::String(program.aquarium()[program.ftp()].name().to_string()),
+        ),
+        ("cell", pair((program.ip().row(), program.ip().col()))),
+        (
+            "direction",
+            Value::String(program.direction().name().to_string()),
+        ),
+        ("stack", ints(program.stack())),
+        ("registers", ints(program.registers())),
+    ])
+}
+
+fn status_name(status: Status) -> String {
+    match status {
+        Status::Running => String::from("running"),
+        Status::Halted(reason) => format!("halted: {}", reason.name()),
+        Status::OutOfFuel => String::from("out of fuel"),
+    }
+}
+
+fn object<const N: usize>(members: [(&str, Value); N]) -> Value {
+    Value::Object(
+        members
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+fn pair((row, col): (usize, usize)) -> Value {
+    Value::Array(vec![Value::Int(row as i64), Value::Int(col as i64)])
+}
+
+fn position(value: Option<&Value>, name: &str) -> Result<(usize, usize), Failure> {
+    let coordinate = |value: &Value| value.as_int().and_then(|x| usize::try_from(x).ok());
+    match value.and_then(Value::as_array) {
+        Some([row, col]) => coordinate(row).zip(coordinate(col)),
+        _ => None,
+    }
+    .ok_or_else(|| invalid_params(format!("{name} must be a [row, col] pair")))
+}
+
+fn count(value: Option<&Value>, name: &str) -> Result<Option<u64>, Failure> {
+    value
+        .map(|value| {
+            value
+                .as_int()
+                .and_then(|x| u64::try_from(x).ok())
+                .ok_or_else(|| invalid_params(format!("{name} must be a non-negative integer")))
+        })
+        .transpose()
+}
+
+/// A count of steps, which may be at most [`MAX_STEP_COUNT`].
+fn steps(value: Option<&Value>, name: &str) -> Result<Option<u64>, Failure> {
+    match count(value, name)? {
+        Some(steps) if steps > MAX_STEP_COUNT => Err(invalid_params(format!(
+            "{name} must be at most {MAX_STEP_COUNT}"
+        ))),
+        steps => Ok(steps),
+    }
+}
+
+fn invalid_params(message: String) -> Failure {
+    (INVALID_PARAMS, message)
+}
+
+fn runtime(error: crate::program::RuntimeError) -> Failure {
+    (RUNTIME_ERROR, error.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::program::Program;
+
+    fn debugger(code: &str) -> Debugger<MockIo> {
+        Debugger::new(Program::with_io(code, MockIo::default()).unwrap())
+    }
+
+    #[test]
+    fn test_handle() {
+        let mut debugger = debugger("ab cd");
+        let mut send = |request: &str| handle(&mut debugger, request);
+        assert_eq!(
+            send(r#"{"jsonrpc":"2.0","id":1,"method":"setBreakpoint","params":{"tank":[0,0],"cell":[2,0]}}"#)
+                .unwrap(),
+            r#"{"jsonrpc":"2.0","id":1,"result":{"id":1}}"#
+        );
+        let response = send(r#"{"jsonrpc":"2.0","id":2,"method":"continue"}"#).unwrap();
+        let response = json::parse(&response).unwrap();
+        let result = response.get("result").unwrap();
+        assert_eq!(result.get("breakpoint").and_then(Value::as_int), Some(1));
+        let state = result.get("state").unwrap();
+        assert_eq!(state.get("steps").and_then(Value::as_int), Some(2));
+        assert_eq!(state.get("cell").unwrap().to_string(), "[2,0]");
+        assert_eq!(state.get("status").and_then(Value::as_str), Some("running"));
+
+        // Notifications run but get no response.
+        assert_eq!(
+            send(r#"{"jsonrpc":"2.0","method":"step","params":{"count":3}}"#),
+            None
+        );
+        let response = send(r#"{"jsonrpc":"2.0","id":"s","method":"state"}"#).unwrap();
+        assert!(response.contains(r#""steps":5"#));
+        assert_eq!(
+            send(r#"{"jsonrpc":"2.0","id":4,"method":"stack"}"#).unwrap(),
+            r#"{"jsonrpc":"2.0","id":4,"result":{"consumed":0,"text":""}}"#
+        );
+        assert_eq!(
+            send(r#"{"jsonrpc":"2.0","id":4,"method":"minimap"}"#).unwrap(),
+            r#"{"jsonrpc":"2.0","id":4,"result":"@·\n"}"#
+        );
+
+        assert_eq!(
+            send(r#"{"jsonrpc":"2.0","id":5,"method":"setBreakpoint","params":{"tank":"cd","cell":[0,0]}}"#)
+                .unwrap(),
+            r#"{"jsonrpc":"2.0","id":5,"result":{"id":2}}"#
+        );
+        let response = send(
+            r#"{"jsonrpc":"2.0","id":5,"method":"setBreakpoint","params":{"tank":"ce","cell":[0,0]}}"#,
+        )
+        .unwrap();
+        assert!(response.contains(r#""message":"no tank called \"ce\"""#));
+        let response = send(
+            r#"{"jsonrpc":"2.0","id":3,"method":"setBreakpoint","params":{"tank":[0,0],"cell":[0,0],"condition":"tnak == 1"}}"#,
+        )
+        .unwrap();
+        assert!(
+            response.contains(r#""message":"invalid condition: no variable \"tnak\" at byte 0""#)
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut debugger = debugger("ab cd");
+        let code = |debugger: &mut Debugger<MockIo>, request: &str| {
+            let response = json::parse(&handle(debugger, request).unwrap()).unwrap();
+            response
+                .get("error")
+                .and_then(|error| error.get("code"))
+                .and_then(Value::as_int)
+        };
+        assert_eq!(code(&mut debugger, "{"), Some(PARSE_ERROR));
+        assert_eq!(code(&mut debugger, &"[".repeat(200_000)), Some(PARSE_ERROR));
+        assert_eq!(
+            code(&mut debugger, r#"{"id":1,"method":"state"}"#),
+            Some(INVALID_REQUEST)
+        );
+        assert_eq!(
+            code(&mut debugger, r#"{"jsonrpc":"2.0","id":1,"method":"fly"}"#),
+            Some(METHOD_NOT_FOUND)
+        );
+        assert_eq!(
+            code(
+                &mut debugger,
+                r#"{"jsonrpc":"2.0","id":1,"method":"setBreakpoint","params":{"tank":[0,9],"cell":[0,0]}}"#
+            ),
+            Some(INVALID_PARAMS)
+        );
+        assert_eq!(
+            code(
+                &mut debugger,
+                r#"{"jsonrpc":"2.0","id":1,"method":"step","params":{"count":-1}}"#
+            ),
+            Some(INVALID_PARAMS)
+        );
+        assert_eq!(
+            code(
+                &mut debugger,
+                r#"{"jsonrpc":"2.0","id":1,"method":"step","params":{"count":1000001}}"#
+            ),
+            Some(INVALID_PARAMS)
+        );
+        assert_eq!(
+            code(
+                &mut debugger,
+                r#"{"jsonrpc":"2.0","id":1,"method":"continue","params":{"maxSteps":1000001}}"#
+            ),
+            Some(INVALID_PARAMS)
+        );
+        // Without a budget, a program that never reaches a breakpoint stops at the most a
+        // request may run.
+        let response = handle(
+            &mut debugger,
+            r#"{"jsonrpc":"2.0","id":1,"method":"continue"}"#,
+        )
+        .unwrap();
+        let result = json::parse(&response)
+            .unwrap()
+            .get("result")
+            .cloned()
+            .unwrap();
+        assert_eq!(result.get("stop").and_then(Value::as_str), Some("budget"));
+        assert_eq!(
+            code(
+                &mut debugger,
+                r#"{"jsonrpc":"2.0","id":1,"method":"timeline"}"#
+            ),
+            Some(NOT_RECORDING)
+        );
+        debugger.record_timeline(8);
+        assert_eq!(
+            code(
+                &mut debugger,
+                r#"{"jsonrpc":"2.0","id":1,"method":"timeline"}"#
+            ),
+            None
+        );
+        assert_eq!(
+            code(
+                &mut debugger,
+                r#"{"jsonrpc":"2.0","id":1,"method":"output"}"#
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_serve() {
+        let mut debugger = debugger("ab cd");
+        let requests = "\n{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"breakpoints\"}\n";
+        let mut responses = Vec::new();
+        serve(&mut debugger, requests.as_bytes(), &mut responses).unwrap();
+        assert_eq!(
+            String::from_utf8(responses).unwrap(),
+            "{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":[]}\n"
+        );
+    }
+}
@@ -0,0 +1,347 @@
+//! Running a program under breakpoints.
+//!
+//! A [`Debugger`] owns a program and a set of breakpoints, each a cell in a tank with an
+//! optional [`Condition`] on the program's state. Stepping
+//! runs single instructions regardless of breakpoints; continuing runs until the instruction
+//! pointer reaches a breakpoint, the program stops, or a step budget runs out. Frontends such
+//! as the debug server drive it and display the program's state however they like.
+//!
+//! Hooks automate the repetitive parts: a hook runs after every step and at every breakpoint,
+//! can read and change the program, say by logging its stack or patching it, and decides
+//! whether running carries on.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    condition::Condition,
+    io::Io,
+    program::{HaltReason, Program, RuntimeError, Status},
+    timeline::Timeline,
+};
+
+/// A cell in the aquarium: the tank's row and column, then the cell's within the tank.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Location {
+    pub tank: (usize, usize),
+    pub cell: (usize, usize),
+}
+
+impl Location {
+    /// Where `program`'s instruction pointer is.
+    pub fn of<I: Io>(program: &Program<I>) -> Self {
+        Self {
+            tank: program.ftp(),
+            cell: (program.ip().row(), program.ip().col()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub location: Location,
+    /// The breakpoint only stops the program when this holds.
+    pub condition: Option<Condition>,
+}
+
+/// What a hook is being run for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// An instruction just ran.
+    Step,
+    /// The pointer arrived at the breakpoint with this id, and its condition held.
+    Breakpoint(u32),
+}
+
+/// What a hook wants [`Debugger::resume`] to do next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Flow {
+    /// Whatever it would have done: carry on after a step, stop at a breakpoint.
+    #[default]
+    Default,
+    /// Stop, with [`Stop::Paused`].
+    Pause,
+    /// Carry on, even at a breakpoint.
+    Resume,
+}
+
+pub type Hook<I> = Box<dyn FnMut(Event, &mut Program<I>) -> Flow>;
+
+/// Why [`Debugger::resume`] returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// The instruction pointer reached the breakpoint with this id, and its condition held.
+    /// Its instruction hasn't run yet.
+    Breakpoint(u32),
+    /// The program stopped running, with this status.
+    Stopped(Status),
+    /// The step budget ran out first.
+    Budget,
+    /// A hook paused the program.
+    Paused,
+}
+
+pub struct Debugger<I> {
+    program: Program<I>,
+    breakpoints: BTreeMap<u32, Breakpoint>,
+    next_id: u32,
+    hooks: Vec<Hook<I>>,
+    timeline: Option<Timeline>,
+}
+
+impl<I: Io> Debugger<I> {
+    pub fn new(program: Program<I>) -> Self {
+        Self {
+            program,
+            breakpoints: BTreeMap::new(),
+            next_id: 1,
+            hooks: Vec::new(),
+            timeline: None,
+        }
+    }
+
+    pub fn program(&self) -> &Program<I> {
+        &self.program
+    }
+
+    pub fn program_mut(&mut self) -> &mut Program<I> {
+        &mut self.program
+    }
+
+    pub fn into_program(self) -> Program<I> {
+        self.program
+    }
+
+    /// Starts recording the last `capacity` steps, replacing any earlier record.
+    pub fn record_timeline(&mut self, capacity: usize) {
+        self.timeline = Some(Timeline::new(capacity));
+    }
+
+    /// The steps recorded since [`Debugger::record_timeline`], if it was called.
+    pub fn timeline(&self) -> Option<&Timeline> {
+        self.timeline.as_ref()
+    }
+
+    /// Sets a breakpoint, returning its id, or `None` if the location is outside the
+    /// aquarium.
+    pub fn add_breakpoint(
+        &mut self,
+        location: Location,
+        condition: Option<Condition>,
+    ) -> Option<u32> {
+        let (row, col) = location.tank;
+        let tank = self.program.aquarium().get(row, col)?;
+        tank.grid().get(location.cell.0, location.cell.1)?;
+        let id = self.next_id;
+        self.next_id += 1;
+        self.breakpoints.insert(
+            id,
+            Breakpoint {
+                location,
+                condition,
+            },
+        );
+        Some(id)
+    }
+
+    /// Removes a breakpoint, returning whether it existed.
+    pub fn remove_breakpoint(&mut self, id: u32) -> bool {
+        self.breakpoints.remove(&id).is_some()
+    }
+
+    /// Every breakpoint, by id.
+    pub fn breakpoints(&self) -> &BTreeMap<u32, Breakpoint> {
+        &self.breakpoints
+    }
+
+    /// Adds a hook, to run after the ones already added. Every hook runs for every event,
+    /// and a pause from any of them wins over carrying on.
+    pub fn add_hook(&mut self, hook: impl FnMut(Event, &mut Program<I>) -> Flow + 'static) {
+        self.hooks.push(Box::new(hook));
+    }
+
+    fn run_hooks(&mut self, event: Event) -> Flow {
+        let mut flow = Flow::Default;
+        for hook in &mut self.hooks {
+            flow = match (flow, hook(event, &mut self.program)) {
+                (Flow::Pause, _) | (_, Flow::Pause) => Flow::Pause,
+                (Flow::Resume, _) | (_, Flow::Resume) => Flow::Resume,
+                _ => Flow::Default,
+            };
+        }
+        flow
+    }
+
+    /// Runs one instruction, even if the pointer is on a breakpoint, then the hooks, though
+    /// a single step stops regardless of what they say. At the step limit the program halts
+    /// instead.
+    pub fn step(&mut self) -> Result<Status, RuntimeError> {
+        let status = self.step_quietly()?;
+        if status == Status::Running {
+            self.run_hooks(Event::Step);
+        }
+        Ok(status)
+    }
+
+    fn step_quietly(&mut self) -> Result<Status, RuntimeError> {
+        if self.program.status() == Status::Running
+            && self
+                .program
+                .step_limit()
+                .is_some_and(|limit| self.program.steps() >= limit)
+        {
+            return Ok(Status::Halted(HaltReason::StepLimit));
+        }
+        let Some(timeline) = &mut self.timeline else {
+            return self.program.step();
+        };
+        if self.program.status() == Status::Running {
+            timeline.start(&self.program);
+        }
+        let status = self.program.step();
+        timeline.finish(&self.program);
+        status
+    }
+
+    /// Runs until the pointer arrives at a breakpoint, a hook pauses, the program stops, or
+    /// `budget` steps have run. The first instruction always runs, so resuming from a
+    /// breakpoint moves on.
+    pub fn resume(&mut self, budget: Option<u64>) -> Result<Stop, RuntimeError> {
+        let mut taken = 0;
+        loop {
+            if budget.is_some_and(|budget| taken >= budget) {
+                return Ok(Stop::Budget);
+            }
+            let status = self.step_quietly()?;
+            taken += 1;
+            if status != Status::Running {
+                return Ok(Stop::Stopped(status));
+            }
+            if self.run_hooks(Event::Step) == Flow::Pause {
+                return Ok(Stop::Paused);
+            }
+            if let Some(id) = self.breakpoint_here() {
+                match self.run_hooks(Event::Breakpoint(id)) {
+                    Flow::Default => return Ok(Stop::Breakpoint(id)),
+                    Flow::Pause => return Ok(Stop::Paused),
+                    Flow::Resume => {}
+                }
+            }
+        }
+    }
+
+    /// The first breakpoint at the instruction pointer whose condition holds, if there is one.
+    pub fn breakpoint_here(&self) -> Option<u32> {
+        let here = Location::of(&self.program);
+        self.breakpoints
+            .iter()
+            .find(|(_, breakpoint)| {
+                breakpoint.location == here
+                    && (breakpoint.condition.as_ref())
+                        .is_none_or(|condition| condition.holds(&self.program))
+            })
+            .map(|(&id, _)| id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::MockIo;
+
+    #[test]
+    fn test_resume() {
+        let program = Program::with_io("ab cd", MockIo::default()).unwrap();
+        let mut debugger = Debugger::new(program);
+        let below = Location {
+            tank: (0, 0),
+            cell: (2, 0),
+        };
+        let id = debugger.add_breakpoint(below, None).unwrap();
+        assert_eq!(
+            debugger.add_breakpoint(
+                Location {
+                    tank: (0, 2),
+                    cell: (0, 0)
+                },
+                None
+            ),
+            None
+        );
+        assert_eq!(debugger.resume(None).unwrap(), Stop::Breakpoint(id));
+        assert_eq!(Location::of(debugger.program()), below);
+        assert_eq!(debugger.program().steps(), 2);
+        // Resuming runs off the breakpoint before looking for the next one, and this program
+        // bounces straight back onto it.
+        assert_eq!(debugger.resume(None).unwrap(), Stop::Breakpoint(id));
+        assert_eq!(debugger.program().steps(), 4);
+        assert_eq!(debugger.resume(Some(1)).unwrap(), Stop::Budget);
+        assert_eq!(debugger.program().steps(), 5);
+
+        assert!(debugger.remove_breakpoint(id));
+        assert!(!debugger.remove_breakpoint(id));
+        debugger.program_mut().set_step_limit(Some(20));
+        assert_eq!(
+            debugger.resume(None).unwrap(),
+            Stop::Stopped(Status::Halted(HaltReason::StepLimit))
+        );
+    }
+
+    #[test]
+    fn test_condition() {
+        let program = Program::with_io("ab cd", MockIo::default()).unwrap();
+        let mut debugger = Debugger::new(program);
+        let location = Location {
+            tank: (0, 0),
+            cell: (2, 0),
+        };
+        let condition = Condition::parse(r#"steps > 5 && tank == "ab""#).unwrap();
+        let id = debugger.add_breakpoint(location, Some(condition)).unwrap();
+        assert_eq!(debugger.resume(None).unwrap(), Stop::Breakpoint(id));
+        assert_eq!(debugger.program().steps(), 6);
+    }
+
+    #[test]
+    fn test_timeline() {
+        let program = Program::with_io("ab cd", MockIo::default()).unwrap();
+        let mut debugger = Debugger::new(program);
+        debugger.record_timeline(3);
+        debugger.program_mut().set_step_limit(Some(5));
+        debugger.resume(None).unwrap();
+        let timeline = debugger.timeline().unwrap();
+        let steps: Vec<_> = timeline.entries().map(|entry| entry.step).collect();
+        assert_eq!(steps, [2, 3, 4]);
+    }
+
+    #[test]
+    fn test_hooks() {
+        let program = Program::with_io("ab cd", MockIo::default()).unwrap();
+        let mut debugger = Debugger::new(program);
+        let location = Location {
+            tank: (0, 0),
+            cell: (2, 0),
+        };
+        let id = debugger.add_breakpoint(location, None).unwrap();
+        // Log the breakpoint and carry on, pushing the step count each time.
+        let log = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let hits = log.clone();
+        debugger.add_hook(move |event, program| match event {
+            Event::Step => Flow::Default,
+            Event::Breakpoint(id) => {
+                hits.borrow_mut().push(id);
+                let steps = program.steps() as isize;
+                program.stack_mut().push(steps);
+                Flow::Resume
+            }
+        });
+        debugger.add_hook(|event, program| {
+            if event == Event::Step && program.stack().len() == 3 {
+                Flow::Pause
+            } else {
+                Flow::Default
+            }
+        });
+        assert_eq!(debugger.resume(None).unwrap(), Stop::Paused);
+        assert_eq!(*log.borrow(), [id; 3]);
+        assert_eq!(debugger.program().stack(), [2, 4, 6]);
+    }
+}
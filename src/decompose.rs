@@ -0,0 +1,260 @@
+//! The inverse of building a tank: recovering letters and swizzles from a grid of glyph sums.
+
+use std::fmt;
+
+use grid::Grid;
+use thiserror::Error;
+
+use crate::{parser::SWIZZLE_SHIFT, program::Tank};
+
+const CELLS: usize = 20;
+/// Swizzling twenty times is the identity, so more swizzles than this are never needed.
+const MAX_SWIZZLES: usize = CELLS - 1;
+
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum SumsError {
+    #[error("invalid glyph sum: {0:?}")]
+    InvalidSum(String),
+    #[error("grid has {0} cells, expected 20")]
+    WrongLength(usize),
+}
+
+/// Parses 20 glyph sums in row-major order, as printed on the `sums` line of `--emit=ir`.
+///
+/// Sums may be separated by commas, slashes or whitespace.
+pub fn parse_sums(s: &str) -> Result<Grid<usize>, SumsError> {
+    let sums = s
+        .split(|c: char| c == ',' || c == '/' || c.is_whitespace())
+        .filter(|sum| !sum.is_empty())
+        .map(|sum| {
+            sum.parse()
+                .map_err(|_| SumsError::InvalidSum(sum.to_string()))
+        })
+        .collect::<Result<Vec<usize>, _>>()?;
+    if sums.len() != CELLS {
+        return Err(SumsError::WrongLength(sums.len()));
+    }
+    Ok(Grid::from_vec(sums, 4))
+}
+
+/// One step in building a tank.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Step {
+    /// Add a letter's glyph.
+    Mask(char),
+    /// Rotate the grid, as an apostrophe does.
+    Swizzle,
+}
+
+/// A sequence of steps that builds a particular grid from an empty one.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Decomposition {
+    pub steps: Vec<Step>,
+}
+
+impl Decomposition {
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// The steps spelled as a name, if that name is one the parser accepts. Sequences that
+    /// start or end with a swizzle, or swizzle twice in a row, have no such spelling.
+    pub fn to_name(&self) -> Option<String> {
+        let name = self.to_string();
+        crate::parser::is_name(&name).then_some(name)
+    }
+}
+
+/// Spells the steps as letters and apostrophes, whether or not that is a valid name.
+impl fmt::Display for Decomposition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for step in &self.steps {
+            match step {
+                Step::Mask(letter) => write!(f, "{letter}")?,
+                Step::Swizzle => write!(f, "'")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A glyph as it ends up in the final grid: rotated once for every swizzle after it.
+struct Vector {
+    letter: char,
+    level: usize,
+    cells: [usize; CELLS],
+    weight: usize,
+    mask: u32,
+}
+
+/// Finds a shortest sequence of glyphs and swizzles, at most `max_len` steps long, that builds
+/// `grid`, or `None` if there is none.
+///
+/// Each letter contributes its glyph rotated once per swizzle that follows it, so a sequence is
+/// equivalent to a multiset of rotated glyphs plus a swizzle count. Sequences are tried in
+/// order of length, with letters at rotation level `k` placed before the `k`th-from-last
+/// swizzle, and letters in each group in alphabetical order.
+pub fn decompose(grid: &Grid<usize>, max_len: usize) -> Option<Decomposition> {
+    let target: [usize; CELLS] = std::array::from_fn(|i| grid[(i / 4, i % 4)]);
+    if target.iter().all(|&x| x == 0) {
+        return Some(Decomposition { steps: Vec::new() });
+    }
+    let glyphs: Vec<[usize; CELLS]> = (b'a'..=b'z')
+        .map(|letter| {
+            let tank = Tank::from_name(&(letter as char).to_string()).expect("letters are names");
+            *tank.grid().as_array()
+        })
+        .collect();
+    let mut vectors = Vec::new();
+    for level in 0..=MAX_SWIZZLES.min(max_len.saturating_sub(1)) {
+        for (i, glyph) in glyphs.iter().enumerate() {
+            let cells: [usize; CELLS] =
+                std::array::from_fn(|cell| glyph[(cell + SWIZZLE_SHIFT * level) % CELLS]);
+            vectors.push(Vector {
+                letter: (b'a' + i as u8) as char,
+                level,
+                weight: cells.iter().sum(),
+                mask: mask_of(&cells),
+                cells,
+            });
+        }
+    }
+
+    for len in 1..=max_len {
+        for swizzles in 0..len.min(MAX_SWIZZLES + 1) {
+            let usable = &vectors[..26 * (swizzles + 1)];
+            // Which cells the vectors from each index onwards can still reach.
+            let mut reach = vec![0u32; usable.len() + 1];
+            for i in (0..usable.len()).rev() {
+                reach[i] = reach[i + 1] | usable[i].mask;
+            }
+            let mut remaining = target;
+            let mut chosen = Vec::new();
+            if search(
+                usable,
+                &reach,
+                0,
+                len - swizzles,
+                &mut remaining,
+                &mut chosen,
+            ) {
+                return Some(assemble(usable, &chosen, swizzles));
+            }
+        }
+    }
+    None
+}
+
+fn search(
+    vectors: &[Vector],
+    reach: &[u32],
+    start: usize,
+    letters: usize,
+    remaining: &mut [usize; CELLS],
+    chosen: &mut Vec<usize>,
+) -> bool {
+    let total: usize = remaining.iter().sum();
+    if letters == 0 {
+        return total == 0;
+    }
+    if mask_of(remaining) & !reach[start] != 0 {
+        return false;
+    }
+    for (i, vector) in vectors.iter().enumerate().skip(start) {
+        if vector.weight > total || remaining.iter().zip(&vector.cells).any(|(r, c)| c > r) {
+            continue;
+        }
+        for (r, c) in remaining.iter_mut().zip(&vector.cells) {
+            *r -= c;
+        }
+        chosen.push(i);
+        if search(vectors, reach, i, letters - 1, remaining, chosen) {
+            return true;
+        }
+        chosen.pop();
+        for (r, c) in remaining.iter_mut().zip(&vector.cells) {
+            *r += c;
+        }
+    }
+    false
+}
+
+fn assemble(vectors: &[Vector], chosen: &[usize], swizzles: usize) -> Decomposition {
+    let mut steps = Vec::new();
+    for level in (0..=swizzles).rev() {
+        let mut letters: Vec<char> = chosen
+            .iter()
+            .map(|&i| &vectors[i])
+            .filter(|v| v.level == level)
+            .map(|v| v.letter)
+            .collect();
+        letters.sort();
+        steps.extend(letters.into_iter().map(Step::Mask));
+        if level > 0 {
+            steps.push(Step::Swizzle);
+        }
+    }
+    Decomposition { steps }
+}
+
+fn mask_of(cells: &[usize; CELLS]) -> u32 {
+    cells
+        .iter()
+        .enumerate()
+        .filter(|&(_, &x)| x > 0)
+        .fold(0, |mask, (i, _)| mask | 1 << i)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sums(name: &str) -> Grid<usize> {
+        Tank::from_name(name).unwrap().grid().to_grid()
+    }
+
+    #[test]
+    fn test_decompose_letters() {
+        let found = decompose(&sums("fish"), 8).unwrap();
+        assert_eq!(found.to_name().as_deref(), Some("fhis"));
+        assert_eq!(decompose(&Grid::new(5, 4), 8).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_decompose_swizzles() {
+        let found = decompose(&sums("ab'c"), 8).unwrap();
+        assert_eq!(found.len(), 4);
+        assert_eq!(
+            Tank::from_name(&found.to_string())
+                .unwrap()
+                .grid()
+                .to_grid(),
+            sums("ab'c")
+        );
+        let found = decompose(&sums("a'"), 8).unwrap();
+        assert_eq!(found.steps, [Step::Mask('a'), Step::Swizzle]);
+        assert_eq!(found.to_name(), None);
+    }
+
+    #[test]
+    fn test_no_decomposition() {
+        let mut grid = Grid::new(5, 4);
+        grid[(0, 0)] = 1;
+        assert_eq!(decompose(&grid, 3), None);
+    }
+
+    #[test]
+    fn test_parse_sums() {
+        let grid = parse_sums("1,2,0,0/1,4,2,0/3,3,2,2/3,3,0,2/1,2,4,1").unwrap();
+        assert_eq!(grid[(1, 1)], 4);
+        assert_eq!(parse_sums("1 2"), Err(SumsError::WrongLength(2)));
+        assert_eq!(
+            parse_sums("x"),
+            Err(SumsError::InvalidSum(String::from("x")))
+        );
+    }
+}
@@ -0,0 +1,279 @@
+//! Bounds on how deep the stack can be, worked out without running a program.
+//!
+//! [`StackDepths`] follows the same paths as [`Reachability`](crate::reach::Reachability),
+//! carrying along the lowest depth the stack could have on each, and notes every instruction
+//! that could pop more values than that. Input and the values on the stack aren't modelled,
+//! so a tunnel is assumed to go both ways. Each tank's cycle phase is followed while the
+//! pointer stays in the tank, and assumed to be anything once it comes back. The result is
+//! cautious: a program it finds no underflows in never underflows, but one it warns about
+//! might never underflow either.
+
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    debugger::Location,
+    dialect::{CycleOp, SkipMode, TunnelMode},
+    io::Io,
+    program::Program,
+    reach::{State, instruction, successors},
+};
+
+/// An instruction that pops values off the stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Popper {
+    Cycle(CycleOp),
+    /// A tunnel taking its skip count from the stack.
+    Tunnel,
+    Call(char),
+}
+
+impl fmt::Display for Popper {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Popper::Cycle(op) => write!(f, "cycle {}", op.name()),
+            Popper::Tunnel => write!(f, "tunnel"),
+            Popper::Call(letter) => write!(f, "call {letter}"),
+        }
+    }
+}
+
+/// An instruction that could find too few values on the stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Underflow {
+    pub location: Location,
+    pub instruction: Popper,
+    /// How many values the instruction needs.
+    pub needed: usize,
+    /// The fewest values the stack could hold when it runs.
+    pub depth: usize,
+}
+
+impl fmt::Display for Underflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} needs {} values but the stack may hold {}",
+            self.instruction, self.needed, self.depth
+        )
+    }
+}
+
+/// How many values an instruction needs, and how the depth changes if it finds them.
+struct Effect {
+    popper: Option<Popper>,
+    needed: usize,
+    popped: usize,
+    pushed: usize,
+}
+
+impl Effect {
+    const NONE: Effect = Effect::new(0, 0);
+
+    const fn new(popped: usize, pushed: usize) -> Self {
+        Self {
+            popper: None,
+            needed: 0,
+            popped,
+            pushed,
+        }
+    }
+
+    /// Pops `needed` values, failing if there aren't that many.
+    const fn needs(popper: Popper, needed: usize, pushed: usize) -> Self {
+        Self {
+            popper: Some(popper),
+            needed,
+            popped: needed,
+            pushed,
+        }
+    }
+
+    /// The lowest depth after the instruction runs, given the lowest before it. Paths with
+    /// too few values stop at the instruction, so the rest had at least `needed`.
+    fn apply(&self, depth: usize) -> usize {
+        depth.max(self.needed).saturating_sub(self.popped) + self.pushed
+    }
+}
+
+/// The effect of a call, with extensions enabled or not.
+fn call_effect(letter: char, extensions: bool) -> Effect {
+    let needs = |needed, pushed| Effect::needs(Popper::Call(letter), needed, pushed);
+    match letter {
+        'i' => Effect::new(0, 1),
+        'o' => needs(1, 0),
+        'p' | 'c' | 'g' | 'v' if extensions => Effect::new(0, 1),
+        'u' | 'b' if extensions => needs(1, 0),
+        'l' if extensions => needs(1, 1),
+        'a' | 'm' | 'q' | 'r' if extensions => needs(2, 1),
+        's' | 't' if extensions => needs(2, 0),
+        // A jump pops its name down to a -1, which could be the whole stack.
+        'j' if extensions => Effect::new(usize::MAX, 0),
+        _ => Effect::NONE,
+    }
+}
+
+fn cycle_effect(op: CycleOp) -> Effect {
+    match op {
+        CycleOp::Subtract => Effect::needs(Popper::Cycle(op), 2, 1),
+        CycleOp::Swap => Effect::needs(Popper::Cycle(op), 2, 2),
+        CycleOp::Dup => Effect::needs(Popper::Cycle(op), 1, 2),
+        CycleOp::Drop => Effect::new(1, 0),
+    }
+}
+
+/// The lowest stack depth at every cell a program can execute, and the instructions that
+/// could underflow.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct StackDepths {
+    lowest: HashMap<Location, usize>,
+    underflows: Vec<Underflow>,
+}
+
+impl StackDepths {
+    /// Follows `program` from where its instruction pointer is now, with its stack as it is.
+    pub fn of<I: Io>(program: &Program<I>) -> Self {
+        let dialect = program.dialect();
+        let ops = dialect.cycle.ops();
+        let start = State::of(program);
+        // The cycle phase of the tank the pointer is in, if it's known.
+        let phase = Some(program.aquarium()[start.tank].cycle);
+        let mut best: HashMap<(State, Option<usize>), usize> = HashMap::new();
+        let mut lowest: HashMap<Location, usize> = HashMap::new();
+        let mut underflows: HashMap<(Location, Popper), Underflow> = HashMap::new();
+        let mut pending = vec![(start, phase, program.stack().len())];
+        while let Some((state, phase, depth)) = pending.pop() {
+            // Only a shallower stack than last time can lead anywhere new.
+            if best.get(&(state, phase)).is_some_and(|&seen| seen <= depth) {
+                continue;
+            }
+            best.insert((state, phase), depth);
+            let location = state.location();
+            lowest
+                .entry(location)
+                .and_modify(|lowest| *lowest = (*lowest).min(depth))
+                .or_insert(depth);
+            // Each way the cell could go: its effect and the phase it leaves behind.
+            let effects: Vec<(Effect, Option<usize>)> = match instruction(program, &state) {
+                5 => vec![(Effect::new(0, 1), phase)],
+                6 => match phase {
+                    Some(phase) => vec![(cycle_effect(ops[phase]), Some((phase + 1) % ops.len()))],
+                    None => (0..ops.len())
+                        .map(|phase| (cycle_effect(ops[phase]), Some((phase + 1) % ops.len())))
+                        .collect(),
+                },
+                7 => {
+                    let skip = match dialect.skip {
+                        SkipMode::Stack => Effect::needs(Popper::Tunnel, 1, 0),
+                        SkipMode::Fixed(_) => Effect::NONE,
+                    };
+                    let condition = usize::from(dialect.tunnel == TunnelMode::Pop);
+                    let effect = Effect {
+                        popped: skip.popped + condition,
+                        ..skip
+                    };
+                    vec![(effect, phase)]
+                }
+                9 => {
+                    let letter = program.aquarium()[state.tank].call_letter();
+                    vec![(call_effect(letter, program.extensions), phase)]
+                }
+                _ => vec![(Effect::NONE, phase)],
+            };
+            for (effect, after) in effects {
+                if let Some(popper) = effect.popper
+                    && depth < effect.needed
+                {
+                    let underflow = Underflow {
+                        location,
+                        instruction: popper,
+                        needed: effect.needed,
+                        depth,
+                    };
+                    underflows
+                        .entry((location, popper))
+                        .and_modify(|seen| seen.depth = seen.depth.min(depth))
+                        .or_insert(underflow);
+                }
+                successors(program, &state, |next, ran| {
+                    let (depth, phase) = if ran {
+                        (effect.apply(depth), after)
+                    } else {
+                        (depth, phase)
+                    };
+                    let phase = phase.filter(|_| next.tank == state.tank);
+                    pending.push((next, phase, depth));
+                });
+            }
+        }
+        let mut underflows: Vec<_> = underflows.into_values().collect();
+        underflows.sort_by_key(|underflow| (underflow.location, underflow.instruction));
+        Self { lowest, underflows }
+    }
+
+    /// The fewest values the stack could hold when the cell at `location` runs, or `None` if
+    /// it can't run.
+    pub fn lowest(&self, location: Location) -> Option<usize> {
+        self.lowest.get(&location).copied()
+    }
+
+    /// Every instruction that could underflow, in order of location.
+    pub fn underflows(&self) -> &[Underflow] {
+        &self.underflows
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        cells::{Cells, LEN},
+        io::MockIo,
+        program::Tank,
+    };
+
+    fn tank(name: &str, first: &[usize]) -> Program<MockIo> {
+        let mut cells = [0; LEN];
+        cells[..first.len()].copy_from_slice(first);
+        Program::build_aquarium(
+            vec![Tank::new(name.into(), Cells::new(cells))],
+            MockIo::default(),
+        )
+    }
+
+    fn at(row: usize, col: usize) -> Location {
+        Location {
+            tank: (0, 0),
+            cell: (row, col),
+        }
+    }
+
+    #[test]
+    fn test_stack_depths() {
+        // Two pushes, then a subtract, then around again with one more value each time.
+        let depths = StackDepths::of(&tank("a", &[5, 5, 6]));
+        assert!(depths.underflows().is_empty());
+        assert_eq!(depths.lowest(at(0, 2)), Some(2));
+        assert_eq!(depths.lowest(at(0, 3)), Some(1));
+        assert_eq!(depths.lowest(at(1, 0)), None);
+
+        // One push, then the cycle's subtract, swap, dup and drop in turn. Only the subtract
+        // finds too few values, the first time round.
+        let depths = StackDepths::of(&tank("a", &[5, 6]));
+        let [underflow] = depths.underflows() else {
+            panic!("expected one underflow, found {:?}", depths.underflows());
+        };
+        assert_eq!(underflow.location, at(0, 1));
+        assert_eq!(
+            underflow.to_string(),
+            "cycle sub needs 2 values but the stack may hold 1"
+        );
+
+        // Writing needs a value, and reading one first is enough.
+        let depths = StackDepths::of(&tank("o", &[9]));
+        assert_eq!(depths.underflows()[0].instruction, Popper::Call('o'));
+        let mut program = tank("o", &[9]);
+        program.stack_mut().push(1);
+        assert_eq!(StackDepths::of(&program).underflows().len(), 1);
+        assert!(StackDepths::of(&tank("i", &[9])).underflows().is_empty());
+    }
+}
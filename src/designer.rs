@@ -0,0 +1,205 @@
+use std::io::{self, Read, Write};
+
+use crate::{
+    apostrophe::placements,
+    cells::{COLS, LEN, ROWS},
+    solver::{Candidate, Solver, Target},
+};
+
+/// An interactive editor for a tank's digit grid, showing the dictionary words that come
+/// closest to it as it's painted.
+///
+/// Arrow keys or `hjkl` move the cursor, a digit paints the cell under it and moves on, and
+/// `.`, space or Backspace makes the cell a don't-care. Tab and Shift-Tab, or `n` and `p`,
+/// pick among the closest words, Enter chooses the picked word, and `q` or Ctrl-D gives up.
+/// Ctrl-C interrupts.
+#[derive(Debug, Clone)]
+pub struct Designer {
+    words: Vec<String>,
+    swizzles: usize,
+    shown: usize,
+    target: [Option<u8>; LEN],
+    cursor: usize,
+    selected: usize,
+}
+
+impl Designer {
+    /// A designer suggesting words from `words`.
+    pub fn new<S: AsRef<str>>(words: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            words: words.into_iter().map(|w| w.as_ref().to_string()).collect(),
+            swizzles: 1,
+            shown: 10,
+            target: [None; LEN],
+            cursor: 0,
+            selected: 0,
+        }
+    }
+
+    /// Also suggests each word with up to this many apostrophes added, wherever they fit.
+    pub fn swizzles(mut self, swizzles: usize) -> Self {
+        self.swizzles = swizzles;
+        self
+    }
+
+    /// How many of the closest words to show.
+    pub fn shown(mut self, shown: usize) -> Self {
+        self.shown = shown.max(1);
+        self
+    }
+
+    /// The grid as painted so far.
+    pub fn target(&self) -> Target {
+        let cells: String = self
+            .target
+            .iter()
+            .map(|cell| cell.map_or('?', |digit| char::from(b'0' + digit)))
+            .collect();
+        Target::parse(&cells).expect("painted cells are digits or don't-cares")
+    }
+
+    /// Reads keypresses from `input`, redrawing the grid and the closest words on `screen`
+    /// after each, until a word is chosen. Returns `None` if the user gives up or input ends.
+    ///
+    /// `input` is expected to deliver keypresses unbuffered, e.g. a terminal in raw mode.
+    pub fn run<R: Read, W: Write>(
+        &mut self,
+        input: &mut R,
+        screen: &mut W,
+    ) -> io::Result<Option<String>> {
+        let mut words = Vec::new();
+        for word in &self.words {
+            words.push(word.to_lowercase());
+            if let Ok(found) = placements(&word.to_lowercase(), self.swizzles) {
+                words.extend(found.into_iter().map(|placement| placement.name));
+            }
+        }
+        let solver = Solver::new().limit(self.shown).dictionary(words);
+        loop {
+            let candidates = solver.solve(&self.target());
+            self.selected = self.selected.min(candidates.len().saturating_sub(1));
+            self.draw(&candidates, screen)?;
+            let Some(byte) = read_byte(input)? else {
+                return Ok(None);
+            };
+            let (row, col) = (self.cursor / COLS, self.cursor % COLS);
+            match byte {
+                b'0'..=b'9' => {
+                    self.target[self.cursor] = Some(byte - b'0');
+                    self.cursor = (self.cursor + 1) % LEN;
+                }
+                b'.' | b' ' | b'?' | 0x7f | 0x08 => self.target[self.cursor] = None,
+                b'h' => self.cursor = row * COLS + (col + COLS - 1) % COLS,
+                b'l' => self.cursor = row * COLS + (col + 1) % COLS,
+                b'k' => self.cursor = (row + ROWS - 1) % ROWS * COLS + col,
+                b'j' => self.cursor = (row + 1) % ROWS * COLS + col,
+                b'\t' | b'n' => self.selected = (self.selected + 1) % candidates.len().max(1),
+                b'p' => self.selected = self.selected.saturating_sub(1),
+                b'\r' | b'\n' => {
+                    if let Some(candidate) = candidates.get(self.selected) {
+                        return Ok(Some(candidate.word.clone()));
+                    }
+                }
+                b'q' | 0x04 => return Ok(None),
+                0x03 => return Err(io::Error::from(io::ErrorKind::Interrupted)),
+                0x1b => {
+                    if read_byte(input)? != Some(b'[') {
+                        continue;
+                    }
+                    match read_byte(input)? {
+                        Some(b'A') => self.cursor = (row + ROWS - 1) % ROWS * COLS + col,
+                        Some(b'B') => self.cursor = (row + 1) % ROWS * COLS + col,
+                        Some(b'C') => self.cursor = row * COLS + (col + 1) % COLS,
+                        Some(b'D') => self.cursor = row * COLS + (col + COLS - 1) % COLS,
+                        Some(b'Z') => self.selected = self.selected.saturating_sub(1),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn draw<W: Write>(&self, candidates: &[Candidate], screen: &mut W) -> io::Result<()> {
+        // Home the cursor and clear the screen.
+        write!(screen, "\x1b[H\x1b[2J")?;
+        for (row, cells) in self.target.chunks(COLS).enumerate() {
+            for (col, cell) in cells.iter().enumerate() {
+                let text = cell.map_or('.', |digit| char::from(b'0' + digit));
+                if row * COLS + col == self.cursor {
+                    write!(screen, "[{text}]")?;
+                } else {
+                    write!(screen, " {text} ")?;
+                }
+            }
+            writeln!(screen)?;
+        }
+        writeln!(screen)?;
+        for (i, candidate) in candidates.iter().enumerate() {
+            let marker = if i == self.selected { '>' } else { ' ' };
+            writeln!(
+                screen,
+                "{marker} {} (distance {})",
+                candidate.word, candidate.distance
+            )?;
+        }
+        writeln!(screen)?;
+        write!(
+            screen,
+            "digits paint, . clears, arrows move, tab picks, enter chooses, q quits"
+        )?;
+        screen.flush()
+    }
+}
+
+fn read_byte<R: Read>(input: &mut R) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    loop {
+        match input.read(&mut byte) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(byte[0])),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::program::Tank;
+
+    fn design(designer: &mut Designer, keys: &[u8]) -> Option<String> {
+        designer.run(&mut &keys[..], &mut Vec::new()).unwrap()
+    }
+
+    #[test]
+    fn test_designer() {
+        let words = ["ab", "fish", "don't", "dont"];
+        // Painting the digits of fish, over a first row painted before moving back up to it.
+        let digits: String = Tank::from_name("fish")
+            .unwrap()
+            .digits()
+            .iter()
+            .map(|digit| char::from(b'0' + digit as u8))
+            .collect();
+        let mut keys = b"9999".to_vec();
+        keys.extend(b"\x1b[A");
+        keys.extend(digits.bytes());
+        keys.push(b'\r');
+        let mut designer = Designer::new(words);
+        assert_eq!(design(&mut designer, &keys).as_deref(), Some("fish"));
+        assert_eq!(designer.target(), Target::parse(&digits).unwrap());
+
+        // With nothing painted every word is as close as any other, so they're shortest first,
+        // and include the words with apostrophes added.
+        let pick = |keys: &[u8]| design(&mut Designer::new(words), keys);
+        assert_eq!(pick(b"\r").as_deref(), Some("ab"));
+        assert_eq!(pick(b"\t\r").as_deref(), Some("a'b"));
+        assert_eq!(pick(b"\t\tp\r").as_deref(), Some("a'b"));
+        assert_eq!(design(&mut Designer::new(words), b"12q"), None);
+        assert_eq!(design(&mut Designer::new(words), b"12"), None);
+        let interrupted = Designer::new(words).run(&mut &b"\x03"[..], &mut Vec::new());
+        assert_eq!(interrupted.unwrap_err().kind(), io::ErrorKind::Interrupted);
+    }
+}
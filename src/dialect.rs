@@ -0,0 +1,279 @@
+//! Optional rule changes that turn the language into a related dialect.
+//!
+//! Each rule can be chosen with a `#!` pragma in the program or from the builder and CLI, which
+//! take precedence over the pragma. A `#!spec N` pragma picks the defaults for a whole spec
+//! version, which the other pragmas then adjust.
+
+use std::fmt;
+
+/// What the instruction pointer does when it moves off the edge of a tank.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum EdgeMode {
+    /// Reappear on the opposite edge of the same tank.
+    #[default]
+    Wrap,
+    /// Turn around and move back into the tank.
+    Bounce,
+    /// Reappear on the opposite edge, but in the neighbouring tank, as if after a hop.
+    Hop,
+}
+
+impl EdgeMode {
+    pub const ALL: [EdgeMode; 3] = [EdgeMode::Wrap, EdgeMode::Bounce, EdgeMode::Hop];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EdgeMode::Wrap => "wrap",
+            EdgeMode::Bounce => "bounce",
+            EdgeMode::Hop => "hop",
+        }
+    }
+
+    pub fn by_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+}
+
+/// What a cell with digit 0 does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ZeroMode {
+    /// Nothing; the instruction pointer moves on.
+    #[default]
+    Noop,
+    /// Halt the program, as if the blank cell were the end of the code.
+    Halt,
+    /// Nothing, but only this many blank cells may run in total before the program halts.
+    Quota(u64),
+}
+
+impl ZeroMode {
+    /// Parses `noop`, `halt` or `quota=N`.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "noop" => Some(ZeroMode::Noop),
+            "halt" => Some(ZeroMode::Halt),
+            _ => Some(ZeroMode::Quota(s.strip_prefix("quota=")?.parse().ok()?)),
+        }
+    }
+}
+
+impl fmt::Display for ZeroMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ZeroMode::Noop => write!(f, "noop"),
+            ZeroMode::Halt => write!(f, "halt"),
+            ZeroMode::Quota(quota) => write!(f, "quota={quota}"),
+        }
+    }
+}
+
+/// How many instructions a tunnel skips when its condition fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SkipMode {
+    /// Always this many.
+    Fixed(usize),
+    /// Pop the count from the stack, from above the condition. Counts below 1 skip nothing.
+    Stack,
+}
+
+impl Default for SkipMode {
+    fn default() -> Self {
+        SkipMode::Fixed(1)
+    }
+}
+
+impl SkipMode {
+    /// Parses a count or `stack`.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "stack" => Some(SkipMode::Stack),
+            _ => Some(SkipMode::Fixed(s.parse().ok()?)),
+        }
+    }
+}
+
+impl fmt::Display for SkipMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipMode::Fixed(count) => write!(f, "{count}"),
+            SkipMode::Stack => write!(f, "stack"),
+        }
+    }
+}
+
+/// Whether a tunnel consumes the condition it tests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TunnelMode {
+    /// Leave the condition on the stack.
+    #[default]
+    Peek,
+    /// Pop the condition. An empty stack still counts as a failed condition.
+    Pop,
+}
+
+impl TunnelMode {
+    pub const ALL: [TunnelMode; 2] = [TunnelMode::Peek, TunnelMode::Pop];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TunnelMode::Peek => "peek",
+            TunnelMode::Pop => "pop",
+        }
+    }
+
+    pub fn by_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+}
+
+/// One of the stack operations the cycle instruction rotates through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum CycleOp {
+    /// Pop `b` then `a` and push `a - b`.
+    Subtract,
+    /// Swap the top two values.
+    Swap,
+    /// Duplicate the top value.
+    Dup,
+    /// Discard the top value, if there is one.
+    Drop,
+}
+
+impl CycleOp {
+    pub const ALL: [CycleOp; 4] = [
+        CycleOp::Subtract,
+        CycleOp::Swap,
+        CycleOp::Dup,
+        CycleOp::Drop,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CycleOp::Subtract => "sub",
+            CycleOp::Swap => "swap",
+            CycleOp::Dup => "dup",
+            CycleOp::Drop => "drop",
+        }
+    }
+
+    pub fn by_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|op| op.name() == name)
+    }
+}
+
+/// The operations each tank's cycle instruction runs, in turn, wrapping back to the first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CycleOrder {
+    ops: [CycleOp; 4],
+    len: usize,
+}
+
+impl Default for CycleOrder {
+    fn default() -> Self {
+        Self {
+            ops: CycleOp::ALL,
+            len: CycleOp::ALL.len(),
+        }
+    }
+}
+
+impl CycleOrder {
+    /// An order of one to four operations, which may repeat.
+    pub fn new(ops: &[CycleOp]) -> Option<Self> {
+        if ops.is_empty() || ops.len() > 4 {
+            return None;
+        }
+        let mut order = Self {
+            len: ops.len(),
+            ..Self::default()
+        };
+        order.ops[..ops.len()].copy_from_slice(ops);
+        Some(order)
+    }
+
+    /// Parses comma-separated operation names, like `dup,sub`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let ops = s
+            .split(',')
+            .map(|name| CycleOp::by_name(name.trim()))
+            .collect::<Option<Vec<_>>>()?;
+        Self::new(&ops)
+    }
+
+    pub fn ops(&self) -> &[CycleOp] {
+        &self.ops[..self.len]
+    }
+}
+
+impl fmt::Display for CycleOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, op) in self.ops().iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", op.name())?;
+        }
+        Ok(())
+    }
+}
+
+/// The full set of rules a program runs under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Dialect {
+    pub edges: EdgeMode,
+    pub zero: ZeroMode,
+    pub skip: SkipMode,
+    pub tunnel: TunnelMode,
+    pub cycle: CycleOrder,
+}
+
+impl Dialect {
+    /// The newest spec version, whose rules are the defaults.
+    pub const CURRENT_SPEC: u32 = 1;
+
+    /// The default rules of a spec version, selected with `#!spec N`.
+    ///
+    /// Version 1 is the language as this interpreter runs it by default. Version 0 is the
+    /// convention some program archives were written for, where blank cells halt.
+    pub fn spec(version: u32) -> Option<Self> {
+        match version {
+            0 => Some(Dialect {
+                zero: ZeroMode::Halt,
+                ..Dialect::default()
+            }),
+            1 => Some(Dialect::default()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_modes_round_trip() {
+        for mode in [ZeroMode::Noop, ZeroMode::Halt, ZeroMode::Quota(12)] {
+            assert_eq!(ZeroMode::parse(&mode.to_string()), Some(mode));
+        }
+        assert_eq!(ZeroMode::parse("quota"), None);
+        assert_eq!(ZeroMode::parse("quota=x"), None);
+        for mode in [SkipMode::Fixed(3), SkipMode::Stack] {
+            assert_eq!(SkipMode::parse(&mode.to_string()), Some(mode));
+        }
+        let order = CycleOrder::parse("dup, sub").unwrap();
+        assert_eq!(order.ops(), [CycleOp::Dup, CycleOp::Subtract]);
+        assert_eq!(CycleOrder::parse(&order.to_string()), Some(order));
+        assert_eq!(
+            CycleOrder::parse(&CycleOrder::default().to_string()),
+            Some(CycleOrder::default())
+        );
+        assert_eq!(CycleOrder::parse(""), None);
+        assert_eq!(CycleOrder::parse("sub,sub,sub,sub,sub"), None);
+        assert_eq!(
+            Dialect::spec(Dialect::CURRENT_SPEC),
+            Some(Dialect::default())
+        );
+        assert_eq!(Dialect::spec(7), None);
+    }
+}
@@ -0,0 +1,145 @@
+//! Line-based unified diffs, for showing how a program's output differs from what was expected,
+//! and finding the first byte two outputs differ at.
+
+use std::fmt::Write;
+
+/// Lines of unchanged context around each change.
+const CONTEXT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// A unified diff from `old` to `new`, with `---` and `+++` headers naming them, or an empty
+/// string if they are the same. Bytes that aren't UTF-8 are shown as replacement characters.
+pub fn unified(old: &[u8], new: &[u8], old_name: &str, new_name: &str) -> String {
+    if old == new {
+        return String::new();
+    }
+    let (old, new) = (String::from_utf8_lossy(old), String::from_utf8_lossy(new));
+    let a: Vec<&str> = old.split_inclusive('\n').collect();
+    let b: Vec<&str> = new.split_inclusive('\n').collect();
+
+    // lcs[i][j] is the length of the longest common subsequence of a[i..] and b[j..].
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    // Each operation with the line it shows and the positions in a and b before it.
+    let mut ops = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            ops.push((Op::Equal, a[i], i, j));
+            (i, j) = (i + 1, j + 1);
+        } else if j == b.len() || i < a.len() && lcs[i + 1][j] >= lcs[i][j + 1] {
+            ops.push((Op::Delete, a[i], i, j));
+            i += 1;
+        } else {
+            ops.push((Op::Insert, b[j], i, j));
+            j += 1;
+        }
+    }
+
+    let mut out = format!("--- {old_name}\n+++ {new_name}\n");
+    let changes: Vec<usize> = (0..ops.len()).filter(|&k| ops[k].0 != Op::Equal).collect();
+    let mut k = 0;
+    while k < changes.len() {
+        // Merge changes whose contexts would touch into one hunk.
+        let mut last = k;
+        while last + 1 < changes.len() && changes[last + 1] - changes[last] <= 2 * CONTEXT + 1 {
+            last += 1;
+        }
+        let start = changes[k].saturating_sub(CONTEXT);
+        let end = (changes[last] + CONTEXT + 1).min(ops.len());
+        let hunk = &ops[start..end];
+        let old_len = hunk.iter().filter(|op| op.0 != Op::Insert).count();
+        let new_len = hunk.iter().filter(|op| op.0 != Op::Delete).count();
+        let from = |pos: usize, len: usize| if len == 0 { pos } else { pos + 1 };
+        writeln!(
+            out,
+            "@@ -{},{old_len} +{},{new_len} @@",
+            from(hunk[0].2, old_len),
+            from(hunk[0].3, new_len)
+        )
+        .unwrap();
+        for &(op, line, _, _) in hunk {
+            out.push(match op {
+                Op::Equal => ' ',
+                Op::Delete => '-',
+                Op::Insert => '+',
+            });
+            out.push_str(line);
+            if !line.ends_with('\n') {
+                out.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+        k = last + 1;
+    }
+    out
+}
+
+/// Where `a` and `b` first differ: the index of the first byte that isn't the same in both, or
+/// the shorter one's length if it's a prefix of the other. `None` if they're the same.
+pub fn first_difference(a: &[u8], b: &[u8]) -> Option<usize> {
+    if a == b {
+        return None;
+    }
+    let same = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    Some(same)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_unified() {
+        assert_eq!(unified(b"same\n", b"same\n", "a", "b"), "");
+        assert_eq!(
+            unified(
+                b"1\n2\n3\n4\n5\n6\n7\n8\n9\n",
+                b"1\n2\n3\n4\nfive\n6\n7\n8\n9",
+                "a",
+                "b"
+            ),
+            "\
+--- a
++++ b
+@@ -2,8 +2,8 @@
+ 2
+ 3
+ 4
+-5
++five
+ 6
+ 7
+ 8
+-9
++9
+\\ No newline at end of file
+"
+        );
+        assert_eq!(
+            unified(b"", b"hi\n", "expected", "actual"),
+            "--- expected\n+++ actual\n@@ -0,0 +1,1 @@\n+hi\n"
+        );
+    }
+
+    #[test]
+    fn test_first_difference() {
+        assert_eq!(first_difference(b"abc", b"abc"), None);
+        assert_eq!(first_difference(b"abc", b"abd"), Some(2));
+        assert_eq!(first_difference(b"ab", b"abc"), Some(2));
+        assert_eq!(first_difference(b"", b"x"), Some(0));
+    }
+}
@@ -0,0 +1,110 @@
+//! Programs parsed at compile time.
+//!
+//! The `puffer!` macro in the `pufferfish-macros` crate builds a program's tanks while the
+//! crate using it compiles, so mistakes in the code are compile errors, and expands to an
+//! [`EmbeddedProgram`] constant holding the finished glyph sums. Turning that into a
+//! [`Program`] skips parsing names and drawing glyphs entirely.
+
+use crate::{
+    builder::ProgramBuilder,
+    cells::Cells,
+    io::{Io, StdIo},
+    program::{Program, Tank},
+};
+
+/// A program's source and its tanks, in aquarium order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EmbeddedProgram {
+    code: &'static str,
+    tanks: &'static [(&'static str, Cells)],
+    extensions: bool,
+}
+
+impl EmbeddedProgram {
+    /// Used by the macro's expansion. `tanks` must be exactly what building `code` gives, so
+    /// anything else should use [`ProgramBuilder`] instead.
+    #[doc(hidden)]
+    pub const fn new(
+        code: &'static str,
+        tanks: &'static [(&'static str, Cells)],
+        extensions: bool,
+    ) -> Self {
+        Self {
+            code,
+            tanks,
+            extensions,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.code
+    }
+
+    /// The tank names and glyph sums, in aquarium order.
+    pub fn tanks(&self) -> &'static [(&'static str, Cells)] {
+        self.tanks
+    }
+
+    /// Whether extension calls are enabled, as they are for code with a
+    /// `#!requires extensions` line.
+    pub fn extensions(&self) -> bool {
+        self.extensions
+    }
+
+    /// A builder for the program with its tanks already built. The code's font and tank order
+    /// were fixed when it was embedded, so setting either on the builder has no effect.
+    pub fn builder(&self) -> ProgramBuilder {
+        let tanks = self
+            .tanks
+            .iter()
+            .map(|&(name, cells)| Tank::new(name.to_string(), cells))
+            .collect();
+        ProgramBuilder::new(self.code)
+            .extensions(self.extensions)
+            .prebuilt(tanks)
+    }
+
+    pub fn program(&self) -> Program {
+        self.program_with_io(StdIo::default())
+    }
+
+    pub fn program_with_io<I: Io>(&self, io: I) -> Program<I> {
+        self.builder()
+            .io(io)
+            .build()
+            .expect("embedded programs were checked when they were compiled")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::MockIo;
+
+    /// Embeds `code` as the macro would, from the tanks the builder gives.
+    fn embed(code: &'static str, extensions: bool) -> (EmbeddedProgram, Program<MockIo>) {
+        let built = ProgramBuilder::new(code)
+            .io(MockIo::default())
+            .extensions(extensions)
+            .build()
+            .unwrap();
+        let tanks: Vec<_> = built
+            .aquarium()
+            .iter()
+            .map(|tank| (&*tank.name().to_string().leak(), *tank.grid()))
+            .collect();
+        (EmbeddedProgram::new(code, tanks.leak(), extensions), built)
+    }
+
+    #[test]
+    fn test_embedded_program() {
+        let (embedded, built) = embed("#!edges bounce\ncd ab", false);
+        let program = embedded.program_with_io(MockIo::default());
+        assert!(program == built);
+        assert_eq!(embedded.tanks()[0].0, "ab");
+
+        let (embedded, built) = embed("#!requires extensions\nab", true);
+        assert!(embedded.extensions());
+        assert!(embedded.program_with_io(MockIo::default()) == built);
+    }
+}
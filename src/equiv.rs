@@ -0,0 +1,229 @@
+//! Deciding whether two sources are the same program.
+
+use thiserror::Error;
+
+use crate::{
+    backend,
+    builder::ProgramBuilder,
+    io::MockIo,
+    program::{Program, Status},
+};
+
+/// One way two programs differ.
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum Difference {
+    #[error("the aquariums are {a:?} and {b:?} tanks (rows, columns)")]
+    Layout {
+        a: (usize, usize),
+        b: (usize, usize),
+    },
+    #[error("tank {row},{col} is {a:?} in one and {b:?} in the other, with different call letters")]
+    CallLetter {
+        row: usize,
+        col: usize,
+        a: String,
+        b: String,
+    },
+    #[error("tank {row},{col} is {a:?} in one and {b:?} in the other, with different grids")]
+    Grid {
+        row: usize,
+        col: usize,
+        a: String,
+        b: String,
+    },
+    #[error("the programs run under different rules")]
+    Rules,
+    #[error("the runs diverge at step {step}: {detail}")]
+    Diverged { step: u64, detail: String },
+}
+
+/// Compares two programs tank by tank and, optionally, by running them side by side.
+///
+/// Two programs are the same when their layouts, dialects and extension settings match and
+/// each pair of tanks has the same call letter and digit grid. With extensions enabled the raw
+/// glyph sums must match too, since extension instructions and the `v` call can see them.
+///
+/// Programs that differ on paper can still behave the same, for instance when the tanks that
+/// differ are never reached. [`EquivChecker::bisimulate`] also runs both on the same input and
+/// seed, comparing their pointers, stacks, registers, output and status after every step.
+#[derive(Debug, Clone, Default)]
+pub struct EquivChecker {
+    extensions: bool,
+    steps: Option<u64>,
+    input: Vec<u8>,
+    seed: u64,
+}
+
+/// What [`EquivChecker::check`] found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    /// Differences between the programs' tanks and rules. If empty, the programs are the same.
+    pub differences: Vec<Difference>,
+    /// Where the side-by-side run first diverged, if it was run and did.
+    pub divergence: Option<Difference>,
+    /// Steps both programs ran in lockstep, at most the bisimulation limit.
+    pub steps: u64,
+}
+
+impl Report {
+    pub fn same(&self) -> bool {
+        self.differences.is_empty()
+    }
+}
+
+impl EquivChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn extensions(mut self, enabled: bool) -> Self {
+        self.extensions = enabled;
+        self
+    }
+
+    /// Also runs both programs side by side for up to `steps` steps.
+    pub fn bisimulate(mut self, steps: u64) -> Self {
+        self.steps = Some(steps);
+        self
+    }
+
+    /// Input to give both programs when bisimulating.
+    pub fn input(mut self, input: impl Into<Vec<u8>>) -> Self {
+        self.input = input.into();
+        self
+    }
+
+    /// Seed for both programs' `y` calls when bisimulating.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    fn build(&self, code: &str) -> Result<Program<MockIo>, crate::Error> {
+        ProgramBuilder::new(code)
+            .extensions(self.extensions)
+            .seed(self.seed)
+            .io(MockIo::new(self.input.clone()))
+            .build()
+    }
+
+    pub fn check(&self, a: &str, b: &str) -> Result<Report, crate::Error> {
+        let (mut a, mut b) = (self.build(a)?, self.build(b)?);
+        let differences = self.differences(&a, &b);
+        let mut report = Report {
+            differences,
+            divergence: None,
+            steps: 0,
+        };
+        if let Some(limit) = self.steps {
+            while report.steps < limit {
+                report.steps += 1;
+                let (ra, rb) = (a.step(), b.step());
+                let step = report.steps;
+                let diverged = |detail: String| Difference::Diverged { step, detail };
+                let status = match (ra, rb) {
+                    (Ok(sa), Ok(sb)) if sa == sb => sa,
+                    (Ok(sa), Ok(sb)) => {
+                        report.divergence = Some(diverged(format!("{sa:?} and {sb:?}")));
+                        break;
+                    }
+                    (Err(ea), Err(eb)) if ea.to_string() == eb.to_string() => break,
+                    (ra, rb) => {
+                        let show = |r: Result<Status, _>| match r {
+                            Ok(status) => format!("{status:?}"),
+                            Err(err) => format!("error: {err}"),
+                        };
+                        report.divergence =
+                            Some(diverged(format!("{} and {}", show(ra), show(rb))));
+                        break;
+                    }
+                };
+                if let Some(detail) = state_difference(&a, &b) {
+                    report.divergence = Some(diverged(detail));
+                    break;
+                }
+                if status != Status::Running {
+                    break;
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    fn differences(&self, a: &Program<MockIo>, b: &Program<MockIo>) -> Vec<Difference> {
+        let mut differences = Vec::new();
+        if (a.dialect(), a.extensions) != (b.dialect(), b.extensions) {
+            differences.push(Difference::Rules);
+        }
+        let (a, b) = (a.aquarium(), b.aquarium());
+        if a.size() != b.size() {
+            differences.push(Difference::Layout {
+                a: a.size(),
+                b: b.size(),
+            });
+            return differences;
+        }
+        for ((row, col), ta) in a.indexed_iter() {
+            let tb = &b[(row, col)];
+            let (a, b) = (ta.name().to_string(), tb.name().to_string());
+            if ta.call_letter() != tb.call_letter() {
+                differences.push(Difference::CallLetter { row, col, a, b });
+            } else if (self.extensions && ta.grid() != tb.grid()) || ta.digits() != tb.digits() {
+                differences.push(Difference::Grid { row, col, a, b });
+            }
+        }
+        differences
+    }
+}
+
+fn state_difference(a: &Program<MockIo>, b: &Program<MockIo>) -> Option<String> {
+    backend::state_difference(a, b)
+        .or_else(|| (a.io().output() != b.io().output()).then(|| String::from("output differs")))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_static_differences() {
+        let checker = EquivChecker::new();
+        assert!(checker.check("stop fish", "fish spot").unwrap().same());
+        assert_eq!(
+            checker.check("stop fish", "fish tops").unwrap().differences,
+            [Difference::CallLetter {
+                row: 0,
+                col: 1,
+                a: String::from("stop"),
+                b: String::from("tops")
+            }]
+        );
+        assert!(matches!(
+            checker.check("ab cd", "ab").unwrap().differences[..],
+            [Difference::Layout { .. }]
+        ));
+        assert_eq!(
+            checker
+                .check("ab", "#!edges bounce\nab")
+                .unwrap()
+                .differences,
+            [Difference::Rules]
+        );
+    }
+
+    #[test]
+    fn test_bisimulation() {
+        let checker = EquivChecker::new().bisimulate(100);
+        let report = checker.check("stop fish", "fish spot").unwrap();
+        assert_eq!((report.divergence, report.steps), (None, 100));
+        // Both exit straight away, so the tanks they never reach don't matter.
+        let report = checker.check("ebbbbbbbbb z", "ebbbbbbbbb zz").unwrap();
+        assert!(!report.same());
+        assert_eq!((report.divergence, report.steps), (None, 1));
+        let report = checker.check("ab", "cd").unwrap();
+        assert!(matches!(
+            report.divergence,
+            Some(Difference::Diverged { .. })
+        ));
+    }
+}
@@ -0,0 +1,32 @@
+//! The error type the library's fallible entry points share.
+
+use std::io;
+
+use thiserror::Error;
+
+use crate::{
+    builder::BuildError, decompose::SumsError, font::FontError, parser::ParseError,
+    program::RuntimeError, replay::BundleError, state::StateError,
+};
+
+/// Any error the library can return, wrapping the more specific error types so callers can
+/// match on what went wrong.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    #[error(transparent)]
+    Build(#[from] BuildError),
+    #[error(transparent)]
+    Font(#[from] FontError),
+    #[error(transparent)]
+    Runtime(#[from] RuntimeError),
+    #[error(transparent)]
+    State(#[from] StateError),
+    #[error(transparent)]
+    Bundle(#[from] BundleError),
+    #[error(transparent)]
+    Sums(#[from] SumsError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
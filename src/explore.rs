@@ -0,0 +1,287 @@
+//! Counting the states a program can reach, across threads.
+//!
+//! An [`Explorer`] walks a program's state space breadth first, splitting wherever a `y` call
+//! is about to run as [model checking](crate::model_check) does. To keep programs whose stack
+//! grows forever finite, the stack is bounded: a state with more than `stack_bound` values
+//! forgets the deepest of them, so states that only differ below the bound are explored once.
+//! What's explored is then a superset of what the program can really do, exact if the stack
+//! never outgrows the bound. Output isn't part of a state, so a program printing forever in a
+//! loop reaches finitely many.
+//!
+//! Each level of the search is shared out among the threads, which steal work from each other
+//! when they run out, and the states found are merged in the order of the states they came
+//! from. The report is the same however many threads there are and however they're scheduled.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    hash::{Hash, Hasher},
+    sync::Mutex,
+    thread,
+};
+
+use crate::{
+    builder::ProgramBuilder,
+    io::MockIo,
+    program::{CallKind, Direction, Program, Status},
+    stats::step_opcode,
+};
+
+/// What an exploration found.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExploreReport {
+    /// Distinct states reached, terminal ones included.
+    pub states: usize,
+    /// States at which the program had halted.
+    pub terminal: usize,
+    /// Steps, from distinct states, that failed with a runtime error.
+    pub errors: usize,
+    /// States at which a `y` call split the search.
+    pub choices: usize,
+    /// States reached whose stack had to be cut down to the bound.
+    pub abstracted: usize,
+    /// How many steps from the start the furthest state is.
+    pub depth: u64,
+    /// The state limit stopped the search before every reachable state was found.
+    pub truncated: bool,
+}
+
+/// A state as the explorer tells them apart: the program's semantic state, plus how much
+/// input it has left.
+struct Key(Program<MockIo>);
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+            && self.0.io().remaining_input().len() == other.0.io().remaining_input().len()
+    }
+}
+
+impl Eq for Key {}
+
+impl Hash for Key {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+        self.0.io().remaining_input().len().hash(state);
+    }
+}
+
+/// What a state steps to: whether it split at a `y` call, and the states it steps to, or
+/// `None` for a step that failed.
+type Expansion = (bool, Vec<Option<Program<MockIo>>>);
+
+/// Explores everything a program can reach, in parallel.
+#[derive(Debug, Clone)]
+pub struct Explorer {
+    input: Vec<u8>,
+    stack_bound: usize,
+    max_states: usize,
+    jobs: usize,
+    extensions: bool,
+}
+
+impl Default for Explorer {
+    fn default() -> Self {
+        Self {
+            input: Vec::new(),
+            stack_bound: 16,
+            max_states: 1_000_000,
+            jobs: thread::available_parallelism().map_or(1, |n| n.get()),
+            extensions: false,
+        }
+    }
+}
+
+impl Explorer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The input every branch reads.
+    pub fn input(mut self, input: impl Into<Vec<u8>>) -> Self {
+        self.input = input.into();
+        self
+    }
+
+    /// How many of the topmost stack values a state keeps.
+    pub fn stack_bound(mut self, bound: usize) -> Self {
+        self.stack_bound = bound;
+        self
+    }
+
+    /// How many distinct states may be reached in all.
+    pub fn max_states(mut self, states: usize) -> Self {
+        self.max_states = states.max(1);
+        self
+    }
+
+    /// How many threads to explore with.
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    pub fn extensions(mut self, enabled: bool) -> Self {
+        self.extensions = enabled;
+        self
+    }
+
+    /// Explores `code` from its start. Only fails if the program doesn't build.
+    pub fn run(&self, code: &str) -> Result<ExploreReport, crate::Error> {
+        let program = ProgramBuilder::new(code)
+            .io(MockIo::new(self.input.clone()))
+            .extensions(self.extensions)
+            .build()?;
+        Ok(self.explore(program))
+    }
+
+    /// Explores from where `program` is now, with whatever input its I/O has left.
+    pub fn explore(&self, mut program: Program<MockIo>) -> ExploreReport {
+        let mut report = ExploreReport::default();
+        let mut seen = HashSet::new();
+        report.abstracted += self.bound(&mut program) as usize;
+        seen.insert(Key(program.clone()));
+        let mut frontier = vec![program];
+        while !frontier.is_empty() {
+            let mut next = Vec::new();
+            for (choosing, successors) in self.expand(&frontier) {
+                report.choices += choosing as usize;
+                for successor in successors {
+                    let Some(mut successor) = successor else {
+                        report.errors += 1;
+                        continue;
+                    };
+                    let abstracted = self.bound(&mut successor);
+                    let key = Key(successor);
+                    if seen.contains(&key) {
+                        continue;
+                    }
+                    if seen.len() == self.max_states {
+                        report.truncated = true;
+                        continue;
+                    }
+                    report.abstracted += abstracted as usize;
+                    next.push(key.0.clone());
+                    seen.insert(key);
+                }
+            }
+            report.terminal += frontier
+                .iter()
+                .filter(|program| matches!(program.status(), Status::Halted(_)))
+                .count();
+            if !next.is_empty() {
+                report.depth += 1;
+            }
+            frontier = next;
+        }
+        report.states = seen.len();
+        report
+    }
+
+    /// Cuts the stack down to the bound, dropping its deepest values. Returns whether there
+    /// were any to drop.
+    fn bound(&self, program: &mut Program<MockIo>) -> bool {
+        let excess = program.stack.len().saturating_sub(self.stack_bound);
+        program.stack.drain(..excess);
+        excess > 0
+    }
+
+    /// Steps every state of a level, across threads, returning what each steps to in the
+    /// level's order.
+    fn expand(&self, frontier: &[Program<MockIo>]) -> Vec<Expansion> {
+        let workers = self.jobs.min(frontier.len());
+        if workers <= 1 {
+            return frontier.iter().map(successors).collect();
+        }
+        // Each worker starts with every `workers`-th state, and takes from the front of its
+        // own queue but steals from the back of others'.
+        let queues: Vec<Mutex<VecDeque<usize>>> = (0..workers)
+            .map(|worker| Mutex::new((worker..frontier.len()).step_by(workers).collect()))
+            .collect();
+        let mut expanded: Vec<(usize, Expansion)> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers)
+                .map(|worker| {
+                    let queues = &queues;
+                    scope.spawn(move || {
+                        let mut done = Vec::new();
+                        loop {
+                            let own = queues[worker].lock().unwrap().pop_front();
+                            let Some(index) = own.or_else(|| {
+                                (1..workers).find_map(|offset| {
+                                    let victim = (worker + offset) % workers;
+                                    queues[victim].lock().unwrap().pop_back()
+                                })
+                            }) else {
+                                break;
+                            };
+                            done.push((index, successors(&frontier[index])));
+                        }
+                        done
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        });
+        expanded.sort_unstable_by_key(|(index, _)| *index);
+        expanded
+            .into_iter()
+            .map(|(_, expansion)| expansion)
+            .collect()
+    }
+}
+
+/// What one state steps to. Halted states step nowhere.
+fn successors(program: &Program<MockIo>) -> Expansion {
+    if program.status() != Status::Running {
+        return (false, Vec::new());
+    }
+    let choosing = step_opcode(program) == 9
+        && program.aquarium()[program.ftp()].call_kind() == CallKind::Random;
+    let next = if choosing {
+        [
+            Direction::Down,
+            Direction::Left,
+            Direction::Right,
+            Direction::Up,
+        ]
+        .into_iter()
+        .map(|dir| {
+            let mut program = program.clone();
+            program.step_choosing(dir).ok().map(|_| program)
+        })
+        .collect()
+    } else {
+        let mut program = program.clone();
+        vec![program.step().ok().map(|_| program)]
+    };
+    (choosing, next)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_explore() {
+        // Without y calls or a growing stack, a looping program has one path round a loop.
+        let report = Explorer::new().jobs(2).run("ab").unwrap();
+        assert!(report.states > 1);
+        assert_eq!(report.states as u64, report.depth + 1);
+        assert_eq!((report.terminal, report.errors, report.choices), (0, 0, 0));
+        assert!(!report.truncated);
+
+        // Some of the directions y picks pop an empty stack.
+        let report = Explorer::new().run("ybbbbbbkk").unwrap();
+        assert!(report.choices > 0 && report.errors > 0);
+        for jobs in [1, 3, 8] {
+            assert_eq!(Explorer::new().jobs(jobs).run("ybbbbbbkk").unwrap(), report);
+        }
+
+        let report = Explorer::new().max_states(3).run("ab").unwrap();
+        assert_eq!(report.states, 3);
+        assert!(report.truncated);
+        assert!(Explorer::new().run("Y").is_err());
+    }
+}
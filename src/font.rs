@@ -0,0 +1,106 @@
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, Error, PartialEq, Eq)]
+pub enum FontError {
+    #[error("the {font} font has no glyph for {letter:?}")]
+    MissingGlyph { font: &'static str, letter: char },
+    #[error("the {font} font's glyph for {letter:?} is {mask:?}, not five hex digits")]
+    InvalidMask {
+        font: &'static str,
+        letter: char,
+        mask: &'static str,
+    },
+}
+
+/// The glyphs letters are drawn with when building tanks.
+///
+/// Each glyph is five hex digits, one per row from the top, with the most significant bit of
+/// each digit as the leftmost cell.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct Font {
+    name: &'static str,
+    masks: [&'static str; 26],
+}
+
+impl Font {
+    /// The font the language is defined with.
+    pub const STANDARD: Font = Font {
+        name: "standard",
+        masks: [
+            "07997", "8e99e", "06886", "17997", "06bc6", "24e44", "79716", "88e99", "04044",
+            "20224", "89ae9", "44442", "0edd9", "0e999", "06996", "e99e8", "79971", "0ac88",
+            "07c3e", "4e442", "00997", "009a4", "09bb7", "0a44a", "99716", "0f24f",
+        ],
+    };
+
+    /// Heavier strokes: each standard glyph with every lit cell also lighting the cell to
+    /// its right, except `o`, which is squared off so it stays distinct from `a`.
+    pub const BOLD: Font = Font {
+        name: "bold",
+        masks: [
+            "07dd7", "cfddf", "07cc7", "17dd7", "07fe7", "36f66", "7d717", "ccfdd", "06066",
+            "30336", "cdffd", "66663", "0fffd", "0fddd", "0f99f", "fddfc", "7dd71", "0fecc",
+            "07e3f", "6f663", "00dd7", "00df6", "0dff7", "0f66f", "dd717", "0f36f",
+        ],
+    };
+
+    /// The standard glyphs standing on a foot: the bottom row widened by a cell each way.
+    pub const SERIF: Font = Font {
+        name: "serif",
+        masks: [
+            "0799f", "8e99f", "0688f", "1799f", "06bcf", "24e4e", "7971f", "88e9f", "0404e",
+            "2022e", "89aef", "44447", "0eddf", "0e99f", "0699f", "e99ec", "79973", "0ac8c",
+            "07c3f", "4e447", "0099f", "009ae", "09bbf", "0a44f", "9971f", "0f24f",
+        ],
+    };
+
+    /// Every built-in font, standard first.
+    pub const BUILTIN: [&'static Font; 3] = [&Font::STANDARD, &Font::BOLD, &Font::SERIF];
+
+    /// Looks up a built-in font by name.
+    pub fn by_name(name: &str) -> Option<&'static Font> {
+        Font::BUILTIN.into_iter().find(|font| font.name == name)
+    }
+
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The mask for a lowercase ASCII letter.
+    pub(crate) const fn mask(&self, letter: u8) -> Result<&'static str, FontError> {
+        if letter.is_ascii_lowercase() {
+            Ok(self.masks[(letter - b'a') as usize])
+        } else {
+            Err(FontError::MissingGlyph {
+                font: self.name,
+                letter: letter as char,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_builtin_fonts() {
+        for font in Font::BUILTIN {
+            assert_eq!(Font::by_name(font.name()), Some(font));
+            let distinct: HashSet<_> = font.masks.iter().collect();
+            assert_eq!(distinct.len(), 26, "{} has duplicate glyphs", font.name());
+            assert!(font.masks.iter().all(|mask| mask.len() == 5));
+        }
+        assert_eq!(Font::by_name("comic"), None);
+        for letter in [b'A', b'{', b'\0'] {
+            assert_eq!(
+                Font::STANDARD.mask(letter),
+                Err(FontError::MissingGlyph {
+                    font: "standard",
+                    letter: letter as char
+                })
+            );
+        }
+    }
+}
@@ -0,0 +1,233 @@
+//! Running one program against random input, looking for input that breaks it.
+//!
+//! A [`Fuzzer`] runs a program over and over, each time with fresh random bytes for its `i`
+//! calls, and keeps the runs that ended badly: in a runtime error, a panic, or at the step
+//! limit. Findings with the same problem are merged, keeping the shortest input that caused
+//! it, since that's the easiest one to debug.
+
+use std::{collections::HashMap, fmt};
+
+use rand::{prelude::*, rngs::StdRng};
+
+use crate::{
+    builder::ProgramBuilder,
+    io::MockIo,
+    program::{HaltReason, seeded_rng},
+    runner::{Ending, Limits, run_captured},
+    sandbox::Sandbox,
+};
+
+/// Runs a program against random input.
+#[derive(Debug, Clone)]
+pub struct Fuzzer {
+    alphabet: Vec<u8>,
+    min_len: usize,
+    max_len: usize,
+    runs: u64,
+    limits: Limits,
+    sandbox: Sandbox,
+    extensions: bool,
+    seed: Option<u64>,
+}
+
+impl Default for Fuzzer {
+    fn default() -> Self {
+        Self {
+            alphabet: (b' '..=b'~').collect(),
+            min_len: 0,
+            max_len: 16,
+            runs: 1000,
+            limits: Limits::default(),
+            sandbox: Sandbox::default(),
+            extensions: false,
+            seed: None,
+        }
+    }
+}
+
+/// What went wrong in a run.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Problem {
+    Error(String),
+    Panic(String),
+    /// The run reached the step limit.
+    StepLimit,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Error(message) => write!(f, "error: {message}"),
+            Problem::Panic(message) => write!(f, "panic: {message}"),
+            Problem::StepLimit => write!(f, "reached the step limit"),
+        }
+    }
+}
+
+/// A problem some inputs caused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub problem: Problem,
+    /// The shortest input that caused it.
+    pub input: Vec<u8>,
+    /// The steps the run with that input took.
+    pub steps: u64,
+    /// How many runs had the problem.
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct FuzzReport {
+    pub runs: u64,
+    /// Every distinct problem, most common first.
+    pub findings: Vec<Finding>,
+}
+
+impl Fuzzer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The bytes inputs are drawn from. Inputs are empty if there are none.
+    pub fn alphabet(mut self, alphabet: impl Into<Vec<u8>>) -> Self {
+        self.alphabet = alphabet.into();
+        self
+    }
+
+    /// The range of input lengths, in bytes.
+    pub fn input_len(mut self, min: usize, max: usize) -> Self {
+        self.min_len = min;
+        self.max_len = max.max(min);
+        self
+    }
+
+    /// How many runs to do.
+    pub fn runs(mut self, runs: u64) -> Self {
+        self.runs = runs;
+        self
+    }
+
+    /// Limits applied to each run separately. Runs reaching the step limit are findings.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// The sandbox every run is confined to.
+    pub fn sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    pub fn extensions(mut self, enabled: bool) -> Self {
+        self.extensions = enabled;
+        self
+    }
+
+    /// Seeds both the inputs and the program's `y` calls, making the whole fuzzing run
+    /// reproducible.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    fn input(&self, rng: &mut StdRng) -> Vec<u8> {
+        if self.alphabet.is_empty() {
+            return Vec::new();
+        }
+        let len = rng.random_range(self.min_len..=self.max_len);
+        (0..len)
+            .map(|_| *self.alphabet.choose(rng).unwrap())
+            .collect()
+    }
+
+    /// Runs `code` against random inputs. Only fails if the program doesn't build.
+    pub fn run(&self, code: &str) -> Result<FuzzReport, crate::Error> {
+        self.run_with(|| ProgramBuilder::new(code))
+    }
+
+    fn run_with(&self, program: impl Fn() -> ProgramBuilder) -> Result<FuzzReport, crate::Error> {
+        let mut rng = seeded_rng(self.seed);
+        let mut findings: HashMap<Problem, Finding> = HashMap::new();
+        let mut order = Vec::new();
+        for _ in 0..self.runs {
+            let input = self.input(&mut rng);
+            let builder = program()
+                .io(MockIo::default())
+                .extensions(self.extensions)
+                .sandbox(self.sandbox)
+                .seed(rng.random());
+            let outcome = run_captured(builder, input.clone(), self.limits)?;
+            let problem = match outcome.ending {
+                Ending::Halted(HaltReason::StepLimit) => Problem::StepLimit,
+                Ending::Halted(_) => continue,
+                Ending::Error(err) => Problem::Error(err.to_string()),
+                Ending::Panic(message) => Problem::Panic(message),
+            };
+            let finding = findings.entry(problem.clone()).or_insert_with(|| {
+                order.push(problem.clone());
+                Finding {
+                    problem,
+                    input: input.clone(),
+                    steps: outcome.steps,
+                    count: 0,
+                }
+            });
+            finding.count += 1;
+            if input.len() < finding.input.len() {
+                finding.input = input;
+                finding.steps = outcome.steps;
+            }
+        }
+        // Ties keep the order problems were first found in.
+        let mut findings: Vec<_> = order
+            .into_iter()
+            .map(|problem| findings.remove(&problem).unwrap())
+            .collect();
+        findings.sort_by_key(|finding| std::cmp::Reverse(finding.count));
+        Ok(FuzzReport {
+            runs: self.runs,
+            findings,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{cells::Cells, program::Tank};
+
+    #[test]
+    fn test_fuzz() {
+        // Reads a byte and tests it: a byte goes on to subtract from a one-value stack, while
+        // the end of input skips back round to read again, forever.
+        let mut cells = [0; 20];
+        cells[..3].copy_from_slice(&[9, 7, 6]);
+        let program =
+            || ProgramBuilder::new("i").prebuilt(vec![Tank::new("i".into(), Cells::new(cells))]);
+        let fuzzer = Fuzzer::new()
+            .runs(50)
+            .input_len(0, 4)
+            .limits(Limits::steps(300))
+            .seed(1);
+        let report = fuzzer.run_with(program).unwrap();
+        assert_eq!(report.runs, 50);
+        let problems: Vec<_> = report.findings.iter().map(|f| &f.problem).collect();
+        assert!(matches!(
+            problems[..],
+            [Problem::Error(_), Problem::StepLimit]
+        ));
+        assert_eq!(report.findings[0].input.len(), 1);
+        assert_eq!(report.findings[0].steps, 3);
+        assert_eq!(report.findings[1].input, b"");
+        let count: u64 = report.findings.iter().map(|finding| finding.count).sum();
+        assert_eq!(count, 50);
+        assert_eq!(fuzzer.run_with(program).unwrap(), report);
+
+        // Without input there's nothing to read, so every run loops.
+        let report = fuzzer.clone().alphabet(b"").run_with(program).unwrap();
+        assert_eq!(report.findings.len(), 1);
+        assert_eq!(report.findings[0].count, 50);
+        assert!(fuzzer.run("Y").is_err());
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::HashSet;
+
+use rand::{prelude::*, rngs::StdRng};
+
+use crate::{parser::is_name, program::seeded_rng};
+
+/// Generates random, syntactically valid programs: distinct names separated by spaces.
+#[derive(Debug, Clone)]
+pub struct Generator {
+    tanks: usize,
+    min_len: usize,
+    max_len: usize,
+    apostrophes: bool,
+    dictionary: Option<Vec<String>>,
+    rng: StdRng,
+}
+
+impl Generator {
+    /// A generator for programs of `tanks` names, seeded from `seed` or, without one, from the
+    /// operating system.
+    pub fn new(tanks: usize, seed: Option<u64>) -> Self {
+        Self {
+            tanks: tanks.max(1),
+            min_len: 1,
+            max_len: 8,
+            apostrophes: false,
+            dictionary: None,
+            rng: seeded_rng(seed),
+        }
+    }
+
+    /// The range of generated name lengths, in letters. Ignored for dictionary words. If there
+    /// are fewer names this short than tanks, programs use every one.
+    pub fn name_len(mut self, min: usize, max: usize) -> Self {
+        self.min_len = min.max(1);
+        self.max_len = max.max(self.min_len);
+        self
+    }
+
+    /// Whether generated names may contain apostrophes.
+    pub fn apostrophes(mut self, apostrophes: bool) -> Self {
+        self.apostrophes = apostrophes;
+        self
+    }
+
+    /// Draws names from the valid names among `words`, lowercased, instead of generating them.
+    /// If there are fewer distinct words than tanks, programs use every word.
+    pub fn dictionary<S: AsRef<str>>(mut self, words: impl IntoIterator<Item = S>) -> Self {
+        let mut words: Vec<String> = words
+            .into_iter()
+            .map(|w| w.as_ref().to_lowercase())
+            .filter(|w| is_name(w))
+            .collect();
+        words.sort();
+        words.dedup();
+        self.dictionary = Some(words);
+        self
+    }
+
+    /// Generates the next program.
+    pub fn program(&mut self) -> String {
+        let names: Vec<String> = match &self.dictionary {
+            Some(words) => {
+                let amount = self.tanks.min(words.len());
+                words
+                    .choose_multiple(&mut self.rng, amount)
+                    .cloned()
+                    .collect()
+            }
+            None => {
+                // Without apostrophes, short names can run out.
+                let available = (self.min_len..=self.max_len)
+                    .map(|len| 26usize.saturating_pow(len as u32))
+                    .fold(0, usize::saturating_add);
+                let amount = self.tanks.min(available);
+                let mut seen = HashSet::new();
+                let mut names = Vec::with_capacity(amount);
+                while names.len() < amount {
+                    let name = self.name();
+                    if seen.insert(name.clone()) {
+                        names.push(name);
+                    }
+                }
+                names
+            }
+        };
+        names.join(" ")
+    }
+
+    fn name(&mut self) -> String {
+        let len = self.rng.random_range(self.min_len..=self.max_len);
+        let mut name = String::with_capacity(len);
+        for i in 0..len {
+            // An apostrophe can't start or end a name, or follow another one.
+            let inner = i > 0 && i + 1 < len && !name.ends_with('\'');
+            if self.apostrophes && inner && self.rng.random_bool(0.1) {
+                name.push('\'');
+            } else {
+                name.push((b'a' + self.rng.random_range(0..26)) as char);
+            }
+        }
+        name
+    }
+}
+
+impl Iterator for Generator {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        Some(self.program())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::parse_names;
+
+    #[test]
+    fn test_programs_are_valid() {
+        for program in Generator::new(6, Some(42))
+            .apostrophes(true)
+            .name_len(1, 5)
+            .take(200)
+        {
+            let names = parse_names(&program).unwrap();
+            assert_eq!(names.len(), 6, "{program}");
+            assert!(
+                names
+                    .iter()
+                    .all(|name| name.chars().filter(|&c| c != '\'').count() <= 5)
+            );
+        }
+    }
+
+    #[test]
+    fn test_seed_is_reproducible() {
+        let a: Vec<_> = Generator::new(3, Some(7)).take(5).collect();
+        let b: Vec<_> = Generator::new(3, Some(7)).take(5).collect();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_dictionary() {
+        let mut generator = Generator::new(2, Some(1)).dictionary(["Fish", "cat", "o'", "dog"]);
+        for _ in 0..20 {
+            let program = generator.program();
+            let names = parse_names(&program).unwrap();
+            assert_eq!(names.len(), 2);
+            assert!(
+                names
+                    .iter()
+                    .all(|name| ["cat", "dog", "fish"].contains(&name.as_str()))
+            );
+        }
+        let mut generator = Generator::new(5, Some(1)).dictionary(["a", "b"]);
+        assert_eq!(parse_names(&generator.program()).unwrap().len(), 2);
+        let mut generator = Generator::new(30, Some(1)).name_len(1, 1);
+        assert_eq!(parse_names(&generator.program()).unwrap().len(), 26);
+    }
+}
@@ -0,0 +1,262 @@
+use std::{
+    sync::{Mutex, mpsc},
+    thread,
+};
+
+use crate::{
+    builder::ProgramBuilder,
+    parser::is_name,
+    runner::{Ending, Limits, run_captured},
+};
+
+/// Searches for the shortest program producing a given output.
+///
+/// Candidates are sets of distinct names joined by single spaces, tried in order of total
+/// length. Since the aquarium is laid out in sorted name order, each set is tried once. Without
+/// a dictionary every lowercase name is considered (apostrophes are not generated), so the
+/// search space grows roughly 27-fold per character.
+#[derive(Debug, Clone)]
+pub struct Golfer {
+    target: Vec<u8>,
+    input: Vec<u8>,
+    max_len: usize,
+    limits: Limits,
+    jobs: usize,
+    require_exit: bool,
+    extensions: bool,
+    dictionary: Option<Vec<String>>,
+}
+
+/// The shortest programs found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GolfResult {
+    /// Every program of the shortest successful length, in search order.
+    pub programs: Vec<String>,
+    pub len: usize,
+}
+
+impl Golfer {
+    pub fn new(target: impl Into<Vec<u8>>, max_len: usize) -> Self {
+        Self {
+            target: target.into(),
+            input: Vec::new(),
+            max_len,
+            limits: Limits::steps(1000),
+            jobs: thread::available_parallelism().map_or(1, |n| n.get()),
+            require_exit: false,
+            extensions: false,
+            dictionary: None,
+        }
+    }
+
+    pub fn input(mut self, input: impl Into<Vec<u8>>) -> Self {
+        self.input = input.into();
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    /// Only accept programs that halt through an `e` call, rather than also accepting ones that
+    /// produce the target and then run into the step limit.
+    pub fn require_exit(mut self, require_exit: bool) -> Self {
+        self.require_exit = require_exit;
+        self
+    }
+
+    pub fn extensions(mut self, enabled: bool) -> Self {
+        self.extensions = enabled;
+        self
+    }
+
+    /// Restricts candidate names to the valid names among `words`, lowercased.
+    pub fn dictionary<S: AsRef<str>>(mut self, words: impl IntoIterator<Item = S>) -> Self {
+        let mut words: Vec<String> = words
+            .into_iter()
+            .map(|w| w.as_ref().to_lowercase())
+            .filter(|w| is_name(w))
+            .collect();
+        words.sort_by(|a, b| (a.len(), a).cmp(&(b.len(), b)));
+        words.dedup();
+        self.dictionary = Some(words);
+        self
+    }
+
+    /// Whether a run's output counts as the target.
+    ///
+    /// `o` pads every value it prints to eight bytes, so NUL bytes are ignored.
+    pub fn matches(&self, output: &[u8]) -> bool {
+        output
+            .iter()
+            .copied()
+            .filter(|&b| b != 0)
+            .eq(self.target.iter().copied())
+    }
+
+    /// Tries every candidate up to `max_len`, reporting the number of candidates run at each
+    /// length to `progress`.
+    pub fn search(&self, mut progress: impl FnMut(usize, usize)) -> Option<GolfResult> {
+        for len in 1..=self.max_len {
+            let found = Mutex::new(Vec::new());
+            let mut count = 0;
+            let (tx, rx) = mpsc::sync_channel::<(usize, String)>(self.jobs * 64);
+            let rx = Mutex::new(rx);
+            thread::scope(|scope| {
+                for _ in 0..self.jobs {
+                    scope.spawn(|| {
+                        loop {
+                            let Ok((index, code)) = rx.lock().unwrap().recv() else {
+                                break;
+                            };
+                            if self.accepts(&code) {
+                                found.lock().unwrap().push((index, code));
+                            }
+                        }
+                    });
+                }
+                self.candidates(len, &mut |code| {
+                    if self.plausible(&code) {
+                        tx.send((count, code)).unwrap();
+                        count += 1;
+                    }
+                });
+                drop(tx);
+            });
+            progress(len, count);
+            let mut found = found.into_inner().unwrap();
+            if !found.is_empty() {
+                found.sort();
+                return Some(GolfResult {
+                    programs: found.into_iter().map(|(_, code)| code).collect(),
+                    len,
+                });
+            }
+        }
+        None
+    }
+
+    /// Cheap filters that don't need a run: output needs an `o` tank and exiting an `e` tank.
+    fn plausible(&self, code: &str) -> bool {
+        let has_call = |letter| code.split(' ').any(|name| name.starts_with(letter));
+        (self.target.is_empty() || has_call('o')) && (!self.require_exit || has_call('e'))
+    }
+
+    fn accepts(&self, code: &str) -> bool {
+        let builder = ProgramBuilder::new(code).extensions(self.extensions);
+        let Ok(outcome) = run_captured(builder, self.input.clone(), self.limits) else {
+            return false;
+        };
+        let ended_ok = match outcome.ending {
+            Ending::Halted(_) if !self.require_exit => true,
+            ref ending => ending.is_exit(),
+        };
+        ended_ok && self.matches(&outcome.output)
+    }
+
+    /// Calls `emit` with every candidate of exactly `len` characters, names ordered by
+    /// (length, spelling) within each candidate.
+    fn candidates(&self, len: usize, emit: &mut impl FnMut(String)) {
+        let mut names = Vec::new();
+        self.extend(len, &mut names, emit);
+    }
+
+    fn extend(&self, remaining: usize, names: &mut Vec<String>, emit: &mut impl FnMut(String)) {
+        let prev = names.last().cloned();
+        let min_len = prev.as_ref().map_or(1, String::len);
+        for name_len in min_len..=remaining {
+            let rest = remaining - name_len;
+            // Anything left over needs room for a space and at least one more letter.
+            if rest == 1 {
+                continue;
+            }
+            let mut visit = |name: String| {
+                if prev
+                    .as_ref()
+                    .is_some_and(|p| p.len() == name.len() && *p >= name)
+                {
+                    return;
+                }
+                names.push(name);
+                if rest == 0 {
+                    emit(names.join(" "));
+                } else {
+                    self.extend(rest - 1, names, emit);
+                }
+                names.pop();
+            };
+            match &self.dictionary {
+                Some(words) => words
+                    .iter()
+                    .filter(|w| w.len() == name_len)
+                    .cloned()
+                    .for_each(&mut visit),
+                None => for_each_name(name_len, &mut visit),
+            }
+        }
+    }
+}
+
+/// Calls `f` with every lowercase name of `len` letters, in alphabetical order.
+fn for_each_name(len: usize, f: &mut impl FnMut(String)) {
+    let mut letters = vec![b'a'; len];
+    loop {
+        f(String::from_utf8(letters.clone()).unwrap());
+        let Some(i) = letters.iter().rposition(|&c| c != b'z') else {
+            return;
+        };
+        letters[i] += 1;
+        letters[i + 1..].fill(b'a');
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn all_candidates(golfer: &Golfer, len: usize) -> Vec<String> {
+        let mut out = Vec::new();
+        golfer.candidates(len, &mut |c| out.push(c));
+        out
+    }
+
+    #[test]
+    fn test_candidates_are_distinct_sets() {
+        let golfer = Golfer::new("", 3).dictionary(["a", "b", "ab"]);
+        assert_eq!(all_candidates(&golfer, 1), ["a", "b"]);
+        assert_eq!(all_candidates(&golfer, 2), ["ab"]);
+        assert_eq!(all_candidates(&golfer, 3), ["a b"]);
+        assert_eq!(
+            all_candidates(&Golfer::new("", 3), 3).len(),
+            26usize.pow(3) + 26 * 25 / 2
+        );
+    }
+
+    #[test]
+    fn test_matches_ignores_padding() {
+        let golfer = Golfer::new("hi", 3);
+        assert!(golfer.matches(b"\0\0h\0i"));
+        assert!(!golfer.matches(b"h"));
+    }
+
+    #[test]
+    fn test_search_finds_exiting_program() {
+        // `ebbbbbbbbb` has a 9 in its first cell, so it exits immediately.
+        let mut lengths = Vec::new();
+        let result = Golfer::new("", 12)
+            .dictionary(["b", "ebbbbbbbbb", "ebbbbbbbbh"])
+            .require_exit(true)
+            .jobs(2)
+            .search(|len, _| lengths.push(len))
+            .unwrap();
+        assert_eq!(result.len, 10);
+        assert_eq!(result.programs, ["ebbbbbbbbb", "ebbbbbbbbh"]);
+        assert_eq!(lengths, (1..=10).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,294 @@
+//! Exporting a program as a single self-contained HTML page, for blog posts and documentation.
+//!
+//! The page shows the aquarium with its cells colored by [`CellClass`], then a table per tank
+//! listing what each cell does. With a recorded [`Frame`] trace it also embeds the trace and a
+//! few lines of script that step the highlighted cell and the stack through it. Nothing is
+//! loaded from elsewhere, so the file works offline and can be attached as is.
+
+use std::fmt::Write;
+
+use itertools::Itertools;
+
+use crate::{
+    backend::Backend,
+    io::Io,
+    program::{Program, Status},
+    render::CellClass,
+};
+
+/// Where the instruction pointer was before one step, and the stack at that point.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub tank: (usize, usize),
+    pub cell: (usize, usize),
+    pub stack: Vec<isize>,
+}
+
+/// Runs `program` for up to `max_steps` steps, recording a frame before each one. Recording
+/// stops early when the program halts or fails; the failing step's frame is kept.
+pub fn record_trace(program: &mut impl Backend, max_steps: u64) -> Vec<Frame> {
+    let mut frames = Vec::new();
+    for _ in 0..max_steps {
+        if program.status() != Status::Running {
+            break;
+        }
+        frames.push(Frame {
+            tank: program.ftp(),
+            cell: (program.ip().row(), program.ip().col()),
+            stack: program.stack().to_vec(),
+        });
+        if program.step().is_err() {
+            break;
+        }
+    }
+    frames
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HtmlExporter {
+    title: String,
+    trace: Option<Vec<Frame>>,
+}
+
+impl HtmlExporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The page's title and heading.
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    /// Embeds a trace, from [`record_trace`], that the page can replay.
+    pub fn trace(mut self, frames: Vec<Frame>) -> Self {
+        self.trace = Some(frames);
+        self
+    }
+
+    pub fn export<I: Io>(&self, program: &Program<I>) -> String {
+        let title = escape(&self.title);
+        let mut out = String::new();
+        writeln!(out, "<!DOCTYPE html>\n<html lang=\"en\">\n<head>").unwrap();
+        writeln!(out, "<meta charset=\"utf-8\">\n<title>{title}</title>").unwrap();
+        writeln!(out, "<style>{STYLE}</style>\n</head>\n<body>").unwrap();
+        writeln!(out, "<h1>{title}</h1>").unwrap();
+
+        let aquarium = program.aquarium();
+        writeln!(out, "<table class=\"aquarium\">").unwrap();
+        for row in 0..aquarium.rows() {
+            out.push_str("<tr>");
+            for col in 0..aquarium.cols() {
+                let tank = &aquarium[(row, col)];
+                write!(
+                    out,
+                    "<td><table class=\"tank\"><caption>{} {row},{col} [{}]</caption>",
+                    tank.name(),
+                    tank.call_letter()
+                )
+                .unwrap();
+                for (r, cells) in tank.grid().iter_rows().enumerate() {
+                    out.push_str("<tr>");
+                    for (c, cell) in cells.enumerate() {
+                        write!(
+                            out,
+                            "<td id=\"c{row}-{col}-{r}-{c}\" class=\"{}\">{}</td>",
+                            class_name(CellClass::of(cell, program.extensions)),
+                            cell % 10
+                        )
+                        .unwrap();
+                    }
+                    out.push_str("</tr>");
+                }
+                out.push_str("</table></td>");
+            }
+            writeln!(out, "</tr>").unwrap();
+        }
+        writeln!(out, "</table>").unwrap();
+
+        if let Some(frames) = &self.trace {
+            writeln!(
+                out,
+                "<p class=\"player\"><button id=\"back\">&lt;</button> \
+                 <input id=\"step\" type=\"range\" min=\"0\" max=\"{}\" value=\"0\"> \
+                 <button id=\"forward\">&gt;</button> <span id=\"label\"></span></p>",
+                frames.len().saturating_sub(1)
+            )
+            .unwrap();
+            writeln!(out, "<pre id=\"stack\"></pre>").unwrap();
+        }
+
+        writeln!(out, "<h2>Disassembly</h2>").unwrap();
+        for ((row, col), tank) in aquarium.indexed_iter() {
+            writeln!(
+                out,
+                "<h3>{} {row},{col}</h3>\n<table class=\"disassembly\">\n\
+                 <tr><th>Cell</th><th>Sum</th><th>Instruction</th></tr>",
+                tank.name()
+            )
+            .unwrap();
+            for ((r, c), cell) in tank.grid().indexed_iter() {
+                writeln!(
+                    out,
+                    "<tr><td>{r},{c}</td><td>{cell}</td><td>{}</td></tr>",
+                    escape(&mnemonic(cell, program.extensions, tank.call_letter()))
+                )
+                .unwrap();
+            }
+            writeln!(out, "</table>").unwrap();
+        }
+
+        if let Some(frames) = &self.trace {
+            let frames = frames
+                .iter()
+                .map(|frame| {
+                    format!(
+                        "[{},{},{},{},[{}]]",
+                        frame.tank.0,
+                        frame.tank.1,
+                        frame.cell.0,
+                        frame.cell.1,
+                        frame.stack.iter().join(",")
+                    )
+                })
+                .join(",");
+            writeln!(
+                out,
+                "<script>\nconst frames = [{frames}];\n{SCRIPT}</script>"
+            )
+            .unwrap();
+        }
+        writeln!(out, "</body>\n</html>").unwrap();
+        out
+    }
+}
+
+const STYLE: &str = "
+body { font-family: sans-serif; }
+table.aquarium td { vertical-align: top; padding: 0 1em 1em 0; }
+table.tank { border-collapse: collapse; font-family: monospace; }
+table.tank td { width: 1.5em; height: 1.5em; text-align: center; border: 1px solid #ccc; }
+table.disassembly { border-collapse: collapse; }
+table.disassembly td, table.disassembly th { padding: 0 1em 0 0; text-align: left; }
+td.blank { color: #bbb; }
+td.move { color: #1f5fbf; }
+td.stack { color: #1f8f3f; }
+td.branch { color: #a07000; }
+td.call { color: #a0209f; font-weight: bold; }
+td.extension { color: #108f9f; }
+td.ip { background: #222; color: #fff; }
+";
+
+const SCRIPT: &str = "
+const slider = document.getElementById('step');
+let current = null;
+function show(i) {
+  const [tr, tc, r, c, stack] = frames[i];
+  if (current) current.classList.remove('ip');
+  current = document.getElementById(`c${tr}-${tc}-${r}-${c}`);
+  current.classList.add('ip');
+  slider.value = i;
+  document.getElementById('label').textContent = `step ${i + 1} of ${frames.length}`;
+  document.getElementById('stack').textContent = `stack: [${stack.join(', ')}]`;
+}
+slider.oninput = () => show(Number(slider.value));
+document.getElementById('back').onclick = () => show(Math.max(0, Number(slider.value) - 1));
+document.getElementById('forward').onclick =
+  () => show(Math.min(frames.length - 1, Number(slider.value) + 1));
+if (frames.length) show(0);
+";
+
+fn class_name(class: CellClass) -> &'static str {
+    match class {
+        CellClass::Blank => "blank",
+        CellClass::Move => "move",
+        CellClass::Stack => "stack",
+        CellClass::Branch => "branch",
+        CellClass::Call => "call",
+        CellClass::Extension => "extension",
+    }
+}
+
+/// What a cell does when executed, in words.
+fn mnemonic(cell: usize, extensions: bool, call_letter: char) -> String {
+    let name = match cell {
+        10 if extensions => "mirror /",
+        11 if extensions => "mirror \\",
+        12 if extensions => "turn up-right",
+        13 if extensions => "turn down-right",
+        14 if extensions => "turn down-left",
+        15 if extensions => "turn up-left",
+        _ => match cell % 10 {
+            0 => "blank",
+            1 => "down",
+            2 => "up",
+            3 => "right",
+            4 => "left",
+            5 => "push accumulator",
+            6 => "cycle",
+            7 => "tunnel",
+            8 => "hop",
+            _ => return format!("call {call_letter}"),
+        },
+    };
+    name.to_string()
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::MockIo;
+
+    #[test]
+    fn test_export() {
+        let mut program = Program::with_io("ab cd", MockIo::default()).unwrap();
+        let page = HtmlExporter::new().title("a <test>").export(&program);
+        assert!(page.contains("<title>a &lt;test&gt;</title>"));
+        assert!(page.contains("<caption>cd 0,1 [c]</caption>"));
+        assert!(page.contains("<td id=\"c0-0-0-0\" class=\"move\">1</td>"));
+        assert!(page.contains("<tr><td>0,0</td><td>1</td><td>down</td></tr>"));
+        assert!(!page.contains("<script>"));
+
+        let frames = record_trace(&mut program, 3);
+        assert_eq!(frames.len(), 3);
+        assert_eq!(
+            frames[..2],
+            [
+                Frame {
+                    tank: (0, 0),
+                    cell: (0, 0),
+                    stack: vec![]
+                },
+                Frame {
+                    tank: (0, 0),
+                    cell: (1, 0),
+                    stack: vec![]
+                }
+            ]
+        );
+        let page = HtmlExporter::new().trace(frames).export(&program);
+        assert!(page.contains("const frames = [[0,0,0,0,[]],[0,0,1,0,[]],"));
+    }
+
+    #[test]
+    fn test_mnemonic() {
+        assert_eq!(mnemonic(19, false, 'o'), "call o");
+        assert_eq!(mnemonic(11, false, 'o'), "down");
+        assert_eq!(mnemonic(11, true, 'o'), "mirror \\");
+    }
+}
@@ -0,0 +1,688 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::{self, Read, Stdout, Write},
+};
+
+/// A call made from a tank whose name starts with `x`, which does nothing but report itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tracepoint<'a> {
+    pub tank: &'a str,
+    /// The number of steps executed, including this call.
+    pub step: u64,
+    pub ftp: (usize, usize),
+    pub stack: &'a [isize],
+}
+
+impl fmt::Display for Tracepoint<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "trace tank={} step={} ftp={},{} stack={:?}",
+            self.tank, self.step, self.ftp.0, self.ftp.1, self.stack
+        )
+    }
+}
+
+/// The byte-level I/O a program performs through its `i` and `o` calls.
+pub trait Io {
+    /// Reads one byte of input, returning `None` once input is exhausted.
+    fn read_byte(&mut self) -> io::Result<Option<u8>>;
+
+    /// Writes program output.
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()>;
+
+    /// Flushes any buffered output.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Reports whether `read_byte` would return without blocking.
+    ///
+    /// The default suits sources that never block, such as files and in-memory buffers.
+    fn poll(&mut self) -> io::Result<bool> {
+        Ok(true)
+    }
+
+    /// Writes a diagnostic line from the `d` call, which is not program output.
+    fn debug(&mut self, line: &str) -> io::Result<()> {
+        writeln!(io::stderr(), "{line}")
+    }
+
+    /// Reports a tracepoint. By default it is written as a debug line.
+    fn trace(&mut self, event: &Tracepoint<'_>) -> io::Result<()> {
+        self.debug(&event.to_string())
+    }
+
+    /// Told the number of steps executed, including the current one, before each call that
+    /// may perform I/O. Does nothing by default.
+    fn at_step(&mut self, _step: u64) {}
+}
+
+impl<T: Io + ?Sized> Io for &mut T {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        (**self).read_byte()
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        (**self).write_bytes(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (**self).flush()
+    }
+
+    fn poll(&mut self) -> io::Result<bool> {
+        (**self).poll()
+    }
+
+    fn debug(&mut self, line: &str) -> io::Result<()> {
+        (**self).debug(line)
+    }
+
+    fn trace(&mut self, event: &Tracepoint<'_>) -> io::Result<()> {
+        (**self).trace(event)
+    }
+
+    fn at_step(&mut self, step: u64) {
+        (**self).at_step(step)
+    }
+}
+
+impl<T: Io + ?Sized> Io for Box<T> {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        (**self).read_byte()
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        (**self).write_bytes(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (**self).flush()
+    }
+
+    fn poll(&mut self) -> io::Result<bool> {
+        (**self).poll()
+    }
+
+    fn debug(&mut self, line: &str) -> io::Result<()> {
+        (**self).debug(line)
+    }
+
+    fn trace(&mut self, event: &Tracepoint<'_>) -> io::Result<()> {
+        (**self).trace(event)
+    }
+
+    fn at_step(&mut self, step: u64) {
+        (**self).at_step(step)
+    }
+}
+
+/// I/O backed by an arbitrary reader and writer.
+#[derive(Debug)]
+pub struct StreamIo<R, W> {
+    input: R,
+    output: W,
+}
+
+impl<R: Read, W: Write> StreamIo<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Self { input, output }
+    }
+
+    pub fn into_inner(self) -> (R, W) {
+        (self.input, self.output)
+    }
+}
+
+impl<R: Read, W: Write> Io for StreamIo<R, W> {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut buf = [0u8; 1];
+        loop {
+            match self.input.read(&mut buf) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(buf[0])),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.output.write_all(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+/// The process's standard input and output.
+#[derive(Debug)]
+pub struct StdIo {
+    inner: StreamIo<UnbufferedStdin, Stdout>,
+}
+
+impl Default for StdIo {
+    fn default() -> Self {
+        Self {
+            inner: StreamIo::new(UnbufferedStdin, io::stdout()),
+        }
+    }
+}
+
+impl Io for StdIo {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        self.inner.read_byte()
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.inner.write_bytes(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    fn poll(&mut self) -> io::Result<bool> {
+        stdin_ready()
+    }
+}
+
+/// Standard input read straight from the file descriptor.
+///
+/// `std::io::Stdin` buffers internally, which would hide bytes from [`stdin_ready`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UnbufferedStdin;
+
+impl Read for UnbufferedStdin {
+    #[cfg(unix)]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // SAFETY: `buf` is valid for writes of `buf.len()` bytes.
+        let n = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), buf.len()) };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(n as usize)
+    }
+
+    #[cfg(not(unix))]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        io::stdin().read(buf)
+    }
+}
+
+/// Reports whether reading standard input would return without blocking.
+#[cfg(unix)]
+pub fn stdin_ready() -> io::Result<bool> {
+    let mut fd = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // SAFETY: `fd` is a single valid `pollfd`.
+    match unsafe { libc::poll(&mut fd, 1, 0) } {
+        n if n < 0 => Err(io::Error::last_os_error()),
+        n => Ok(n > 0),
+    }
+}
+
+/// Reports whether reading standard input would return without blocking.
+///
+/// Not supported on this platform, so input is always assumed to be ready.
+#[cfg(not(unix))]
+pub fn stdin_ready() -> io::Result<bool> {
+    Ok(true)
+}
+
+/// Wraps another [`Io`], keeping a copy of every byte of input the program reads.
+#[derive(Debug)]
+pub struct RecordingIo<I> {
+    inner: I,
+    input: Vec<u8>,
+}
+
+impl<I: Io> RecordingIo<I> {
+    pub fn new(inner: I) -> Self {
+        Self {
+            inner,
+            input: Vec::new(),
+        }
+    }
+
+    /// The input read so far.
+    pub fn recorded(&self) -> &[u8] {
+        &self.input
+    }
+
+    pub fn into_inner(self) -> (I, Vec<u8>) {
+        (self.inner, self.input)
+    }
+}
+
+impl<I: Io> Io for RecordingIo<I> {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let byte = self.inner.read_byte()?;
+        self.input.extend(byte);
+        Ok(byte)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.inner.write_bytes(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    fn poll(&mut self) -> io::Result<bool> {
+        self.inner.poll()
+    }
+
+    fn debug(&mut self, line: &str) -> io::Result<()> {
+        self.inner.debug(line)
+    }
+
+    fn trace(&mut self, event: &Tracepoint<'_>) -> io::Result<()> {
+        self.inner.trace(event)
+    }
+
+    fn at_step(&mut self, step: u64) {
+        self.inner.at_step(step)
+    }
+}
+
+/// Wraps another [`Io`], holding output back and passing it on in batches: at the end of each
+/// line, once `capacity` bytes have built up, and whenever it's flushed, which the program does
+/// when it halts or runs out of fuel. Reading input passes the output on first, so a prompt
+/// shows before the program waits for an answer.
+///
+/// Hosts that would rather poll for output than be handed it can take what's built up with
+/// [`BufferedIo::take_pending`], which never reaches the wrapped I/O.
+#[derive(Debug)]
+pub struct BufferedIo<I> {
+    inner: I,
+    pending: Vec<u8>,
+    capacity: usize,
+    line_flush: bool,
+}
+
+impl<I: Io> BufferedIo<I> {
+    /// Batches `inner`'s output by line, and in 8 KiB at most.
+    pub fn new(inner: I) -> Self {
+        Self {
+            inner,
+            pending: Vec::new(),
+            capacity: 8 * 1024,
+            line_flush: true,
+        }
+    }
+
+    /// How many bytes to hold back before passing them on without waiting for a newline.
+    pub fn capacity(mut self, bytes: usize) -> Self {
+        self.capacity = bytes.max(1);
+        self
+    }
+
+    /// Whether a newline passes the output on. Without it, output waits for the buffer to
+    /// fill or a flush.
+    pub fn line_flush(mut self, enabled: bool) -> Self {
+        self.line_flush = enabled;
+        self
+    }
+
+    /// Output written but not passed on yet.
+    pub fn pending(&self) -> &[u8] {
+        &self.pending
+    }
+
+    /// Takes the output written but not passed on yet, so it never is.
+    pub fn take_pending(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.pending)
+    }
+
+    pub fn inner(&self) -> &I {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.inner
+    }
+
+    /// Passes on any output still held back, then returns the wrapped I/O.
+    pub fn into_inner(mut self) -> io::Result<I> {
+        self.pass_on()?;
+        Ok(self.inner)
+    }
+
+    fn pass_on(&mut self) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            self.inner.write_bytes(&self.pending)?;
+            self.pending.clear();
+        }
+        Ok(())
+    }
+}
+
+impl<I: Io> Io for BufferedIo<I> {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        self.flush()?;
+        self.inner.read_byte()
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.pending.extend_from_slice(bytes);
+        if self.pending.len() >= self.capacity {
+            self.pass_on()
+        } else if self.line_flush && bytes.contains(&b'\n') {
+            self.flush()
+        } else {
+            Ok(())
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.pass_on()?;
+        self.inner.flush()
+    }
+
+    fn poll(&mut self) -> io::Result<bool> {
+        self.inner.poll()
+    }
+
+    fn debug(&mut self, line: &str) -> io::Result<()> {
+        self.inner.debug(line)
+    }
+
+    fn trace(&mut self, event: &Tracepoint<'_>) -> io::Result<()> {
+        self.inner.trace(event)
+    }
+
+    fn at_step(&mut self, step: u64) {
+        self.inner.at_step(step)
+    }
+}
+
+/// One line of an I/O event log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// An `i` call read a byte, or found the input exhausted.
+    Read { step: u64, byte: Option<u8> },
+    /// An output call wrote these bytes.
+    Write { step: u64, bytes: Vec<u8> },
+}
+
+impl Event {
+    /// Parses a line written by [`Event`]'s `Display`, like `read step=3 byte=104`,
+    /// `read step=5 eof` or `write step=9 bytes=104,105`.
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut words = line.split(' ');
+        let kind = words.next()?;
+        let step = words.next()?.strip_prefix("step=")?.parse().ok()?;
+        let rest = words.next()?;
+        if words.next().is_some() {
+            return None;
+        }
+        match kind {
+            "read" if rest == "eof" => Some(Event::Read { step, byte: None }),
+            "read" => Some(Event::Read {
+                step,
+                byte: Some(rest.strip_prefix("byte=")?.parse().ok()?),
+            }),
+            "write" => Some(Event::Write {
+                step,
+                bytes: rest
+                    .strip_prefix("bytes=")?
+                    .split(',')
+                    .map(|byte| byte.parse().ok())
+                    .collect::<Option<_>>()?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Read {
+                step,
+                byte: Some(byte),
+            } => write!(f, "read step={step} byte={byte}"),
+            Event::Read { step, byte: None } => write!(f, "read step={step} eof"),
+            Event::Write { step, bytes } => {
+                write!(f, "write step={step} bytes=")?;
+                for (i, byte) in bytes.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{byte}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Wraps another [`Io`], writing an [`Event`] line to `log` for every byte of input read and
+/// every write of output, tagged with the step that made the call.
+#[derive(Debug)]
+pub struct EventLogIo<I, W> {
+    inner: I,
+    log: W,
+    step: u64,
+}
+
+impl<I: Io, W: Write> EventLogIo<I, W> {
+    pub fn new(inner: I, log: W) -> Self {
+        Self {
+            inner,
+            log,
+            step: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> (I, W) {
+        (self.inner, self.log)
+    }
+
+    /// Writes each line whole, so an unbuffered log survives the process being killed.
+    fn log(&mut self, event: Event) -> io::Result<()> {
+        self.log.write_all(format!("{event}\n").as_bytes())
+    }
+}
+
+impl<I: Io, W: Write> Io for EventLogIo<I, W> {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let byte = self.inner.read_byte()?;
+        self.log(Event::Read {
+            step: self.step,
+            byte,
+        })?;
+        Ok(byte)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.inner.write_bytes(bytes)?;
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        self.log(Event::Write {
+            step: self.step,
+            bytes: bytes.to_vec(),
+        })
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()?;
+        self.log.flush()
+    }
+
+    fn poll(&mut self) -> io::Result<bool> {
+        self.inner.poll()
+    }
+
+    fn debug(&mut self, line: &str) -> io::Result<()> {
+        self.inner.debug(line)
+    }
+
+    fn trace(&mut self, event: &Tracepoint<'_>) -> io::Result<()> {
+        self.inner.trace(event)
+    }
+
+    fn at_step(&mut self, step: u64) {
+        self.step = step;
+        self.inner.at_step(step)
+    }
+}
+
+/// In-memory I/O with fixed input and captured output and debug lines.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MockIo {
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+    debug: Vec<String>,
+}
+
+impl MockIo {
+    pub fn new(input: impl Into<Vec<u8>>) -> Self {
+        Self {
+            input: input.into().into(),
+            output: Vec::new(),
+            debug: Vec::new(),
+        }
+    }
+
+    /// The input bytes not yet consumed.
+    pub fn remaining_input(&self) -> &VecDeque<u8> {
+        &self.input
+    }
+
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// The lines written by `d` calls.
+    pub fn debug_lines(&self) -> &[String] {
+        &self.debug
+    }
+
+    pub fn into_output(self) -> Vec<u8> {
+        self.output
+    }
+}
+
+impl Io for MockIo {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        Ok(self.input.pop_front())
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.output.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn debug(&mut self, line: &str) -> io::Result<()> {
+        self.debug.push(line.to_string());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_stream_io() {
+        let mut io = StreamIo::new(Cursor::new(b"ab".to_vec()), Vec::new());
+        assert_eq!(io.read_byte().unwrap(), Some(b'a'));
+        assert_eq!(io.read_byte().unwrap(), Some(b'b'));
+        assert_eq!(io.read_byte().unwrap(), None);
+        io.write_bytes(b"out").unwrap();
+        assert_eq!(io.into_inner().1, b"out");
+    }
+
+    #[test]
+    fn test_buffered_io() {
+        let mut io = BufferedIo::new(MockIo::new("x")).capacity(4);
+        io.write_bytes(b"ab").unwrap();
+        assert_eq!((io.pending(), io.inner().output()), (&b"ab"[..], &b""[..]));
+        io.write_bytes(b"c\n").unwrap();
+        assert_eq!(io.inner().output(), b"abc\n");
+        io.write_bytes(b"def").unwrap();
+        assert_eq!(io.inner().output(), b"abc\n");
+        io.write_bytes(b"\t").unwrap();
+        assert_eq!(io.inner().output(), b"abc\ndef\t");
+
+        // Reading passes the output on first; taking it means it's never passed on.
+        io.write_bytes(b"?").unwrap();
+        assert_eq!(io.read_byte().unwrap(), Some(b'x'));
+        io.write_bytes(b"gone").unwrap();
+        let mut io = io.line_flush(false).capacity(16);
+        io.write_bytes(b"\n").unwrap();
+        assert_eq!(io.take_pending(), b"\n");
+        assert_eq!(io.into_inner().unwrap().output(), b"abc\ndef\t?gone");
+    }
+
+    #[test]
+    fn test_mock_io() {
+        let mut io = MockIo::new("x");
+        assert_eq!(io.read_byte().unwrap(), Some(b'x'));
+        assert_eq!(io.read_byte().unwrap(), None);
+        io.write_bytes(b"hi").unwrap();
+        assert_eq!(io.output(), b"hi");
+    }
+
+    #[test]
+    fn test_recording_io() {
+        let mut io = RecordingIo::new(MockIo::new("xy"));
+        assert_eq!(io.read_byte().unwrap(), Some(b'x'));
+        io.write_bytes(b"out").unwrap();
+        assert_eq!(io.recorded(), b"x");
+        let (inner, input) = io.into_inner();
+        assert_eq!((inner.output(), &input[..]), (&b"out"[..], &b"x"[..]));
+    }
+
+    #[test]
+    fn test_event_log_io() {
+        let mut io = EventLogIo::new(MockIo::new("x"), Vec::new());
+        io.at_step(3);
+        assert_eq!(io.read_byte().unwrap(), Some(b'x'));
+        io.at_step(7);
+        assert_eq!(io.read_byte().unwrap(), None);
+        io.write_bytes(b"hi").unwrap();
+        io.write_bytes(b"").unwrap();
+        let (inner, log) = io.into_inner();
+        assert_eq!(inner.output(), b"hi");
+        let log = String::from_utf8(log).unwrap();
+        assert_eq!(
+            log,
+            "read step=3 byte=120\nread step=7 eof\nwrite step=7 bytes=104,105\n"
+        );
+        let events: Vec<_> = log
+            .lines()
+            .map(|line| Event::parse(line).unwrap())
+            .collect();
+        assert_eq!(
+            events,
+            [
+                Event::Read {
+                    step: 3,
+                    byte: Some(b'x')
+                },
+                Event::Read {
+                    step: 7,
+                    byte: None
+                },
+                Event::Write {
+                    step: 7,
+                    bytes: b"hi".to_vec()
+                },
+            ]
+        );
+        assert_eq!(Event::parse("write step=1 bytes=256"), None);
+    }
+}
@@ -0,0 +1,107 @@
+//! A stable textual dump of a program after parsing and tank population.
+//!
+//! The format is line-based, with space-separated `key=value` fields after a leading keyword:
+//!
+//! ```text
+//! pufferfish-ir 1
+//! layout rows=1 cols=2 tanks=2
+//! font name=standard
+//! dialect edges=wrap zero=noop skip=1 tunnel=peek cycle=sub,swap,dup,drop
+//! tank row=0 col=0 name=ab call=a acc=0
+//! digits 1000/1221/2002/2002/1221
+//! sums 1,0,0,0/1,2,2,1/2,0,0,2/2,0,0,2/1,2,2,1
+//! tank row=0 col=1 name=cd call=c acc=0
+//! ...
+//! ```
+//!
+//! Tanks are listed in row-major aquarium order. `digits` gives each tank row as four digits,
+//! which is what the decoder executes; `sums` gives the glyph sums before taking them mod 10.
+//! The version on the first line changes whenever the format does, except that new fields may
+//! be added to the end of a line.
+
+use std::fmt::Write;
+
+use itertools::Itertools;
+
+use crate::{io::Io, program::Program};
+
+pub const VERSION: u32 = 3;
+
+pub fn emit<I: Io>(program: &Program<I>) -> String {
+    let aquarium = program.aquarium();
+    let mut out = String::new();
+    writeln!(out, "pufferfish-ir {VERSION}").unwrap();
+    writeln!(
+        out,
+        "layout rows={} cols={} tanks={}",
+        aquarium.rows(),
+        aquarium.cols(),
+        aquarium.rows() * aquarium.cols()
+    )
+    .unwrap();
+    writeln!(out, "font name={}", program.font().name()).unwrap();
+    let dialect = program.dialect();
+    writeln!(
+        out,
+        "dialect edges={} zero={} skip={} tunnel={} cycle={}",
+        dialect.edges.name(),
+        dialect.zero,
+        dialect.skip,
+        dialect.tunnel.name(),
+        dialect.cycle
+    )
+    .unwrap();
+    for ((row, col), tank) in aquarium.indexed_iter() {
+        writeln!(
+            out,
+            "tank row={row} col={col} name={} call={} acc={}",
+            tank.name(),
+            tank.call_letter(),
+            tank.acc()
+        )
+        .unwrap();
+        let rows = || tank.grid().iter_rows();
+        writeln!(
+            out,
+            "digits {}",
+            rows().map(|row| row.map(|x| x % 10).join("")).join("/")
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "sums {}",
+            rows().map(|mut row| row.join(",")).join("/")
+        )
+        .unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{builder::ProgramBuilder, io::MockIo};
+
+    #[test]
+    fn test_emit() {
+        let program = ProgramBuilder::new("ab cd")
+            .io(MockIo::default())
+            .accumulator("cd", 3)
+            .build()
+            .unwrap();
+        let ir = emit(&program);
+        let lines: Vec<_> = ir.lines().collect();
+        assert_eq!(lines.len(), 10);
+        assert_eq!(lines[0], "pufferfish-ir 3");
+        assert_eq!(lines[1], "layout rows=1 cols=2 tanks=2");
+        assert_eq!(lines[2], "font name=standard");
+        assert_eq!(
+            lines[3],
+            "dialect edges=wrap zero=noop skip=1 tunnel=peek cycle=sub,swap,dup,drop"
+        );
+        assert_eq!(lines[4], "tank row=0 col=0 name=ab call=a acc=0");
+        assert_eq!(lines[5], "digits 1000/1221/2002/2002/1221");
+        assert_eq!(lines[6], "sums 1,0,0,0/1,2,2,1/2,0,0,2/2,0,0,2/1,2,2,1");
+        assert_eq!(lines[7], "tank row=0 col=1 name=cd call=c acc=3");
+    }
+}
@@ -1,2 +1,66 @@
+pub mod analysis;
+pub mod apostrophe;
+pub mod backend;
+pub mod builder;
+pub mod canonical;
+pub mod cells;
+pub mod checkpoint;
+pub mod condition;
+#[cfg(feature = "host-tools")]
+pub mod corpus;
+#[cfg(feature = "host-tools")]
+pub mod debug_server;
+pub mod debugger;
+pub mod decompose;
+pub mod depth;
+pub mod designer;
+pub mod dialect;
+pub mod diff;
+pub mod embed;
+pub mod equiv;
+mod error;
+#[cfg(feature = "host-tools")]
+pub mod explore;
+pub mod font;
+#[cfg(feature = "host-tools")]
+pub mod fuzz;
+pub mod generate;
+#[cfg(feature = "host-tools")]
+pub mod golf;
+pub mod html;
+pub mod io;
+pub mod ir;
+#[cfg(feature = "host-tools")]
+mod json;
+#[cfg(all(unix, feature = "line-editor"))]
+pub mod line_editor;
+pub mod metrics;
+pub mod model_check;
 pub mod parser;
+pub mod phrase;
 pub mod program;
+#[cfg(all(unix, feature = "raw-input"))]
+pub mod raw_input;
+pub mod reach;
+pub mod reduce;
+pub mod render;
+pub mod replay;
+pub mod runner;
+pub mod sandbox;
+#[cfg(feature = "host-tools")]
+pub mod search;
+#[cfg(feature = "host-tools")]
+pub mod snapshot;
+pub mod solver;
+pub mod state;
+pub mod stats;
+#[cfg(feature = "host-tools")]
+pub mod sweep;
+pub mod symbolic;
+pub mod testkit;
+pub mod timeline;
+#[cfg(all(unix, feature = "tty"))]
+pub mod tty;
+
+pub use error::Error;
+pub use runner::{EvalResult, eval};
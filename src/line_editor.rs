@@ -0,0 +1,364 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+};
+
+use crate::{
+    io::{Io, UnbufferedStdin, stdin_ready},
+    tty::{RawMode, raise_interrupt},
+};
+
+/// A minimal Emacs-style line editor with in-memory history.
+///
+/// Supports cursor movement (arrows, Home/End, Ctrl-A/E/B/F), deletion (Backspace, Delete,
+/// Ctrl-U/K/W), history (Up/Down, Ctrl-P/N), Ctrl-D for end of input and Ctrl-C to interrupt.
+/// The cursor is only ever moved relative to where the prompt ended, so output the program
+/// printed on the same line before the prompt is left intact.
+#[derive(Debug, Default)]
+pub struct LineEditor {
+    history: Vec<String>,
+}
+
+struct LineState {
+    buf: Vec<char>,
+    cursor: usize,
+    history_index: usize,
+    draft: String,
+}
+
+impl LineEditor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Reads a line from `input`, echoing the prompt and edits to `echo`.
+    ///
+    /// `input` is expected to deliver keypresses unbuffered, e.g. a terminal in raw mode.
+    /// Returns `None` at end of input or on Ctrl-D at an empty line.
+    pub fn read_line<R: Read, W: Write>(
+        &mut self,
+        prompt: &str,
+        input: &mut R,
+        echo: &mut W,
+    ) -> io::Result<Option<String>> {
+        let mut state = LineState {
+            buf: Vec::new(),
+            cursor: 0,
+            history_index: self.history.len(),
+            draft: String::new(),
+        };
+        write!(echo, "{prompt}")?;
+        echo.flush()?;
+        loop {
+            let Some(byte) = read_one(input)? else {
+                if state.buf.is_empty() {
+                    return Ok(None);
+                }
+                break;
+            };
+            let old_cursor = state.cursor;
+            match byte {
+                b'\r' | b'\n' => break,
+                0x03 => {
+                    writeln!(echo)?;
+                    return Err(io::ErrorKind::Interrupted.into());
+                }
+                0x04 if state.buf.is_empty() => {
+                    writeln!(echo)?;
+                    return Ok(None);
+                }
+                0x04 => state.delete(),
+                0x7f | 0x08 => state.backspace(),
+                0x01 => state.cursor = 0,
+                0x05 => state.cursor = state.buf.len(),
+                0x02 => state.cursor = state.cursor.saturating_sub(1),
+                0x06 => state.cursor = (state.cursor + 1).min(state.buf.len()),
+                0x15 => {
+                    state.buf.drain(..state.cursor);
+                    state.cursor = 0;
+                }
+                0x0b => state.buf.truncate(state.cursor),
+                0x17 => state.delete_word(),
+                0x10 => self.history_prev(&mut state),
+                0x0e => self.history_next(&mut state),
+                0x1b => self.escape(&mut state, input)?,
+                byte if byte < 0x20 => continue,
+                byte => {
+                    let c = read_char(byte, input)?;
+                    state.buf.insert(state.cursor, c);
+                    state.cursor += 1;
+                }
+            }
+            state.redraw(old_cursor, echo)?;
+        }
+        writeln!(echo)?;
+        echo.flush()?;
+        let line: String = state.buf.into_iter().collect();
+        if !line.is_empty() && self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+        }
+        Ok(Some(line))
+    }
+
+    fn escape<R: Read>(&self, state: &mut LineState, input: &mut R) -> io::Result<()> {
+        match read_one(input)? {
+            Some(b'[') => match read_one(input)? {
+                Some(b'A') => self.history_prev(state),
+                Some(b'B') => self.history_next(state),
+                Some(b'C') => state.cursor = (state.cursor + 1).min(state.buf.len()),
+                Some(b'D') => state.cursor = state.cursor.saturating_sub(1),
+                Some(b'H') => state.cursor = 0,
+                Some(b'F') => state.cursor = state.buf.len(),
+                Some(digit @ b'0'..=b'9') => {
+                    let mut code = vec![digit];
+                    while let Some(byte) = read_one(input)?
+                        && byte != b'~'
+                    {
+                        code.push(byte);
+                    }
+                    match code.as_slice() {
+                        b"3" => state.delete(),
+                        b"1" | b"7" => state.cursor = 0,
+                        b"4" | b"8" => state.cursor = state.buf.len(),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            },
+            Some(b'O') => match read_one(input)? {
+                Some(b'H') => state.cursor = 0,
+                Some(b'F') => state.cursor = state.buf.len(),
+                _ => {}
+            },
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn history_prev(&self, state: &mut LineState) {
+        if state.history_index == 0 {
+            return;
+        }
+        if state.history_index == self.history.len() {
+            state.draft = state.buf.iter().collect();
+        }
+        state.history_index -= 1;
+        state.replace(&self.history[state.history_index]);
+    }
+
+    fn history_next(&self, state: &mut LineState) {
+        if state.history_index >= self.history.len() {
+            return;
+        }
+        state.history_index += 1;
+        if let Some(entry) = self.history.get(state.history_index) {
+            state.replace(entry);
+        } else {
+            let draft = std::mem::take(&mut state.draft);
+            state.replace(&draft);
+        }
+    }
+}
+
+impl LineState {
+    fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.buf.remove(self.cursor);
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.cursor < self.buf.len() {
+            self.buf.remove(self.cursor);
+        }
+    }
+
+    fn delete_word(&mut self) {
+        let mut start = self.cursor;
+        while start > 0 && self.buf[start - 1] == ' ' {
+            start -= 1;
+        }
+        while start > 0 && self.buf[start - 1] != ' ' {
+            start -= 1;
+        }
+        self.buf.drain(start..self.cursor);
+        self.cursor = start;
+    }
+
+    fn replace(&mut self, line: &str) {
+        self.buf = line.chars().collect();
+        self.cursor = self.buf.len();
+    }
+
+    fn redraw<W: Write>(&self, old_cursor: usize, echo: &mut W) -> io::Result<()> {
+        if old_cursor > 0 {
+            write!(echo, "\x1b[{old_cursor}D")?;
+        }
+        let line: String = self.buf.iter().collect();
+        write!(echo, "{line}\x1b[K")?;
+        let back = self.buf.len() - self.cursor;
+        if back > 0 {
+            write!(echo, "\x1b[{back}D")?;
+        }
+        echo.flush()
+    }
+}
+
+fn read_one<R: Read>(input: &mut R) -> io::Result<Option<u8>> {
+    let mut buf = [0u8; 1];
+    loop {
+        match input.read(&mut buf) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(buf[0])),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn read_char<R: Read>(first: u8, input: &mut R) -> io::Result<char> {
+    let len = match first {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 1,
+    };
+    let mut bytes = vec![first];
+    for _ in 1..len {
+        match read_one(input)? {
+            Some(byte) => bytes.push(byte),
+            None => break,
+        }
+    }
+    Ok(String::from_utf8_lossy(&bytes).chars().next().unwrap())
+}
+
+/// Interactive terminal input read a line at a time through a [`LineEditor`].
+///
+/// Each edited line (plus its newline) is handed out one byte per `read_byte`, so successive
+/// `i` calls drain a line before the user is prompted again.
+pub struct LineIo<W> {
+    editor: LineEditor,
+    prompt: String,
+    pending: VecDeque<u8>,
+    output: W,
+}
+
+impl<W: Write> LineIo<W> {
+    pub fn new(output: W, prompt: impl Into<String>) -> Self {
+        Self {
+            editor: LineEditor::new(),
+            prompt: prompt.into(),
+            pending: VecDeque::new(),
+            output,
+        }
+    }
+
+    pub fn editor(&self) -> &LineEditor {
+        &self.editor
+    }
+}
+
+impl<W: Write> Io for LineIo<W> {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if self.pending.is_empty() {
+            self.output.flush()?;
+            let line = {
+                let _raw = RawMode::enable()?;
+                self.editor
+                    .read_line(&self.prompt, &mut UnbufferedStdin, &mut io::stderr())
+            };
+            match line {
+                Ok(Some(line)) => {
+                    self.pending.extend(line.bytes());
+                    self.pending.push_back(b'\n');
+                }
+                Ok(None) => return Ok(None),
+                Err(e) => {
+                    if e.kind() == io::ErrorKind::Interrupted {
+                        raise_interrupt();
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(self.pending.pop_front())
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.output.write_all(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+
+    /// Input counts as available once a whole line has been entered, since the terminal only
+    /// hands over input at Enter outside of editing.
+    fn poll(&mut self) -> io::Result<bool> {
+        Ok(!self.pending.is_empty() || stdin_ready()?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn edit(editor: &mut LineEditor, keys: &[u8]) -> Option<String> {
+        editor
+            .read_line("> ", &mut &keys[..], &mut Vec::new())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_editing_keys() {
+        let mut editor = LineEditor::new();
+        assert_eq!(edit(&mut editor, b"ab\x7fc\r").as_deref(), Some("ac"));
+        assert_eq!(
+            edit(&mut editor, b"bc\x01a\x05d\n").as_deref(),
+            Some("abcd")
+        );
+        assert_eq!(
+            edit(&mut editor, b"xy\x1b[D\x1b[Dw\x1b[3~\r").as_deref(),
+            Some("wy")
+        );
+        assert_eq!(edit(&mut editor, b"one two\x17\r").as_deref(), Some("one "));
+        assert_eq!(
+            edit(&mut editor, "\u{e9}t\u{e9}\r".as_bytes()).as_deref(),
+            Some("\u{e9}t\u{e9}")
+        );
+    }
+
+    #[test]
+    fn test_history() {
+        let mut editor = LineEditor::new();
+        edit(&mut editor, b"first\r");
+        edit(&mut editor, b"second\r");
+        assert_eq!(
+            edit(&mut editor, b"\x1b[A\x1b[A\r").as_deref(),
+            Some("first")
+        );
+        assert_eq!(
+            edit(&mut editor, b"dra\x1b[A\x1b[Bft\r").as_deref(),
+            Some("draft")
+        );
+        assert_eq!(editor.history(), ["first", "second", "first", "draft"]);
+    }
+
+    #[test]
+    fn test_end_of_input() {
+        let mut editor = LineEditor::new();
+        assert_eq!(edit(&mut editor, b"\x04"), None);
+        assert_eq!(edit(&mut editor, b""), None);
+        assert_eq!(edit(&mut editor, b"ab\x01\x04\r").as_deref(), Some("b"));
+        let err = editor
+            .read_line("> ", &mut &b"a\x03"[..], &mut Vec::new())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+    }
+}
@@ -1,14 +1,1066 @@
-use std::{fs::read_to_string, path::PathBuf};
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    fs::{File, create_dir_all, metadata, read_to_string, write},
+    io::{BufReader, BufWriter, Cursor, IsTerminal, Read, stderr, stdin, stdout},
+    net::TcpListener,
+    ops::Range,
+    panic,
+    path::{Path, PathBuf},
+    process,
+    rc::Rc,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
 
-use pufferfish::program::Program;
+use pufferfish::{
+    analysis::{AnagramIndex, find_collisions, tank_usage},
+    apostrophe::placements,
+    builder::ProgramBuilder,
+    canonical::Canonicalizer,
+    cells::Cells,
+    checkpoint::{Checkpointer, DirStore},
+    corpus::{CorpusRunner, Verdict, json_report, junit_xml, load_dir},
+    debug_server,
+    debugger::Debugger,
+    decompose::{decompose, parse_sums},
+    depth::StackDepths,
+    dialect::{CycleOrder, Dialect, EdgeMode, SkipMode, TunnelMode, ZeroMode},
+    diff::{first_difference, unified},
+    equiv::EquivChecker,
+    explore::Explorer,
+    font::Font,
+    fuzz::Fuzzer,
+    generate::Generator,
+    golf::Golfer,
+    html::{HtmlExporter, record_trace},
+    io::{BufferedIo, EventLogIo, Io, MockIo, RecordingIo, StdIo, StreamIo, UnbufferedStdin},
+    ir,
+    model_check::{ModelChecker, ModelReport, Terminal},
+    parser::{TankOrder, parse_pragmas},
+    phrase::{PhrasePlanner, TankSpec},
+    program::{Program, RuntimeError, Status, Tank},
+    reach::Reachability,
+    reduce::{Failure, Reducer},
+    render::{Compact, RenderOptions, render, render_compact, render_svg},
+    replay::Bundle,
+    runner::{Limits, Pacer},
+    sandbox::{Capability, Quotas, Sandbox, Violation},
+    search::Evolver,
+    snapshot,
+    solver::{Solver, Target},
+    state,
+    stats::{CsvSink, Stats, StatsSink, TextReport},
+    sweep::{Sweep, SweepReport},
+    symbolic::{Answer, Goal, SymbolicExecutor},
+};
 
-use clap::{Args, Parser};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use itertools::Itertools;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Report which words in a word list compile to interchangeable tanks
+    Collisions {
+        /// A file of whitespace-separated words, or - for stdin
+        words: PathBuf,
+    },
+    /// List the words in a word list that build the same tank as some word
+    Anagrams {
+        /// A file of whitespace-separated words, or - for stdin
+        dict: PathBuf,
+
+        /// Words to look up; with none, every group of anagrams in the list is shown
+        words: Vec<String>,
+    },
+    /// Pick a word for each tank from what each tank has to do
+    Plan {
+        /// A file of whitespace-separated words, or - for stdin
+        dict: PathBuf,
+
+        /// One spec per tank, in the order the tanks fill the aquarium: a call letter or ?,
+        /// then optionally a colon and instructions to require, or rule out with !, like
+        /// o:call,!cycle
+        #[arg(required = true)]
+        specs: Vec<String>,
+
+        /// Keep the words in the order of the specs with an order pragma, rather than
+        /// needing them to sort into it
+        #[arg(long)]
+        source_order: bool,
+
+        /// Enable extension instructions
+        #[arg(short = 'x', long)]
+        extensions: bool,
+    },
+    /// Find names whose tanks decode to a target digit grid
+    Solve {
+        /// 20 digits in row-major order; ? or . marks a cell that may hold anything
+        target: String,
+
+        /// Only consider words from this file of whitespace-separated words
+        #[arg(short, long, value_name = "FILE")]
+        dict: Option<PathBuf>,
+
+        /// Longest name to generate when no dictionary is given
+        #[arg(long, default_value_t = 4)]
+        max_len: usize,
+
+        /// Number of candidates to show
+        #[arg(short = 'n', long, default_value_t = 10)]
+        limit: usize,
+
+        /// Only show names starting with this letter
+        #[arg(long)]
+        call_letter: Option<char>,
+    },
+    /// Search for the shortest program that produces some output
+    Golf(GolfArgs),
+    /// Evolve programs towards some output (experimental)
+    Search(SearchArgs),
+    /// Run a program against random input, reporting input that makes it fail or loop
+    Fuzz(FuzzArgs),
+    /// Run every NAME.puff in a directory, with NAME.in as input and NAME.out as expected output
+    Corpus {
+        /// The directory of programs
+        dir: PathBuf,
+
+        /// Report format
+        #[arg(long, value_enum, default_value_t = ReportFormat::Junit)]
+        format: ReportFormat,
+
+        /// Steps each program may take
+        #[arg(long, default_value_t = 1_000_000)]
+        steps: u64,
+
+        /// Worker threads; defaults to the number of CPUs
+        #[arg(short, long)]
+        jobs: Option<usize>,
+
+        /// Enable extension calls
+        #[arg(short = 'x', long)]
+        extensions: bool,
+    },
+    /// Shrink a failing program while keeping its failure
+    Reduce {
+        /// The file containing the program
+        file: PathBuf,
+
+        /// The failure to preserve
+        #[arg(long, value_enum)]
+        check: FailureCheck,
+
+        /// Input to give the program
+        #[arg(long, value_name = "STRING", default_value = "")]
+        input: String,
+
+        /// Steps each run may take before it counts as diverging
+        #[arg(long, default_value_t = 100_000)]
+        steps: u64,
+
+        /// Enable extension calls
+        #[arg(short = 'x', long)]
+        extensions: bool,
+    },
+    /// Rewrite a program with its names in layout order, each renamed to the shortest
+    /// equivalent dictionary word where possible
+    Canonicalize {
+        /// The file containing the program, or - for stdin
+        file: PathBuf,
+
+        /// Rename tanks to words from this file of whitespace-separated words
+        #[arg(short, long, value_name = "FILE")]
+        dict: Option<PathBuf>,
+
+        /// Only treat tanks as equivalent if extension instructions can't tell them apart
+        #[arg(short = 'x', long)]
+        extensions: bool,
+    },
+    /// Decide whether two sources are the same program
+    Equiv {
+        /// The first program's file
+        a: PathBuf,
+
+        /// The second program's file
+        b: PathBuf,
+
+        /// Also run both side by side for up to this many steps, comparing their state
+        #[arg(long)]
+        steps: Option<u64>,
+
+        /// Input to give both programs when running them
+        #[arg(long, value_name = "STRING", default_value = "", requires = "steps")]
+        input: String,
+
+        /// Seed for both programs' y calls when running them
+        #[arg(long, default_value_t = 0, requires = "steps")]
+        seed: u64,
+
+        /// Enable extension calls
+        #[arg(short = 'x', long)]
+        extensions: bool,
+    },
+    /// Write a program as a standalone document
+    Export {
+        /// The file containing the program, or - for stdin
+        file: PathBuf,
+
+        /// Document format
+        #[arg(long, value_enum, default_value_t = ExportFormat::Html)]
+        format: ExportFormat,
+
+        /// Embed a replayable trace of up to this many steps
+        #[arg(long)]
+        trace: Option<u64>,
+
+        /// Input to give the program when tracing
+        #[arg(long, value_name = "STRING", default_value = "", requires = "trace")]
+        input: String,
+
+        /// Seed for the program's y calls when tracing
+        #[arg(long, default_value_t = 0, requires = "trace")]
+        seed: u64,
+
+        /// Enable extension calls
+        #[arg(short = 'x', long)]
+        extensions: bool,
+    },
+    /// Find the shortest letters and apostrophes that build a grid of glyph sums
+    Decompose {
+        /// Twenty glyph sums in row-major order, as on the sums line of --emit=ir
+        #[arg(required_unless_present = "name", conflicts_with = "name")]
+        sums: Option<String>,
+
+        /// Decompose the tank this name builds instead
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Longest sequence to try
+        #[arg(long, default_value_t = 12)]
+        max_len: usize,
+    },
+    /// Show the digit grid of a word with apostrophes in every place they could go
+    Apostrophes {
+        word: String,
+
+        /// Most apostrophes to add
+        #[arg(long, default_value_t = 2)]
+        max: usize,
+
+        /// Leave out placements whose digits match one shown already
+        #[arg(long)]
+        distinct: bool,
+
+        /// Placements to show side by side, next to the word without apostrophes
+        #[arg(long, default_value_t = 4)]
+        columns: usize,
+    },
+    /// Paint a tank's digit grid in the terminal, then add the closest word to a program
+    Design {
+        /// The program file to add the chosen word to, created if it doesn't exist
+        file: PathBuf,
+
+        /// A file of whitespace-separated words to suggest
+        #[arg(short, long, value_name = "FILE")]
+        dict: PathBuf,
+
+        /// Also suggest each word with up to this many apostrophes added
+        #[arg(long, default_value_t = 1)]
+        swizzles: usize,
+
+        /// Number of words to show
+        #[arg(short = 'n', long, default_value_t = 10)]
+        limit: usize,
+    },
+    /// Print random valid programs
+    Gen {
+        /// Names per program
+        #[arg(long, default_value_t = 6)]
+        tanks: usize,
+
+        /// Seed for reproducible output
+        #[arg(long)]
+        seed: Option<u64>,
+
+        /// How many programs to print, one per line
+        #[arg(short = 'n', long, default_value_t = 1)]
+        count: usize,
+
+        /// Longest generated name, in letters
+        #[arg(long, default_value_t = 8)]
+        max_len: usize,
+
+        /// Allow apostrophes in generated names
+        #[arg(long)]
+        apostrophes: bool,
+
+        /// Pick names from the words in this file instead of generating them
+        #[arg(short, long, value_name = "FILE")]
+        dict: Option<PathBuf>,
+    },
+    /// Continue a run from one of the checkpoints written by --checkpoint-dir
+    Resume(ResumeArgs),
+    /// Rerun a program exactly as it ran when recorded with --record
+    Replay {
+        /// The replay bundle
+        bundle: PathBuf,
+
+        /// Replay the bundle with both this binary and another pufferfish binary, and report
+        /// the first byte of output or the exit status they disagree on
+        #[arg(long, value_name = "BINARY")]
+        compare_with: Option<PathBuf>,
+    },
+    /// Report what each of a program's tanks is made of and which instructions it holds
+    Tanks {
+        /// The program, or - for stdin
+        file: PathBuf,
+
+        /// Decode extension instructions
+        #[arg(short = 'x', long)]
+        extensions: bool,
+    },
+    /// Warn about the parts of a program that can never execute, and the instructions that
+    /// could find too few values on the stack
+    Lint {
+        /// The program, or - for stdin
+        file: PathBuf,
+
+        /// Enable extension calls
+        #[arg(short = 'x', long)]
+        extensions: bool,
+    },
+    /// Print a program's metadata and facts about its aquarium
+    Info {
+        /// The program, or - for stdin
+        file: PathBuf,
+    },
+    /// Serve a JSON-RPC debugger for a program over TCP, one connection at a time
+    Debug {
+        /// The program, or - for stdin
+        file: PathBuf,
+
+        /// The address to listen on
+        #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:6464")]
+        listen: String,
+
+        /// Input for the program's i calls
+        #[arg(long, value_name = "STRING", default_value = "")]
+        input: String,
+
+        /// Seed for the program's random calls
+        #[arg(long)]
+        seed: Option<u64>,
+
+        /// Enable extension calls
+        #[arg(short = 'x', long)]
+        extensions: bool,
+
+        /// Record the last STEPS steps for the timeline method
+        #[arg(long, value_name = "STEPS")]
+        timeline: Option<usize>,
+    },
+    /// Show the snapshots waiting for review in a directory, and accept or reject each one
+    Review {
+        /// The directory to look in, including its subdirectories
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+
+        /// Accept every snapshot without asking
+        #[arg(long, conflicts_with = "reject")]
+        accept: bool,
+
+        /// Reject every snapshot without asking
+        #[arg(long)]
+        reject: bool,
+    },
+    /// Look for input that makes a program exit or fail, by running it on every input at
+    /// once (experimental)
+    Symbolic {
+        /// The program, or - for stdin
+        file: PathBuf,
+
+        /// What to look for
+        #[arg(long, value_enum, default_value_t = SymbolicGoal::Exit)]
+        goal: SymbolicGoal,
+
+        /// Steps each path may take
+        #[arg(long, default_value_t = 1000)]
+        steps: u64,
+
+        /// Paths that may be followed in all
+        #[arg(long, default_value_t = 10_000)]
+        paths: usize,
+
+        /// Enable extension calls
+        #[arg(short = 'x', long)]
+        extensions: bool,
+    },
+    /// Follow every direction a program's y calls could pick, and report whether each branch
+    /// halts and writes output
+    ModelCheck {
+        /// The program, or - for stdin
+        file: PathBuf,
+
+        /// Input for every branch's i calls
+        #[arg(long, value_name = "STRING", default_value = "")]
+        input: String,
+
+        /// Steps from the start each branch is followed
+        #[arg(long, default_value_t = 10_000)]
+        depth: u64,
+
+        /// Distinct states that may be reached in all
+        #[arg(long, default_value_t = 100_000)]
+        states: usize,
+
+        /// Enable extension calls
+        #[arg(short = 'x', long)]
+        extensions: bool,
+    },
+    /// Count the states a program can reach on any choice its y calls make, with its stack
+    /// bounded so the count is finite
+    Explore {
+        /// The program, or - for stdin
+        file: PathBuf,
+
+        /// Input for every branch's i calls
+        #[arg(long, value_name = "STRING", default_value = "")]
+        input: String,
+
+        /// How many of the topmost stack values a state keeps
+        #[arg(long, value_name = "VALUES", default_value_t = 16)]
+        stack_bound: usize,
+
+        /// Distinct states that may be reached in all
+        #[arg(long, default_value_t = 1_000_000)]
+        states: usize,
+
+        /// Worker threads; defaults to the number of CPUs
+        #[arg(short, long)]
+        jobs: Option<usize>,
+
+        /// Enable extension calls
+        #[arg(short = 'x', long)]
+        extensions: bool,
+    },
+    /// Run a program once for every seed in a range, and summarize how the runs went
+    Sweep {
+        /// The program, or - for stdin
+        file: PathBuf,
+
+        /// The seeds to run with, such as 0..1000
+        #[arg(long, value_name = "START..END", value_parser = parse_seeds)]
+        seeds: Range<u64>,
+
+        /// Input for every run's i calls
+        #[arg(long, value_name = "STRING", default_value = "")]
+        input: String,
+
+        /// Steps each run may take before it counts as looping
+        #[arg(long, default_value_t = 1_000_000)]
+        steps: u64,
+
+        /// Worker threads; defaults to the number of CPUs
+        #[arg(short, long)]
+        jobs: Option<usize>,
+
+        /// Enable extension calls
+        #[arg(short = 'x', long)]
+        extensions: bool,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum StatsFormat {
+    /// A report sorted by count
+    Text,
+    /// Rows of kind,row,col,name,count for spreadsheets
+    Csv,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    /// A single HTML page with no external resources
+    Html,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ReportFormat {
+    Junit,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FailureCheck {
+    Panics,
+    Errors,
+    Diverges,
+}
+
+impl From<FailureCheck> for Failure {
+    fn from(check: FailureCheck) -> Self {
+        match check {
+            FailureCheck::Panics => Failure::Panics,
+            FailureCheck::Errors => Failure::Errors,
+            FailureCheck::Diverges => Failure::Diverges,
+        }
+    }
+}
+
+#[derive(Args)]
+struct GolfArgs {
+    /// The output to produce; NUL padding from the o call is ignored
+    #[arg(long, value_name = "STRING")]
+    target_output: String,
+
+    /// Longest program to try, counting the spaces between names
+    #[arg(long, default_value_t = 6)]
+    max_len: usize,
+
+    /// Input to give each candidate
+    #[arg(long, value_name = "STRING", default_value = "")]
+    input: String,
+
+    /// Steps each candidate may take
+    #[arg(long, default_value_t = 1000)]
+    steps: u64,
+
+    /// Worker threads; defaults to the number of CPUs
+    #[arg(short, long)]
+    jobs: Option<usize>,
+
+    /// Only build programs from words in this file
+    #[arg(short, long, value_name = "FILE")]
+    dict: Option<PathBuf>,
+
+    /// Only accept programs that end with an e call
+    #[arg(long)]
+    require_exit: bool,
+
+    /// Enable extension calls in candidates
+    #[arg(short = 'x', long)]
+    extensions: bool,
+}
+
+#[derive(Args)]
+struct SearchArgs {
+    /// The output to produce; NUL padding from the o call is ignored
+    #[arg(long, value_name = "STRING")]
+    target_output: String,
+
+    /// Candidates per generation
+    #[arg(long, default_value_t = 64)]
+    population: usize,
+
+    /// Generations to run before giving up
+    #[arg(long, default_value_t = 200)]
+    generations: usize,
+
+    /// Most names per candidate
+    #[arg(long, default_value_t = 12)]
+    max_tanks: usize,
+
+    /// Seed for a reproducible search
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Input to give each candidate
+    #[arg(long, value_name = "STRING", default_value = "")]
+    input: String,
+
+    /// Steps each candidate may take
+    #[arg(long, default_value_t = 1000)]
+    steps: u64,
+
+    /// Worker threads; defaults to the number of CPUs
+    #[arg(short, long)]
+    jobs: Option<usize>,
+
+    /// Only build programs from words in this file
+    #[arg(short, long, value_name = "FILE")]
+    dict: Option<PathBuf>,
+
+    /// Only accept programs that end with an e call
+    #[arg(long)]
+    require_exit: bool,
+
+    /// Enable extension calls in candidates
+    #[arg(short = 'x', long)]
+    extensions: bool,
+}
+
+#[derive(Args)]
+struct ResumeArgs {
+    /// The directory the checkpoints were written to
+    dir: PathBuf,
+
+    /// The checkpoint to continue from; defaults to the latest
+    #[arg(long, value_name = "NUMBER")]
+    checkpoint: Option<u64>,
+
+    /// List the checkpoints in the directory, with the step each was taken at, instead of
+    /// resuming
+    #[arg(long, conflicts_with = "checkpoint")]
+    list: bool,
+
+    #[command(flatten)]
+    sandbox: SandboxArgs,
+
+    /// Seed for the random directions y calls choose from here on
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Keep checkpointing the resumed run to DIR, which may be the directory it was resumed
+    /// from; checkpoints are numbered by step, so they carry on where the run left off, and
+    /// those up to the resumed one count towards --checkpoint-keep
+    #[arg(long, value_name = "DIR", conflicts_with = "list")]
+    checkpoint_dir: Option<PathBuf>,
+
+    #[command(flatten)]
+    checkpointing: CheckpointArgs,
+}
+
+#[derive(Args)]
+struct FuzzArgs {
+    /// The program, or - for stdin
+    file: PathBuf,
+
+    /// How many inputs to try
+    #[arg(long, default_value_t = 1000)]
+    runs: u64,
+
+    /// The bytes inputs are made of; defaults to printable ASCII
+    #[arg(long, value_name = "STRING")]
+    alphabet: Option<String>,
+
+    /// Shortest input to try, in bytes
+    #[arg(long, default_value_t = 0)]
+    min_len: usize,
+
+    /// Longest input to try, in bytes
+    #[arg(long, default_value_t = 16)]
+    max_len: usize,
+
+    /// Steps each run may take before it counts as looping
+    #[arg(long, default_value_t = 100_000)]
+    steps: u64,
+
+    /// Seed for the inputs and the program's random calls
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Forbid calls that need a capability, as for running; may be repeated
+    #[arg(long, value_name = "CAPABILITY", value_parser = parse_capability)]
+    deny: Vec<Capability>,
+
+    /// Enable extension calls
+    #[arg(short = 'x', long)]
+    extensions: bool,
+}
+
+/// The restrictions a run is under, which runs resumed from a save state or checkpoint take
+/// too.
+#[derive(Args)]
+struct SandboxArgs {
+    /// Forbid calls that need a capability: input, output, exit, random or debug; may be
+    /// repeated
+    #[arg(long, value_name = "CAPABILITY", value_parser = parse_capability)]
+    deny: Vec<Capability>,
+
+    /// What a forbidden call does
+    #[arg(long, value_enum, value_name = "ACTION", default_value_t = OnViolation::Error)]
+    on_violation: OnViolation,
+
+    /// Halt once the program has written this many bytes of output
+    #[arg(long, value_name = "BYTES")]
+    max_output_bytes: Option<u64>,
+
+    /// Like --max-output-bytes, but as a sandbox quota, which reports its own halt reason
+    #[arg(long, value_name = "BYTES")]
+    output_quota: Option<u64>,
+
+    /// Halt rather than read more than this many bytes of input
+    #[arg(long, value_name = "BYTES")]
+    max_input_bytes: Option<u64>,
+
+    /// Halt rather than make more than this many y calls
+    #[arg(long, value_name = "CALLS")]
+    max_random_calls: Option<u64>,
+
+    /// Halt rather than run more than this many hop instructions
+    #[arg(long, value_name = "HOPS")]
+    max_hops: Option<u64>,
+}
+
+/// How often a run that takes checkpoints takes them, and how many it keeps.
+#[derive(Args)]
+struct CheckpointArgs {
+    /// How many steps apart --checkpoint-dir checkpoints are; when resuming, use the run's
+    /// original interval to keep the numbering
+    #[arg(
+        long,
+        value_name = "STEPS",
+        default_value_t = 1000,
+        value_parser = clap::value_parser!(u64).range(1..),
+        requires = "checkpoint_dir"
+    )]
+    checkpoint_every: u64,
+
+    /// How many of the latest --checkpoint-dir checkpoints to keep
+    #[arg(
+        long,
+        value_name = "COUNT",
+        default_value_t = 10,
+        value_parser = clap::value_parser!(u64).range(1..),
+        requires = "checkpoint_dir"
+    )]
+    checkpoint_keep: u64,
+}
+
+#[derive(Args)]
+struct RunArgs {
     #[command(flatten)]
     input: Input,
+
+    /// Input to give the program instead of stdin; repeated values are concatenated
+    #[arg(long = "input", value_name = "STRING")]
+    stdin: Vec<String>,
+
+    /// Read input a keypress at a time from the terminal, without waiting for Enter
+    #[arg(long, conflicts_with = "stdin")]
+    raw_input: bool,
+
+    /// Enable extension calls and instructions beyond the core language
+    #[arg(short = 'x', long)]
+    extensions: bool,
+
+    /// An argument for the program, pushed onto its stack before it starts; may be repeated
+    #[arg(long = "arg", value_name = "ARG")]
+    args: Vec<String>,
+
+    /// Initial stack contents, bottom to top
+    #[arg(
+        long,
+        value_name = "VALUES",
+        value_delimiter = ',',
+        allow_negative_numbers = true
+    )]
+    init_stack: Vec<isize>,
+
+    /// Initial accumulator value for a tank; may be repeated
+    #[arg(long, value_name = "TANK=VALUE", value_parser = parse_accumulator)]
+    init_acc: Vec<(String, usize)>,
+
+    /// Draw names with this font, overriding any #!font line in the program
+    #[arg(long, value_name = "NAME", value_parser = parse_font)]
+    font: Option<&'static Font>,
+
+    /// What the instruction pointer does at tank edges, overriding any #!edges line
+    #[arg(long, value_enum, value_name = "MODE")]
+    edges: Option<Edges>,
+
+    /// What blank cells do: noop, halt or quota=N; overrides any #!zero line
+    #[arg(long, value_name = "MODE", value_parser = parse_zero_mode)]
+    zero: Option<ZeroMode>,
+
+    /// How many instructions a failed tunnel skips: a count, or stack to pop one; overrides
+    /// any #!skip line
+    #[arg(long, value_name = "COUNT", value_parser = parse_skip_mode)]
+    skip: Option<SkipMode>,
+
+    /// Whether tunnels pop the condition they test; overrides any #!tunnel line
+    #[arg(long, value_enum, value_name = "MODE")]
+    tunnel: Option<Tunnel>,
+
+    /// The operations the cycle instruction rotates through, like sub,swap,dup,drop;
+    /// overrides any #!cycle line
+    #[arg(long, value_name = "OPS", value_parser = parse_cycle_order)]
+    cycle: Option<CycleOrder>,
+
+    /// The order tanks fill the aquarium in, overriding any #!order line
+    #[arg(long, value_enum)]
+    order: Option<Order>,
+
+    /// Start from the rules of this spec version, overriding any #!spec line
+    #[arg(long, value_name = "VERSION")]
+    spec: Option<u32>,
+
+    #[command(flatten)]
+    sandbox: SandboxArgs,
+
+    /// Seed for the random directions y calls choose, making the run reproducible
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Write the code, seed, options and input of this run to a bundle that the replay
+    /// command can rerun exactly
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["watch", "emit", "save_state", "load_state"]
+    )]
+    record: Option<PathBuf>,
+
+    /// When the program stops, including on Ctrl-C, write its state to FILE so the run can be
+    /// resumed with --load-state
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["watch", "emit"])]
+    save_state: Option<PathBuf>,
+
+    /// Log every byte of input read and every write of output, with the step that made it,
+    /// to FILE
+    #[arg(long, value_name = "FILE", conflicts_with = "emit")]
+    io_log: Option<PathBuf>,
+
+    /// Capture the program's output instead of printing it, and fail with a diff unless it
+    /// matches this file's contents, or this string if there is no such file
+    #[arg(
+        long,
+        value_name = "FILE_OR_STRING",
+        conflicts_with_all = ["watch", "emit", "raw_input"]
+    )]
+    expect_output: Option<String>,
+
+    /// Fail unless pufferfish would exit with this status: 0 if the program halts, 1 if it
+    /// fails
+    #[arg(long, value_name = "STATUS", conflicts_with_all = ["watch", "emit"])]
+    expect_exit: Option<i32>,
+
+    /// Run at this many steps per second, flushing output as it goes, for demos
+    #[arg(long, value_name = "STEPS", value_parser = parse_speed, conflicts_with_all = ["emit", "stats"])]
+    speed: Option<f64>,
+
+    /// Write an SVG image of the aquarium to DIR every --frame-every steps, as
+    /// frame-000000.svg, frame-000001.svg and so on, for assembling into a video
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = ["watch", "emit", "save_state", "record", "stats"]
+    )]
+    frames: Option<PathBuf>,
+
+    /// How many steps apart --frames images are
+    #[arg(long, value_name = "STEPS", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..), requires = "frames")]
+    frame_every: u64,
+
+    /// Save the program's state to DIR every --checkpoint-every steps, as
+    /// checkpoint-000001.state and so on, keeping only the latest --checkpoint-keep, so the
+    /// run can be continued from part way through with the resume command
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = ["watch", "emit", "save_state", "record", "frames", "stats"]
+    )]
+    checkpoint_dir: Option<PathBuf>,
+
+    #[command(flatten)]
+    checkpointing: CheckpointArgs,
+
+    /// Count the steps taken in each tank and by each instruction and how the stack grew, and
+    /// write the counters to FILE when the program stops; - writes them to stderr
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["watch", "emit", "save_state", "record"]
+    )]
+    stats: Option<PathBuf>,
+
+    /// How to write --stats counters
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = StatsFormat::Text, requires = "stats")]
+    stats_format: StatsFormat,
+
+    /// Only count every STEPS-th step for --stats, weighting each by STEPS, for runs too long
+    /// to count in full
+    #[arg(long, value_name = "STEPS", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..), requires = "stats")]
+    stats_sample: u64,
+
+    /// Keep running, reloading the program whenever its file changes
+    #[arg(long, requires = "file", conflicts_with = "emit")]
+    watch: bool,
+
+    /// With --watch, start over after each reload instead of keeping the stack and pointers
+    #[arg(long, requires = "watch")]
+    restart_on_reload: bool,
+
+    /// Print an internal representation of the program instead of running it
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    emit: Option<Emit>,
+
+    /// When to color --emit aquarium output
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// With --emit aquarium, shade the cells that can never execute
+    #[arg(long)]
+    dead_code: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Edges {
+    /// Reappear on the opposite edge of the same tank
+    Wrap,
+    /// Turn around
+    Bounce,
+    /// Reappear on the opposite edge of the neighbouring tank
+    Hop,
+}
+
+impl From<Edges> for EdgeMode {
+    fn from(edges: Edges) -> Self {
+        match edges {
+            Edges::Wrap => EdgeMode::Wrap,
+            Edges::Bounce => EdgeMode::Bounce,
+            Edges::Hop => EdgeMode::Hop,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Tunnel {
+    /// Leave the condition on the stack
+    Peek,
+    /// Pop the condition
+    Pop,
+}
+
+impl From<Tunnel> for TunnelMode {
+    fn from(tunnel: Tunnel) -> Self {
+        match tunnel {
+            Tunnel::Peek => TunnelMode::Peek,
+            Tunnel::Pop => TunnelMode::Pop,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ColorChoice {
+    /// When writing to a terminal
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Order {
+    /// Sorted by name
+    Sorted,
+    /// The order the names appear in the source
+    Source,
+}
+
+impl From<Order> for TankOrder {
+    fn from(order: Order) -> Self {
+        match order {
+            Order::Sorted => TankOrder::Sorted,
+            Order::Source => TankOrder::Source,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SymbolicGoal {
+    /// An e call
+    Exit,
+    /// Any runtime error
+    Error,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OnViolation {
+    /// Stop with an error
+    Error,
+    /// Halt, as if the program had exited
+    Halt,
+}
+
+impl From<OnViolation> for Violation {
+    fn from(action: OnViolation) -> Self {
+        match action {
+            OnViolation::Error => Violation::Error,
+            OnViolation::Halt => Violation::Halt,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Emit {
+    /// The parsed tanks and aquarium layout
+    Ir,
+    /// The aquarium drawn as digit grids, labelled with names, positions and call letters
+    Aquarium,
+    /// The aquarium drawn with a braille dot for each nonzero digit
+    Braille,
+    /// The aquarium drawn with a half block for each nonzero digit
+    Blocks,
+}
+
+fn parse_accumulator(s: &str) -> Result<(String, usize), String> {
+    let (name, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected TANK=VALUE, got {s}"))?;
+    let value = value
+        .parse()
+        .map_err(|e| format!("invalid value {value}: {e}"))?;
+    Ok((name.to_string(), value))
+}
+
+fn parse_zero_mode(s: &str) -> Result<ZeroMode, String> {
+    ZeroMode::parse(s).ok_or_else(|| format!("expected noop, halt or quota=N, got {s}"))
+}
+
+fn parse_skip_mode(s: &str) -> Result<SkipMode, String> {
+    SkipMode::parse(s).ok_or_else(|| format!("expected a count or stack, got {s}"))
+}
+
+fn parse_cycle_order(s: &str) -> Result<CycleOrder, String> {
+    CycleOrder::parse(s).ok_or_else(|| {
+        format!("expected one to four of sub, swap, dup and drop separated by commas, got {s}")
+    })
+}
+
+fn parse_capability(s: &str) -> Result<Capability, String> {
+    Capability::by_name(s).ok_or_else(|| {
+        let names: Vec<_> = Capability::ALL.iter().map(|cap| cap.name()).collect();
+        format!(
+            "unknown capability {s}; the capabilities are {}",
+            names.join(", ")
+        )
+    })
+}
+
+fn parse_speed(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(speed) if speed.is_finite() && speed > 0.0 => Ok(speed),
+        _ => Err(format!(
+            "expected a positive number of steps per second, got {s}"
+        )),
+    }
+}
+
+fn parse_seeds(s: &str) -> Result<Range<u64>, String> {
+    let parsed = s.split_once("..").and_then(|(start, end)| {
+        let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+        (start <= end).then_some(start..end)
+    });
+    parsed.ok_or_else(|| format!("expected a range of seeds such as 0..1000, got {s}"))
+}
+
+fn parse_font(s: &str) -> Result<&'static Font, String> {
+    Font::by_name(s).ok_or_else(|| {
+        let names: Vec<_> = Font::BUILTIN.iter().map(|font| font.name()).collect();
+        format!("unknown font {s}; the fonts are {}", names.join(", "))
+    })
 }
 
 #[derive(Args)]
@@ -20,17 +1072,1621 @@ struct Input {
 
     /// The program
     code: Option<String>,
+
+    /// Resume a run from a file written by --save-state, instead of starting a program. The
+    /// state keeps no sandbox, limits or seed, so give them again for the resumed run
+    #[arg(long, value_name = "FILE")]
+    load_state: Option<PathBuf>,
 }
 
 fn main() -> Result<(), anyhow::Error> {
     let cli = Cli::parse();
-    let code = if let Some(input_file) = cli.input.file {
-        read_to_string(input_file)?
+    match cli.command {
+        Some(Command::Collisions { words }) => collisions(&words),
+        Some(Command::Anagrams { dict, words }) => anagrams(&dict, &words),
+        Some(Command::Plan {
+            dict,
+            specs,
+            source_order,
+            extensions,
+        }) => {
+            let specs = specs
+                .iter()
+                .map(|spec| spec.parse())
+                .collect::<Result<Vec<TankSpec>, _>>()?;
+            let order = if source_order {
+                TankOrder::Source
+            } else {
+                TankOrder::Sorted
+            };
+            let planner = PhrasePlanner::new(read_path_or_stdin(&dict)?.split_whitespace())
+                .order(order)
+                .extensions(extensions);
+            let Some(phrase) = planner.plan(&specs) else {
+                anyhow::bail!("no words in the dictionary fit the plan");
+            };
+            println!("{}", phrase.source());
+            Ok(())
+        }
+        Some(Command::Solve {
+            target,
+            dict,
+            max_len,
+            limit,
+            call_letter,
+        }) => solve(&target, dict.as_deref(), max_len, limit, call_letter),
+        Some(Command::Golf(args)) => golf(args),
+        Some(Command::Search(args)) => search(args),
+        Some(Command::Fuzz(args)) => fuzz(args),
+        Some(Command::Corpus {
+            dir,
+            format,
+            steps,
+            jobs,
+            extensions,
+        }) => corpus(&dir, format, steps, jobs, extensions),
+        Some(Command::Reduce {
+            file,
+            check,
+            input,
+            steps,
+            extensions,
+        }) => {
+            let code = read_path_or_stdin(&file)?;
+            let reducer = Reducer::new(check.into())
+                .input(input)
+                .limits(Limits::steps(steps))
+                .extensions(extensions);
+            // Every panicking candidate would otherwise print its panic message.
+            if matches!(check, FailureCheck::Panics) {
+                panic::set_hook(Box::new(|_| {}));
+            }
+            let reduced = reducer.reduce(&code)?;
+            eprintln!("reduced {} bytes to {}", code.len(), reduced.len());
+            println!("{reduced}");
+            Ok(())
+        }
+        Some(Command::Canonicalize {
+            file,
+            dict,
+            extensions,
+        }) => {
+            let mut canonicalizer = Canonicalizer::new().extensions(extensions);
+            if let Some(dict) = dict {
+                canonicalizer =
+                    canonicalizer.dictionary(read_path_or_stdin(&dict)?.split_whitespace());
+            }
+            print!(
+                "{}",
+                canonicalizer.canonicalize(&read_path_or_stdin(&file)?)?
+            );
+            Ok(())
+        }
+        Some(Command::Equiv {
+            a,
+            b,
+            steps,
+            input,
+            seed,
+            extensions,
+        }) => {
+            let mut checker = EquivChecker::new().extensions(extensions);
+            if let Some(steps) = steps {
+                checker = checker.bisimulate(steps).input(input).seed(seed);
+            }
+            equiv(&checker, &a, &b)
+        }
+        Some(Command::Export {
+            file,
+            format: ExportFormat::Html,
+            trace,
+            input,
+            seed,
+            extensions,
+        }) => {
+            let code = read_path_or_stdin(&file)?;
+            let build = |io| {
+                ProgramBuilder::new(code.as_str())
+                    .extensions(extensions)
+                    .seed(seed)
+                    .io(io)
+                    .build()
+            };
+            let mut exporter = HtmlExporter::new().title(file.display().to_string());
+            if let Some(steps) = trace {
+                let mut program = build(MockIo::new(input.into_bytes()))?;
+                exporter = exporter.trace(record_trace(&mut program, steps));
+            }
+            print!("{}", exporter.export(&build(MockIo::default())?));
+            Ok(())
+        }
+        Some(Command::Decompose {
+            sums,
+            name,
+            max_len,
+        }) => {
+            let grid = match name {
+                Some(name) => Tank::from_name(&name)?.grid().to_grid(),
+                None => parse_sums(&sums.unwrap())?,
+            };
+            let Some(found) = decompose(&grid, max_len) else {
+                anyhow::bail!("no sequence of up to {max_len} steps builds this grid");
+            };
+            match found.to_name() {
+                Some(name) => println!("{name}"),
+                None => println!("{found} (not a valid name)"),
+            }
+            Ok(())
+        }
+        Some(Command::Apostrophes {
+            word,
+            max,
+            distinct,
+            columns,
+        }) => apostrophes(&word, max, distinct, columns),
+        Some(Command::Design {
+            file,
+            dict,
+            swizzles,
+            limit,
+        }) => design(&file, &dict, swizzles, limit),
+        Some(Command::Info { file }) => info(&file),
+        Some(Command::Tanks { file, extensions }) => tanks(&file, extensions),
+        Some(Command::Lint { file, extensions }) => lint(&file, extensions),
+        Some(Command::Resume(args)) => resume(args),
+        Some(Command::Replay {
+            bundle,
+            compare_with: None,
+        }) => replay(&bundle),
+        Some(Command::Replay {
+            bundle,
+            compare_with: Some(other),
+        }) => compare_replays(&bundle, &other),
+        Some(Command::Debug {
+            file,
+            listen,
+            input,
+            seed,
+            extensions,
+            timeline,
+        }) => debug(&file, &listen, input, seed, extensions, timeline),
+        Some(Command::Review {
+            dir,
+            accept,
+            reject,
+        }) => review(&dir, accept, reject),
+        Some(Command::Sweep {
+            file,
+            seeds,
+            input,
+            steps,
+            jobs,
+            extensions,
+        }) => {
+            let mut sweep = Sweep::new()
+                .input(input)
+                .limits(Limits::steps(steps))
+                .extensions(extensions);
+            if let Some(jobs) = jobs {
+                sweep = sweep.jobs(jobs);
+            }
+            let report = sweep.run(&read_path_or_stdin(&file)?, seeds)?;
+            print_sweep(&report);
+            Ok(())
+        }
+        Some(Command::ModelCheck {
+            file,
+            input,
+            depth,
+            states,
+            extensions,
+        }) => {
+            let report = ModelChecker::new()
+                .input(input)
+                .max_depth(depth)
+                .max_states(states)
+                .extensions(extensions)
+                .run(&read_path_or_stdin(&file)?)?;
+            print_model_report(&report);
+            Ok(())
+        }
+        Some(Command::Explore {
+            file,
+            input,
+            stack_bound,
+            states,
+            jobs,
+            extensions,
+        }) => {
+            let mut explorer = Explorer::new()
+                .input(input)
+                .stack_bound(stack_bound)
+                .max_states(states)
+                .extensions(extensions);
+            if let Some(jobs) = jobs {
+                explorer = explorer.jobs(jobs);
+            }
+            let report = explorer.run(&read_path_or_stdin(&file)?)?;
+            println!("states      {}", report.states);
+            println!("terminal    {}", report.terminal);
+            println!("errors      {}", report.errors);
+            println!("y calls     {}", report.choices);
+            println!("abstracted  {}", report.abstracted);
+            println!("depth       {}", report.depth);
+            if report.truncated {
+                println!("stopped at the state limit; there may be more");
+            }
+            Ok(())
+        }
+        Some(Command::Symbolic {
+            file,
+            goal,
+            steps,
+            paths,
+            extensions,
+        }) => {
+            let program = ProgramBuilder::new(read_path_or_stdin(&file)?)
+                .io(MockIo::default())
+                .extensions(extensions)
+                .build()?;
+            let goal = match goal {
+                SymbolicGoal::Exit => Goal::Exit,
+                SymbolicGoal::Error => Goal::Error,
+            };
+            let executor = SymbolicExecutor::new().max_steps(steps).max_paths(paths);
+            match executor.search(&program, goal) {
+                Answer::Reachable(witness) => {
+                    println!(
+                        "reachable in {} steps with input \"{}\"",
+                        witness.steps,
+                        witness.input.escape_ascii()
+                    );
+                    if !witness.choices.is_empty() {
+                        let choices = witness.choices.iter().map(|dir| dir.name()).join(" ");
+                        println!("with y calls picking {choices}");
+                    }
+                }
+                Answer::Unreachable => println!("unreachable with any input"),
+                Answer::Unknown(cutoff) => println!("unknown: {cutoff}"),
+            }
+            Ok(())
+        }
+        Some(Command::Gen {
+            tanks,
+            seed,
+            count,
+            max_len,
+            apostrophes,
+            dict,
+        }) => {
+            let mut generator = Generator::new(tanks, seed)
+                .name_len(1, max_len)
+                .apostrophes(apostrophes);
+            if let Some(dict) = dict {
+                generator = generator.dictionary(read_path_or_stdin(&dict)?.split_whitespace());
+            }
+            for program in generator.take(count) {
+                println!("{program}");
+            }
+            Ok(())
+        }
+        None => run(cli.run),
+    }
+}
+
+fn read_path_or_stdin(path: &Path) -> Result<String, anyhow::Error> {
+    if path == Path::new("-") {
+        let mut buf = String::new();
+        stdin().read_to_string(&mut buf)?;
+        Ok(buf)
     } else {
-        cli.input.code.unwrap()
+        Ok(read_to_string(path)?)
+    }
+}
+
+fn info(path: &Path) -> Result<(), anyhow::Error> {
+    let code = read_path_or_stdin(path)?;
+    let (pragmas, _) = parse_pragmas(&code)?;
+    let metadata = &pragmas.metadata;
+    let program = ProgramBuilder::new(code.as_str())
+        .io(MockIo::default())
+        .extensions(metadata.extensions)
+        .build()?;
+    if let Some(title) = &metadata.title {
+        println!("title     {title}");
+    }
+    if let Some(author) = &metadata.author {
+        println!("author    {author}");
+    }
+    match pragmas.spec {
+        Some(spec) => println!("spec      {spec}"),
+        None => println!("spec      {} (current)", Dialect::CURRENT_SPEC),
+    }
+    if metadata.extensions {
+        println!("requires  extensions");
+    }
+    let aquarium = program.aquarium();
+    println!("tanks     {}", aquarium.iter().count());
+    println!("aquarium  {}x{}", aquarium.rows(), aquarium.cols());
+    // Only tanks with a call cell ever make a call.
+    let calls: Vec<char> = aquarium
+        .iter()
+        .filter(|tank| tank.digits().as_array().contains(&9))
+        .map(|tank| tank.call_letter())
+        .sorted()
+        .dedup()
+        .collect();
+    if calls.is_empty() {
+        println!("calls     none");
+    } else {
+        println!("calls     {}", calls.iter().join(" "));
+    }
+    Ok(())
+}
+
+fn print_model_report(report: &ModelReport) {
+    let answer = |answer: Option<bool>| match answer {
+        Some(true) => "yes",
+        Some(false) => "no",
+        None => "unknown",
+    };
+    println!("states          {}", report.states);
+    println!("y calls         {}", report.choices);
+    println!("always halts    {}", answer(report.always_halts()));
+    println!("always outputs  {}", answer(report.always_outputs()));
+    for outcome in &report.outcomes {
+        let ending = match &outcome.ending {
+            Terminal::Halted(reason) => format!("halted: {}", reason.name()),
+            Terminal::Error(message) => format!("error: {message}"),
+        };
+        println!("{ending}, output \"{}\"", outcome.output.escape_ascii());
+    }
+    if report.silent_loop {
+        println!("some branches loop forever without writing anything");
+    } else if report.loops {
+        println!("some branches loop forever");
+    }
+    if report.truncated {
+        println!("some branches were cut off by the depth or state limit");
+    }
+}
+
+fn lint(path: &Path, extensions: bool) -> Result<(), anyhow::Error> {
+    let program = ProgramBuilder::new(read_path_or_stdin(path)?)
+        .io(MockIo::default())
+        .extensions(extensions)
+        .build()?;
+    let reach = Reachability::of(&program);
+    let aquarium = program.aquarium();
+    for (position, tank) in aquarium.indexed_iter() {
+        if !reach.entered(position) {
+            println!("warning: tank {} is never entered", tank.name());
+        }
+    }
+    // Tanks that are never entered were already reported whole.
+    for location in reach
+        .unreachable(&program)
+        .filter(|location| reach.entered(location.tank))
+    {
+        let (row, col) = location.cell;
+        println!(
+            "warning: unreachable cell at tank {} cell ({row},{col})",
+            aquarium[location.tank].name()
+        );
+    }
+    for underflow in StackDepths::of(&program).underflows() {
+        let (row, col) = underflow.location.cell;
+        println!(
+            "warning: possible stack underflow at tank {} cell ({row},{col}): {underflow}",
+            aquarium[underflow.location.tank].name()
+        );
+    }
+    Ok(())
+}
+
+fn tanks(path: &Path, extensions: bool) -> Result<(), anyhow::Error> {
+    let program = ProgramBuilder::new(read_path_or_stdin(path)?)
+        .io(MockIo::default())
+        .extensions(extensions)
+        .build()?;
+    for (i, usage) in tank_usage(&program).iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        let (row, col) = usage.position;
+        println!("{} (row {row}, col {col})", usage.name);
+        println!(
+            "  letters       {}, apostrophes {}",
+            usage.letters, usage.apostrophes
+        );
+        // A tank has 20 cells, so no count is wider than two digits.
+        let row = |counts: &[usize]| counts.iter().map(|count| format!("{count:>2}")).join(" ");
+        println!("  digits       {}", row(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]));
+        println!("  cells        {}", row(&usage.digits));
+        println!("  instructions  {}", usage.instructions.join(" "));
+        if usage.calls() {
+            println!("  call letter   {}", usage.call_letter);
+        } else {
+            println!("  call letter   {} (never calls)", usage.call_letter);
+        }
+    }
+    Ok(())
+}
+
+fn collisions(words: &Path) -> Result<(), anyhow::Error> {
+    let words = read_path_or_stdin(words)?;
+    let report = find_collisions(words.split_whitespace());
+    println!("Semantic collisions (same grid and call letter):");
+    for group in &report.semantic {
+        println!("  {}", group.join(" "));
+    }
+    println!("Grid collisions (same grid, different call letters):");
+    for group in &report.grid_only {
+        println!("  {}", group.join(" "));
+    }
+    println!("Unique ({}):", report.unique.len());
+    for word in &report.unique {
+        println!("  {word}");
+    }
+    if !report.invalid.is_empty() {
+        println!("Skipped invalid names: {}", report.invalid.join(" "));
+    }
+    Ok(())
+}
+
+fn anagrams(dict: &Path, words: &[String]) -> Result<(), anyhow::Error> {
+    let dict = read_path_or_stdin(dict)?;
+    let index = AnagramIndex::new(dict.split_whitespace());
+    if words.is_empty() {
+        for group in index.groups() {
+            println!("{}", group.join(" "));
+        }
+        return Ok(());
+    }
+    for word in words {
+        let word = word.to_lowercase();
+        let Some(first) = word.chars().next() else {
+            continue;
+        };
+        // Anagrams starting with the same letter behave the same wherever they go; the rest
+        // only differ when their tank makes a call.
+        let (same, other): (Vec<&String>, Vec<&String>) = index
+            .anagrams(&word)
+            .iter()
+            .filter(|&anagram| *anagram != word)
+            .partition(|anagram| anagram.starts_with(first));
+        if same.is_empty() && other.is_empty() {
+            println!("{word}: no anagrams");
+            continue;
+        }
+        println!("{word}:");
+        if !same.is_empty() {
+            println!("  same call letter: {}", same.iter().join(" "));
+        }
+        if !other.is_empty() {
+            println!("  other call letters: {}", other.iter().join(" "));
+        }
+    }
+    Ok(())
+}
+
+fn solve(
+    target: &str,
+    dict: Option<&Path>,
+    max_len: usize,
+    limit: usize,
+    call_letter: Option<char>,
+) -> Result<(), anyhow::Error> {
+    let target = Target::parse(target)?;
+    let mut solver = Solver::new()
+        .max_len(max_len)
+        .limit(limit)
+        .call_letter(call_letter);
+    if let Some(dict) = dict {
+        solver = solver.dictionary(read_path_or_stdin(dict)?.split_whitespace());
+    }
+    for candidate in solver.solve(&target) {
+        println!("{} (distance {})", candidate.word, candidate.distance);
+        for row in candidate.residual.to_string().lines() {
+            println!("  {row}");
+        }
+    }
+    Ok(())
+}
+
+/// Prints the digits of `word` and of each way of adding apostrophes to it, a row of grids at
+/// a time. Each row starts with the word's own grid, and cells that differ from it are marked
+/// with a `*`.
+fn apostrophes(
+    word: &str,
+    max: usize,
+    distinct: bool,
+    columns: usize,
+) -> Result<(), anyhow::Error> {
+    let letters: String = word.chars().filter(|&c| c != '\'').collect();
+    let base = letters.parse::<Tank>()?.digits();
+    let mut seen = HashSet::from([base]);
+    let found: Vec<_> = placements(word, max)?
+        .into_iter()
+        .filter(|placement| !distinct || seen.insert(placement.tank.digits()))
+        .collect();
+    if found.is_empty() {
+        println!("no apostrophes fit in {letters}");
+        return Ok(());
+    }
+    // A block of lines for each grid: its name, a note, then its rows.
+    let block = |name: &str, note: String, digits: Cells| {
+        let mut lines = vec![name.to_string(), note];
+        for (row, base_row) in digits.iter_rows().zip(base.iter_rows()) {
+            lines.push(
+                row.zip(base_row)
+                    .map(|(digit, was)| format!("{digit}{}", if digit == was { ' ' } else { '*' }))
+                    .collect(),
+            );
+        }
+        lines
+    };
+    for group in found.chunks(columns.max(1)) {
+        let mut blocks = vec![block(&letters, "as spelled".to_string(), base)];
+        for placement in group {
+            let note = format!("{} changed", placement.changed);
+            blocks.push(block(&placement.name, note, placement.tank.digits()));
+        }
+        let widths: Vec<usize> = blocks
+            .iter()
+            .map(|lines| lines.iter().map(String::len).max().unwrap_or(0))
+            .collect();
+        for line in 0..blocks[0].len() {
+            let text = blocks
+                .iter()
+                .zip(&widths)
+                .map(|(lines, width)| format!("{:width$}", lines[line]))
+                .join("  ");
+            println!("{}", text.trim_end());
+        }
+        println!();
+    }
+    Ok(())
+}
+
+fn equiv(checker: &EquivChecker, a: &Path, b: &Path) -> Result<(), anyhow::Error> {
+    let report = checker.check(&read_to_string(a)?, &read_to_string(b)?)?;
+    if report.same() {
+        println!("same program");
+        return Ok(());
+    }
+    for difference in &report.differences {
+        println!("{difference}");
+    }
+    match report.divergence {
+        Some(divergence) => anyhow::bail!("{divergence}"),
+        None if report.steps > 0 => {
+            println!(
+                "but they behave the same for the {} steps run",
+                report.steps
+            );
+            Ok(())
+        }
+        None => anyhow::bail!("the programs differ"),
+    }
+}
+
+fn corpus(
+    dir: &Path,
+    format: ReportFormat,
+    steps: u64,
+    jobs: Option<usize>,
+    extensions: bool,
+) -> Result<(), anyhow::Error> {
+    let cases = load_dir(dir)?;
+    let mut runner = CorpusRunner::new()
+        .limits(Limits::steps(steps))
+        .extensions(extensions);
+    if let Some(jobs) = jobs {
+        runner = runner.jobs(jobs);
+    }
+    let results = runner.run(&cases);
+    match format {
+        ReportFormat::Junit => print!("{}", junit_xml(&dir.display().to_string(), &results)),
+        ReportFormat::Json => print!("{}", json_report(&results)),
+    }
+    let failed = results
+        .iter()
+        .filter(|r| r.verdict != Verdict::Passed)
+        .count();
+    if failed > 0 {
+        anyhow::bail!("{failed} of {} programs failed", results.len());
+    }
+    Ok(())
+}
+
+fn golf(args: GolfArgs) -> Result<(), anyhow::Error> {
+    let mut golfer = Golfer::new(args.target_output, args.max_len)
+        .input(args.input)
+        .limits(Limits::steps(args.steps))
+        .require_exit(args.require_exit)
+        .extensions(args.extensions);
+    if let Some(jobs) = args.jobs {
+        golfer = golfer.jobs(jobs);
+    }
+    if let Some(dict) = &args.dict {
+        golfer = golfer.dictionary(read_path_or_stdin(dict)?.split_whitespace());
+    }
+    let result = golfer.search(|len, count| eprintln!("length {len}: {count} candidates"));
+    let Some(result) = result else {
+        anyhow::bail!("no program of up to {} characters matched", args.max_len);
+    };
+    println!("{}", result.programs[0]);
+    if result.programs.len() > 1 {
+        eprintln!(
+            "{} other programs of length {} also match",
+            result.programs.len() - 1,
+            result.len
+        );
+    }
+    Ok(())
+}
+
+fn search(args: SearchArgs) -> Result<(), anyhow::Error> {
+    let mut evolver = Evolver::new(args.target_output)
+        .population(args.population)
+        .generations(args.generations)
+        .max_tanks(args.max_tanks)
+        .seed(args.seed)
+        .input(args.input)
+        .limits(Limits::steps(args.steps))
+        .require_exit(args.require_exit)
+        .extensions(args.extensions);
+    if let Some(jobs) = args.jobs {
+        evolver = evolver.jobs(jobs);
+    }
+    if let Some(dict) = &args.dict {
+        evolver = evolver.dictionary(read_path_or_stdin(dict)?.split_whitespace());
+    }
+    let mut last = None;
+    let best = evolver.run(|generation, leader| {
+        if last.as_ref() != Some(&leader.fitness) {
+            eprintln!(
+                "generation {generation}: distance {}, {} steps: {}",
+                leader.fitness.distance, leader.fitness.steps, leader.program
+            );
+            last = Some(leader.fitness);
+        }
+    });
+    println!("{}", best.program);
+    if best.fitness.distance > 0 {
+        anyhow::bail!(
+            "no exact match found; the best candidate is {} edits away",
+            best.fitness.distance
+        );
+    } else if best.fitness.failed {
+        anyhow::bail!("no exact match found; the best candidate's output matches but it fails");
+    }
+    Ok(())
+}
+
+fn fuzz(args: FuzzArgs) -> Result<(), anyhow::Error> {
+    let sandbox = args
+        .deny
+        .iter()
+        .fold(Sandbox::new(), |sandbox, &cap| sandbox.deny(cap));
+    let mut fuzzer = Fuzzer::new()
+        .runs(args.runs)
+        .input_len(args.min_len, args.max_len)
+        .limits(Limits::steps(args.steps))
+        .sandbox(sandbox)
+        .extensions(args.extensions);
+    if let Some(alphabet) = args.alphabet {
+        fuzzer = fuzzer.alphabet(alphabet);
+    }
+    if let Some(seed) = args.seed {
+        fuzzer = fuzzer.seed(seed);
+    }
+    let report = fuzzer.run(&read_path_or_stdin(&args.file)?)?;
+    for finding in &report.findings {
+        println!(
+            "{:>6}  {} (input \"{}\", {} steps)",
+            finding.count,
+            finding.problem,
+            finding.input.escape_ascii(),
+            finding.steps
+        );
+    }
+    let failed: u64 = report.findings.iter().map(|finding| finding.count).sum();
+    if failed > 0 {
+        anyhow::bail!("{failed} of {} runs failed", report.runs);
+    }
+    println!("all {} runs halted", report.runs);
+    Ok(())
+}
+
+fn run(cli: RunArgs) -> Result<(), anyhow::Error> {
+    if cli.expect_output.is_some() || cli.expect_exit.is_some() {
+        return run_expecting(cli);
+    }
+    let io = if cli.emit.is_some() {
+        Box::new(StdIo::default())
+    } else {
+        build_io(&cli)?
+    };
+    let io = log_io(&cli, io)?;
+    run_with_io(cli, io)
+}
+
+/// Wraps the I/O to log it to --io-log, if given.
+fn log_io(cli: &RunArgs, io: Box<dyn Io>) -> Result<Box<dyn Io>, anyhow::Error> {
+    Ok(match &cli.io_log {
+        Some(path) => Box::new(EventLogIo::new(io, File::create(path)?)),
+        None => io,
+    })
+}
+
+/// A writer into a buffer that can still be read after the writer has been given away.
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl std::io::Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Runs the program, then checks its output and exit status against --expect-output and
+/// --expect-exit.
+fn run_expecting(cli: RunArgs) -> Result<(), anyhow::Error> {
+    let expected_output = match &cli.expect_output {
+        Some(expected) if Path::new(expected).is_file() => Some(std::fs::read(expected)?),
+        Some(expected) => Some(expected.clone().into_bytes()),
+        None => None,
+    };
+    let expected_exit = cli.expect_exit;
+    let output = SharedBuffer::default();
+    let io: Box<dyn Io> = match expected_output {
+        Some(_) if !cli.stdin.is_empty() => Box::new(StreamIo::new(
+            Cursor::new(cli.stdin.concat()),
+            output.clone(),
+        )),
+        Some(_) => Box::new(StreamIo::new(UnbufferedStdin, output.clone())),
+        None => build_io(&cli)?,
+    };
+    let io = log_io(&cli, io)?;
+    let result = run_with_io(cli, io);
+
+    let mut failures = Vec::new();
+    if let Some(expected) = expected_output {
+        let diff = unified(&expected, &output.0.borrow(), "expected", "actual");
+        if !diff.is_empty() {
+            print!("{diff}");
+            failures.push(String::from("output differs from expected"));
+        }
+    }
+    failures.extend(exit_failure(expected_exit, result)?);
+    if !failures.is_empty() {
+        anyhow::bail!("{}", failures.join("; "));
+    }
+    Ok(())
+}
+
+/// How a run's result fails --expect-exit, if it does. Without --expect-exit a failed run's
+/// error is passed on as it is.
+fn exit_failure(
+    expected: Option<i32>,
+    result: Result<(), anyhow::Error>,
+) -> Result<Option<String>, anyhow::Error> {
+    Ok(match (expected, result) {
+        (None, Err(err)) => return Err(err),
+        (Some(1), Err(_)) | (None | Some(0), Ok(())) => None,
+        (Some(status), Err(err)) => Some(format!("expected exit status {status}, got 1: {err}")),
+        (Some(status), Ok(())) => Some(format!("expected exit status {status}, got 0")),
+    })
+}
+
+fn run_with_io(cli: RunArgs, io: Box<dyn Io>) -> Result<(), anyhow::Error> {
+    let program_path = cli.input.file.clone();
+    let code = match (&cli.input.file, &cli.input.code) {
+        (Some(input_file), _) => Some(read_to_string(input_file)?),
+        (None, code) => code.clone(),
+    };
+    if let Some(path) = &cli.record {
+        return run_recording(&cli, code.unwrap(), io, path);
+    }
+    let mut program = match (&cli.input.load_state, code) {
+        (Some(path), _) => {
+            let mut program = state::load(&read_to_string(path)?, io)?;
+            restrict(&mut program, &cli.sandbox, cli.seed);
+            program
+        }
+        (None, code) => configure(&cli, code.unwrap()).io(io).build()?,
+    };
+    match cli.emit {
+        Some(Emit::Ir) => {
+            print!("{}", ir::emit(&program));
+            return Ok(());
+        }
+        Some(Emit::Aquarium) => {
+            let color = match cli.color {
+                ColorChoice::Auto => stdout().is_terminal(),
+                ColorChoice::Always => true,
+                ColorChoice::Never => false,
+            };
+            let options = RenderOptions {
+                color,
+                dead_code: cli.dead_code,
+                ..RenderOptions::default()
+            };
+            print!("{}", render(&program, &options));
+            return Ok(());
+        }
+        Some(Emit::Braille) => {
+            print!("{}", render_compact(&program, Compact::Braille));
+            return Ok(());
+        }
+        Some(Emit::Blocks) => {
+            print!("{}", render_compact(&program, Compact::HalfBlock));
+            return Ok(());
+        }
+        None => {}
+    }
+    if cli.watch {
+        return watch(&cli, &program_path.unwrap(), program);
+    }
+    if let Some(path) = &cli.save_state {
+        return run_saving_state(program, path, cli.speed);
+    }
+    if let Some(dir) = &cli.frames {
+        return run_dumping_frames(program, dir, cli.frame_every, cli.speed);
+    }
+    if let Some(dir) = &cli.checkpoint_dir {
+        let checkpointer = checkpointer(dir, &cli.checkpointing)?;
+        return run_checkpointing(program, checkpointer, cli.speed);
+    }
+    if let Some(path) = &cli.stats {
+        let mut stats = Stats::new(&program).sampling(cli.stats_sample);
+        let result = stats.run(&mut program);
+        program.io_mut().flush()?;
+        write_stats(&stats, path, cli.stats_format)?;
+        result?;
+        return Ok(());
+    }
+    let result = run_at(&mut program, cli.speed);
+    program.io_mut().flush()?;
+    result?;
+    Ok(())
+}
+
+/// Runs the program until it stops, at `speed` steps per second if set.
+fn run_at<I: Io>(program: &mut Program<I>, speed: Option<f64>) -> Result<Status, RuntimeError> {
+    let Some(speed) = speed else {
+        return program.run();
+    };
+    let mut pacer = Some(Pacer::new(speed));
+    loop {
+        pace(&mut pacer, program)?;
+        match program.step()? {
+            Status::Running => {}
+            status => return Ok(status),
+        }
+    }
+}
+
+/// With a pacer, flushes output and sleeps if the next step isn't due yet. Returns whether it
+/// slept.
+fn pace<I: Io>(pacer: &mut Option<Pacer>, program: &mut Program<I>) -> std::io::Result<bool> {
+    match pacer.as_mut().and_then(Pacer::delay) {
+        Some(delay) => {
+            program.io_mut().flush()?;
+            thread::sleep(delay);
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Runs the program, writing a frame before the first step, after every `every` steps while it
+/// runs, and once it stops.
+fn run_dumping_frames(
+    mut program: Program<Box<dyn Io>>,
+    dir: &Path,
+    every: u64,
+    speed: Option<f64>,
+) -> Result<(), anyhow::Error> {
+    create_dir_all(dir)?;
+    let mut frame = 0;
+    let mut dump = |program: &Program<Box<dyn Io>>| {
+        let path = dir.join(format!("frame-{frame:06}.svg"));
+        frame += 1;
+        write(path, render_svg(program))
+    };
+    dump(&program)?;
+    let mut pacer = speed.map(Pacer::new);
+    let result = loop {
+        pace(&mut pacer, &mut program)?;
+        match program.step() {
+            Ok(Status::Running) => {}
+            Ok(_) => break Ok(()),
+            Err(err) => break Err(err),
+        }
+        if program.steps().is_multiple_of(every) {
+            dump(&program)?;
+        }
+    };
+    // Also show where the program stopped, and for errors the step that failed.
+    dump(&program)?;
+    program.io_mut().flush()?;
+    Ok(result?)
+}
+
+/// The checkpointer the options describe, writing to `dir`.
+fn checkpointer(
+    dir: &Path,
+    args: &CheckpointArgs,
+) -> Result<Checkpointer<DirStore>, anyhow::Error> {
+    Ok(
+        Checkpointer::new(args.checkpoint_every, DirStore::new(dir)?)
+            .keep(args.checkpoint_keep as usize),
+    )
+}
+
+/// Runs the program, handing its state to `checkpointer` after every step.
+fn run_checkpointing(
+    mut program: Program<Box<dyn Io>>,
+    mut checkpointer: Checkpointer<DirStore>,
+    speed: Option<f64>,
+) -> Result<(), anyhow::Error> {
+    let mut pacer = speed.map(Pacer::new);
+    let result = loop {
+        pace(&mut pacer, &mut program)?;
+        match program.step() {
+            Ok(Status::Running) => {}
+            Ok(_) => break Ok(()),
+            Err(err) => break Err(err),
+        }
+        checkpointer.after_step(&program)?;
+    };
+    program.io_mut().flush()?;
+    Ok(result?)
+}
+
+/// Continues a run from a checkpoint in `dir`, or lists them.
+fn resume(args: ResumeArgs) -> Result<(), anyhow::Error> {
+    let dir = &args.dir;
+    if !dir.is_dir() {
+        anyhow::bail!("no checkpoint directory {}", dir.display());
+    }
+    let store = DirStore::new(dir)?;
+    let numbers = store.numbers()?;
+    if args.list {
+        for number in numbers {
+            let program = state::load(&store.load(number)?, MockIo::default())?;
+            println!("{number:>6}  step {}", program.steps());
+        }
+        return Ok(());
+    }
+    let Some(number) = args.checkpoint.or(numbers.last().copied()) else {
+        anyhow::bail!("no checkpoints in {}", dir.display());
+    };
+    if !numbers.contains(&number) {
+        anyhow::bail!("no checkpoint {number} in {}", dir.display());
+    }
+    let mut program = state::load(&store.load(number)?, terminal_io())?;
+    restrict(&mut program, &args.sandbox, args.seed);
+    if let Some(out) = &args.checkpoint_dir {
+        let mut checkpointer = checkpointer(out, &args.checkpointing)?;
+        // Carrying on in the same directory, the checkpoints up to this one are the oldest
+        // kept; later ones are overwritten as the run reaches them again.
+        if out.canonicalize()? == dir.canonicalize()? {
+            checkpointer = checkpointer.existing(numbers.into_iter().filter(|&n| n <= number));
+        }
+        return run_checkpointing(program, checkpointer, None);
+    }
+    let result = program.run();
+    program.io_mut().flush()?;
+    result?;
+    Ok(())
+}
+
+/// Writes the counters of a finished run, even one that failed.
+fn write_stats(stats: &Stats, path: &Path, format: StatsFormat) -> Result<(), anyhow::Error> {
+    let writer: Box<dyn std::io::Write> = if path == Path::new("-") {
+        Box::new(stderr())
+    } else {
+        Box::new(BufWriter::new(File::create(path)?))
+    };
+    let mut sink: Box<dyn StatsSink> = match format {
+        StatsFormat::Text => Box::new(TextReport::new(writer)),
+        StatsFormat::Csv => Box::new(CsvSink::new(writer)),
     };
-    let mut program = Program::new(&code)?;
+    stats.report(sink.as_mut())?;
+    Ok(())
+}
+
+/// Runs the program and writes a replay bundle, whether or not the run succeeds.
+fn run_recording(
+    cli: &RunArgs,
+    code: String,
+    io: Box<dyn Io>,
+    path: &Path,
+) -> Result<(), anyhow::Error> {
+    let seed = cli.seed.unwrap_or_else(rand::random);
+    let mut program = configure(cli, code.clone())
+        .seed(seed)
+        .io(RecordingIo::new(io))
+        .build()?;
+    let result = run_at(&mut program, cli.speed);
+    let (mut io, input) = program.into_io().into_inner();
+    io.flush()?;
+    let bundle = Bundle {
+        seed,
+        args: program_args(cli),
+        code,
+        input,
+    };
+    write(path, bundle.to_bytes())?;
+    result?;
+    Ok(())
+}
+
+/// The options [`configure`] reads, as arguments that parse back to the same settings.
+fn program_args(cli: &RunArgs) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut option = |flag: &str, value: String| {
+        args.push(flag.to_string());
+        args.push(value);
+    };
+    for arg in &cli.args {
+        option("--arg", arg.clone());
+    }
+    if !cli.init_stack.is_empty() {
+        option("--init-stack", cli.init_stack.iter().join(","));
+    }
+    for (name, value) in &cli.init_acc {
+        option("--init-acc", format!("{name}={value}"));
+    }
+    if let Some(font) = cli.font {
+        option("--font", font.name().to_string());
+    }
+    if let Some(spec) = cli.spec {
+        option("--spec", spec.to_string());
+    }
+    if let Some(edges) = cli.edges {
+        option("--edges", EdgeMode::from(edges).name().to_string());
+    }
+    if let Some(zero) = cli.zero {
+        option("--zero", zero.to_string());
+    }
+    if let Some(skip) = cli.skip {
+        option("--skip", skip.to_string());
+    }
+    if let Some(tunnel) = cli.tunnel {
+        option("--tunnel", TunnelMode::from(tunnel).name().to_string());
+    }
+    if let Some(cycle) = cli.cycle {
+        option("--cycle", cycle.to_string());
+    }
+    if let Some(order) = cli.order {
+        option("--order", TankOrder::from(order).name().to_string());
+    }
+    let sandbox = &cli.sandbox;
+    for cap in &sandbox.deny {
+        option("--deny", cap.name().to_string());
+    }
+    if sandbox.on_violation != OnViolation::Error {
+        option("--on-violation", String::from("halt"));
+    }
+    if let Some(limit) = sandbox.max_output_bytes {
+        option("--max-output-bytes", limit.to_string());
+    }
+    if let Some(quota) = sandbox.output_quota {
+        option("--output-quota", quota.to_string());
+    }
+    if let Some(quota) = sandbox.max_input_bytes {
+        option("--max-input-bytes", quota.to_string());
+    }
+    if let Some(quota) = sandbox.max_random_calls {
+        option("--max-random-calls", quota.to_string());
+    }
+    if let Some(quota) = sandbox.max_hops {
+        option("--max-hops", quota.to_string());
+    }
+    if cli.extensions {
+        args.push(String::from("--extensions"));
+    }
+    args
+}
+
+/// Reruns a recorded program with its recorded seed, options and input.
+fn replay(path: &Path) -> Result<(), anyhow::Error> {
+    let bundle = Bundle::parse(&std::fs::read(path)?)?;
+    let argv = ["pufferfish".to_string()]
+        .into_iter()
+        .chain(bundle.args)
+        .chain(["--seed".to_string(), bundle.seed.to_string()])
+        .chain(["--".to_string(), bundle.code]);
+    let cli = Cli::try_parse_from(argv)?;
+    let io = Box::new(StreamIo::new(Cursor::new(bundle.input), stdout()));
+    run_with_io(cli.run, io)
+}
+
+/// Bytes of output shown on each side of a difference between two binaries.
+const COMPARE_CONTEXT: usize = 16;
+
+/// Replays a bundle with this binary and `other`, each in its own process, and fails
+/// describing the first thing their runs disagree on.
+fn compare_replays(bundle: &Path, other: &Path) -> Result<(), anyhow::Error> {
+    // Check the bundle here, so a bad one isn't reported as both binaries failing alike.
+    Bundle::parse(&std::fs::read(bundle)?)?;
+    let replay = |binary: &Path| {
+        process::Command::new(binary)
+            .arg("replay")
+            .arg(bundle)
+            .stdin(process::Stdio::null())
+            .stderr(process::Stdio::null())
+            .output()
+            .map_err(|e| anyhow::anyhow!("couldn't run {}: {e}", binary.display()))
+    };
+    let this = replay(&std::env::current_exe()?)?;
+    let that = replay(other)?;
+    let name = other.display();
+    if let Some(at) = first_difference(&this.stdout, &that.stdout) {
+        let around = |output: &[u8]| {
+            let start = at.saturating_sub(COMPARE_CONTEXT);
+            let end = output.len().min(at + COMPARE_CONTEXT);
+            output
+                .get(start..end)
+                .unwrap_or_default()
+                .escape_ascii()
+                .to_string()
+        };
+        anyhow::bail!(
+            "output differs at byte {at}:\n  this binary: \"{}\" ({} bytes)\n  {name}: \"{}\" ({} bytes)",
+            around(&this.stdout),
+            this.stdout.len(),
+            around(&that.stdout),
+            that.stdout.len()
+        );
+    }
+    let status = |status: process::ExitStatus| {
+        status
+            .code()
+            .map_or_else(|| String::from("a signal"), |code| format!("status {code}"))
+    };
+    if this.status.code() != that.status.code() {
+        anyhow::bail!(
+            "same output, but this binary exited with {} and {name} with {}",
+            status(this.status),
+            status(that.status)
+        );
+    }
+    println!(
+        "both binaries wrote the same {} bytes and exited with {}",
+        this.stdout.len(),
+        status(this.status)
+    );
+    Ok(())
+}
+
+/// Serves a debugger for the program in `path` to one client at a time. The program keeps
+/// its state between connections, so a client can disconnect and pick up where it left off.
+fn debug(
+    path: &Path,
+    addr: &str,
+    input: String,
+    seed: Option<u64>,
+    extensions: bool,
+    timeline: Option<usize>,
+) -> Result<(), anyhow::Error> {
+    let mut builder = ProgramBuilder::new(read_path_or_stdin(path)?)
+        .io(MockIo::new(input))
+        .extensions(extensions);
+    if let Some(seed) = seed {
+        builder = builder.seed(seed);
+    }
+    let mut debugger = Debugger::new(builder.build()?);
+    if let Some(capacity) = timeline {
+        debugger.record_timeline(capacity);
+    }
+    let listener = TcpListener::bind(addr)?;
+    eprintln!("listening on {}", listener.local_addr()?);
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let reader = BufReader::new(stream.try_clone()?);
+        if let Err(e) = debug_server::serve(&mut debugger, reader, stream) {
+            eprintln!("connection closed: {e}");
+        }
+    }
+    Ok(())
+}
+
+/// Shows each snapshot waiting for review as a diff from the snapshot it would replace, and
+/// accepts or rejects it as the flags say or, without them, as the user answers.
+fn review(dir: &Path, accept_all: bool, reject_all: bool) -> Result<(), anyhow::Error> {
+    let pending = snapshot::pending(dir)?;
+    if pending.is_empty() {
+        println!("no snapshots to review");
+        return Ok(());
+    }
+    let (mut accepted, mut rejected) = (0, 0);
+    for path in &pending {
+        let new = std::fs::read(path)?;
+        let old_path = snapshot::snapshot_path(path);
+        let old = std::fs::read(&old_path).unwrap_or_default();
+        print!(
+            "{}",
+            unified(&old, &new, &old_path.display().to_string(), "new")
+        );
+        let answer = if accept_all || reject_all {
+            Some(accept_all)
+        } else {
+            loop {
+                eprint!("accept {}? [y/n/s] ", old_path.display());
+                let mut answer = String::new();
+                if stdin().read_line(&mut answer)? == 0 {
+                    anyhow::bail!("review stopped at the end of input");
+                }
+                match answer.trim() {
+                    "y" => break Some(true),
+                    "n" => break Some(false),
+                    "s" => break None,
+                    _ => {}
+                }
+            }
+        };
+        let Some(accept) = answer else {
+            continue;
+        };
+        if accept {
+            snapshot::accept(path)?;
+            accepted += 1;
+        } else {
+            snapshot::reject(path)?;
+            rejected += 1;
+        }
+    }
+    let skipped = pending.len() - accepted - rejected;
+    println!("{accepted} accepted, {rejected} rejected, {skipped} skipped");
+    Ok(())
+}
+
+/// How many distinct outputs and errors a sweep summary lists.
+const SWEEP_SHOWN: usize = 10;
+
+fn print_sweep(report: &SweepReport) {
+    println!(
+        "{} runs: {} halted, {} looped, {} errored",
+        report.runs, report.halted, report.looped, report.errored
+    );
+    let more = |len: usize| {
+        if len > SWEEP_SHOWN {
+            println!("  and {} more", len - SWEEP_SHOWN);
+        }
+    };
+    println!("outputs ({}):", report.outputs.len());
+    for output in report.outputs.iter().take(SWEEP_SHOWN) {
+        println!(
+            "  {:>6}  \"{}\" (first seed {})",
+            output.count,
+            output.value.escape_ascii(),
+            output.first_seed
+        );
+    }
+    more(report.outputs.len());
+    if !report.errors.is_empty() {
+        println!("errors ({}):", report.errors.len());
+        for error in report.errors.iter().take(SWEEP_SHOWN) {
+            println!(
+                "  {:>6}  {} (first seed {})",
+                error.count, error.value, error.first_seed
+            );
+        }
+        more(report.errors.len());
+    }
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Makes Ctrl-C set [`INTERRUPTED`] instead of killing the process.
+#[cfg(unix)]
+fn catch_interrupts() {
+    extern "C" fn on_interrupt(_: libc::c_int) {
+        INTERRUPTED.store(true, Ordering::Relaxed);
+    }
+    // SAFETY: the handler only stores to an atomic, which is async-signal-safe.
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            on_interrupt as *const () as libc::sighandler_t,
+        );
+    }
+}
+
+#[cfg(not(unix))]
+fn catch_interrupts() {}
+
+/// Runs the program until it halts, fails or is interrupted, then saves its state.
+fn run_saving_state(
+    mut program: Program<Box<dyn Io>>,
+    path: &Path,
+    speed: Option<f64>,
+) -> Result<(), anyhow::Error> {
+    catch_interrupts();
+    let mut pacer = speed.map(Pacer::new);
+    let result = loop {
+        if INTERRUPTED.load(Ordering::Relaxed) {
+            eprintln!(
+                "pufferfish: interrupted; saving state to {}",
+                path.display()
+            );
+            break Ok(());
+        }
+        pace(&mut pacer, &mut program)?;
+        match program.step() {
+            Ok(Status::Running) => {}
+            Ok(Status::Halted(_) | Status::OutOfFuel) => break Ok(()),
+            Err(err) => break Err(err),
+        }
+    };
+    program.io_mut().flush()?;
+    write(path, state::save(&program))?;
+    Ok(result?)
+}
+
+/// Applies every option that shapes the program itself, but not its I/O.
+fn configure(cli: &RunArgs, code: String) -> ProgramBuilder {
+    let mut builder = Program::builder(code)
+        .extensions(cli.extensions)
+        .args(cli.args.clone())
+        .stack(cli.init_stack.clone());
+    if let Some(font) = cli.font {
+        builder = builder.font(font);
+    }
+    if let Some(spec) = cli.spec {
+        builder = builder.spec(spec);
+    }
+    if let Some(edges) = cli.edges {
+        builder = builder.edges(edges.into());
+    }
+    if let Some(zero) = cli.zero {
+        builder = builder.zero(zero);
+    }
+    if let Some(skip) = cli.skip {
+        builder = builder.skip(skip);
+    }
+    if let Some(tunnel) = cli.tunnel {
+        builder = builder.tunnel(tunnel.into());
+    }
+    if let Some(cycle) = cli.cycle {
+        builder = builder.cycle(cycle);
+    }
+    if let Some(order) = cli.order {
+        builder = builder.order(order.into());
+    }
+    for (name, value) in &cli.init_acc {
+        builder = builder.accumulator(name.clone(), *value);
+    }
+    if let Some(seed) = cli.seed {
+        builder = builder.seed(seed);
+    }
+    builder
+        .output_limit(cli.sandbox.max_output_bytes)
+        .sandbox(sandbox(&cli.sandbox))
+}
+
+/// The sandbox the options describe.
+fn sandbox(args: &SandboxArgs) -> Sandbox {
+    args.deny
+        .iter()
+        .fold(Sandbox::new(), |sandbox, &cap| sandbox.deny(cap))
+        .quotas(Quotas {
+            input_bytes: args.max_input_bytes,
+            output_bytes: args.output_quota,
+            random_calls: args.max_random_calls,
+            hops: args.max_hops,
+        })
+        .on_violation(args.on_violation.into())
+}
+
+/// Puts a program loaded from a save state or checkpoint, which keeps no sandbox, limits or
+/// seed, under the ones the options give.
+fn restrict<I: Io>(program: &mut Program<I>, args: &SandboxArgs, seed: Option<u64>) {
+    program.set_sandbox(sandbox(args));
+    program.set_output_limit(args.max_output_bytes);
+    if seed.is_some() {
+        program.set_seed(seed);
+    }
+}
+
+/// How many steps to run between checks for changes to the program file.
+const WATCH_STEPS: usize = 10_000;
+const WATCH_POLL: Duration = Duration::from_millis(200);
+
+/// Runs the program, reloading it whenever its file changes, until interrupted.
+///
+/// Halting or a runtime error doesn't end the run; the program waits for its file to change
+/// and then continues under the new code.
+fn watch(
+    cli: &RunArgs,
+    path: &Path,
+    mut program: Program<Box<dyn Io>>,
+) -> Result<(), anyhow::Error> {
+    let modified = || metadata(path).and_then(|m| m.modified()).ok();
+    let mut last_modified = modified();
+    let mut failed = false;
+    let mut pacer = cli.speed.map(Pacer::new);
     loop {
-        program.step();
+        if failed || program.status() != Status::Running {
+            program.io_mut().flush()?;
+            thread::sleep(WATCH_POLL);
+        } else {
+            for _ in 0..WATCH_STEPS {
+                // Check for changes after every pause, however slowly the program runs.
+                let paused = pace(&mut pacer, &mut program)?;
+                match program.step() {
+                    Ok(Status::Running) if paused => break,
+                    Ok(Status::Running) => {}
+                    Ok(Status::Halted(_) | Status::OutOfFuel) => break,
+                    Err(err) => {
+                        eprintln!(
+                            "pufferfish: {err}; waiting for {} to change",
+                            path.display()
+                        );
+                        failed = true;
+                        break;
+                    }
+                }
+            }
+        }
+        let now = modified();
+        if now == last_modified {
+            continue;
+        }
+        last_modified = now;
+        let code = read_to_string(path)?;
+        match configure(cli, code).io(MockIo::default()).build() {
+            Ok(fresh) => {
+                let reload = program.reload(fresh, cli.restart_on_reload);
+                failed = false;
+                eprintln!(
+                    "pufferfish: reloaded {}: {} tanks kept, {} changed, {} added, {} removed",
+                    path.display(),
+                    reload.kept,
+                    reload.changed,
+                    reload.added,
+                    reload.removed
+                );
+            }
+            Err(err) => eprintln!("pufferfish: not reloading {}: {err}", path.display()),
+        }
+    }
+}
+
+fn build_io(cli: &RunArgs) -> Result<Box<dyn Io>, anyhow::Error> {
+    Ok(if !cli.stdin.is_empty() {
+        Box::new(StreamIo::new(Cursor::new(cli.stdin.concat()), stdout()))
+    } else if cli.raw_input {
+        raw_io()?
+    } else {
+        terminal_io()
+    })
+}
+
+/// The I/O for a program talking to the user: line editing at a terminal, and otherwise
+/// standard input and batched standard output.
+fn terminal_io() -> Box<dyn Io> {
+    if cfg!(all(unix, feature = "line-editor")) && stdin().is_terminal() {
+        interactive_io()
+    } else {
+        Box::new(BufferedIo::new(StdIo::default()))
+    }
+}
+
+#[cfg(all(unix, feature = "raw-input"))]
+fn raw_io() -> Result<Box<dyn Io>, anyhow::Error> {
+    use anyhow::Context;
+
+    let io = pufferfish::raw_input::RawIo::new(stdout())
+        .context("--raw-input requires standard input to be a terminal")?;
+    Ok(Box::new(io))
+}
+
+#[cfg(not(all(unix, feature = "raw-input")))]
+fn raw_io() -> Result<Box<dyn Io>, anyhow::Error> {
+    anyhow::bail!("this build of pufferfish does not support --raw-input")
+}
+
+#[cfg(all(unix, feature = "tty"))]
+fn design(file: &Path, dict: &Path, swizzles: usize, limit: usize) -> Result<(), anyhow::Error> {
+    use pufferfish::{
+        designer::Designer,
+        tty::{RawMode, raise_interrupt},
+    };
+
+    if !stdin().is_terminal() {
+        anyhow::bail!("design needs a terminal on standard input");
+    }
+    let words = read_to_string(dict)?;
+    let mut designer = Designer::new(words.split_whitespace())
+        .swizzles(swizzles)
+        .shown(limit);
+    let chosen = {
+        let raw = RawMode::enable()?;
+        raw.install_exit_hooks();
+        designer.run(&mut UnbufferedStdin, &mut stdout())
+    };
+    println!();
+    let word = match chosen {
+        Ok(Some(word)) => word,
+        Ok(None) => return Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {
+            raise_interrupt();
+            return Err(err.into());
+        }
+        Err(err) => return Err(err.into()),
+    };
+    // Keep the word apart from whatever the file ends with.
+    let existing = read_to_string(file).unwrap_or_default();
+    let separator = if existing.is_empty() || existing.ends_with(char::is_whitespace) {
+        ""
+    } else {
+        " "
+    };
+    let mut out = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file)?;
+    std::io::Write::write_all(&mut out, format!("{separator}{word}\n").as_bytes())?;
+    println!("added {word} to {}", file.display());
+    Ok(())
+}
+
+#[cfg(not(all(unix, feature = "tty")))]
+fn design(_: &Path, _: &Path, _: usize, _: usize) -> Result<(), anyhow::Error> {
+    anyhow::bail!("this build of pufferfish does not support design")
+}
+
+#[cfg(all(unix, feature = "line-editor"))]
+fn interactive_io() -> Box<dyn Io> {
+    Box::new(pufferfish::line_editor::LineIo::new(stdout(), "> "))
+}
+
+#[cfg(not(all(unix, feature = "line-editor")))]
+fn interactive_io() -> Box<dyn Io> {
+    Box::new(StdIo::default())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_exit_failure() {
+        let failed = || Err(anyhow::anyhow!("stack underflow"));
+        assert!(exit_failure(None, Ok(())).unwrap().is_none());
+        assert!(exit_failure(Some(0), Ok(())).unwrap().is_none());
+        assert!(exit_failure(Some(1), failed()).unwrap().is_none());
+        assert_eq!(
+            exit_failure(None, failed()).unwrap_err().to_string(),
+            "stack underflow"
+        );
+        assert_eq!(
+            exit_failure(Some(0), failed()).unwrap().as_deref(),
+            Some("expected exit status 0, got 1: stack underflow")
+        );
+        assert_eq!(
+            exit_failure(Some(1), Ok(())).unwrap().as_deref(),
+            Some("expected exit status 1, got 0")
+        );
+        assert_eq!(
+            exit_failure(Some(2), failed()).unwrap().as_deref(),
+            Some("expected exit status 2, got 1: stack underflow")
+        );
+        assert_eq!(
+            exit_failure(Some(2), Ok(())).unwrap().as_deref(),
+            Some("expected exit status 2, got 0")
+        );
     }
 }
@@ -0,0 +1,172 @@
+//! Counters for monitoring a service that runs programs on others' behalf, rendered in the
+//! Prometheus text exposition format for a `/metrics` endpoint.
+//!
+//! Recording is lock-free, so one [`Metrics`] can be shared by every worker thread.
+
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use crate::{
+    program::{HaltReason, RuntimeError},
+    runner::{Ending, Outcome},
+};
+
+/// Upper bounds, in seconds, of the run duration histogram's buckets.
+const BUCKETS: [f64; 7] = [0.0001, 0.001, 0.01, 0.1, 1.0, 10.0, 60.0];
+
+/// Error kinds besides the runtime errors: programs that failed to build, and interpreter
+/// panics.
+const OTHER_ERRORS: [&str; 2] = ["build", "panic"];
+const ERROR_KINDS: usize = OTHER_ERRORS.len() + RuntimeError::KINDS.len();
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    executions: AtomicU64,
+    steps: AtomicU64,
+    timeouts: AtomicU64,
+    errors: [AtomicU64; ERROR_KINDS],
+    /// Runs per bucket, not yet cumulative; the last entry counts runs above every bound.
+    durations: [AtomicU64; BUCKETS.len() + 1],
+    duration_nanos: AtomicU64,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records one run, as returned by [`crate::runner::run_captured`], that took `duration`.
+    /// Runs that hit their step limit count as timeouts.
+    pub fn record(&self, result: &Result<Outcome, crate::Error>, duration: Duration) {
+        self.executions.fetch_add(1, Ordering::Relaxed);
+        let error = match result {
+            Err(_) => Some("build"),
+            Ok(outcome) => {
+                self.steps.fetch_add(outcome.steps, Ordering::Relaxed);
+                match &outcome.ending {
+                    Ending::Halted(HaltReason::StepLimit) => {
+                        self.timeouts.fetch_add(1, Ordering::Relaxed);
+                        None
+                    }
+                    Ending::Halted(_) => None,
+                    Ending::Error(err) => Some(err.kind()),
+                    Ending::Panic(_) => Some("panic"),
+                }
+            }
+        };
+        if let Some(kind) = error {
+            let index = error_kinds().position(|k| k == kind).unwrap();
+            self.errors[index].fetch_add(1, Ordering::Relaxed);
+        }
+        let seconds = duration.as_secs_f64();
+        let bucket = BUCKETS
+            .iter()
+            .position(|&bound| seconds <= bound)
+            .unwrap_or(BUCKETS.len());
+        self.durations[bucket].fetch_add(1, Ordering::Relaxed);
+        self.duration_nanos.fetch_add(
+            duration.as_nanos().try_into().unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+    }
+
+    pub fn render(&self) -> String {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let mut out = String::new();
+        let mut counter = |name: &str, help: &str, value: u64| {
+            writeln!(out, "# HELP pufferfish_{name} {help}").unwrap();
+            writeln!(out, "# TYPE pufferfish_{name} counter").unwrap();
+            writeln!(out, "pufferfish_{name} {value}").unwrap();
+        };
+        counter(
+            "executions_total",
+            "Programs run, including ones that failed to build.",
+            load(&self.executions),
+        );
+        counter("steps_total", "Steps executed.", load(&self.steps));
+        counter(
+            "timeouts_total",
+            "Runs stopped by their step limit.",
+            load(&self.timeouts),
+        );
+
+        writeln!(
+            out,
+            "# HELP pufferfish_errors_total Runs that ended in an error, by kind."
+        )
+        .unwrap();
+        writeln!(out, "# TYPE pufferfish_errors_total counter").unwrap();
+        for (kind, count) in error_kinds().zip(&self.errors) {
+            writeln!(
+                out,
+                "pufferfish_errors_total{{kind=\"{kind}\"}} {}",
+                load(count)
+            )
+            .unwrap();
+        }
+
+        let name = "pufferfish_run_duration_seconds";
+        writeln!(out, "# HELP {name} Wall-clock time per run.").unwrap();
+        writeln!(out, "# TYPE {name} histogram").unwrap();
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(&self.durations) {
+            cumulative += load(count);
+            writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {cumulative}").unwrap();
+        }
+        cumulative += load(&self.durations[BUCKETS.len()]);
+        writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {cumulative}").unwrap();
+        writeln!(
+            out,
+            "{name}_sum {}",
+            load(&self.duration_nanos) as f64 / 1e9
+        )
+        .unwrap();
+        writeln!(out, "{name}_count {cumulative}").unwrap();
+        out
+    }
+}
+
+fn error_kinds() -> impl Iterator<Item = &'static str> {
+    OTHER_ERRORS.into_iter().chain(RuntimeError::KINDS)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        builder::ProgramBuilder,
+        runner::{Limits, run_captured},
+    };
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        let run = |code: &str| run_captured(ProgramBuilder::new(code), "", Limits::steps(10));
+        metrics.record(&run("ebbbbbbbbb"), Duration::from_micros(50));
+        metrics.record(&run("ab"), Duration::from_millis(5));
+        metrics.record(&run("bbbbbbbbb"), Duration::from_secs(100));
+        metrics.record(&run("a a"), Duration::ZERO);
+        let text = metrics.render();
+        for line in [
+            "pufferfish_executions_total 4",
+            "pufferfish_steps_total 12",
+            "pufferfish_timeouts_total 1",
+            "pufferfish_errors_total{kind=\"build\"} 1",
+            "pufferfish_errors_total{kind=\"unknown_call\"} 1",
+            "pufferfish_errors_total{kind=\"panic\"} 0",
+            "pufferfish_run_duration_seconds_bucket{le=\"0.0001\"} 2",
+            "pufferfish_run_duration_seconds_bucket{le=\"0.01\"} 3",
+            "pufferfish_run_duration_seconds_bucket{le=\"60\"} 3",
+            "pufferfish_run_duration_seconds_bucket{le=\"+Inf\"} 4",
+            "pufferfish_run_duration_seconds_count 4",
+        ] {
+            assert!(
+                text.lines().any(|l| l == line),
+                "missing {line:?} in\n{text}"
+            );
+        }
+    }
+}
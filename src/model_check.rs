@@ -0,0 +1,330 @@
+//! Following every direction `y` calls can pick, to see what a program can do on any of them.
+//!
+//! A [`ModelChecker`] runs a program breadth first from its start, splitting into four copies
+//! wherever a `y` call is about to run, one for each direction it could pick. Copies that
+//! reach a state another already reached, with the same input left and the same output
+//! written, are merged, so a program whose choices lead back to where they started is
+//! explored once rather than forever. That also shows which branches loop: a loop in the
+//! states reached is a branch that never stops.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::{Hash, Hasher},
+};
+
+use crate::{
+    builder::ProgramBuilder,
+    io::MockIo,
+    program::{CallKind, Direction, HaltReason, Program, Status},
+    stats::step_opcode,
+};
+
+/// How a branch stopped.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Terminal {
+    Halted(HaltReason),
+    /// A runtime error, as its message.
+    Error(String),
+}
+
+/// A way some branch stopped, and what it had written.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Outcome {
+    pub ending: Terminal,
+    pub output: Vec<u8>,
+}
+
+/// What a model check saw.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ModelReport {
+    /// Distinct states reached.
+    pub states: usize,
+    /// States at which a `y` call split the run.
+    pub choices: usize,
+    /// Every distinct way a branch stopped, in the order they were found, so the first took
+    /// the fewest steps.
+    pub outcomes: Vec<Outcome>,
+    /// Some branch runs forever.
+    pub loops: bool,
+    /// Some branch runs forever without writing anything.
+    pub silent_loop: bool,
+    /// The depth or state limit stopped some branch being followed to its end.
+    pub truncated: bool,
+}
+
+impl ModelReport {
+    /// Whether every branch halts, rather than looping or failing. `None` if no branch was
+    /// seen not to, but not every branch could be followed.
+    pub fn always_halts(&self) -> Option<bool> {
+        let stops = |outcome: &Outcome| matches!(outcome.ending, Terminal::Halted(_));
+        if self.loops || !self.outcomes.iter().all(stops) {
+            Some(false)
+        } else if self.truncated {
+            None
+        } else {
+            Some(true)
+        }
+    }
+
+    /// Whether every branch writes something, whether or not it then stops. `None` if no
+    /// branch was seen not to, but not every branch could be followed.
+    pub fn always_outputs(&self) -> Option<bool> {
+        if self.silent_loop
+            || self
+                .outcomes
+                .iter()
+                .any(|outcome| outcome.output.is_empty())
+        {
+            Some(false)
+        } else if self.truncated {
+            None
+        } else {
+            Some(true)
+        }
+    }
+}
+
+/// A state as the model check tells them apart: the program's semantic state, plus how much
+/// input it has left and what it has written.
+struct Key(Program<MockIo>);
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+            && self.0.io().remaining_input().len() == other.0.io().remaining_input().len()
+            && self.0.io().output() == other.0.io().output()
+    }
+}
+
+impl Eq for Key {}
+
+impl Hash for Key {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+        self.0.io().remaining_input().len().hash(state);
+        self.0.io().output().hash(state);
+    }
+}
+
+/// Explores every choice a program's `y` calls could make.
+#[derive(Debug, Clone)]
+pub struct ModelChecker {
+    input: Vec<u8>,
+    max_depth: u64,
+    max_states: usize,
+    extensions: bool,
+}
+
+impl Default for ModelChecker {
+    fn default() -> Self {
+        Self {
+            input: Vec::new(),
+            max_depth: 10_000,
+            max_states: 100_000,
+            extensions: false,
+        }
+    }
+}
+
+impl ModelChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The input every branch reads.
+    pub fn input(mut self, input: impl Into<Vec<u8>>) -> Self {
+        self.input = input.into();
+        self
+    }
+
+    /// How many steps from the start a branch is followed.
+    pub fn max_depth(mut self, depth: u64) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// How many distinct states may be reached in all.
+    pub fn max_states(mut self, states: usize) -> Self {
+        self.max_states = states.max(1);
+        self
+    }
+
+    pub fn extensions(mut self, enabled: bool) -> Self {
+        self.extensions = enabled;
+        self
+    }
+
+    /// Checks `code` from its start. Only fails if the program doesn't build.
+    pub fn run(&self, code: &str) -> Result<ModelReport, crate::Error> {
+        let program = ProgramBuilder::new(code)
+            .io(MockIo::new(self.input.clone()))
+            .extensions(self.extensions)
+            .build()?;
+        Ok(self.check(program))
+    }
+
+    /// Checks `program` from where it is now, with whatever input its I/O has left.
+    pub fn check(&self, program: Program<MockIo>) -> ModelReport {
+        let mut report = ModelReport::default();
+        let mut ids: HashMap<Key, usize> = HashMap::new();
+        // Each state's successors, and whether it has written anything.
+        let mut edges: Vec<(Vec<usize>, bool)> = Vec::new();
+        let mut pending = VecDeque::new();
+        let mut outcomes = Vec::new();
+        let mut intern = |program: Program<MockIo>,
+                          depth: u64,
+                          edges: &mut Vec<(Vec<usize>, bool)>,
+                          pending: &mut VecDeque<_>,
+                          truncated: &mut bool| {
+            let key = Key(program);
+            if let Some(&id) = ids.get(&key) {
+                return Some(id);
+            }
+            if ids.len() == self.max_states {
+                *truncated = true;
+                return None;
+            }
+            let id = ids.len();
+            edges.push((Vec::new(), !key.0.io().output().is_empty()));
+            pending.push_back((id, depth, key.0.clone()));
+            ids.insert(key, id);
+            Some(id)
+        };
+        intern(program, 0, &mut edges, &mut pending, &mut report.truncated);
+        while let Some((id, depth, program)) = pending.pop_front() {
+            if let Status::Halted(reason) = program.status() {
+                outcomes.push(Outcome {
+                    ending: Terminal::Halted(reason),
+                    output: program.io().output().to_vec(),
+                });
+                continue;
+            }
+            if depth == self.max_depth {
+                report.truncated = true;
+                continue;
+            }
+            let choosing = program.status() == Status::Running
+                && step_opcode(&program) == 9
+                && program.aquarium()[program.ftp()].call_kind() == CallKind::Random;
+            let next: Vec<_> = if choosing {
+                report.choices += 1;
+                [
+                    Direction::Down,
+                    Direction::Left,
+                    Direction::Right,
+                    Direction::Up,
+                ]
+                .into_iter()
+                .map(|dir| {
+                    let mut program = program.clone();
+                    (program.step_choosing(dir), program)
+                })
+                .collect()
+            } else {
+                let mut program = program;
+                vec![(program.step(), program)]
+            };
+            for (result, next) in next {
+                match result {
+                    Ok(_) => {
+                        let truncated = &mut report.truncated;
+                        if let Some(to) =
+                            intern(next, depth + 1, &mut edges, &mut pending, truncated)
+                        {
+                            edges[id].0.push(to);
+                        }
+                    }
+                    Err(err) => outcomes.push(Outcome {
+                        ending: Terminal::Error(err.to_string()),
+                        output: next.io().output().to_vec(),
+                    }),
+                }
+            }
+        }
+        report.states = edges.len();
+        (report.loops, report.silent_loop) = find_loops(&edges);
+        for outcome in outcomes {
+            if !report.outcomes.contains(&outcome) {
+                report.outcomes.push(outcome);
+            }
+        }
+        report
+    }
+}
+
+/// Whether the states reached have a loop, and whether any loop writes nothing. Every state
+/// in a loop has written the same output, since the output is part of the state.
+fn find_loops(edges: &[(Vec<usize>, bool)]) -> (bool, bool) {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum Mark {
+        New,
+        Open,
+        Done,
+    }
+    let mut marks = vec![Mark::New; edges.len()];
+    let (mut loops, mut silent) = (false, false);
+    for root in 0..edges.len() {
+        if marks[root] != Mark::New {
+            continue;
+        }
+        marks[root] = Mark::Open;
+        let mut stack = vec![(root, 0)];
+        while let Some((node, next)) = stack.last_mut() {
+            let node = *node;
+            match edges[node].0.get(*next) {
+                Some(&to) => {
+                    *next += 1;
+                    match marks[to] {
+                        Mark::New => {
+                            marks[to] = Mark::Open;
+                            stack.push((to, 0));
+                        }
+                        Mark::Open => {
+                            loops = true;
+                            silent |= !edges[to].1;
+                        }
+                        Mark::Done => {}
+                    }
+                }
+                None => {
+                    marks[node] = Mark::Done;
+                    stack.pop();
+                }
+            }
+        }
+    }
+    (loops, silent)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_model_check() {
+        // The first cell calls y: some directions loop forever without writing, and the rest
+        // pop an empty stack.
+        let report = ModelChecker::new().run("ybbbbbbkk").unwrap();
+        assert!(report.choices > 0);
+        assert!(report.loops && report.silent_loop);
+        assert!(!report.truncated);
+        assert!(
+            report
+                .outcomes
+                .iter()
+                .any(|outcome| matches!(&outcome.ending, Terminal::Error(message) if message.starts_with("stack underflow")))
+        );
+        assert_eq!(report.always_halts(), Some(false));
+        assert_eq!(report.always_outputs(), Some(false));
+
+        // Without y calls there's one branch, and it loops.
+        let report = ModelChecker::new().run("ab").unwrap();
+        assert_eq!(report.choices, 0);
+        assert!(report.loops && report.outcomes.is_empty());
+
+        let report = ModelChecker::new().max_depth(2).run("ab").unwrap();
+        assert!(report.truncated && !report.loops);
+        assert_eq!(report.always_halts(), None);
+        assert!(ModelChecker::new().run("Y").is_err());
+    }
+}
@@ -1,9 +1,13 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, str::FromStr};
 
-use grid::Grid;
 use thiserror::Error;
 
-use crate::program::Tank;
+use crate::{
+    cells::{self, Cells},
+    dialect::{CycleOrder, EdgeMode, SkipMode, TunnelMode, ZeroMode},
+    font::{Font, FontError},
+    program::Tank,
+};
 
 #[derive(Debug, Clone, Error, PartialEq, Eq)]
 pub enum ParseError {
@@ -11,87 +15,321 @@ pub enum ParseError {
     DuplicateName(String),
     #[error("invalid name found: {0}")]
     InvalidName(String),
+    #[error("unknown pragma: {0}")]
+    UnknownPragma(String),
+    #[error("invalid value in pragma: {0}")]
+    InvalidPragma(String),
 }
 
-fn is_valid_name_char(c: char) -> bool {
-    c.is_ascii_lowercase() || c == '\''
+/// The order tanks fill the aquarium in, row by row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TankOrder {
+    /// Sorted by name, so the layout doesn't depend on how the source arranges its names.
+    #[default]
+    Sorted,
+    /// The order the names appear in the source, so the first name is the first tank.
+    Source,
 }
 
-fn is_valid_name(name: &str) -> bool {
-    !name.starts_with("'") && !name.contains("''") && !name.ends_with("'")
+impl TankOrder {
+    pub const ALL: [TankOrder; 2] = [TankOrder::Sorted, TankOrder::Source];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TankOrder::Sorted => "sorted",
+            TankOrder::Source => "source",
+        }
+    }
+
+    pub fn by_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|order| order.name() == name)
+    }
 }
 
-pub fn parse_names(code: &str) -> Result<HashSet<String>, ParseError> {
-    let mut names = HashSet::new();
-    let mut chars = code.chars().fuse();
-    while let Some(c) = chars.next() {
-        if !is_valid_name_char(c) {
+/// What a program's `#!` lines say about it, as opposed to how it runs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProgramMetadata {
+    /// From `#!title TEXT`.
+    pub title: Option<String>,
+    /// From `#!author NAME`.
+    pub author: Option<String>,
+    /// From `#!requires extensions`: the program only works with extension calls enabled.
+    pub extensions: bool,
+}
+
+/// Settings given by `#!` lines at the top of a program.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pragmas {
+    /// From `#!font NAME`.
+    pub font: Option<String>,
+    /// From `#!spec N`.
+    pub spec: Option<u32>,
+    /// From `#!edges wrap|bounce|hop`.
+    pub edges: Option<EdgeMode>,
+    /// From `#!zero noop|halt|quota=N`.
+    pub zero: Option<ZeroMode>,
+    /// From `#!skip N|stack`.
+    pub skip: Option<SkipMode>,
+    /// From `#!tunnel peek|pop`.
+    pub tunnel: Option<TunnelMode>,
+    /// From `#!cycle OP,OP,...`.
+    pub cycle: Option<CycleOrder>,
+    /// From `#!order sorted|source`.
+    pub order: Option<TankOrder>,
+    pub metadata: ProgramMetadata,
+}
+
+/// Splits the leading `#!` lines off `code`, returning the settings they give and the rest of
+/// the program.
+///
+/// Each pragma line is `#!KEY VALUE`. Lines starting `#!/` are skipped, so a program can also
+/// start with a shebang line.
+pub fn parse_pragmas(code: &str) -> Result<(Pragmas, &str), ParseError> {
+    let mut pragmas = Pragmas::default();
+    let mut rest = code;
+    while let Some(line) = rest.strip_prefix("#!") {
+        let (line, after) = line.split_once('\n').unwrap_or((line, ""));
+        rest = after;
+        if line.starts_with('/') {
             continue;
         }
-        let mut name = format!("{c}");
-        while let Some(c) = chars.next()
-            && is_valid_name_char(c)
-        {
-            name.push(c);
+        let line = line.trim();
+        let invalid = || ParseError::InvalidPragma(line.to_string());
+        match line.split_once(char::is_whitespace) {
+            Some(("font", name)) => pragmas.font = Some(name.trim().to_string()),
+            Some(("spec", version)) => {
+                pragmas.spec = Some(version.trim().parse().map_err(|_| invalid())?)
+            }
+            Some(("edges", mode)) => {
+                pragmas.edges = Some(EdgeMode::by_name(mode.trim()).ok_or_else(invalid)?)
+            }
+            Some(("zero", mode)) => {
+                pragmas.zero = Some(ZeroMode::parse(mode.trim()).ok_or_else(invalid)?)
+            }
+            Some(("skip", mode)) => {
+                pragmas.skip = Some(SkipMode::parse(mode.trim()).ok_or_else(invalid)?)
+            }
+            Some(("tunnel", mode)) => {
+                pragmas.tunnel = Some(TunnelMode::by_name(mode.trim()).ok_or_else(invalid)?)
+            }
+            Some(("cycle", order)) => {
+                pragmas.cycle = Some(CycleOrder::parse(order.trim()).ok_or_else(invalid)?)
+            }
+            Some(("order", order)) => {
+                pragmas.order = Some(TankOrder::by_name(order.trim()).ok_or_else(invalid)?)
+            }
+            Some(("title", title)) => pragmas.metadata.title = Some(title.trim().to_string()),
+            Some(("author", author)) => pragmas.metadata.author = Some(author.trim().to_string()),
+            Some(("requires", requirements)) => {
+                for requirement in requirements.split([',', ' ']).filter(|r| !r.is_empty()) {
+                    match requirement {
+                        "extensions" => pragmas.metadata.extensions = true,
+                        _ => return Err(invalid()),
+                    }
+                }
+            }
+            _ => return Err(ParseError::UnknownPragma(line.to_string())),
         }
-        if !is_valid_name(&name) {
-            return Err(ParseError::InvalidName(name));
+    }
+    Ok((pragmas, rest))
+}
+
+// The checks below are `const fn`s, written with indexing loops since iterators aren't
+// available in constants, so tables of names and tanks can be checked and built at compile
+// time.
+
+const fn is_valid_name_char(c: char) -> bool {
+    c.is_ascii_lowercase() || c == '\''
+}
+
+/// Whether a run of name characters is a name: no apostrophe at either end or next to another.
+const fn is_valid_name(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\'' && (i == 0 || i == bytes.len() - 1 || bytes[i + 1] == b'\'') {
+            return false;
         }
-        if !names.insert(name.clone()) {
-            return Err(ParseError::DuplicateName(name));
+        i += 1;
+    }
+    true
+}
+
+/// Whether `word` is, in its entirety, a name the parser would accept.
+pub const fn is_name(word: &str) -> bool {
+    let bytes = word.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if !is_valid_name_char(bytes[i] as char) {
+            return false;
         }
+        i += 1;
+    }
+    !bytes.is_empty() && is_valid_name(word)
+}
+
+/// A name as it appears in the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Span<'a> {
+    pub text: &'a str,
+    /// The byte offset `text` starts at.
+    pub start: usize,
+}
+
+impl Span<'_> {
+    /// The byte offset just past the end of the name.
+    pub fn end(&self) -> usize {
+        self.start + self.text.len()
+    }
+}
+
+/// The runs of name characters in `code`, in the order they appear, without allocating. Runs
+/// that aren't valid names are errors, but scanning carries on after them; duplicates are not
+/// checked, so tools that only need the token stream can see every occurrence.
+pub fn parse_name_spans(code: &str) -> impl Iterator<Item = Result<Span<'_>, ParseError>> {
+    let mut rest = code.char_indices().peekable();
+    std::iter::from_fn(move || {
+        let (start, _) = rest.find(|&(_, c)| is_valid_name_char(c))?;
+        let mut end = start + 1;
+        while let Some((i, _)) = rest.next_if(|&(_, c)| is_valid_name_char(c)) {
+            end = i + 1;
+        }
+        let text = &code[start..end];
+        Some(if is_valid_name(text) {
+            Ok(Span { text, start })
+        } else {
+            Err(ParseError::InvalidName(text.to_string()))
+        })
+    })
+}
+
+/// The names in `code`, in the order they appear.
+pub fn parse_names(code: &str) -> Result<Vec<String>, ParseError> {
+    let mut names = Vec::new();
+    let mut seen = HashSet::new();
+    for span in parse_name_spans(code) {
+        let name = span?.text;
+        if !seen.insert(name) {
+            return Err(ParseError::DuplicateName(name.to_string()));
+        }
+        names.push(name.to_string());
     }
     Ok(names)
 }
 
 impl Tank {
-    fn from_mask_and_name(name: String, mask: &str) -> Result<Self, anyhow::Error> {
-        let mut data = Vec::with_capacity(20);
-        for x in mask.bytes() {
-            let val = byte_to_hex(x);
-            data.extend((0..4).map(move |i| (val >> i) & 1).rev());
+    /// Builds the tank for a single name by summing its letters' glyphs, applying a swizzle at
+    /// each apostrophe.
+    pub fn from_name(name: &str) -> Result<Self, FontError> {
+        Self::from_name_with_font(name, &Font::STANDARD)
+    }
+
+    /// Like [`Tank::from_name`], drawing the letters with `font`.
+    pub fn from_name_with_font(name: &str, font: &Font) -> Result<Self, FontError> {
+        Ok(Self::new(name.to_string(), tank_cells(name, font)?))
+    }
+}
+
+/// The glyph sums of the tank `name` builds with `font`. This is a `const fn`, so static
+/// tables of tanks can be built at compile time.
+pub const fn tank_cells(name: &str, font: &Font) -> Result<Cells, FontError> {
+    // Every glyph is added straight into one buffer, and swizzles rotate it in place, so
+    // building the cells allocates nothing however long the name is.
+    let mut cells = [0; cells::LEN];
+    let bytes = name.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\'' {
+            swizzle(&mut cells);
+        } else if let Err(err) = add_glyph(&mut cells, font, bytes[i]) {
+            return Err(err);
+        }
+        i += 1;
+    }
+    Ok(Cells::new(cells))
+}
+
+/// Parses a single name with the standard font, like [`Tank::from_name`], but rejects
+/// anything that isn't a valid name.
+impl FromStr for Tank {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !is_name(s) {
+            return Err(ParseError::InvalidName(s.to_string()).into());
         }
-        Ok(Self::new(name, Grid::from_vec(data, 4)))
+        Ok(Self::from_name(s)?)
+    }
+}
+
+/// How many cells, in row-major order, an apostrophe rotates a tank's grid to the left.
+pub(crate) const SWIZZLE_SHIFT: usize = 11;
+
+const fn swizzle(cells: &mut [usize; cells::LEN]) {
+    let old = *cells;
+    let mut i = 0;
+    while i < cells::LEN {
+        cells[i] = old[(i + SWIZZLE_SHIFT) % cells::LEN];
+        i += 1;
     }
+}
 
-    fn swizzle(mut self) -> Self {
-        let mut elems = self.grid.into_vec();
-        let (a, b) = elems.split_at_mut(11);
-        a.swap_with_slice(b);
-        self.grid = Grid::from_vec(elems, 4);
-        self
+/// Adds `letter`'s glyph in `font` to a tank's cells, in row-major order.
+const fn add_glyph(
+    cells: &mut [usize; cells::LEN],
+    font: &Font,
+    letter: u8,
+) -> Result<(), FontError> {
+    let mask = match font.mask(letter) {
+        Ok(mask) => mask,
+        Err(err) => return Err(err),
+    };
+    let invalid = FontError::InvalidMask {
+        font: font.name(),
+        letter: letter as char,
+        mask,
+    };
+    let rows = mask.as_bytes();
+    if rows.len() != cells::ROWS {
+        return Err(invalid);
     }
+    let mut row = 0;
+    while row < cells::ROWS {
+        let Some(val) = byte_to_hex(rows[row]) else {
+            return Err(invalid);
+        };
+        let mut col = 0;
+        while col < cells::COLS {
+            cells[row * cells::COLS + col] += ((val >> (cells::COLS - 1 - col)) & 1) as usize;
+            col += 1;
+        }
+        row += 1;
+    }
+    Ok(())
 }
 
-fn byte_to_hex(byte: u8) -> usize {
-    (match byte {
+const fn byte_to_hex(byte: u8) -> Option<u8> {
+    let digit = match byte {
         b'0'..=b'9' => byte - b'0',
         b'a'..=b'f' => byte - b'a' + 10,
         b'A'..=b'F' => byte - b'A' + 10,
-        _ => panic!("invalid hex byte: {byte:x}"),
-    }) as usize
+        _ => return None,
+    };
+    Some(digit)
 }
 
-const FONT: [&str; 26] = [
-    "07997", "8e99e", "06886", "17997", "06bc6", "24e44", "79716", "88e99", "04044", "20224",
-    "89ae9", "44442", "0edd9", "0e999", "06996", "e99e8", "79971", "0ac88", "07c3e", "4e442",
-    "00997", "009a4", "09bb7", "0a44a", "99716", "0f24f",
-];
-
-pub fn populate_tanks(names: HashSet<String>) -> Result<Vec<Tank>, anyhow::Error> {
+/// Builds a tank for each name, in the order the aquarium lays them out.
+pub fn populate_tanks(
+    mut names: Vec<String>,
+    font: &Font,
+    order: TankOrder,
+) -> Result<Vec<Tank>, crate::Error> {
+    if order == TankOrder::Sorted {
+        names.sort();
+    }
     names
-        .into_iter()
-        .map(|name| {
-            name.bytes()
-                .try_fold(Tank::new(name.clone(), Grid::new(5, 4)), |acc, x| {
-                    if x == b'\'' {
-                        Ok(acc.swizzle())
-                    } else {
-                        Tank::from_mask_and_name(Default::default(), FONT[(x - b'a') as usize])
-                            .map(|t| acc + t)
-                    }
-                })
-        })
+        .iter()
+        .map(|name| Ok(Tank::from_name_with_font(name, font)?))
         .collect()
 }
 
@@ -107,6 +345,18 @@ mod test {
         for invalid_name in ["'ello", "m''ke", "qwert'"] {
             assert!(!is_valid_name(invalid_name));
         }
+        assert!(is_name("o'brien"));
+        for not_a_name in ["", "Mike", "two words", "'ello"] {
+            assert!(!is_name(not_a_name));
+        }
+    }
+
+    #[test]
+    fn test_tank_from_str() {
+        let tank: Tank = "o'brien".parse().unwrap();
+        assert_eq!(tank.grid(), Tank::from_name("o'brien").unwrap().grid());
+        assert!("two words".parse::<Tank>().is_err());
+        assert!("Mike".parse::<Tank>().is_err());
     }
 
     #[test]
@@ -114,9 +364,7 @@ mod test {
         let good_res = parse_names("What is going on? Must be the w'ind.");
         assert_eq!(
             good_res.unwrap(),
-            HashSet::from_iter(
-                ["hat", "is", "going", "on", "ust", "be", "the", "w'ind"].map(String::from)
-            )
+            ["hat", "is", "going", "on", "ust", "be", "the", "w'ind"].map(String::from)
         );
 
         let bad_res_invalid_name = parse_names("'ah', said the fish to the fish.");
@@ -137,27 +385,161 @@ mod test {
     }
 
     #[test]
-    fn test_tank_from_mask_and_name() {
-        let mask = FONT[0];
-        let tank = Tank::from_mask_and_name(String::default(), mask).unwrap();
-        let expected = Grid::from_vec(
-            vec![0, 0, 0, 0, 0, 1, 1, 1, 1, 0, 0, 1, 1, 0, 0, 1, 0, 1, 1, 1],
-            4,
+    fn test_parse_name_spans() {
+        let spans: Vec<_> = parse_name_spans("ab, c'd\n\u{e9}ab").collect();
+        assert_eq!(
+            spans,
+            [
+                Ok(Span {
+                    text: "ab",
+                    start: 0
+                }),
+                Ok(Span {
+                    text: "c'd",
+                    start: 4
+                }),
+                Ok(Span {
+                    text: "ab",
+                    start: 10
+                }),
+            ]
+        );
+        assert_eq!(spans[1].as_ref().unwrap().end(), 7);
+        let spans: Vec<_> = parse_name_spans("'ab cd").collect();
+        assert_eq!(
+            spans,
+            [
+                Err(ParseError::InvalidName("'ab".to_string())),
+                Ok(Span {
+                    text: "cd",
+                    start: 4
+                })
+            ]
+        );
+    }
+
+    #[test]
+    fn test_const_tank_cells() {
+        const AB: Cells = match tank_cells("ab", &Font::STANDARD) {
+            Ok(cells) => cells,
+            Err(_) => panic!(),
+        };
+        const _: () = assert!(is_name("c'd") && !is_name("c''d") && !is_name("cd'"));
+        assert_eq!(AB, *Tank::from_name("ab").unwrap().grid());
+        assert_eq!(
+            tank_cells("a'", &Font::STANDARD),
+            Ok(*Tank::from_name("a'").unwrap().grid())
+        );
+    }
+
+    #[test]
+    fn test_add_glyph() {
+        let mut cells = [0; 20];
+        add_glyph(&mut cells, &Font::STANDARD, b'a').unwrap();
+        assert_eq!(
+            cells,
+            [0, 0, 0, 0, 0, 1, 1, 1, 1, 0, 0, 1, 1, 0, 0, 1, 0, 1, 1, 1]
+        );
+        add_glyph(&mut cells, &Font::STANDARD, b'a').unwrap();
+        assert_eq!(cells[5], 2);
+        // Sums aren't capped, however long the name.
+        let long = Tank::from_name(&"e".repeat(300)).unwrap();
+        let e = Tank::from_name("e").unwrap();
+        assert_eq!(*long.grid(), e.grid().map(|x| x * 300));
+        assert_eq!(byte_to_hex(b'F'), Some(15));
+        assert_eq!(byte_to_hex(b'g'), None);
+        assert_eq!(
+            Tank::from_name("Fish").unwrap_err(),
+            FontError::MissingGlyph {
+                font: "standard",
+                letter: 'F'
+            }
         );
-        assert_eq!(tank.grid, expected);
     }
 
     #[test]
     fn test_populate_tanks() {
-        let names = HashSet::from([String::from("ab")]);
-        let tanks = populate_tanks(names).unwrap();
-        assert_eq!(tanks[0].name, String::from("ab"));
+        let names = vec![String::from("ab")];
+        let tanks = populate_tanks(names, &Font::STANDARD, TankOrder::Sorted).unwrap();
+        assert_eq!(tanks[0].name(), "ab");
         assert_eq!(
             tanks[0].grid,
-            Grid::from_vec(
-                vec![1, 0, 0, 0, 1, 2, 2, 1, 2, 0, 0, 2, 2, 0, 0, 2, 1, 2, 2, 1],
-                4
-            )
+            Cells::new([1, 0, 0, 0, 1, 2, 2, 1, 2, 0, 0, 2, 2, 0, 0, 2, 1, 2, 2, 1])
+        );
+    }
+
+    #[test]
+    fn test_populate_tanks_is_sorted() {
+        let names = parse_names("the quick brown fox").unwrap();
+        let tanks = populate_tanks(names.clone(), &Font::STANDARD, TankOrder::Sorted).unwrap();
+        let sorted: Vec<_> = tanks.iter().map(|t| t.name()).collect();
+        assert_eq!(sorted, ["brown", "fox", "quick", "the"]);
+        let tanks = populate_tanks(names, &Font::STANDARD, TankOrder::Source).unwrap();
+        let source: Vec<_> = tanks.iter().map(|t| t.name()).collect();
+        assert_eq!(source, ["the", "quick", "brown", "fox"]);
+    }
+
+    #[test]
+    fn test_swizzle() {
+        let tank = Tank::from_name("a'").unwrap();
+        let mut expected = *Tank::from_name("a").unwrap().grid.as_array();
+        expected.rotate_left(11);
+        assert_eq!(tank.grid, Cells::new(expected));
+        assert_eq!(tank.name(), "a'");
+    }
+
+    #[test]
+    fn test_parse_pragmas() {
+        let (pragmas, rest) =
+            parse_pragmas("#!/usr/bin/env pufferfish\n#!font bold\nab cd").unwrap();
+        assert_eq!(pragmas.font.as_deref(), Some("bold"));
+        assert_eq!(rest, "ab cd");
+        let (pragmas, rest) = parse_pragmas("ab\n#!font bold").unwrap();
+        assert_eq!(pragmas, Pragmas::default());
+        assert_eq!(rest, "ab\n#!font bold");
+        assert_eq!(
+            parse_pragmas("#!fnot bold\nab"),
+            Err(ParseError::UnknownPragma(String::from("fnot bold")))
+        );
+        let (pragmas, _) = parse_pragmas(
+            "#!spec 0\n#!edges bounce\n#!zero quota=5\n#!skip stack\n#!tunnel pop\n#!cycle dup,drop\n#!order source\nab",
+        )
+        .unwrap();
+        assert_eq!(pragmas.spec, Some(0));
+        assert_eq!(pragmas.edges, Some(EdgeMode::Bounce));
+        assert_eq!(pragmas.zero, Some(ZeroMode::Quota(5)));
+        assert_eq!(pragmas.skip, Some(SkipMode::Stack));
+        assert_eq!(pragmas.tunnel, Some(TunnelMode::Pop));
+        assert_eq!(
+            pragmas.cycle.map(|order| order.to_string()).as_deref(),
+            Some("dup,drop")
+        );
+        assert_eq!(pragmas.order, Some(TankOrder::Source));
+        assert_eq!(
+            parse_pragmas("#!edges sideways\nab"),
+            Err(ParseError::InvalidPragma(String::from("edges sideways")))
+        );
+        let (pragmas, _) =
+            parse_pragmas("#!title Hello, world\n#!author Ada\n#!requires extensions\nab").unwrap();
+        assert_eq!(
+            pragmas.metadata,
+            ProgramMetadata {
+                title: Some(String::from("Hello, world")),
+                author: Some(String::from("Ada")),
+                extensions: true,
+            }
+        );
+        assert_eq!(
+            parse_pragmas("#!requires wings\nab"),
+            Err(ParseError::InvalidPragma(String::from("requires wings")))
         );
     }
+
+    #[test]
+    fn test_fonts_change_tanks() {
+        let standard = Tank::from_name("fish").unwrap();
+        let bold = Tank::from_name_with_font("fish", &Font::BOLD).unwrap();
+        assert_ne!(standard.grid, bold.grid);
+        assert_eq!(bold.name(), "fish");
+    }
 }
@@ -0,0 +1,287 @@
+//! Planning a whole program's worth of names at once, from what each tank has to do.
+//!
+//! A [`PhrasePlanner`] takes a [`TankSpec`] for each tank, in the order the tanks fill the
+//! aquarium, and picks a word from a dictionary for each: one starting with the call letter
+//! the spec asks for, whose grid holds the instructions it needs and none that it rules out.
+//! With the usual sorted tank order the words also have to sort into the order of the specs,
+//! so the tanks land where they were planned.
+
+use std::{collections::HashSet, str::FromStr};
+
+use thiserror::Error;
+
+use crate::{
+    parser::{TankOrder, is_name},
+    program::Tank,
+    stats::{OPCODES, opcode},
+};
+
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum SpecError {
+    #[error("invalid call letter: {0:?}")]
+    InvalidLetter(String),
+    #[error("unknown instruction: {0:?}")]
+    UnknownInstruction(String),
+}
+
+/// What one tank of a plan has to be.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct TankSpec {
+    /// The letter the tank's name starts with, if it matters.
+    pub letter: Option<char>,
+    /// Instructions, as indices into [`OPCODES`], that some cell has to hold.
+    pub require: Vec<usize>,
+    /// Instructions no cell may hold.
+    pub forbid: Vec<usize>,
+}
+
+impl TankSpec {
+    /// Whether `tank`'s name and cells meet the spec, with extensions enabled or not.
+    pub fn matches(&self, tank: &Tank, extensions: bool) -> bool {
+        let mut held = [false; OPCODES.len()];
+        for (_, sum) in tank.cells() {
+            held[opcode(sum, extensions)] = true;
+        }
+        self.letter
+            .is_none_or(|letter| tank.call_letter() == letter)
+            && self.require.iter().all(|&op| held[op])
+            && !self.forbid.iter().any(|&op| held[op])
+    }
+}
+
+/// Parses a call letter, or `?` for any, optionally followed by a colon and a comma-separated
+/// list of instruction names, as in [`OPCODES`]. A name with `!` in front rules the
+/// instruction out rather than requiring it: `o:call,push_acc,!cycle`.
+impl FromStr for TankSpec {
+    type Err = SpecError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (letter, instructions) = s.split_once(':').unwrap_or((s, ""));
+        let letter = match letter {
+            "?" => None,
+            _ => match letter.chars().collect::<Vec<_>>()[..] {
+                [c] if c.is_ascii_lowercase() => Some(c),
+                _ => return Err(SpecError::InvalidLetter(letter.to_string())),
+            },
+        };
+        let mut spec = Self {
+            letter,
+            ..Self::default()
+        };
+        for name in instructions.split(',').filter(|name| !name.is_empty()) {
+            let (list, bare) = match name.strip_prefix('!') {
+                Some(bare) => (&mut spec.forbid, bare),
+                None => (&mut spec.require, name),
+            };
+            // The last entry is for skipped cells, which no cell holds.
+            let op = OPCODES[..OPCODES.len() - 1]
+                .iter()
+                .position(|&op| op == bare)
+                .ok_or_else(|| SpecError::UnknownInstruction(bare.to_string()))?;
+            list.push(op);
+        }
+        Ok(spec)
+    }
+}
+
+/// A planned program: a word for each tank, in the order the tanks fill the aquarium.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Phrase {
+    pub words: Vec<String>,
+    pub order: TankOrder,
+}
+
+impl Phrase {
+    /// The program's source, with the pragma its tank order needs, if any.
+    pub fn source(&self) -> String {
+        let words = self.words.join(" ");
+        match self.order {
+            TankOrder::Sorted => words,
+            TankOrder::Source => format!("#!order source\n{words}"),
+        }
+    }
+}
+
+/// Picks dictionary words to meet a [`TankSpec`] for each tank.
+#[derive(Debug, Clone, Default)]
+pub struct PhrasePlanner {
+    dictionary: Vec<String>,
+    order: TankOrder,
+    extensions: bool,
+}
+
+impl PhrasePlanner {
+    /// Plans with the valid names among `words`, lowercased.
+    pub fn new<S: AsRef<str>>(words: impl IntoIterator<Item = S>) -> Self {
+        let mut dictionary: Vec<String> = words
+            .into_iter()
+            .map(|w| w.as_ref().to_lowercase())
+            .filter(|w| is_name(w))
+            .collect();
+        dictionary.sort();
+        dictionary.dedup();
+        Self {
+            dictionary,
+            ..Self::default()
+        }
+    }
+
+    /// The order the planned program's tanks fill the aquarium in. With [`TankOrder::Source`]
+    /// the phrase can put its words in any order, at the cost of a pragma.
+    pub fn order(mut self, order: TankOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Whether the planned program runs with extensions, which decides what its cells decode
+    /// to.
+    pub fn extensions(mut self, enabled: bool) -> Self {
+        self.extensions = enabled;
+        self
+    }
+
+    /// The words that could fill each spec's tank, in alphabetical order.
+    fn candidates(&self, specs: &[TankSpec]) -> Vec<Vec<&str>> {
+        let mut candidates = vec![Vec::new(); specs.len()];
+        for word in &self.dictionary {
+            let tank = Tank::from_name(word).expect("dictionary words are valid names");
+            for (spec, found) in specs.iter().zip(&mut candidates) {
+                if spec.matches(&tank, self.extensions) {
+                    found.push(word.as_str());
+                }
+            }
+        }
+        candidates
+    }
+
+    /// Proposes a word for each of `specs`, or `None` if the dictionary can't meet them all.
+    ///
+    /// In sorted order the phrase found is the shortest, with ties going to the earliest
+    /// words alphabetically. In source order each tank takes the shortest word no earlier
+    /// tank took, which is usually but not always the shortest phrase.
+    pub fn plan(&self, specs: &[TankSpec]) -> Option<Phrase> {
+        let candidates = self.candidates(specs);
+        let words = match self.order {
+            TankOrder::Sorted => shortest_sorted(&candidates)?,
+            TankOrder::Source => {
+                let mut used = HashSet::new();
+                candidates
+                    .iter()
+                    .map(|found| {
+                        let word = found
+                            .iter()
+                            .filter(|word| !used.contains(*word))
+                            .min_by_key(|word| word.len())?;
+                        used.insert(*word);
+                        Some(word.to_string())
+                    })
+                    .collect::<Option<_>>()?
+            }
+        };
+        Some(Phrase {
+            words,
+            order: self.order,
+        })
+    }
+}
+
+/// The shortest sequence taking a word from each list in turn, each sorting after the last.
+/// Each list is in alphabetical order.
+fn shortest_sorted(candidates: &[Vec<&str>]) -> Option<Vec<String>> {
+    // For each list, the shortest total length of a sequence ending at each of its words,
+    // and the index in the previous list that sequence came through.
+    let mut best: Vec<Vec<(usize, usize)>> = Vec::with_capacity(candidates.len());
+    for (i, found) in candidates.iter().enumerate() {
+        let mut ending = Vec::with_capacity(found.len());
+        // The previous list's words sorting before the current one, and the best of them.
+        let mut before = 0;
+        let mut cheapest: Option<(usize, usize)> = None;
+        for word in found {
+            if i == 0 {
+                ending.push((word.len(), 0));
+                continue;
+            }
+            while before < candidates[i - 1].len() && candidates[i - 1][before] < *word {
+                let (cost, _) = best[i - 1][before];
+                if cheapest.is_none_or(|(lowest, _)| cost < lowest) {
+                    cheapest = Some((cost, before));
+                }
+                before += 1;
+            }
+            ending.push(match cheapest {
+                Some((cost, from)) => (cost + word.len(), from),
+                None => (usize::MAX, 0),
+            });
+        }
+        best.push(ending);
+    }
+    let last = best.last()?;
+    let (mut at, _) = last
+        .iter()
+        .enumerate()
+        .filter(|(_, (cost, _))| *cost != usize::MAX)
+        .min_by_key(|(_, (cost, _))| *cost)?;
+    let mut words = Vec::with_capacity(candidates.len());
+    for i in (0..candidates.len()).rev() {
+        words.push(candidates[i][at].to_string());
+        at = best[i][at].1;
+    }
+    words.reverse();
+    Some(words)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{builder::ProgramBuilder, io::MockIo};
+
+    #[test]
+    fn test_spec() {
+        let spec: TankSpec = "o:call,!cycle".parse().unwrap();
+        assert_eq!(spec.letter, Some('o'));
+        assert_eq!(spec.require, [9]);
+        assert_eq!(spec.forbid, [6]);
+        assert_eq!("?".parse::<TankSpec>().unwrap(), TankSpec::default());
+        assert_eq!(
+            "O".parse::<TankSpec>(),
+            Err(SpecError::InvalidLetter("O".into()))
+        );
+        assert_eq!(
+            "o:skipped".parse::<TankSpec>(),
+            Err(SpecError::UnknownInstruction("skipped".into()))
+        );
+    }
+
+    #[test]
+    fn test_plan() {
+        let words = [
+            "in", "ice", "off", "out", "owl", "echo", "exit", "ear", "bad", "output",
+        ];
+        let specs = |specs: &[&str]| -> Vec<TankSpec> {
+            specs.iter().map(|spec| spec.parse().unwrap()).collect()
+        };
+        // Sorted, the i word has to come before the o word, so there's no e word to follow.
+        let ioe = specs(&["i", "o", "e"]);
+        assert_eq!(PhrasePlanner::new(words).plan(&ioe), None);
+        let phrase = PhrasePlanner::new(words)
+            .order(TankOrder::Source)
+            .plan(&ioe)
+            .unwrap();
+        assert_eq!(phrase.words, ["in", "off", "ear"]);
+        let program = ProgramBuilder::new(phrase.source())
+            .io(MockIo::default())
+            .build()
+            .unwrap();
+        let letters: Vec<char> = program.aquarium().iter().map(Tank::call_letter).collect();
+        assert_eq!(letters, ['i', 'o', 'e']);
+
+        let phrase = PhrasePlanner::new(words)
+            .plan(&specs(&["b", "e:left", "?:!left"]))
+            .unwrap();
+        assert_eq!(phrase.words, ["bad", "echo", "in"]);
+        assert_eq!(phrase.source(), "bad echo in");
+        let phrase = PhrasePlanner::new(words).plan(&specs(&["o:push_acc"]));
+        assert_eq!(phrase.unwrap().words, ["output"]);
+        assert_eq!(PhrasePlanner::new(words).plan(&specs(&["?:call"])), None);
+    }
+}
@@ -1,79 +1,539 @@
 use std::{
-    io::{self, Read},
-    ops::{Add, AddAssign, Index},
-    process::exit,
+    collections::HashMap,
+    hash::{Hash, Hasher},
+    io,
+    ops::{Add, AddAssign, Index, Sub, SubAssign},
+    str::FromStr,
+    sync::Arc,
 };
 
-use bounded_integer::bounded_integer;
 use divisors_fixed::Divisors;
 use grid::Grid;
-use rand::{prelude::*, rng};
+use itertools::Itertools;
+use rand::{SeedableRng, prelude::*, rngs::StdRng};
+use thiserror::Error;
 
-use crate::parser::{parse_names, populate_tanks};
+use crate::{
+    builder::ProgramBuilder,
+    cells::{COLS, Cells, LEN, ROWS},
+    dialect::{CycleOp, Dialect, EdgeMode, SkipMode, TunnelMode, ZeroMode},
+    font::Font,
+    io::{Io, StdIo, Tracepoint},
+    sandbox::{Capability, Sandbox, Violation},
+};
+
+/// How many registers the `l` and `s` extension calls can address.
+pub const REGISTERS: usize = 16;
+
+#[derive(Debug, Error)]
+pub enum RuntimeError {
+    #[error("stack underflow: needed {needed} values but the stack held {found}")]
+    StackUnderflow { needed: usize, found: usize },
+    #[error("no call is defined for tanks starting with {0:?}")]
+    UnknownCall(char),
+    #[error("assertion failed: expected {expected}, found {actual}")]
+    AssertionFailed { expected: isize, actual: isize },
+    #[error("the sandbox does not allow {0} calls")]
+    Forbidden(Capability),
+    #[error("division by zero")]
+    DivisionByZero,
+    #[error("no register {0}; registers are numbered 0 to {max}", max = REGISTERS - 1)]
+    InvalidRegister(isize),
+    #[error("no tank named {name:?}{}", did_you_mean(similar))]
+    UnknownTank {
+        name: String,
+        /// The tanks with the closest names, closest first.
+        similar: Vec<String>,
+    },
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+}
+
+impl RuntimeError {
+    /// Every name [`RuntimeError::kind`] can return.
+    pub const KINDS: [&'static str; 8] = [
+        "stack_underflow",
+        "unknown_call",
+        "assertion_failed",
+        "forbidden",
+        "division_by_zero",
+        "invalid_register",
+        "unknown_tank",
+        "io",
+    ];
+
+    /// A short name for the kind of error, for grouping errors in reports.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RuntimeError::StackUnderflow { .. } => "stack_underflow",
+            RuntimeError::UnknownCall(_) => "unknown_call",
+            RuntimeError::AssertionFailed { .. } => "assertion_failed",
+            RuntimeError::Forbidden(_) => "forbidden",
+            RuntimeError::DivisionByZero => "division_by_zero",
+            RuntimeError::InvalidRegister(_) => "invalid_register",
+            RuntimeError::UnknownTank { .. } => "unknown_tank",
+            RuntimeError::Io(_) => "io",
+        }
+    }
+}
 
-bounded_integer! {
-    struct IpRow(0, 4);
+fn did_you_mean(similar: &[String]) -> String {
+    if similar.is_empty() {
+        String::new()
+    } else {
+        format!("; similar names: {}", similar.join(", "))
+    }
 }
 
-bounded_integer! {
-    struct IpCol(0, 3);
+/// Why a program stopped running.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum HaltReason {
+    /// An `e` call ended the program.
+    Exit,
+    /// The step limit was reached before the program halted on its own.
+    StepLimit,
+    /// A blank cell ran under [`ZeroMode::Halt`].
+    Blank,
+    /// More blank cells ran than [`ZeroMode::Quota`] allows.
+    BlankQuota,
+    /// The program tried to write past its output limit.
+    OutputLimit,
+    /// The program made a call its sandbox forbids, under [`Violation::Halt`].
+    Forbidden,
+    /// The program tried to read past its sandbox's input quota.
+    InputQuota,
+    /// The program tried to write past its sandbox's output quota.
+    OutputQuota,
+    /// The program made more `y` calls than its sandbox's quota allows.
+    RandomQuota,
+    /// The program ran more hop instructions than its sandbox's quota allows.
+    HopQuota,
 }
 
-bounded_integer! {
-    #[allow(dead_code)]
-    enum CycleInstruction {
-        Subtract,
-        Swap,
-        Dup,
-        Drop
+/// How [`Program::reload`] matched the new tanks against the old ones, by name.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Reload {
+    /// Same name, same grid.
+    pub kept: usize,
+    /// Same name, different grid.
+    pub changed: usize,
+    pub added: usize,
+    pub removed: usize,
+}
+
+impl HaltReason {
+    pub const ALL: [HaltReason; 10] = [
+        HaltReason::Exit,
+        HaltReason::StepLimit,
+        HaltReason::Blank,
+        HaltReason::BlankQuota,
+        HaltReason::OutputLimit,
+        HaltReason::Forbidden,
+        HaltReason::InputQuota,
+        HaltReason::OutputQuota,
+        HaltReason::RandomQuota,
+        HaltReason::HopQuota,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            HaltReason::Exit => "exit",
+            HaltReason::StepLimit => "step-limit",
+            HaltReason::Blank => "blank",
+            HaltReason::BlankQuota => "blank-quota",
+            HaltReason::OutputLimit => "output-limit",
+            HaltReason::Forbidden => "forbidden",
+            HaltReason::InputQuota => "input-quota",
+            HaltReason::OutputQuota => "output-quota",
+            HaltReason::RandomQuota => "random-quota",
+            HaltReason::HopQuota => "hop-quota",
+        }
+    }
+
+    pub fn by_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|reason| reason.name() == name)
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
-pub struct InstructionPointer(IpRow, IpCol);
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Status {
+    Running,
+    Halted(HaltReason),
+    /// The program has used up its fuel and will run again once given more.
+    OutOfFuel,
+}
+
+/// A cell of a tank, as its index in row-major order.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct InstructionPointer(u8);
+
+/// Marks a move off an edge in [`CHECKED_MOVES`].
+const OFF_EDGE: u8 = u8::MAX;
+
+/// Where a move in each direction, by [`Direction::ALL`] order, leads from each cell, wrapping
+/// around the edges.
+const WRAPPING_MOVES: [[u8; 8]; LEN] = moves(true);
+
+/// Like [`WRAPPING_MOVES`], but [`OFF_EDGE`] for moves past an edge.
+const CHECKED_MOVES: [[u8; 8]; LEN] = moves(false);
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+const fn moves(wrap: bool) -> [[u8; 8]; LEN] {
+    let mut table = [[OFF_EDGE; 8]; LEN];
+    let mut cell = 0;
+    while cell < LEN {
+        let mut dir = 0;
+        while dir < 8 {
+            let (dr, dc) = Direction::ALL[dir].delta();
+            let row = (cell / COLS) as isize + dr;
+            let col = (cell % COLS) as isize + dc;
+            let (rows, cols) = (ROWS as isize, COLS as isize);
+            if wrap || (0 <= row && row < rows && 0 <= col && col < cols) {
+                let (row, col) = (row.rem_euclid(rows), col.rem_euclid(cols));
+                table[cell][dir] = (row * cols + col) as u8;
+            }
+            dir += 1;
+        }
+        cell += 1;
+    }
+    table
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 pub enum Direction {
     Up,
     Left,
     Right,
     Down,
+    /// The diagonals are only reachable through the extension instructions.
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
 }
 
 impl InstructionPointer {
+    /// The pointer to a cell, or `None` if the cell is outside a tank.
+    pub fn new(row: usize, col: usize) -> Option<Self> {
+        (row < ROWS && col < COLS).then(|| Self((row * COLS + col) as u8))
+    }
+
+    /// The pointer to the cell at `index` in row-major order, or `None` if there's no such
+    /// cell.
+    pub fn from_index(index: usize) -> Option<Self> {
+        (index < LEN).then_some(Self(index as u8))
+    }
+
+    /// The cell's index in row-major order.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn row(self) -> usize {
+        self.index() / COLS
+    }
+
+    pub fn col(self) -> usize {
+        self.index() % COLS
+    }
+
     pub fn move_dir(self, rhs: Direction) -> Self {
-        match rhs {
-            Direction::Up => Self(self.0.wrapping_sub(1), self.1),
-            Direction::Down => Self(self.0.wrapping_add(1), self.1),
-            Direction::Left => Self(self.0, self.1.wrapping_sub(1)),
-            Direction::Right => Self(self.0, self.1.wrapping_add(1)),
+        Self(WRAPPING_MOVES[self.index()][rhs as usize])
+    }
+
+    /// Like [`InstructionPointer::move_dir`], but `None` instead of wrapping past an edge.
+    pub fn checked_move_dir(self, rhs: Direction) -> Option<Self> {
+        match CHECKED_MOVES[self.index()][rhs as usize] {
+            OFF_EDGE => None,
+            index => Some(Self(index)),
         }
     }
 }
 
-impl Default for InstructionPointer {
-    fn default() -> Self {
-        Self(IpRow::const_new::<0>(), IpCol::const_new::<0>())
+impl Direction {
+    pub const ALL: [Direction; 8] = [
+        Direction::Up,
+        Direction::Left,
+        Direction::Right,
+        Direction::Down,
+        Direction::UpLeft,
+        Direction::UpRight,
+        Direction::DownLeft,
+        Direction::DownRight,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Direction::Up => "up",
+            Direction::Left => "left",
+            Direction::Right => "right",
+            Direction::Down => "down",
+            Direction::UpLeft => "up-left",
+            Direction::UpRight => "up-right",
+            Direction::DownLeft => "down-left",
+            Direction::DownRight => "down-right",
+        }
+    }
+
+    pub fn by_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|dir| dir.name() == name)
     }
+
+    /// The change in row and column for one move, each -1, 0 or 1.
+    pub const fn delta(self) -> (isize, isize) {
+        match self {
+            Direction::Up => (-1, 0),
+            Direction::Left => (0, -1),
+            Direction::Right => (0, 1),
+            Direction::Down => (1, 0),
+            Direction::UpLeft => (-1, -1),
+            Direction::UpRight => (-1, 1),
+            Direction::DownLeft => (1, -1),
+            Direction::DownRight => (1, 1),
+        }
+    }
+
+    pub fn from_delta(dr: isize, dc: isize) -> Option<Self> {
+        Some(match (dr, dc) {
+            (-1, 0) => Direction::Up,
+            (0, -1) => Direction::Left,
+            (0, 1) => Direction::Right,
+            (1, 0) => Direction::Down,
+            (-1, -1) => Direction::UpLeft,
+            (-1, 1) => Direction::UpRight,
+            (1, -1) => Direction::DownLeft,
+            (1, 1) => Direction::DownRight,
+            _ => return None,
+        })
+    }
+
+    pub fn reversed(self) -> Self {
+        let (dr, dc) = self.delta();
+        Self::from_delta(-dr, -dc).unwrap()
+    }
+
+    /// The direction after reflecting off a `/` mirror.
+    pub fn mirror_slash(self) -> Self {
+        let (dr, dc) = self.delta();
+        Self::from_delta(-dc, -dr).unwrap()
+    }
+
+    /// The direction after reflecting off a `\` mirror.
+    pub fn mirror_backslash(self) -> Self {
+        let (dr, dc) = self.delta();
+        Self::from_delta(dc, dr).unwrap()
+    }
+}
+
+/// What a tank's call cells do, as chosen by the first letter of its name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CallKind {
+    Exit,
+    Input,
+    Output,
+    Random,
+    Trace,
+    // The rest only exist with extensions enabled.
+    Poll,
+    Cycle,
+    GetAcc,
+    SetAcc,
+    BumpAcc,
+    Add,
+    Mul,
+    Div,
+    Rem,
+    Load,
+    Store,
+    Debug,
+    Assert,
+    WriteStack,
+    Value,
+    Jump,
+    /// A letter with no call.
+    Unknown,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl CallKind {
+    pub fn of(letter: char) -> Self {
+        match letter {
+            'e' => CallKind::Exit,
+            'i' => CallKind::Input,
+            'o' => CallKind::Output,
+            'y' => CallKind::Random,
+            'x' => CallKind::Trace,
+            'p' => CallKind::Poll,
+            'c' => CallKind::Cycle,
+            'g' => CallKind::GetAcc,
+            'u' => CallKind::SetAcc,
+            'b' => CallKind::BumpAcc,
+            'a' => CallKind::Add,
+            'm' => CallKind::Mul,
+            'q' => CallKind::Div,
+            'r' => CallKind::Rem,
+            'l' => CallKind::Load,
+            's' => CallKind::Store,
+            'd' => CallKind::Debug,
+            't' => CallKind::Assert,
+            'w' => CallKind::WriteStack,
+            'v' => CallKind::Value,
+            'j' => CallKind::Jump,
+            _ => CallKind::Unknown,
+        }
+    }
+
+    /// Whether the call only exists with extensions enabled.
+    pub fn is_extension(self) -> bool {
+        !matches!(
+            self,
+            CallKind::Exit
+                | CallKind::Input
+                | CallKind::Output
+                | CallKind::Random
+                | CallKind::Trace
+                | CallKind::Unknown
+        )
+    }
+
+    /// The call as a program with extensions enabled or not runs it: without them, extension
+    /// calls are unknown.
+    pub fn enabled(self, extensions: bool) -> Self {
+        if self.is_extension() && !extensions {
+            CallKind::Unknown
+        } else {
+            self
+        }
+    }
+
+    /// The capability the call needs, if any. Calls that only touch the program's own state
+    /// need none.
+    pub fn capability(self) -> Option<Capability> {
+        match self {
+            CallKind::Input | CallKind::Poll => Some(Capability::Input),
+            CallKind::Output | CallKind::WriteStack => Some(Capability::Output),
+            CallKind::Exit => Some(Capability::Exit),
+            CallKind::Random => Some(Capability::Random),
+            CallKind::Debug | CallKind::Trace => Some(Capability::Debug),
+            _ => None,
+        }
+    }
+
+    /// How many values the call pops, given the stack it would run on.
+    fn pops(self, stack: &[isize]) -> usize {
+        match self {
+            CallKind::Output | CallKind::SetAcc | CallKind::BumpAcc | CallKind::Load => 1,
+            CallKind::Add
+            | CallKind::Mul
+            | CallKind::Div
+            | CallKind::Rem
+            | CallKind::Store
+            | CallKind::Assert => 2,
+            CallKind::Jump => stack
+                .iter()
+                .rev()
+                .position(|&value| value == -1)
+                .map_or(stack.len(), |i| i + 1),
+            _ => 0,
+        }
+    }
+}
+
+/// A tank's name, interned: the tank's place in the aquarium, counting along each row in
+/// turn. Names are unique, so the id stands for the name as well as the tank.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NameId(u32);
+
+impl NameId {
+    /// The tank's place in row-major order.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Every tank's [`NameId`], by name.
+fn intern(aquarium: &Grid<Tank>) -> Arc<HashMap<Arc<str>, NameId>> {
+    let mut names = HashMap::with_capacity(aquarium.rows() * aquarium.cols());
+    for (i, tank) in aquarium.iter().enumerate() {
+        names.entry(tank.name.clone()).or_insert(NameId(i as u32));
+    }
+    Arc::new(names)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Tank {
-    pub(crate) grid: Grid<usize>,
-    pub(crate) name: String,
-    cycle_instr: CycleInstruction,
-    acc: usize,
+    pub(crate) grid: Cells,
+    /// Shared, so that cloning a program to explore from it stays cheap.
+    pub(crate) name: Arc<str>,
+    /// Decided once from the name, rather than on every call.
+    pub(crate) call: CallKind,
+    /// The position in the dialect's cycle order of the operation the next cycle runs.
+    pub(crate) cycle: usize,
+    pub(crate) acc: usize,
 }
 
 impl Tank {
-    pub(crate) fn new(name: String, grid: Grid<usize>) -> Self {
+    pub(crate) fn new(name: String, grid: Cells) -> Self {
         Self {
             grid,
-            name,
-            cycle_instr: Default::default(),
+            call: name.chars().next().map_or(CallKind::Unknown, CallKind::of),
+            name: name.into(),
+            cycle: 0,
             acc: Default::default(),
         }
     }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn grid(&self) -> &Cells {
+        &self.grid
+    }
+
+    /// The grid as the instruction decoder sees it, with every cell taken mod 10.
+    pub fn digits(&self) -> Cells {
+        self.grid.digits()
+    }
+
+    /// Every cell's glyph sum mod `modulus`.
+    pub fn rem(&self, modulus: usize) -> Cells {
+        self.grid % modulus
+    }
+
+    /// How many cells decode to different digits in the two tanks, whatever their sums.
+    pub fn hamming(&self, other: &Tank) -> usize {
+        self.digits().hamming(&other.digits())
+    }
+
+    /// Every cell's glyph sum with its row and column, in row-major order.
+    pub fn cells(&self) -> impl Iterator<Item = ((usize, usize), usize)> + '_ {
+        self.grid.indexed_iter()
+    }
+
+    pub fn acc(&self) -> usize {
+        self.acc
+    }
+
+    pub fn set_acc(&mut self, acc: usize) {
+        self.acc = acc;
+    }
+
+    /// Overwrites one cell's glyph sum, returning the old one, or `None` if the cell is
+    /// outside the tank. The change takes effect from the next step.
+    pub fn set_cell(&mut self, row: usize, col: usize, value: usize) -> Option<usize> {
+        self.grid
+            .get_mut(row, col)
+            .map(|cell| std::mem::replace(cell, value))
+    }
+
+    /// The letter that selects what this tank does when it executes a call.
+    pub fn call_letter(&self) -> char {
+        self.name.chars().next().unwrap()
+    }
+
+    /// What this tank does when it executes a call.
+    pub fn call_kind(&self) -> CallKind {
+        self.call
+    }
 }
 
 impl Add for Tank {
@@ -85,11 +545,26 @@ impl Add for Tank {
     }
 }
 
+/// Adds the glyph sums cell by cell, saturating at `usize::MAX`.
 impl AddAssign for Tank {
     fn add_assign(&mut self, rhs: Self) {
-        self.grid.indexed_iter_mut().for_each(|(i, x)| {
-            *x += rhs.grid[i];
-        });
+        self.grid += rhs.grid;
+    }
+}
+
+impl Sub for Tank {
+    type Output = Self;
+
+    fn sub(mut self, rhs: Self) -> Self::Output {
+        self -= rhs;
+        self
+    }
+}
+
+/// Subtracts the glyph sums cell by cell, saturating at 0.
+impl SubAssign for Tank {
+    fn sub_assign(&mut self, rhs: Self) {
+        self.grid -= rhs.grid;
     }
 }
 
@@ -97,21 +572,246 @@ impl Index<InstructionPointer> for Tank {
     type Output = usize;
 
     fn index(&self, index: InstructionPointer) -> &Self::Output {
-        &self.grid[(index.0.into(), index.1.into())]
+        &self.grid.as_array()[index.index()]
     }
 }
 
-pub struct Program {
-    aquarium: Grid<Tank>,
-    ftp: (usize, usize),
+pub struct Program<I = StdIo> {
+    pub(crate) aquarium: Grid<Tank>,
+    /// The tanks' names, interned, and shared between clones since tanks are never renamed.
+    names: Arc<HashMap<Arc<str>, NameId>>,
+    pub(crate) ftp: (usize, usize),
+    pub(crate) ip: InstructionPointer,
+    pub(crate) ip_dir: Direction,
+    pub(crate) stack: Vec<isize>,
+    pub(crate) registers: [isize; REGISTERS],
+    /// How many more non-blank instructions to skip.
+    pub(crate) trampoline: usize,
+    pub(crate) extensions: bool,
+    pub(crate) steps: u64,
+    pub(crate) blanks: u64,
+    pub(crate) step_limit: Option<u64>,
+    pub(crate) output_limit: Option<u64>,
+    /// Bytes written by `o` and `w` calls so far.
+    pub(crate) output_bytes: u64,
+    /// Bytes read by `i` calls so far.
+    input_bytes: u64,
+    random_calls: u64,
+    /// Hop instructions run so far.
+    hops: u64,
+    pub(crate) halted: Option<HaltReason>,
+    pub(crate) sandbox: Sandbox,
+    /// Steps left before the program pauses, if it runs on fuel.
+    fuel: Option<u64>,
+    /// Seeds the generator `y` calls use; without it the generator is seeded from the OS.
+    pub(crate) seed: Option<u64>,
+    /// Created on the first `y` call.
+    rng: Option<StdRng>,
+    /// What the next `y` call picks instead of a random direction.
+    choice: Option<Direction>,
+    pub(crate) font: &'static Font,
+    pub(crate) dialect: Dialect,
+    io: I,
+}
+
+/// A generator seeded with `seed`, or without one from the operating system. Without the
+/// `rand-os` feature there is no OS generator, so the seed comes from the clock instead.
+pub(crate) fn seeded_rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        #[cfg(feature = "rand-os")]
+        None => StdRng::from_os_rng(),
+        #[cfg(not(feature = "rand-os"))]
+        None => {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default();
+            StdRng::seed_from_u64(now.as_nanos() as u64)
+        }
+    }
+}
+
+/// The tighter of two optional limits.
+pub(crate) fn tighter(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Where the instruction pointer goes when it moves on from `ip` heading `dir`, and the way
+/// it then heads. The flag is set when it went off an edge and the tank changes too, as after
+/// a hop.
+pub(crate) fn advance(
     ip: InstructionPointer,
-    ip_dir: Direction,
-    stack: Vec<isize>,
-    trampoline_set: bool,
+    dir: Direction,
+    edges: EdgeMode,
+) -> (InstructionPointer, Direction, bool) {
+    if let Some(ip) = ip.checked_move_dir(dir) {
+        return (ip, dir, false);
+    }
+    match edges {
+        EdgeMode::Wrap => (ip.move_dir(dir), dir, false),
+        EdgeMode::Bounce => {
+            // Only the blocked part of a diagonal move turns around.
+            let (dr, dc) = dir.delta();
+            let blocked =
+                |dir: Option<Direction>| dir.is_some_and(|dir| ip.checked_move_dir(dir).is_none());
+            let dr = if blocked(Direction::from_delta(dr, 0)) {
+                -dr
+            } else {
+                dr
+            };
+            let dc = if blocked(Direction::from_delta(0, dc)) {
+                -dc
+            } else {
+                dc
+            };
+            let dir = Direction::from_delta(dr, dc).unwrap();
+            (ip.move_dir(dir), dir, false)
+        }
+        EdgeMode::Hop => (ip.move_dir(dir), dir, true),
+    }
+}
+
+/// The tank next to `ftp` heading `dir`, wrapping around the aquarium.
+pub(crate) fn neighbour<T>(
+    ftp: (usize, usize),
+    dir: Direction,
+    aquarium: &Grid<T>,
+) -> (usize, usize) {
+    let (dr, dc) = dir.delta();
+    let (rows, cols) = (aquarium.rows() as isize, aquarium.cols() as isize);
+    (
+        (ftp.0 as isize + dr).rem_euclid(rows) as usize,
+        (ftp.1 as isize + dc).rem_euclid(cols) as usize,
+    )
+}
+
+/// Clones everything, including the I/O and the state of the `y` calls' generator, so the
+/// clone makes the same choices as the original from here on.
+impl<I: Clone> Clone for Program<I> {
+    fn clone(&self) -> Self {
+        Self {
+            aquarium: self.aquarium.clone(),
+            names: self.names.clone(),
+            ftp: self.ftp,
+            ip: self.ip,
+            ip_dir: self.ip_dir,
+            stack: self.stack.clone(),
+            registers: self.registers,
+            trampoline: self.trampoline,
+            extensions: self.extensions,
+            steps: self.steps,
+            blanks: self.blanks,
+            step_limit: self.step_limit,
+            output_limit: self.output_limit,
+            output_bytes: self.output_bytes,
+            input_bytes: self.input_bytes,
+            random_calls: self.random_calls,
+            hops: self.hops,
+            halted: self.halted,
+            sandbox: self.sandbox,
+            fuel: self.fuel,
+            seed: self.seed,
+            rng: self.rng.clone(),
+            choice: self.choice,
+            font: self.font,
+            dialect: self.dialect,
+            io: self.io.clone(),
+        }
+    }
+}
+
+/// Programs are equal when they are in the same semantic state: the same tanks, pointers,
+/// stack, registers and rules, and the same pending skips and halt. Counters and limits, the
+/// I/O and the random generator are left out, so two searches that reach the same state by
+/// different paths compare equal. The exception is a counter with a quota or limit on it,
+/// blanks under [`ZeroMode::Quota`], output under an output limit, or the sandbox's quotas,
+/// which changes when the program will halt.
+impl<I> PartialEq for Program<I> {
+    fn eq(&self, other: &Self) -> bool {
+        self.semantic_state() == other.semantic_state()
+    }
+}
+
+impl<I> Eq for Program<I> {}
+
+impl<I> Hash for Program<I> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.semantic_state().hash(state);
+    }
+}
+
+/// What [`Program`]'s equality and hashing look at.
+type SemanticState<'a> = (
+    &'a Grid<Tank>,
+    (usize, usize),
+    InstructionPointer,
+    Direction,
+    &'a [isize],
+    &'a [isize; REGISTERS],
+    (usize, bool, Option<HaltReason>),
+    (Dialect, &'static str, Sandbox),
+    [Option<u64>; 6],
+);
+
+impl<I> Program<I> {
+    fn semantic_state(&self) -> SemanticState<'_> {
+        (
+            &self.aquarium,
+            self.ftp,
+            self.ip,
+            self.ip_dir,
+            &self.stack,
+            &self.registers,
+            (self.trampoline, self.extensions, self.halted),
+            (self.dialect, self.font.name(), self.sandbox),
+            self.quota_counters(),
+        )
+    }
+
+    /// The counters the program's quotas and output limit are checked against, with `None`
+    /// for those without one.
+    fn quota_counters(&self) -> [Option<u64>; 6] {
+        let quotas = &self.sandbox.quotas;
+        let counted = |quota: Option<u64>, count| quota.map(|_| count);
+        let blank_quota = match self.dialect.zero {
+            ZeroMode::Quota(quota) => Some(quota),
+            ZeroMode::Noop | ZeroMode::Halt => None,
+        };
+        [
+            counted(blank_quota, self.blanks),
+            counted(quotas.input_bytes, self.input_bytes),
+            counted(quotas.output_bytes, self.output_bytes),
+            counted(self.output_limit, self.output_bytes),
+            counted(quotas.random_calls, self.random_calls),
+            counted(quotas.hops, self.hops),
+        ]
+    }
 }
 
 impl Program {
-    pub(crate) fn build_aquarium(tanks: Vec<Tank>) -> Self {
+    pub fn new(code: &str) -> Result<Self, crate::Error> {
+        Self::with_io(code, StdIo::default())
+    }
+
+    pub fn builder(code: impl Into<String>) -> ProgramBuilder {
+        ProgramBuilder::new(code)
+    }
+}
+
+/// Parses a whole source, pragmas included, like [`Program::new`].
+impl FromStr for Program {
+    type Err = crate::Error;
+
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        Self::new(code)
+    }
+}
+
+impl<I: Io> Program<I> {
+    pub(crate) fn build_aquarium(tanks: Vec<Tank>, io: I) -> Self {
         let n = tanks.len();
         let sqrt_n = (n as f64).sqrt();
         let height = n
@@ -120,24 +820,338 @@ impl Program {
             .min_by(|&a, &b| (((a as f64) - sqrt_n).abs()).total_cmp(&((b as f64) - sqrt_n).abs()))
             .unwrap();
         let width = n / height;
+        let aquarium = Grid::from_vec(tanks, width);
         Self {
-            aquarium: Grid::from_vec(tanks, width),
+            names: intern(&aquarium),
+            aquarium,
             ftp: (0, 0),
             ip: Default::default(),
             ip_dir: Direction::Right,
             stack: Default::default(),
-            trampoline_set: false,
+            registers: [0; REGISTERS],
+            trampoline: 0,
+            extensions: false,
+            steps: 0,
+            blanks: 0,
+            step_limit: None,
+            output_limit: None,
+            output_bytes: 0,
+            input_bytes: 0,
+            random_calls: 0,
+            hops: 0,
+            halted: None,
+            sandbox: Sandbox::default(),
+            fuel: None,
+            seed: None,
+            rng: None,
+            choice: None,
+            font: &Font::STANDARD,
+            dialect: Dialect::default(),
+            io,
+        }
+    }
+
+    pub fn with_io(code: &str, io: I) -> Result<Self, crate::Error> {
+        ProgramBuilder::new(code).io(io).build()
+    }
+
+    /// The rules the program runs under.
+    pub fn dialect(&self) -> Dialect {
+        self.dialect
+    }
+
+    /// The font the program's names were drawn with.
+    pub fn font(&self) -> &'static Font {
+        self.font
+    }
+
+    /// The tanks, laid out as the fish tank pointer moves between them.
+    pub fn aquarium(&self) -> &Grid<Tank> {
+        &self.aquarium
+    }
+
+    /// A hash of what the program does, for spotting equivalent submissions: the layout, and
+    /// each tank's call letter and cells as the instruction decoder sees them, in layout
+    /// order. Names that spell the same tank differently hash the same, and the value is
+    /// stable across builds and platforms. The dialect, font and I/O are not included, but
+    /// whether extensions are enabled is wherever it changes how a cell decodes.
+    pub fn fingerprint(&self) -> u64 {
+        // 64-bit FNV-1a, which unlike `DefaultHasher` is fixed.
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut feed = |value: u64| {
+            for byte in value.to_le_bytes() {
+                hash = (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+            }
+        };
+        feed(self.aquarium.rows() as u64);
+        feed(self.aquarium.cols() as u64);
+        for tank in self.aquarium.iter() {
+            feed(tank.call_letter() as u64);
+            for cell in tank.grid.iter() {
+                let instr = if self.extensions && (10..=15).contains(&cell) {
+                    cell
+                } else {
+                    cell % 10
+                };
+                feed(instr as u64);
+            }
         }
+        hash
+    }
+
+    /// The row and column of the tank the fish tank pointer is in.
+    pub fn ftp(&self) -> (usize, usize) {
+        self.ftp
+    }
+
+    /// The instruction pointer's cell within the current tank.
+    pub fn ip(&self) -> InstructionPointer {
+        self.ip
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.ip_dir
+    }
+
+    pub(crate) fn tank_by_name_mut(&mut self, name: &str) -> Option<&mut Tank> {
+        let position = self.position(self.name_id(name)?);
+        self.aquarium.get_mut(position.0, position.1)
+    }
+
+    /// The interned id of the tank called `name`, if there is one.
+    pub fn name_id(&self, name: &str) -> Option<NameId> {
+        self.names.get(name).copied()
+    }
+
+    /// The interned id of the tank at a row and column of the aquarium.
+    pub fn name_id_at(&self, (row, col): (usize, usize)) -> Option<NameId> {
+        self.aquarium.get(row, col)?;
+        Some(NameId((row * self.aquarium.cols() + col) as u32))
+    }
+
+    /// Where the tank with id `id` is.
+    pub fn position(&self, id: NameId) -> (usize, usize) {
+        let cols = self.aquarium.cols();
+        (id.index() / cols, id.index() % cols)
+    }
+
+    /// The tank with id `id`. Panics if the id is from a program with more tanks.
+    pub fn tank_by_id(&self, id: NameId) -> &Tank {
+        let (row, col) = self.position(id);
+        &self.aquarium[(row, col)]
+    }
+
+    /// The tank at a row and column of the aquarium, for editing a program mid-run.
+    pub fn tank_mut(&mut self, row: usize, col: usize) -> Option<&mut Tank> {
+        self.aquarium.get_mut(row, col)
+    }
+
+    pub fn stack(&self) -> &[isize] {
+        &self.stack
+    }
+
+    pub fn stack_mut(&mut self) -> &mut Vec<isize> {
+        &mut self.stack
+    }
+
+    /// The registers used by the `l` and `s` extension calls, all zero at the start.
+    pub fn registers(&self) -> &[isize; REGISTERS] {
+        &self.registers
     }
 
-    pub fn new(code: &str) -> Result<Self, anyhow::Error> {
-        let names = parse_names(code)?;
-        let tanks = populate_tanks(names)?;
-        Ok(Self::build_aquarium(tanks))
+    pub fn registers_mut(&mut self) -> &mut [isize; REGISTERS] {
+        &mut self.registers
+    }
+
+    /// The number of steps executed so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn step_limit(&self) -> Option<u64> {
+        self.step_limit
+    }
+
+    /// Limits how many steps `run` may execute in total before it stops with
+    /// [`HaltReason::StepLimit`].
+    pub fn set_step_limit(&mut self, limit: Option<u64>) {
+        self.step_limit = limit;
+    }
+
+    pub fn output_limit(&self) -> Option<u64> {
+        self.output_limit
+    }
+
+    /// Limits how many bytes of output the program may write. The write that would pass the
+    /// limit is cut short and the program halts with [`HaltReason::OutputLimit`].
+    pub fn set_output_limit(&mut self, limit: Option<u64>) {
+        self.output_limit = limit;
+    }
+
+    pub fn sandbox(&self) -> Sandbox {
+        self.sandbox
+    }
+
+    /// Runs the program under `sandbox` from now on, as for a program loaded from a save
+    /// state. As on the builder, the sandbox's limits tighten the program's own step and
+    /// output limits. Quotas count everything the program has done since it was built or
+    /// loaded, not just what it does once they are set.
+    pub fn set_sandbox(&mut self, sandbox: Sandbox) {
+        self.step_limit = tighter(self.step_limit, sandbox.limits.max_steps);
+        self.output_limit = tighter(self.output_limit, sandbox.limits.max_output);
+        self.sandbox = sandbox;
+    }
+
+    /// Seeds the generator `y` calls use from here on, or with `None` seeds it from the OS.
+    pub fn set_seed(&mut self, seed: Option<u64>) {
+        self.seed = seed;
+        self.rng = None;
+    }
+
+    /// The number of bytes of output written so far.
+    pub fn output_bytes(&self) -> u64 {
+        self.output_bytes
+    }
+
+    /// The number of bytes `i` calls have read so far.
+    pub fn input_bytes(&self) -> u64 {
+        self.input_bytes
+    }
+
+    /// The number of `y` calls made so far.
+    pub fn random_calls(&self) -> u64 {
+        self.random_calls
+    }
+
+    /// The number of hop instructions run so far.
+    pub fn hops(&self) -> u64 {
+        self.hops
+    }
+
+    /// Gives the program `fuel` steps to run, replacing whatever was left. Once they are used
+    /// up, output is flushed and [`Program::run`] and [`Program::step`] return
+    /// [`Status::OutOfFuel`] until refuelled, so a host can run a program a slice at a time.
+    pub fn set_fuel(&mut self, fuel: u64) {
+        self.fuel = Some(fuel);
+    }
+
+    /// The steps left, or `None` if the program doesn't run on fuel.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// Lets the program run without fuel again.
+    pub fn clear_fuel(&mut self) {
+        self.fuel = None;
+    }
+
+    pub fn status(&self) -> Status {
+        match self.halted {
+            Some(reason) => Status::Halted(reason),
+            None if self.fuel == Some(0) => Status::OutOfFuel,
+            None => Status::Running,
+        }
+    }
+
+    pub fn io(&self) -> &I {
+        &self.io
+    }
+
+    pub fn io_mut(&mut self) -> &mut I {
+        &mut self.io
+    }
+
+    pub fn into_io(self) -> I {
+        self.io
+    }
+
+    /// Swaps in the tanks and rules of `fresh`, a rebuild of this program from edited code,
+    /// keeping this program's I/O and step limit.
+    ///
+    /// With `restart`, the program starts over exactly as `fresh` would, its counters and
+    /// random generator included. Otherwise the stack, registers, counters and pointers carry
+    /// over, the fish tank pointer moving back to the first tank if the new layout has no tank
+    /// where it was, and tanks whose name and grid are unchanged keep their accumulator and
+    /// cycle phase, wrapped to fit a shorter cycle order. Either way a halted program becomes
+    /// runnable again.
+    pub fn reload<J: Io>(&mut self, fresh: Program<J>, restart: bool) -> Reload {
+        let old = std::mem::replace(&mut self.aquarium, fresh.aquarium);
+        let mut old: HashMap<Arc<str>, Tank> = old
+            .into_vec()
+            .into_iter()
+            .map(|tank| (tank.name.clone(), tank))
+            .collect();
+        let mut reload = Reload::default();
+        for tank in self.aquarium.iter_mut() {
+            match old.remove(&tank.name) {
+                Some(previous) if previous.grid == tank.grid => {
+                    reload.kept += 1;
+                    if !restart {
+                        *tank = previous;
+                    }
+                }
+                Some(_) => reload.changed += 1,
+                None => reload.added += 1,
+            }
+        }
+        reload.removed = old.len();
+        self.names = fresh.names;
+
+        self.font = fresh.font;
+        self.dialect = fresh.dialect;
+        self.extensions = fresh.extensions;
+        self.halted = None;
+        let phases = self.dialect.cycle.ops().len();
+        for tank in self.aquarium.iter_mut() {
+            tank.cycle %= phases;
+        }
+        if restart {
+            self.ftp = fresh.ftp;
+            self.ip = fresh.ip;
+            self.ip_dir = fresh.ip_dir;
+            self.stack = fresh.stack;
+            self.registers = fresh.registers;
+            self.trampoline = fresh.trampoline;
+            self.steps = fresh.steps;
+            self.blanks = fresh.blanks;
+            self.output_bytes = fresh.output_bytes;
+            self.input_bytes = fresh.input_bytes;
+            self.random_calls = fresh.random_calls;
+            self.hops = fresh.hops;
+            self.seed = fresh.seed;
+            self.rng = fresh.rng;
+            self.choice = fresh.choice;
+        } else if self.ftp.0 >= self.aquarium.rows() || self.ftp.1 >= self.aquarium.cols() {
+            self.ftp = (0, 0);
+        }
+        reload
     }
 
     fn update_ip(&mut self) {
-        self.ip = self.ip.move_dir(self.ip_dir);
+        let (ip, dir, hops) = advance(self.ip, self.ip_dir, self.dialect.edges);
+        self.ip = ip;
+        self.ip_dir = dir;
+        if hops {
+            self.hop();
+        }
+    }
+
+    fn blank(&mut self) -> Result<(), RuntimeError> {
+        self.blanks += 1;
+        match self.dialect.zero {
+            ZeroMode::Noop => {}
+            ZeroMode::Halt => self.halted = Some(HaltReason::Blank),
+            ZeroMode::Quota(quota) if self.blanks > quota => {
+                self.halted = Some(HaltReason::BlankQuota)
+            }
+            ZeroMode::Quota(_) => {}
+        }
+        if self.halted.is_none() {
+            self.update_ip();
+        } else {
+            self.io.flush()?;
+        }
+        Ok(())
     }
 
     fn down(&mut self) {
@@ -166,120 +1180,448 @@ impl Program {
         tank.acc += 1;
     }
 
-    fn cycle_sub(&mut self) {
-        assert!(self.stack.len() >= 2);
+    fn require(&self, needed: usize) -> Result<(), RuntimeError> {
+        if self.stack.len() < needed {
+            return Err(RuntimeError::StackUnderflow {
+                needed,
+                found: self.stack.len(),
+            });
+        }
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<isize, RuntimeError> {
+        self.stack.pop().ok_or(RuntimeError::StackUnderflow {
+            needed: 1,
+            found: 0,
+        })
+    }
+
+    /// Pops `b` then `a` and pushes `op(a, b)`, with both values still on the stack if `op`
+    /// fails.
+    fn binary(
+        &mut self,
+        op: fn(isize, isize) -> Result<isize, RuntimeError>,
+    ) -> Result<(), RuntimeError> {
+        self.require(2)?;
+        let last = self.stack.len() - 1;
+        let value = op(self.stack[last - 1], self.stack[last])?;
+        self.stack.truncate(last - 1);
+        self.stack.push(value);
+        Ok(())
+    }
+
+    /// Writes program output, up to the output limit and the sandbox's output quota. Output
+    /// cut short halts for whichever of the two was reached.
+    fn write_output(&mut self, bytes: &[u8]) -> Result<(), RuntimeError> {
+        let left =
+            |cap: Option<u64>| cap.map_or(u64::MAX, |cap| cap.saturating_sub(self.output_bytes));
+        let (limit, quota) = (
+            left(self.output_limit),
+            left(self.sandbox.quotas.output_bytes),
+        );
+        let len = bytes
+            .len()
+            .min(limit.min(quota).try_into().unwrap_or(usize::MAX));
+        self.io.write_bytes(&bytes[..len])?;
+        self.output_bytes += len as u64;
+        if len < bytes.len() {
+            self.io.flush()?;
+            self.halted = Some(if limit <= quota {
+                HaltReason::OutputLimit
+            } else {
+                HaltReason::OutputQuota
+            });
+        }
+        Ok(())
+    }
+
+    /// Halts for `reason`, flushing output, if `used` has reached `quota`. Returns whether
+    /// it did.
+    fn reached(
+        &mut self,
+        quota: Option<u64>,
+        used: u64,
+        reason: HaltReason,
+    ) -> Result<bool, RuntimeError> {
+        if quota.is_none_or(|quota| used < quota) {
+            return Ok(false);
+        }
+        self.io.flush()?;
+        self.halted = Some(reason);
+        Ok(true)
+    }
+
+    /// Pops a register number and checks that it names a register.
+    fn pop_register(&mut self) -> Result<usize, RuntimeError> {
+        let index = self.pop()?;
+        usize::try_from(index)
+            .ok()
+            .filter(|&i| i < REGISTERS)
+            .ok_or(RuntimeError::InvalidRegister(index))
+    }
+
+    fn cycle_sub(&mut self) -> Result<(), RuntimeError> {
+        self.require(2)?;
         let b = self.stack.pop().unwrap();
         let a = self.stack.pop().unwrap();
-        self.stack.push(a - b);
+        self.stack.push(a.wrapping_sub(b));
+        Ok(())
     }
 
-    fn cycle_swap(&mut self) {
-        assert!(self.stack.len() >= 2);
+    fn cycle_swap(&mut self) -> Result<(), RuntimeError> {
+        self.require(2)?;
         let last = self.stack.len() - 1;
         self.stack.swap(last, last - 1);
+        Ok(())
     }
 
-    fn cycle_dup(&mut self) {
-        assert!(!self.stack.is_empty());
+    fn cycle_dup(&mut self) -> Result<(), RuntimeError> {
+        self.require(1)?;
         let &a = self.stack.last().unwrap();
         self.stack.push(a);
+        Ok(())
     }
 
-    fn cycle_drop(&mut self) {
+    fn cycle_drop(&mut self) -> Result<(), RuntimeError> {
         self.stack.pop();
+        Ok(())
     }
 
-    fn cycle(&mut self) {
-        match self.aquarium[self.ftp].cycle_instr {
-            CycleInstruction::Subtract => self.cycle_sub(),
-            CycleInstruction::Drop => self.cycle_drop(),
-            CycleInstruction::Dup => self.cycle_dup(),
-            CycleInstruction::Swap => self.cycle_swap(),
+    fn cycle(&mut self) -> Result<(), RuntimeError> {
+        let order = self.dialect.cycle;
+        let ops = order.ops();
+        match ops[self.aquarium[self.ftp].cycle] {
+            CycleOp::Subtract => self.cycle_sub()?,
+            CycleOp::Drop => self.cycle_drop()?,
+            CycleOp::Dup => self.cycle_dup()?,
+            CycleOp::Swap => self.cycle_swap()?,
         }
-        self.aquarium[self.ftp].cycle_instr += 1;
+        let tank = &mut self.aquarium[self.ftp];
+        tank.cycle = (tank.cycle + 1) % ops.len();
         self.update_ip();
+        Ok(())
     }
 
-    fn tunnel(&mut self) {
-        if let Some(&a) = self.stack.last()
+    fn tunnel(&mut self) -> Result<(), RuntimeError> {
+        let skip = match self.dialect.skip {
+            SkipMode::Fixed(count) => count,
+            SkipMode::Stack => self.pop()?.max(0) as usize,
+        };
+        let condition = match self.dialect.tunnel {
+            TunnelMode::Peek => self.stack.last().copied(),
+            TunnelMode::Pop => self.stack.pop(),
+        };
+        if let Some(a) = condition
             && a > 0
         {
-            self.trampoline_set = false;
+            self.trampoline = 0;
         } else {
-            self.trampoline_set = true;
+            self.trampoline = skip;
         }
         self.update_ip();
+        Ok(())
+    }
+
+    /// Where the tank called `name` is, or an error suggesting the closest names.
+    fn tank_position(&self, name: &str) -> Result<(usize, usize), RuntimeError> {
+        if let Some(id) = self.name_id(name) {
+            return Ok(self.position(id));
+        }
+        let mut similar: Vec<_> = self
+            .aquarium
+            .iter()
+            .map(|tank| (strsim::levenshtein(name, &tank.name), &tank.name))
+            .filter(|&(distance, _)| distance <= 2.max(name.len() / 3))
+            .collect();
+        similar.sort();
+        Err(RuntimeError::UnknownTank {
+            name: name.to_string(),
+            similar: similar
+                .into_iter()
+                .take(3)
+                .map(|(_, name)| name.to_string())
+                .collect(),
+        })
     }
 
     fn hop(&mut self) {
-        match self.ip_dir {
-            Direction::Down => {
-                self.ftp.0 += 1;
-                self.ftp.0 %= self.aquarium.rows();
-            }
-            Direction::Up => {
-                self.ftp.0 = self
-                    .ftp
-                    .0
-                    .checked_sub(1)
-                    .unwrap_or(self.aquarium.rows() - 1);
-            }
-            Direction::Left => {
-                self.ftp.1 = self
-                    .ftp
-                    .1
-                    .checked_sub(1)
-                    .unwrap_or(self.aquarium.cols() - 1);
-            }
-            Direction::Right => {
-                self.ftp.1 += 1;
-                self.ftp.1 %= self.aquarium.cols();
-            }
-        }
-    }
-
-    fn call(&mut self) {
-        match self.aquarium[self.ftp].name.chars().next().unwrap() {
-            'e' => exit(0),
-            'i' => {
-                let mut buf = [0u8; 1];
-                if let Ok(n) = io::stdin().read(&mut buf)
-                    && n == 0
-                {
-                    self.stack.push(-1);
-                } else {
-                    self.stack.push(buf[0] as isize);
+        self.ftp = neighbour(self.ftp, self.ip_dir, &self.aquarium);
+    }
+
+    /// Extension instructions 10 and 11 reflect off a mirror, and 12 to 15 turn diagonally.
+    fn extended(&mut self, instr: usize) {
+        self.ip_dir = match instr {
+            10 => self.ip_dir.mirror_slash(),
+            11 => self.ip_dir.mirror_backslash(),
+            12 => Direction::UpRight,
+            13 => Direction::DownRight,
+            14 => Direction::DownLeft,
+            15 => Direction::UpLeft,
+            _ => unreachable!(),
+        };
+        self.update_ip();
+    }
+
+    fn call(&mut self) -> Result<(), RuntimeError> {
+        let kind = self.aquarium[self.ftp].call.enabled(self.extensions);
+        if let Some(capability) = kind.capability() {
+            if !self.sandbox.allows(capability) {
+                match self.sandbox.violation {
+                    Violation::Error => return Err(RuntimeError::Forbidden(capability)),
+                    Violation::Halt => {
+                        self.io.flush()?;
+                        self.halted = Some(HaltReason::Forbidden);
+                        return Ok(());
+                    }
                 }
             }
-            'o' => {
-                let val = self.stack.pop().unwrap();
+            self.io.at_step(self.steps);
+        }
+        let quotas = self.sandbox.quotas;
+        let over = match kind {
+            CallKind::Input => {
+                self.reached(quotas.input_bytes, self.input_bytes, HaltReason::InputQuota)?
+            }
+            CallKind::Random => {
+                let over = self.reached(
+                    quotas.random_calls,
+                    self.random_calls,
+                    HaltReason::RandomQuota,
+                )?;
+                self.random_calls += u64::from(!over);
+                over
+            }
+            _ => false,
+        };
+        if over {
+            return Ok(());
+        }
+        match kind {
+            CallKind::Exit => {
+                self.io.flush()?;
+                self.halted = Some(HaltReason::Exit);
+                return Ok(());
+            }
+            CallKind::Input => match self.io.read_byte() {
+                Ok(None) => self.stack.push(-1),
+                Ok(Some(byte)) => {
+                    self.input_bytes += 1;
+                    self.stack.push(byte as isize);
+                }
+                Err(_) => self.stack.push(0),
+            },
+            CallKind::Output => {
+                let val = self.pop()?;
                 let s = String::from_utf8_lossy(&val.to_be_bytes()).to_string();
-                print!("{s}");
+                self.write_output(s.as_bytes())?;
             }
-            'y' => {
-                let mut rng = rng();
+            CallKind::Random if self.choice.is_some() => self.ip_dir = self.choice.take().unwrap(),
+            CallKind::Random => {
+                let seed = self.seed;
+                let rng = self.rng.get_or_insert_with(|| seeded_rng(seed));
                 self.ip_dir = *[
                     Direction::Down,
                     Direction::Left,
                     Direction::Right,
                     Direction::Up,
                 ]
-                .choose(&mut rng)
+                .choose(rng)
                 .unwrap();
             }
-            _ => unimplemented!(),
+            // Tracepoints are part of the core language so that instrumenting a program
+            // never needs a flag.
+            CallKind::Trace => {
+                let event = Tracepoint {
+                    tank: &self.aquarium[self.ftp].name,
+                    step: self.steps,
+                    ftp: self.ftp,
+                    stack: &self.stack,
+                };
+                self.io.trace(&event)?;
+            }
+            CallKind::Poll => {
+                let ready = self.io.poll().unwrap_or(false);
+                self.stack.push(ready as isize);
+            }
+            CallKind::Cycle => {
+                // Report the tank's cycle phase, then rewind it to the first operation.
+                let tank = &mut self.aquarium[self.ftp];
+                self.stack.push(tank.cycle as isize);
+                tank.cycle = 0;
+            }
+            // Accumulator access: get it without incrementing, update it from the stack, or
+            // bump it by the stack top. Accumulators never go below zero.
+            CallKind::GetAcc => {
+                self.stack.push(self.aquarium[self.ftp].acc as isize);
+            }
+            CallKind::SetAcc => {
+                let val = self.pop()?;
+                self.aquarium[self.ftp].acc = val.max(0) as usize;
+            }
+            CallKind::BumpAcc => {
+                let val = self.pop()?;
+                let tank = &mut self.aquarium[self.ftp];
+                tank.acc = tank.acc.saturating_add_signed(val);
+            }
+            // Arithmetic on the top two values, in the same order as the cycle's subtract.
+            // Division truncates towards zero and the remainder takes the sign of `a`.
+            CallKind::Add => self.binary(|a, b| Ok(a.wrapping_add(b)))?,
+            CallKind::Mul => self.binary(|a, b| Ok(a.wrapping_mul(b)))?,
+            CallKind::Div => self.binary(|a, b| {
+                (b != 0)
+                    .then(|| a.wrapping_div(b))
+                    .ok_or(RuntimeError::DivisionByZero)
+            })?,
+            CallKind::Rem => self.binary(|a, b| {
+                (b != 0)
+                    .then(|| a.wrapping_rem(b))
+                    .ok_or(RuntimeError::DivisionByZero)
+            })?,
+            // Registers: load pops a register number and pushes its value, and store pops a
+            // register number and then the value to put in it.
+            CallKind::Load => {
+                let index = self.pop_register()?;
+                self.stack.push(self.registers[index]);
+            }
+            CallKind::Store => {
+                self.require(2)?;
+                let index = self.pop_register()?;
+                self.registers[index] = self.pop()?;
+            }
+            CallKind::Debug => {
+                let tank = &self.aquarium[self.ftp];
+                let line = format!(
+                    "step={} tank={} ftp={},{} ip={},{} dir={:?} acc={} stack={:?}",
+                    self.steps,
+                    tank.name,
+                    self.ftp.0,
+                    self.ftp.1,
+                    self.ip.row(),
+                    self.ip.col(),
+                    self.ip_dir,
+                    tank.acc,
+                    self.stack
+                );
+                self.io.debug(&line)?;
+            }
+            CallKind::Assert => {
+                self.require(2)?;
+                let expected = self.stack.pop().unwrap();
+                let actual = self.stack.pop().unwrap();
+                if actual != expected {
+                    return Err(RuntimeError::AssertionFailed { expected, actual });
+                }
+            }
+            CallKind::WriteStack => {
+                // The whole stack, top first, without consuming it.
+                let line = self.stack.iter().rev().map(|x| x.to_string()).join(" ");
+                self.write_output(format!("{line}\n").as_bytes())?;
+            }
+            CallKind::Value => {
+                // The call cell itself, whose glyph sum may be 9, 19, 29 and so on.
+                let value = self.aquarium[self.ftp][self.ip];
+                self.stack.push(value as isize);
+            }
+            CallKind::Jump => {
+                // Jump to a tank by name. The name is popped a character at a time, down to a
+                // -1 or the bottom of the stack, so it is pushed the way arguments are.
+                let mut name = String::new();
+                while let Some(value) = self.stack.pop()
+                    && value != -1
+                {
+                    name.push(u8::try_from(value).map_or(char::REPLACEMENT_CHARACTER, char::from));
+                }
+                self.ftp = self.tank_position(&name)?;
+            }
+            CallKind::Unknown => {
+                let letter = self.aquarium[self.ftp].call_letter();
+                return Err(RuntimeError::UnknownCall(letter));
+            }
         }
         self.update_ip();
+        Ok(())
+    }
+
+    /// How many values at the top of the stack the next step will pop or move, as far as the
+    /// stack goes. Values an instruction only reads, like a peeking tunnel's condition, don't
+    /// count.
+    pub fn consumed(&self) -> usize {
+        if self.status() != Status::Running {
+            return 0;
+        }
+        let cell = self.aquarium[self.ftp][self.ip];
+        let instr = if self.extensions && (10..=15).contains(&cell) {
+            cell
+        } else {
+            cell % 10
+        };
+        let count = match instr {
+            0 => 0,
+            _ if self.trampoline > 0 => 0,
+            6 => match self.dialect.cycle.ops()[self.aquarium[self.ftp].cycle] {
+                CycleOp::Subtract | CycleOp::Swap => 2,
+                CycleOp::Drop => 1,
+                CycleOp::Dup => 0,
+            },
+            7 => {
+                usize::from(self.dialect.skip == SkipMode::Stack)
+                    + usize::from(self.dialect.tunnel == TunnelMode::Pop)
+            }
+            9 => self.aquarium[self.ftp]
+                .call
+                .enabled(self.extensions)
+                .pops(&self.stack),
+            _ => 0,
+        };
+        count.min(self.stack.len())
+    }
+
+    /// Runs until the program halts, the step limit is reached or its fuel runs out.
+    pub fn run(&mut self) -> Result<Status, RuntimeError> {
+        loop {
+            if self.halted.is_none() && self.step_limit.is_some_and(|limit| self.steps >= limit) {
+                self.io.flush()?;
+                return Ok(Status::Halted(HaltReason::StepLimit));
+            }
+            match self.step()? {
+                Status::Running => {}
+                status => return Ok(status),
+            }
+        }
+    }
+
+    /// Executes a single instruction like [`Program::step`], except that a `y` call picks
+    /// `dir` rather than a random direction.
+    pub(crate) fn step_choosing(&mut self, dir: Direction) -> Result<Status, RuntimeError> {
+        self.choice = Some(dir);
+        let status = self.step();
+        self.choice = None;
+        status
     }
 
-    pub fn step(&mut self) {
-        let instr = self.aquarium[self.ftp][self.ip] % 10;
+    /// Executes a single instruction. Once the program has halted, or while it is out of
+    /// fuel, this does nothing.
+    pub fn step(&mut self) -> Result<Status, RuntimeError> {
+        if self.status() != Status::Running {
+            return Ok(self.status());
+        }
+        if let Some(fuel) = &mut self.fuel {
+            *fuel -= 1;
+        }
+        self.steps += 1;
+        let cell = self.aquarium[self.ftp][self.ip];
+        let instr = if self.extensions && (10..=15).contains(&cell) {
+            cell
+        } else {
+            cell % 10
+        };
         match instr {
             0 => {
-                self.update_ip();
+                self.blank()?;
             }
-            _ if self.trampoline_set => {
-                self.trampoline_set = false;
+            _ if self.trampoline > 0 => {
+                self.trampoline -= 1;
                 self.update_ip();
             }
             1 => {
@@ -298,18 +1640,791 @@ impl Program {
                 self.push_acc();
             }
             6 => {
-                self.cycle();
+                self.cycle()?;
             }
             7 => {
-                self.tunnel();
+                self.tunnel()?;
             }
             8 => {
-                self.hop();
+                let quota = self.sandbox.quotas.hops;
+                if !self.reached(quota, self.hops, HaltReason::HopQuota)? {
+                    self.hops += 1;
+                    self.hop();
+                }
             }
             9 => {
-                self.call();
+                self.call()?;
+            }
+            10..=15 => {
+                self.extended(instr);
             }
             _ => unreachable!(),
         }
+        // Running out of fuel hands control back to the host, which should see what the
+        // program has written so far.
+        if self.fuel == Some(0) {
+            self.io.flush()?;
+        }
+        Ok(self.status())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        dialect::CycleOrder,
+        io::{BufferedIo, EventLogIo, MockIo},
+        sandbox::Quotas,
+    };
+
+    fn single_cell_program(name: &str, first_cell: usize, io: MockIo) -> Program<MockIo> {
+        let mut cells = [0; 20];
+        cells[0] = first_cell;
+        Program::build_aquarium(vec![Tank::new(name.into(), Cells::new(cells))], io)
+    }
+
+    #[test]
+    fn test_consumed() {
+        let mut program = single_cell_program("j", 9, MockIo::default());
+        assert_eq!(program.consumed(), 0);
+        program.stack = vec![1, -1, 98, 97];
+        assert_eq!(program.consumed(), 0);
+        program.extensions = true;
+        assert_eq!(program.consumed(), 3);
+        program.stack.remove(1);
+        assert_eq!(program.consumed(), 3);
+
+        let mut program = single_cell_program("a", 7, MockIo::default());
+        program.stack = vec![1, 2];
+        assert_eq!(program.consumed(), 0);
+        program.dialect.tunnel = TunnelMode::Pop;
+        program.dialect.skip = SkipMode::Stack;
+        assert_eq!(program.consumed(), 2);
+    }
+
+    #[test]
+    fn test_edge_modes() {
+        let blank = |name: &str| Tank::new(name.into(), Cells::default());
+        let walk = |edges| {
+            let mut program =
+                Program::build_aquarium(vec![blank("a"), blank("b")], MockIo::default());
+            program.dialect.edges = edges;
+            for _ in 0..4 {
+                program.step().unwrap();
+            }
+            (program.ftp, program.ip, program.ip_dir)
+        };
+        let at = |row, col| InstructionPointer::new(row, col).unwrap();
+        assert_eq!(walk(EdgeMode::Wrap), ((0, 0), at(0, 0), Direction::Right));
+        assert_eq!(walk(EdgeMode::Bounce), ((0, 0), at(0, 2), Direction::Left));
+        assert_eq!(walk(EdgeMode::Hop), ((0, 1), at(0, 0), Direction::Right));
+    }
+
+    #[test]
+    fn test_extended_instructions() {
+        let at = |row, col| InstructionPointer::new(row, col).unwrap();
+        let mut program = single_cell_program("a", 10, MockIo::default());
+        program.step().unwrap();
+        assert_eq!((program.ip, program.ip_dir), (at(0, 1), Direction::Right));
+
+        let mut program = single_cell_program("a", 10, MockIo::default());
+        program.extensions = true;
+        program.step().unwrap();
+        assert_eq!((program.ip, program.ip_dir), (at(4, 0), Direction::Up));
+
+        let mut program = single_cell_program("a", 11, MockIo::default());
+        program.extensions = true;
+        program.step().unwrap();
+        assert_eq!((program.ip, program.ip_dir), (at(1, 0), Direction::Down));
+
+        let mut program = single_cell_program("a", 15, MockIo::default());
+        program.extensions = true;
+        program.dialect.edges = EdgeMode::Bounce;
+        program.step().unwrap();
+        assert_eq!(
+            (program.ip, program.ip_dir),
+            (at(1, 1), Direction::DownRight)
+        );
+    }
+
+    #[test]
+    fn test_moves() {
+        let at = |row, col| InstructionPointer::new(row, col).unwrap();
+        assert_eq!(at(4, 3).index(), 19);
+        assert_eq!(InstructionPointer::from_index(6), Some(at(1, 2)));
+        assert_eq!(
+            (
+                InstructionPointer::new(5, 0),
+                InstructionPointer::from_index(20)
+            ),
+            (None, None)
+        );
+        assert_eq!(at(0, 0).move_dir(Direction::UpLeft), at(4, 3));
+        assert_eq!(at(4, 3).move_dir(Direction::Right), at(4, 0));
+        assert_eq!(at(2, 1).move_dir(Direction::DownRight), at(3, 2));
+        assert_eq!(at(0, 2).checked_move_dir(Direction::Up), None);
+        assert_eq!(at(4, 3).checked_move_dir(Direction::DownLeft), None);
+        assert_eq!(at(1, 1).checked_move_dir(Direction::UpLeft), Some(at(0, 0)));
+    }
+
+    #[test]
+    fn test_mirrors() {
+        assert_eq!(Direction::Right.mirror_slash(), Direction::Up);
+        assert_eq!(Direction::Down.mirror_slash(), Direction::Left);
+        assert_eq!(Direction::UpRight.mirror_slash(), Direction::UpRight);
+        assert_eq!(Direction::Left.mirror_backslash(), Direction::Up);
+        assert_eq!(Direction::UpRight.mirror_backslash(), Direction::DownLeft);
+    }
+
+    #[test]
+    fn test_zero_modes() {
+        let mut program = single_cell_program("a", 3, MockIo::default());
+        program.dialect.zero = ZeroMode::Halt;
+        assert_eq!(program.step().unwrap(), Status::Running);
+        assert_eq!(program.step().unwrap(), Status::Halted(HaltReason::Blank));
+
+        let mut program = single_cell_program("a", 0, MockIo::default());
+        program.dialect.zero = ZeroMode::Quota(5);
+        assert_eq!(
+            program.run().unwrap(),
+            Status::Halted(HaltReason::BlankQuota)
+        );
+        assert_eq!(program.steps(), 6);
+    }
+
+    /// A tunnel at the start of a row of `right` instructions, to count how many are skipped.
+    fn tunnel_program(stack: Vec<isize>, skip: SkipMode, tunnel: TunnelMode) -> Program<MockIo> {
+        let mut cells = [0; 20];
+        cells[..4].copy_from_slice(&[7, 3, 3, 3]);
+        cells[4..8].copy_from_slice(&[3, 3, 3, 3]);
+        let tank = Tank::new("a".into(), Cells::new(cells));
+        let mut program = Program::build_aquarium(vec![tank], MockIo::default());
+        program.stack = stack;
+        program.dialect.skip = skip;
+        program.dialect.tunnel = tunnel;
+        program.step().unwrap();
+        program
+    }
+
+    #[test]
+    fn test_tunnel_pop() {
+        let program = tunnel_program(vec![4, 1], SkipMode::Fixed(1), TunnelMode::Pop);
+        assert_eq!((program.trampoline, program.stack.clone()), (0, vec![4]));
+        let program = tunnel_program(vec![4, 0], SkipMode::Fixed(1), TunnelMode::Pop);
+        assert_eq!((program.trampoline, program.stack.clone()), (1, vec![4]));
+        let program = tunnel_program(vec![], SkipMode::Fixed(1), TunnelMode::Pop);
+        assert_eq!(program.trampoline, 1);
+        let program = tunnel_program(vec![0, 2], SkipMode::Stack, TunnelMode::Pop);
+        assert_eq!((program.trampoline, program.stack.clone()), (2, vec![]));
+    }
+
+    #[test]
+    fn test_trampoline_skip_counts() {
+        let program = tunnel_program(vec![0], SkipMode::Fixed(3), TunnelMode::Peek);
+        assert_eq!(program.trampoline, 3);
+        let program = tunnel_program(vec![1], SkipMode::Fixed(3), TunnelMode::Peek);
+        assert_eq!(program.trampoline, 0);
+        let program = tunnel_program(vec![0, 2], SkipMode::Stack, TunnelMode::Peek);
+        assert_eq!((program.trampoline, program.stack.clone()), (2, vec![0]));
+        let program = tunnel_program(vec![0, -4], SkipMode::Stack, TunnelMode::Peek);
+        assert_eq!(program.trampoline, 0);
+
+        let mut program = tunnel_program(vec![0], SkipMode::Fixed(2), TunnelMode::Peek);
+        program.step().unwrap();
+        program.step().unwrap();
+        assert_eq!(program.trampoline, 0);
+        assert_eq!(program.ip_dir, Direction::Right);
+    }
+
+    #[test]
+    fn test_input_call_reads_from_io() {
+        let mut program = single_cell_program("i", 9, MockIo::new("a"));
+        program.step().unwrap();
+        assert_eq!(program.stack, vec![b'a' as isize]);
+        program.ip = Default::default();
+        program.step().unwrap();
+        assert_eq!(program.stack, vec![b'a' as isize, -1]);
+    }
+
+    #[test]
+    fn test_output_call_writes_to_io() {
+        let mut program = single_cell_program("o", 9, MockIo::default());
+        program.stack.push(b'z' as isize);
+        program.step().unwrap();
+        assert!(program.stack.is_empty());
+        assert_eq!(program.io().output(), b"\0\0\0\0\0\0\0z");
+    }
+
+    #[test]
+    fn test_poll_call_pushes_readiness() {
+        let mut program = single_cell_program("p", 9, MockIo::default());
+        program.extensions = true;
+        program.step().unwrap();
+        assert_eq!(program.stack, vec![1]);
+    }
+
+    #[test]
+    fn test_raw_cell_call() {
+        let mut program = single_cell_program("v", 29, MockIo::default());
+        program.extensions = true;
+        program.step().unwrap();
+        assert_eq!(program.stack, vec![29]);
+        let mut program = single_cell_program("v", 29, MockIo::default());
+        assert!(matches!(
+            program.step(),
+            Err(RuntimeError::UnknownCall('v'))
+        ));
+    }
+
+    #[test]
+    fn test_exit_call_halts() {
+        let mut program = single_cell_program("e", 9, MockIo::default());
+        assert_eq!(program.step().unwrap(), Status::Halted(HaltReason::Exit));
+        assert_eq!(program.run().unwrap(), Status::Halted(HaltReason::Exit));
+        assert_eq!(program.steps(), 1);
+    }
+
+    #[test]
+    fn test_step_limit() {
+        let mut program = single_cell_program("a", 0, MockIo::default());
+        program.set_step_limit(Some(7));
+        assert_eq!(
+            program.run().unwrap(),
+            Status::Halted(HaltReason::StepLimit)
+        );
+        assert_eq!(program.steps(), 7);
+        assert_eq!(program.status(), Status::Running);
+    }
+
+    #[test]
+    fn test_runtime_errors() {
+        let mut program = single_cell_program("o", 9, MockIo::default());
+        assert!(matches!(
+            program.step(),
+            Err(RuntimeError::StackUnderflow {
+                needed: 1,
+                found: 0
+            })
+        ));
+        let mut program = single_cell_program("z", 9, MockIo::default());
+        assert!(matches!(
+            program.step(),
+            Err(RuntimeError::UnknownCall('z'))
+        ));
+    }
+
+    #[test]
+    fn test_name_ids() {
+        let mut program = Program::with_io("ab cd ef gh", MockIo::default()).unwrap();
+        let id = program.name_id("ef").unwrap();
+        assert_eq!(program.tank_by_id(id).name(), "ef");
+        assert_eq!(program.name_id_at(program.position(id)), Some(id));
+        assert_eq!(program.name_id("eg"), None);
+        assert_eq!(program.name_id_at((5, 5)), None);
+        let fresh = Program::with_io("ab ef", MockIo::default()).unwrap();
+        program.reload(fresh, false);
+        assert_eq!(program.name_id("ef").map(NameId::index), Some(1));
+        assert_eq!(program.name_id("cd"), None);
+    }
+
+    #[test]
+    fn test_call_kind() {
+        assert_eq!(
+            Tank::from_name("y'all").unwrap().call_kind(),
+            CallKind::Random
+        );
+        assert_eq!(CallKind::of('z'), CallKind::Unknown);
+        assert_eq!(CallKind::Add.enabled(false), CallKind::Unknown);
+        assert_eq!(CallKind::Add.enabled(true), CallKind::Add);
+        assert_eq!(CallKind::Output.enabled(false), CallKind::Output);
+        // Without extensions an extension call is unknown, by the letter that named it.
+        let mut program = single_cell_program("add", 9, MockIo::default());
+        program.stack = vec![1, 2];
+        assert!(matches!(
+            program.step(),
+            Err(RuntimeError::UnknownCall('a'))
+        ));
+    }
+
+    #[test]
+    fn test_cycle_wraps_around() {
+        let mut program = single_cell_program("a", 6, MockIo::default());
+        program.stack = vec![5, 3, 1];
+        for expected in [vec![5, 2], vec![2, 5], vec![2, 5, 5], vec![2, 5], vec![-3]] {
+            program.ip = Default::default();
+            program.step().unwrap();
+            assert_eq!(program.stack, expected);
+        }
+    }
+
+    #[test]
+    fn test_accumulator_calls() {
+        let run = |name: &str, acc: usize, stack: Vec<isize>| {
+            let mut program = single_cell_program(name, 9, MockIo::default());
+            program.extensions = true;
+            program.aquarium[(0, 0)].acc = acc;
+            program.stack = stack;
+            program.step().unwrap();
+            (program.aquarium[(0, 0)].acc, program.stack)
+        };
+        assert_eq!(run("g", 3, vec![]), (3, vec![3]));
+        assert_eq!(run("u", 3, vec![1, 7]), (7, vec![1]));
+        assert_eq!(run("u", 3, vec![-7]), (0, vec![]));
+        assert_eq!(run("b", 3, vec![4]), (7, vec![]));
+        assert_eq!(run("b", 3, vec![-4]), (0, vec![]));
+    }
+
+    #[test]
+    fn test_arithmetic_calls() {
+        let run = |name: &str, stack: Vec<isize>| {
+            let mut program = single_cell_program(name, 9, MockIo::default());
+            program.extensions = true;
+            program.stack = stack;
+            program.step().map(|_| program.stack)
+        };
+        assert_eq!(run("a", vec![1, 7, 5]).unwrap(), [1, 12]);
+        assert_eq!(run("m", vec![-3, 5]).unwrap(), [-15]);
+        assert_eq!(run("q", vec![-7, 2]).unwrap(), [-3]);
+        assert_eq!(run("r", vec![-7, 2]).unwrap(), [-1]);
+        assert_eq!(run("q", vec![isize::MIN, -1]).unwrap(), [isize::MIN]);
+        assert!(matches!(
+            run("r", vec![7, 0]),
+            Err(RuntimeError::DivisionByZero)
+        ));
+        assert!(matches!(
+            run("a", vec![7]),
+            Err(RuntimeError::StackUnderflow {
+                needed: 2,
+                found: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn test_register_calls() {
+        let mut program = single_cell_program("s", 9, MockIo::default());
+        program.extensions = true;
+        program.stack = vec![1, 42, 3];
+        program.step().unwrap();
+        assert_eq!((program.registers[3], program.stack()), (42, &[1][..]));
+
+        let mut program = single_cell_program("l", 9, MockIo::default());
+        program.extensions = true;
+        program.registers[15] = -8;
+        program.stack = vec![15];
+        program.step().unwrap();
+        assert_eq!(program.stack, [-8]);
+
+        for index in [-1, REGISTERS as isize] {
+            let mut program = single_cell_program("l", 9, MockIo::default());
+            program.extensions = true;
+            program.stack = vec![index];
+            assert!(matches!(
+                program.step(),
+                Err(RuntimeError::InvalidRegister(i)) if i == index
+            ));
+        }
+    }
+
+    #[test]
+    fn test_jump_call() {
+        let program = |stack: &[isize]| {
+            let mut cells = [0; 20];
+            cells[0] = 9;
+            let mut tanks: Vec<_> = ["cod", "fish", "fist"]
+                .map(|name| Tank::new(name.into(), Cells::default()))
+                .into();
+            tanks.push(Tank::new("j".into(), Cells::new(cells)));
+            let mut program = Program::build_aquarium(tanks, MockIo::default());
+            program.extensions = true;
+            program.ftp = (1, 1);
+            program.stack = stack.to_vec();
+            program
+        };
+        let mut jumper = program(&[7, -1, 104, 115, 105, 102]);
+        jumper.step().unwrap();
+        assert_eq!((jumper.ftp(), jumper.stack()), ((0, 1), &[7][..]));
+
+        let err = program(&[104, 115, 102]).step().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "no tank named \"fsh\"; similar names: fish, fist"
+        );
+    }
+
+    #[test]
+    fn test_debug_call() {
+        let mut program = single_cell_program("d", 9, MockIo::default());
+        program.extensions = true;
+        program.stack = vec![1, -2];
+        program.step().unwrap();
+        assert_eq!(program.stack, [1, -2]);
+        assert_eq!(
+            program.io.debug_lines(),
+            ["step=1 tank=d ftp=0,0 ip=0,0 dir=Right acc=0 stack=[1, -2]"]
+        );
+    }
+
+    #[test]
+    fn test_assert_call() {
+        let mut program = single_cell_program("t", 9, MockIo::default());
+        program.extensions = true;
+        program.stack = vec![1, 4, 4];
+        program.step().unwrap();
+        assert_eq!(program.stack, [1]);
+
+        let mut program = single_cell_program("t", 9, MockIo::default());
+        program.extensions = true;
+        program.stack = vec![3, 4];
+        assert!(matches!(
+            program.step(),
+            Err(RuntimeError::AssertionFailed {
+                expected: 4,
+                actual: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn test_write_stack_call() {
+        let mut program = single_cell_program("w", 9, MockIo::default());
+        program.extensions = true;
+        program.stack = vec![1, -2, 30];
+        program.step().unwrap();
+        assert_eq!(program.stack, [1, -2, 30]);
+        assert_eq!(program.io.output(), b"30 -2 1\n");
+    }
+
+    #[test]
+    fn test_clone_eq_hash() {
+        use std::collections::HashSet;
+
+        let mut program = Program::with_io("ab cd", MockIo::default()).unwrap();
+        let mut branch = program.clone();
+        assert!(program == branch);
+        branch.step().unwrap();
+        assert!(program != branch);
+        program.step().unwrap();
+        assert!(program == branch);
+        // The same state reached again later is still the same state.
+        let mut seen = HashSet::new();
+        let mut revisited = false;
+        for _ in 0..100 {
+            revisited |= !seen.insert(program.clone());
+            program.step().unwrap();
+        }
+        assert!(revisited);
+        assert!(seen.len() < 100);
+
+        // Counters only matter with a quota on them.
+        let mut program = Program::with_io("ab cd", MockIo::default()).unwrap();
+        let mut branch = program.clone();
+        (branch.blanks, branch.hops) = (3, 2);
+        assert!(program == branch);
+        program.dialect.zero = ZeroMode::Quota(10);
+        branch.dialect.zero = ZeroMode::Quota(10);
+        assert!(program != branch);
+        program.blanks = 3;
+        assert!(program == branch);
+        let quotas = Sandbox::new().quotas(Quotas::new().hops(5));
+        (program.sandbox, branch.sandbox) = (quotas, quotas);
+        assert!(program != branch);
+        program.hops = 2;
+        assert!(program == branch);
+        // So does output written under an output limit.
+        branch.output_bytes = 4;
+        assert!(program == branch);
+        program.set_output_limit(Some(8));
+        branch.set_output_limit(Some(8));
+        assert!(program != branch);
+        program.output_bytes = 4;
+        assert!(program == branch);
+    }
+
+    #[test]
+    fn test_from_str() {
+        let program: Program = "#!edges bounce\nab cd".parse().unwrap();
+        assert_eq!(program.aquarium().size(), (1, 2));
+        assert_eq!(program.dialect().edges, EdgeMode::Bounce);
+        assert!("ab ab".parse::<Program>().is_err());
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let fingerprint = |code| Program::new(code).unwrap().fingerprint();
+        assert_eq!(fingerprint("spot fish"), fingerprint("stop fish"));
+        assert_ne!(fingerprint("spot fish"), fingerprint("tops fish"));
+        assert_ne!(fingerprint("spot fish"), fingerprint("spot"));
+        // Archives store fingerprints, so the hash must not change.
+        assert_eq!(fingerprint("ab"), 0x68f3_ff02_a27f_4465);
+
+        // Cells of 10 to 15 are instructions of their own only with extensions.
+        let mut plain = single_cell_program("a", 12, MockIo::default());
+        let mut extended = plain.clone();
+        extended.extensions = true;
+        assert_ne!(plain.fingerprint(), extended.fingerprint());
+        plain.aquarium[(0, 0)].grid = Cells::new([2; 20]);
+        extended.aquarium[(0, 0)].grid = Cells::new([2; 20]);
+        assert_eq!(plain.fingerprint(), extended.fingerprint());
+    }
+
+    #[test]
+    fn test_event_log_steps() {
+        let mut cells = [0; 20];
+        cells[0] = 9;
+        let tank = Tank::new("i".into(), Cells::new(cells));
+        let io = EventLogIo::new(MockIo::new("x"), Vec::new());
+        let mut program = Program::build_aquarium(vec![tank], io);
+        for _ in 0..5 {
+            program.step().unwrap();
+        }
+        let (_, log) = program.into_io().into_inner();
+        assert_eq!(log, b"read step=1 byte=120\nread step=5 eof\n");
+    }
+
+    #[test]
+    fn test_tracepoint_call() {
+        let mut program = single_cell_program("xloop", 9, MockIo::default());
+        program.stack = vec![7];
+        program.step().unwrap();
+        assert_eq!(program.stack, [7]);
+        assert_eq!(
+            program.io.debug_lines(),
+            ["trace tank=xloop step=1 ftp=0,0 stack=[7]"]
+        );
+    }
+
+    #[test]
+    fn test_reload() {
+        let build = |code: &str| ProgramBuilder::new(code).io(MockIo::default()).build();
+        let mut program = build("ab cd ef gh").unwrap();
+        program.stack = vec![3];
+        program.ftp = (1, 1);
+        program.steps = 10;
+        program.tank_by_name_mut("ab").unwrap().acc = 4;
+        program.tank_by_name_mut("cd").unwrap().acc = 5;
+        program.halted = Some(HaltReason::Exit);
+
+        let reload = program.reload(build("ab cde fish").unwrap(), false);
+        assert_eq!(
+            reload,
+            Reload {
+                kept: 1,
+                changed: 0,
+                added: 2,
+                removed: 3
+            }
+        );
+        assert_eq!(program.aquarium[(0, 0)].acc, 4);
+        assert_eq!(program.aquarium[(0, 1)].acc, 0);
+        assert_eq!(
+            (program.ftp, program.steps, program.stack()),
+            ((0, 0), 10, &[3][..])
+        );
+        assert_eq!(program.status(), Status::Running);
+
+        let reload = program.reload(build("#!font bold\nab cde").unwrap(), true);
+        assert_eq!((reload.kept, reload.changed, reload.removed), (0, 2, 1));
+        assert_eq!(program.aquarium[(0, 0)].acc, 0);
+        assert_eq!((program.steps, program.stack()), (0, &[][..]));
+
+        // A kept tank's cycle phase has to fit the new cycle order.
+        let mut program = build("#!cycle sub,swap,dup,drop\nf").unwrap();
+        program.aquarium[(0, 0)].cycle = 3;
+        let reload = program.reload(build("#!cycle dup\nf").unwrap(), false);
+        assert_eq!(reload.kept, 1);
+        assert_eq!(program.aquarium[(0, 0)].cycle, 0);
+        program.consumed();
+
+        let mut program = build("ab").unwrap();
+        (program.output_bytes, program.hops) = (8, 2);
+        program.reload(build("ab").unwrap(), true);
+        assert_eq!((program.output_bytes(), program.hops()), (0, 0));
+    }
+
+    #[test]
+    fn test_editing_state() {
+        let mut program = single_cell_program("a", 0, MockIo::default());
+        let tank = program.tank_mut(0, 0).unwrap();
+        assert_eq!(tank.set_cell(0, 0, 5), Some(0));
+        assert_eq!(tank.set_cell(5, 0, 5), None);
+        tank.set_acc(8);
+        assert!(program.tank_mut(0, 1).is_none());
+        program.stack_mut().push(1);
+        program.step().unwrap();
+        assert_eq!(program.stack(), [1, 8]);
+    }
+
+    #[test]
+    fn test_seeded_random_call() {
+        let directions = |seed| {
+            let mut program = single_cell_program("y", 9, MockIo::default());
+            program.seed = Some(seed);
+            (0..20)
+                .map(|_| {
+                    program.ip = Default::default();
+                    program.step().unwrap();
+                    program.ip_dir
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(directions(7), directions(7));
+        assert_ne!(directions(7), directions(8));
+    }
+
+    #[test]
+    fn test_output_limit() {
+        let mut program = single_cell_program("w", 9, MockIo::default());
+        program.extensions = true;
+        program.stack = vec![1, 22];
+        program.set_output_limit(Some(8));
+        assert_eq!(program.step().unwrap(), Status::Running);
+        program.ip = Default::default();
+        assert_eq!(
+            program.step().unwrap(),
+            Status::Halted(HaltReason::OutputLimit)
+        );
+        assert_eq!(program.io.output(), b"22 1\n22 ");
+        assert_eq!(program.output_bytes(), 8);
+    }
+
+    #[test]
+    fn test_sandbox() {
+        let mut program = single_cell_program("i", 9, MockIo::new("x"));
+        program.sandbox = Sandbox::new().deny(Capability::Input);
+        assert!(matches!(
+            program.step(),
+            Err(RuntimeError::Forbidden(Capability::Input))
+        ));
+
+        let mut program = single_cell_program("e", 9, MockIo::default());
+        program.sandbox = Sandbox::deny_all().on_violation(Violation::Halt);
+        assert_eq!(
+            program.step().unwrap(),
+            Status::Halted(HaltReason::Forbidden)
+        );
+
+        let mut program = single_cell_program("g", 9, MockIo::default());
+        program.extensions = true;
+        program.sandbox = Sandbox::deny_all();
+        program.step().unwrap();
+        assert_eq!(program.stack, [0]);
+
+        // Without extensions there is no d call, so there's no capability to forbid.
+        let mut program = single_cell_program("d", 9, MockIo::default());
+        program.sandbox = Sandbox::deny_all();
+        assert!(matches!(
+            program.step(),
+            Err(RuntimeError::UnknownCall('d'))
+        ));
+    }
+
+    #[test]
+    fn test_quotas() {
+        let mut program = single_cell_program("i", 9, MockIo::new("ab"));
+        program.sandbox = Sandbox::new().quotas(Quotas::new().input_bytes(1));
+        program.step().unwrap();
+        program.ip = Default::default();
+        let halted = Status::Halted(HaltReason::InputQuota);
+        assert_eq!(program.step().unwrap(), halted);
+        assert_eq!((program.input_bytes(), &program.stack[..]), (1, &[97][..]));
+        assert_eq!(program.io().remaining_input(), b"b");
+
+        // An o call writes eight bytes, so both caps cut it short, and the tighter one halts.
+        let mut program = single_cell_program("o", 9, MockIo::default());
+        program.sandbox = Sandbox::new().quotas(Quotas::new().output_bytes(4));
+        program.stack.push(b'z' as isize);
+        let halted = Status::Halted(HaltReason::OutputQuota);
+        assert_eq!(program.step().unwrap(), halted);
+        assert_eq!(program.io().output(), b"\0\0\0\0");
+        let mut program = single_cell_program("o", 9, MockIo::default());
+        program.sandbox = Sandbox::new().quotas(Quotas::new().output_bytes(4));
+        program.set_output_limit(Some(2));
+        program.stack.push(b'z' as isize);
+        let halted = Status::Halted(HaltReason::OutputLimit);
+        assert_eq!(program.step().unwrap(), halted);
+
+        let mut program = single_cell_program("y", 9, MockIo::default());
+        program.sandbox = Sandbox::new().quotas(Quotas::new().random_calls(1));
+        program.step().unwrap();
+        program.ip = Default::default();
+        let halted = Status::Halted(HaltReason::RandomQuota);
+        assert_eq!(program.step().unwrap(), halted);
+        assert_eq!(program.random_calls(), 1);
+
+        let mut program = single_cell_program("a", 8, MockIo::default());
+        program.sandbox = Sandbox::new().quotas(Quotas::new().hops(2));
+        program.step().unwrap();
+        program.step().unwrap();
+        let halted = Status::Halted(HaltReason::HopQuota);
+        assert_eq!(program.step().unwrap(), halted);
+        assert_eq!((program.hops(), program.steps()), (2, 3));
+    }
+
+    #[test]
+    fn test_fuel() {
+        let mut program = single_cell_program("a", 3, MockIo::default());
+        program.set_fuel(5);
+        assert_eq!(program.run().unwrap(), Status::OutOfFuel);
+        assert_eq!(program.steps(), 5);
+        assert_eq!(program.step().unwrap(), Status::OutOfFuel);
+        assert_eq!(program.steps(), 5);
+        program.set_fuel(2);
+        assert_eq!(program.step().unwrap(), Status::Running);
+        assert_eq!(program.run().unwrap(), Status::OutOfFuel);
+        assert_eq!(program.steps(), 7);
+        program.set_step_limit(Some(10));
+        program.clear_fuel();
+        assert_eq!(
+            program.run().unwrap(),
+            Status::Halted(HaltReason::StepLimit)
+        );
+    }
+
+    #[test]
+    fn test_flush_when_stopping() {
+        let writer = || {
+            let mut cells = [0; 20];
+            cells[0] = 9;
+            let tank = Tank::new("o".into(), Cells::new(cells));
+            let mut program =
+                Program::build_aquarium(vec![tank], BufferedIo::new(MockIo::default()));
+            program.stack.push(b'z' as isize);
+            program
+        };
+        let mut program = writer();
+        program.dialect.zero = ZeroMode::Halt;
+        program.step().unwrap();
+        assert!(program.io().inner().output().is_empty());
+        assert_eq!(program.step().unwrap(), Status::Halted(HaltReason::Blank));
+        assert_eq!(program.io().inner().output(), b"\0\0\0\0\0\0\0z");
+
+        let mut program = writer();
+        program.set_fuel(1);
+        assert_eq!(program.step().unwrap(), Status::OutOfFuel);
+        assert_eq!(program.io().inner().output(), b"\0\0\0\0\0\0\0z");
+    }
+
+    #[test]
+    fn test_cycle_reset_call() {
+        let mut program = single_cell_program("c", 9, MockIo::default());
+        program.extensions = true;
+        program.aquarium[(0, 0)].cycle = 2;
+        program.step().unwrap();
+        assert_eq!(program.stack, vec![2]);
+        assert_eq!(program.aquarium[(0, 0)].cycle, 0);
+    }
+
+    #[test]
+    fn test_cycle_order() {
+        let mut program = single_cell_program("a", 6, MockIo::default());
+        program.dialect.cycle = CycleOrder::parse("dup,sub").unwrap();
+        program.stack = vec![4];
+        for expected in [vec![4, 4], vec![0], vec![0, 0]] {
+            program.ip = Default::default();
+            program.step().unwrap();
+            assert_eq!(program.stack, expected);
+        }
     }
 }
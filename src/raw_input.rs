@@ -0,0 +1,59 @@
+use std::io::{self, Read, Write};
+
+use crate::{
+    io::{Io, UnbufferedStdin, stdin_ready},
+    tty::{RawMode, raise_interrupt},
+};
+
+/// Terminal input delivered a keypress at a time, without waiting for Enter.
+///
+/// Keys arrive exactly as the terminal sends them, so Enter reads as `\r` and special keys as
+/// escape sequences. Ctrl-C interrupts the process as usual and Ctrl-D reads as end of input.
+pub struct RawIo<W> {
+    raw: RawMode,
+    output: W,
+}
+
+impl<W: Write> RawIo<W> {
+    pub fn new(output: W) -> io::Result<Self> {
+        let raw = RawMode::enable()?;
+        raw.install_exit_hooks();
+        Ok(Self { raw, output })
+    }
+}
+
+impl<W: Write> Io for RawIo<W> {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        self.output.flush()?;
+        let mut buf = [0u8; 1];
+        loop {
+            match UnbufferedStdin.read(&mut buf) {
+                Ok(0) => return Ok(None),
+                Ok(_) => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        match buf[0] {
+            0x03 => {
+                let _ = self.raw.restore();
+                raise_interrupt();
+                Err(io::ErrorKind::Interrupted.into())
+            }
+            0x04 => Ok(None),
+            byte => Ok(Some(byte)),
+        }
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.output.write_all(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+
+    fn poll(&mut self) -> io::Result<bool> {
+        stdin_ready()
+    }
+}
@@ -0,0 +1,292 @@
+//! Which cells a program can ever execute, worked out without running it.
+//!
+//! [`Reachability`] follows every way the instruction pointer could go from where it starts:
+//! through turns, edges and hops, in every direction a `y` call could pick, and into every
+//! tank a `j` call could jump to. The stack isn't modelled, so a tunnel is assumed to both
+//! skip and not skip, and a skip is assumed to last any number of cells. That makes the result
+//! an overestimate: a cell it marks unreachable can never execute, but one it marks reachable
+//! might not either.
+
+use std::collections::HashSet;
+
+use crate::{
+    cells::{COLS, LEN},
+    debugger::Location,
+    dialect::ZeroMode,
+    io::Io,
+    program::{Direction, InstructionPointer, Program, advance, neighbour},
+    stats::opcode,
+};
+
+/// Where the instruction pointer could be and which way it heads, and whether it might still
+/// be skipping cells after a tunnel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct State {
+    pub(crate) tank: (usize, usize),
+    pub(crate) ip: InstructionPointer,
+    pub(crate) dir: Direction,
+    pub(crate) skipping: bool,
+}
+
+impl State {
+    pub(crate) fn of<I: Io>(program: &Program<I>) -> Self {
+        Self {
+            tank: program.ftp(),
+            ip: program.ip(),
+            dir: program.direction(),
+            skipping: program.trampoline > 0,
+        }
+    }
+
+    pub(crate) fn location(&self) -> Location {
+        Location {
+            tank: self.tank,
+            cell: (self.ip.row(), self.ip.col()),
+        }
+    }
+}
+
+/// The instruction the cell at `state` decodes to, as in [`Program::step`].
+pub(crate) fn instruction<I: Io>(program: &Program<I>, state: &State) -> usize {
+    opcode(program.aquarium()[state.tank][state.ip], program.extensions)
+}
+
+/// Calls `visit` with every state that could follow `state`, and whether the cell at `state`
+/// ran on the way there rather than being skipped.
+pub(crate) fn successors<I: Io>(
+    program: &Program<I>,
+    state: &State,
+    mut visit: impl FnMut(State, bool),
+) {
+    let aquarium = program.aquarium();
+    let dialect = program.dialect();
+    let State {
+        tank,
+        ip,
+        dir,
+        skipping,
+    } = *state;
+    // Moving on from a cell, as `update_ip` does.
+    let onward = |tank, dir, skipping| {
+        let (ip, dir, hops) = advance(ip, dir, dialect.edges);
+        let tank = if hops {
+            neighbour(tank, dir, aquarium)
+        } else {
+            tank
+        };
+        State {
+            tank,
+            ip,
+            dir,
+            skipping,
+        }
+    };
+    let instr = instruction(program, state);
+    // Blanks run whether or not a skip is pending, and don't use it up.
+    if instr != 0 && skipping {
+        visit(onward(tank, dir, true), false);
+    }
+    match instr {
+        0 if dialect.zero == ZeroMode::Halt => {}
+        0 => visit(onward(tank, dir, skipping), true),
+        1..=4 => {
+            let dir = [
+                Direction::Down,
+                Direction::Up,
+                Direction::Right,
+                Direction::Left,
+            ][instr - 1];
+            visit(onward(tank, dir, false), true);
+        }
+        5 | 6 => visit(onward(tank, dir, false), true),
+        7 => {
+            visit(onward(tank, dir, false), true);
+            visit(onward(tank, dir, true), true);
+        }
+        // A hop runs the same cell of the next tank over.
+        8 => visit(
+            State {
+                tank: neighbour(tank, dir, aquarium),
+                ip,
+                dir,
+                skipping: false,
+            },
+            true,
+        ),
+        9 => match aquarium[tank].call_letter() {
+            'e' => {}
+            'y' => {
+                for dir in [
+                    Direction::Down,
+                    Direction::Left,
+                    Direction::Right,
+                    Direction::Up,
+                ] {
+                    visit(onward(tank, dir, false), true);
+                }
+            }
+            'j' if program.extensions => {
+                for (tank, _) in aquarium.indexed_iter() {
+                    visit(onward(tank, dir, false), true);
+                }
+            }
+            _ => visit(onward(tank, dir, false), true),
+        },
+        _ => {
+            let dir = match instr {
+                10 => dir.mirror_slash(),
+                11 => dir.mirror_backslash(),
+                12 => Direction::UpRight,
+                13 => Direction::DownRight,
+                14 => Direction::DownLeft,
+                _ => Direction::UpLeft,
+            };
+            visit(onward(tank, dir, false), true);
+        }
+    }
+}
+
+/// The cells of each tank that can execute.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reachability {
+    cols: usize,
+    /// For each tank in row-major order, each of its cells in row-major order.
+    executed: Vec<[bool; LEN]>,
+}
+
+impl Reachability {
+    /// The cells that can execute from where `program`'s instruction pointer is now.
+    pub fn of<I: Io>(program: &Program<I>) -> Self {
+        Self::explore(program, [State::of(program)])
+    }
+
+    /// The cells that can execute starting at any of `entries`, heading the given way.
+    pub fn from_entries<I: Io>(
+        program: &Program<I>,
+        entries: impl IntoIterator<Item = (Location, Direction)>,
+    ) -> Self {
+        let starts = entries.into_iter().filter_map(|(location, dir)| {
+            Some(State {
+                tank: location.tank,
+                ip: InstructionPointer::new(location.cell.0, location.cell.1)?,
+                dir,
+                skipping: false,
+            })
+        });
+        Self::explore(program, starts)
+    }
+
+    fn explore<I: Io>(program: &Program<I>, starts: impl IntoIterator<Item = State>) -> Self {
+        let aquarium = program.aquarium();
+        let mut reach = Self {
+            cols: aquarium.cols(),
+            executed: vec![[false; LEN]; aquarium.rows() * aquarium.cols()],
+        };
+        let mut seen = HashSet::new();
+        let mut pending: Vec<State> = starts
+            .into_iter()
+            .filter(|state| aquarium.get(state.tank.0, state.tank.1).is_some())
+            .collect();
+        while let Some(state) = pending.pop() {
+            if !seen.insert(state) {
+                continue;
+            }
+            let (tank, ip) = (state.tank, state.ip);
+            reach.executed[tank.0 * reach.cols + tank.1][ip.row() * COLS + ip.col()] = true;
+            successors(program, &state, |next, _| pending.push(next));
+        }
+        reach
+    }
+
+    /// Whether the cell at `location` can execute.
+    pub fn is_reachable(&self, location: Location) -> bool {
+        let (row, col) = location.cell;
+        self.executed
+            .get(location.tank.0 * self.cols + location.tank.1)
+            .is_some_and(|cells| col < COLS && row * COLS + col < LEN && cells[row * COLS + col])
+    }
+
+    /// Whether any cell of the tank at `tank` can execute.
+    pub fn entered(&self, tank: (usize, usize)) -> bool {
+        self.executed
+            .get(tank.0 * self.cols + tank.1)
+            .is_some_and(|cells| cells.contains(&true))
+    }
+
+    /// Every cell of `program` that isn't blank but can never execute, tank by tank. Blank
+    /// cells are left out, since they're only padding.
+    pub fn unreachable<'a, I: Io>(
+        &'a self,
+        program: &'a Program<I>,
+    ) -> impl Iterator<Item = Location> + 'a {
+        program
+            .aquarium()
+            .indexed_iter()
+            .flat_map(move |(position, tank)| {
+                tank.digits()
+                    .indexed_iter()
+                    .filter(|&(_, digit)| digit != 0)
+                    .map(move |(cell, _)| Location {
+                        tank: position,
+                        cell,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .filter(|&location| !self.is_reachable(location))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{cells::Cells, dialect::EdgeMode, io::MockIo, program::Tank};
+
+    fn aquarium(tanks: &[(&str, [usize; LEN])]) -> Program<MockIo> {
+        let tanks = tanks
+            .iter()
+            .map(|&(name, cells)| Tank::new(name.into(), Cells::new(cells)))
+            .collect();
+        Program::build_aquarium(tanks, MockIo::default())
+    }
+
+    fn at(tank: (usize, usize), row: usize, col: usize) -> Location {
+        Location {
+            tank,
+            cell: (row, col),
+        }
+    }
+
+    #[test]
+    fn test_reachability() {
+        // Right along the top row, down, then left round the second row forever, so the
+        // top-right cell and the other tank are never reached.
+        let mut cells = [6; LEN];
+        cells[..8].copy_from_slice(&[3, 3, 1, 5, 0, 0, 4, 5]);
+        let program = aquarium(&[("a", cells), ("b", [5; LEN])]);
+        let reach = Reachability::of(&program);
+        assert!(reach.is_reachable(at((0, 0), 1, 3)));
+        assert!(!reach.is_reachable(at((0, 0), 0, 3)));
+        assert!(reach.entered((0, 0)));
+        assert!(!reach.entered((0, 1)));
+        let unreachable: Vec<_> = reach.unreachable(&program).collect();
+        assert_eq!(unreachable.len(), 1 + 12 + 20);
+        assert_eq!(unreachable[0], at((0, 0), 0, 3));
+
+        // The cell after a tunnel only runs if the tunnel can skip the one before it.
+        let mut cells = [0; LEN];
+        cells[..3].copy_from_slice(&[7, 1, 5]);
+        let mut program = aquarium(&[("a", cells), ("b", [5; LEN])]);
+        let reach = Reachability::of(&program);
+        assert!(reach.is_reachable(at((0, 0), 0, 2)));
+        assert!(reach.is_reachable(at((0, 0), 3, 1)));
+        assert!(!reach.is_reachable(at((0, 0), 3, 0)));
+        assert!(!reach.entered((0, 1)));
+
+        // Going off the edge leads into the other tank when edges hop.
+        program.dialect.edges = EdgeMode::Hop;
+        assert!(Reachability::of(&program).entered((0, 1)));
+        let reach = Reachability::from_entries(&program, [(at((0, 1), 2, 2), Direction::Up)]);
+        assert!(reach.is_reachable(at((0, 1), 0, 2)));
+        assert!(!reach.entered((0, 0)));
+    }
+}
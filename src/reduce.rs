@@ -0,0 +1,174 @@
+use thiserror::Error;
+
+use crate::{
+    builder::ProgramBuilder,
+    parser::parse_pragmas,
+    program::HaltReason,
+    runner::{Ending, Limits, run_captured},
+};
+
+/// The kind of failure a reduction is meant to preserve.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    /// The interpreter panics.
+    Panics,
+    /// The program stops with a runtime error.
+    Errors,
+    /// The program runs into the step limit.
+    Diverges,
+}
+
+impl Failure {
+    fn matches(self, ending: &Ending) -> bool {
+        match self {
+            Failure::Panics => matches!(ending, Ending::Panic(_)),
+            Failure::Errors => matches!(ending, Ending::Error(_)),
+            Failure::Diverges => matches!(ending, Ending::Halted(HaltReason::StepLimit)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum ReduceError {
+    #[error("the original program does not show the failure")]
+    NotFailing,
+}
+
+/// Shrinks a failing program to a smaller one that fails the same way.
+///
+/// The program is treated as its list of names. Whole runs of names are removed first, halving
+/// the run length each time nothing more can go, and then single letters are removed from the
+/// names that remain. Every candidate is rerun under the limits, and candidates that no longer
+/// parse are simply rejected.
+#[derive(Debug, Clone)]
+pub struct Reducer {
+    failure: Failure,
+    input: Vec<u8>,
+    limits: Limits,
+    extensions: bool,
+}
+
+impl Reducer {
+    pub fn new(failure: Failure) -> Self {
+        Self {
+            failure,
+            input: Vec::new(),
+            limits: Limits::steps(100_000),
+            extensions: false,
+        }
+    }
+
+    pub fn input(mut self, input: impl Into<Vec<u8>>) -> Self {
+        self.input = input.into();
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn extensions(mut self, enabled: bool) -> Self {
+        self.extensions = enabled;
+        self
+    }
+
+    /// Whether `code` shows the failure.
+    pub fn fails(&self, code: &str) -> bool {
+        let builder = ProgramBuilder::new(code).extensions(self.extensions);
+        run_captured(builder, self.input.clone(), self.limits)
+            .is_ok_and(|outcome| self.failure.matches(&outcome.ending))
+    }
+
+    /// Returns the reduced program, with its names separated by single spaces after any
+    /// pragma lines.
+    pub fn reduce(&self, code: &str) -> Result<String, ReduceError> {
+        if !self.fails(code) {
+            return Err(ReduceError::NotFailing);
+        }
+        // Pragmas stay as they are; only the names are reduced.
+        let header = match parse_pragmas(code) {
+            Ok((_, body)) => &code[..code.len() - body.len()],
+            Err(_) => "",
+        };
+        let body = &code[header.len()..];
+        let fails = |names: &[String]| self.fails(&format!("{header}{}", names.join(" ")));
+        let mut names: Vec<String> = body
+            .split(|c: char| !(c.is_ascii_lowercase() || c == '\''))
+            .filter(|word| !word.is_empty())
+            .map(String::from)
+            .collect();
+        // Even with nothing removed, normalizing the separators can change the program, since
+        // the parser may reject what the tokenizer above accepted.
+        if !fails(&names) {
+            return Ok(code.to_string());
+        }
+
+        let mut chunk = names.len().div_ceil(2);
+        while chunk > 0 {
+            let mut start = 0;
+            let mut removed = false;
+            while start < names.len() {
+                let end = (start + chunk).min(names.len());
+                let candidate: Vec<_> = [&names[..start], &names[end..]].concat();
+                if !candidate.is_empty() && fails(&candidate) {
+                    names = candidate;
+                    removed = true;
+                } else {
+                    start = end;
+                }
+            }
+            if !removed {
+                chunk /= 2;
+            }
+        }
+
+        let mut shrunk = true;
+        while shrunk {
+            shrunk = false;
+            for i in 0..names.len() {
+                let mut at = 0;
+                while at < names[i].len() && names[i].len() > 1 {
+                    let mut candidate = names.clone();
+                    candidate[i].remove(at);
+                    if fails(&candidate) {
+                        names = candidate;
+                        shrunk = true;
+                    } else {
+                        at += 1;
+                    }
+                }
+            }
+        }
+        Ok(format!("{header}{}", names.join(" ")))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_reduce_divergence() {
+        let reducer = Reducer::new(Failure::Diverges).limits(Limits::steps(100));
+        assert_eq!(reducer.reduce("ebbbbbbbbb"), Err(ReduceError::NotFailing));
+        // Most single letters loop forever on their own.
+        let reduced = reducer.reduce("ebbbbbbbbb, fish and chips.").unwrap();
+        assert_eq!(reduced.len(), 1);
+        assert!(reducer.fails(&reduced));
+    }
+
+    #[test]
+    fn test_reduce_error() {
+        let reducer = Reducer::new(Failure::Errors).limits(Limits::steps(1000));
+        assert!(
+            !reducer.fails("fish fish"),
+            "programs that don't build don't count"
+        );
+        // Nine `b`s put a 9 in the first cell, calling the unknown `b`.
+        let reduced = reducer.reduce("cod bbbbbbbbb, more fish").unwrap();
+        assert_eq!(reduced, "bbbbbbbbb");
+        let reduced = reducer.reduce("#!font standard\ncod bbbbbbbbb").unwrap();
+        assert_eq!(reduced, "#!font standard\nbbbbbbbbb");
+    }
+}
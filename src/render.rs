@@ -0,0 +1,587 @@
+//! Drawing the aquarium as text, for terminals and screenshots.
+//!
+//! Tanks are drawn in their aquarium positions as five rows of four digits, with `.` for the
+//! blank digit 0 and `>` before the cell the instruction pointer is on. A label above each tank
+//! can give its name, its row and column, and its call letter:
+//!
+//! ```text
+//! ab 0,0 [a]   cd 0,1 [c]
+//! >1  .  .  .   .  .  .  1
+//!  1  2  2  1   .  2  2  1
+//! ...
+//! ```
+//!
+//! With the `color` feature, [`RenderOptions::color`] also colors each cell by its
+//! [`CellClass`] using ANSI escapes, and highlights the instruction pointer's cell, in red while
+//! a failed tunnel is still skipping instructions.
+//!
+//! [`RenderOptions::dead_code`] shades the cells [`Reachability`] finds can never execute:
+//! dimmed with color, or drawn as `-` without it.
+//!
+//! [`render_compact`] instead draws each tank as a bitmap of its nonzero digits, packing several
+//! cells into each character so that large aquariums fit on one screen, and [`render_svg`]
+//! draws a standalone SVG image, for assembling into videos. For aquariums too big even for
+//! that, [`render_minimap`] draws one character per tank, to show where the pointer is and
+//! where it has been.
+//!
+//! [`render_stack`] lists the stack rather than the aquarium, decoding each value.
+
+use std::fmt::Write;
+
+use crate::{debugger::Location, io::Io, program::Program, reach::Reachability};
+
+/// What to draw besides the digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RenderOptions {
+    pub names: bool,
+    /// Each tank's row and column in the aquarium.
+    pub coordinates: bool,
+    /// Each tank's call letter, in brackets.
+    pub call_letters: bool,
+    /// Marks the instruction pointer's cell in the current tank.
+    pub pointer: bool,
+    /// Colors the cells with ANSI escapes. Ignored without the `color` feature.
+    pub color: bool,
+    /// Shades the cells that can never execute from where the instruction pointer is.
+    pub dead_code: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            names: true,
+            coordinates: true,
+            call_letters: true,
+            pointer: true,
+            color: false,
+            dead_code: false,
+        }
+    }
+}
+
+impl RenderOptions {
+    /// Just the digits, with no labels or pointer.
+    pub fn plain() -> Self {
+        Self {
+            names: false,
+            coordinates: false,
+            call_letters: false,
+            pointer: false,
+            color: false,
+            dead_code: false,
+        }
+    }
+
+    fn labelled(&self) -> bool {
+        self.names || self.coordinates || self.call_letters
+    }
+}
+
+/// The kind of instruction a cell decodes to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CellClass {
+    Blank,
+    /// Up, down, left and right.
+    Move,
+    /// Pushing the accumulator and cycling.
+    Stack,
+    /// Tunnels and hops.
+    Branch,
+    Call,
+    /// Mirrors and diagonal turns, with extensions enabled.
+    Extension,
+}
+
+impl CellClass {
+    pub fn of(cell: usize, extensions: bool) -> Self {
+        match cell {
+            10..=15 if extensions => CellClass::Extension,
+            _ => match cell % 10 {
+                0 => CellClass::Blank,
+                1..=4 => CellClass::Move,
+                5 | 6 => CellClass::Stack,
+                7 | 8 => CellClass::Branch,
+                _ => CellClass::Call,
+            },
+        }
+    }
+}
+
+/// Wraps a cell's character in the escapes for its class and pointer state. Dead cells are
+/// dimmed whatever their class.
+#[cfg(feature = "color")]
+fn paint(text: char, class: CellClass, dead: bool, pointer: Option<bool>) -> String {
+    use anstyle::{AnsiColor, Style};
+
+    let style = match class {
+        _ if dead => Style::new().dimmed(),
+        CellClass::Blank => Style::new().dimmed(),
+        CellClass::Move => AnsiColor::Blue.on_default(),
+        CellClass::Stack => AnsiColor::Green.on_default(),
+        CellClass::Branch => AnsiColor::Yellow.on_default(),
+        CellClass::Call => AnsiColor::Magenta.on_default().bold(),
+        CellClass::Extension => AnsiColor::Cyan.on_default(),
+    };
+    let style = match pointer {
+        Some(false) => style.invert(),
+        Some(true) => style.bg_color(Some(AnsiColor::Red.into())),
+        None => style,
+    };
+    format!("{style}{text}{style:#}")
+}
+
+/// A line of output and how many columns it takes up, not counting escapes.
+struct Line {
+    text: String,
+    width: usize,
+}
+
+impl Line {
+    fn push(&mut self, c: char) {
+        self.text.push(c);
+        self.width += 1;
+    }
+}
+
+/// One tank's label, if any, and its five rows of cells.
+fn tank_lines<I: Io>(
+    program: &Program<I>,
+    (row, col): (usize, usize),
+    options: &RenderOptions,
+    reach: Option<&Reachability>,
+) -> Vec<Line> {
+    let tank = &program.aquarium()[(row, col)];
+    let mut lines = Vec::with_capacity(6);
+    if options.labelled() {
+        let mut parts = Vec::new();
+        if options.names {
+            parts.push(tank.name().to_string());
+        }
+        if options.coordinates {
+            parts.push(format!("{row},{col}"));
+        }
+        if options.call_letters {
+            parts.push(format!("[{}]", tank.call_letter()));
+        }
+        let text = parts.join(" ");
+        let width = text.chars().count();
+        lines.push(Line { text, width });
+    }
+    let ip = (program.ftp() == (row, col)).then(|| (program.ip().row(), program.ip().col()));
+    for (r, cells) in tank.grid().iter_rows().enumerate() {
+        let mut line = Line {
+            text: String::new(),
+            width: 0,
+        };
+        for (c, cell) in cells.enumerate() {
+            if c > 0 {
+                line.push(' ');
+            }
+            let here = ip == Some((r, c));
+            if options.pointer {
+                line.push(if here { '>' } else { ' ' });
+            }
+            let dead = reach.is_some_and(|reach| {
+                cell % 10 != 0
+                    && !reach.is_reachable(Location {
+                        tank: (row, col),
+                        cell: (r, c),
+                    })
+            });
+            let text = match cell % 10 {
+                0 => '.',
+                _ if dead && !(cfg!(feature = "color") && options.color) => '-',
+                digit => char::from(b'0' + digit as u8),
+            };
+            #[cfg(feature = "color")]
+            if options.color {
+                let class = CellClass::of(cell, program.extensions);
+                let pointer = (options.pointer && here).then_some(program.trampoline > 0);
+                line.text.push_str(&paint(text, class, dead, pointer));
+                line.width += 1;
+                continue;
+            }
+            line.push(text);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+pub fn render<I: Io>(program: &Program<I>, options: &RenderOptions) -> String {
+    let aquarium = program.aquarium();
+    let reach = options.dead_code.then(|| Reachability::of(program));
+    let tanks: Vec<Vec<Line>> = aquarium
+        .indexed_iter()
+        .map(|(position, _)| tank_lines(program, position, options, reach.as_ref()))
+        .collect();
+    let width = tanks
+        .iter()
+        .flatten()
+        .map(|line| line.width)
+        .max()
+        .unwrap_or(0);
+
+    let mut out = String::new();
+    for (row, tanks) in tanks.chunks(aquarium.cols()).enumerate() {
+        if row > 0 {
+            out.push('\n');
+        }
+        for i in 0..tanks[0].len() {
+            let mut line = String::new();
+            for (col, lines) in tanks.iter().enumerate() {
+                if col > 0 {
+                    line.push_str("  ");
+                }
+                line.push_str(&lines[i].text);
+                line.extend(std::iter::repeat_n(' ', width - lines[i].width));
+            }
+            writeln!(out, "{}", line.trim_end()).unwrap();
+        }
+    }
+    out
+}
+
+/// Characters that draw several cells each, for [`render_compact`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Compact {
+    /// Braille patterns, two cells wide and four tall.
+    Braille,
+    /// Half blocks, one cell wide and two tall.
+    HalfBlock,
+}
+
+/// Draws the aquarium as a bitmap with a dot for every nonzero digit, so even a large
+/// aquarium fits on screen. Tanks are separated by one blank cell.
+pub fn render_compact<I: Io>(program: &Program<I>, style: Compact) -> String {
+    let aquarium = program.aquarium();
+    let height = aquarium.rows() * 6 - 1;
+    let width = aquarium.cols() * 5 - 1;
+    let mut pixels = vec![vec![false; width]; height];
+    for ((row, col), tank) in aquarium.indexed_iter() {
+        for ((r, c), cell) in tank.grid().indexed_iter() {
+            pixels[row * 6 + r][col * 5 + c] = cell % 10 != 0;
+        }
+    }
+    let pixel = |r: usize, c: usize| pixels.get(r).and_then(|row| row.get(c)) == Some(&true);
+
+    let (block_height, block_width) = match style {
+        Compact::Braille => (4, 2),
+        Compact::HalfBlock => (2, 1),
+    };
+    let mut out = String::new();
+    for r in (0..height).step_by(block_height) {
+        let mut line = String::new();
+        for c in (0..width).step_by(block_width) {
+            line.push(match style {
+                Compact::Braille => {
+                    // Braille numbers its dots down the left column, down the right column,
+                    // and then across the bottom row.
+                    const DOTS: [[u32; 2]; 4] =
+                        [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+                    let mut bits = 0;
+                    for (dr, row) in DOTS.iter().enumerate() {
+                        for (dc, bit) in row.iter().enumerate() {
+                            if pixel(r + dr, c + dc) {
+                                bits |= bit;
+                            }
+                        }
+                    }
+                    char::from_u32(0x2800 + bits).unwrap()
+                }
+                Compact::HalfBlock => match (pixel(r, c), pixel(r + 1, c)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                },
+            });
+        }
+        writeln!(out, "{}", line.trim_end()).unwrap();
+    }
+    out
+}
+
+/// Lists the stack top first, one value per line as decimal, hex and the byte `o` would
+/// write last, with `>` marking the values the next step will pop or move:
+///
+/// ```text
+/// > 2  72  0x48  'H'
+/// > 1  -1  -0x1  '\xff'
+///   0  10   0xa  '\n'
+/// ```
+///
+/// The first column is each value's index from the bottom.
+pub fn render_stack<I: Io>(program: &Program<I>) -> String {
+    let stack = program.stack();
+    let consumed = program.consumed();
+    let rows: Vec<_> = stack
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(i, &value)| {
+            let hex = if value < 0 {
+                format!("-{:#x}", value.unsigned_abs())
+            } else {
+                format!("{value:#x}")
+            };
+            let byte = std::ascii::escape_default(value as u8).to_string();
+            let marker = if i >= stack.len() - consumed {
+                '>'
+            } else {
+                ' '
+            };
+            (marker, i.to_string(), value.to_string(), hex, byte)
+        })
+        .collect();
+    let width = |column: fn(&(char, String, String, String, String)) -> &String| {
+        rows.iter().map(|row| column(row).len()).max().unwrap_or(0)
+    };
+    let index_width = width(|row| &row.1);
+    let value_width = width(|row| &row.2);
+    let hex_width = width(|row| &row.3);
+    let mut out = String::new();
+    for (marker, index, value, hex, byte) in &rows {
+        writeln!(
+            out,
+            "{marker} {index:<index_width$}  {value:>value_width$}  {hex:>hex_width$}  '{byte}'"
+        )
+        .unwrap();
+    }
+    out
+}
+
+/// Trail marks for [`render_minimap`], most recent first.
+const TRAIL: [char; 4] = ['█', '▓', '▒', '░'];
+
+/// Draws the aquarium as one character per tank: `@` for the current tank, `·` for the rest,
+/// and a fading trail through the tanks in `trail`, which lists the most recently visited
+/// first. Tanks past the fourth in the trail are left out.
+pub fn render_minimap<I: Io>(program: &Program<I>, trail: &[(usize, usize)]) -> String {
+    let aquarium = program.aquarium();
+    let mut map = vec![vec!['·'; aquarium.cols()]; aquarium.rows()];
+    for (&(row, col), mark) in trail.iter().zip(TRAIL).rev() {
+        if let Some(cell) = map.get_mut(row).and_then(|line| line.get_mut(col)) {
+            *cell = mark;
+        }
+    }
+    let (row, col) = program.ftp();
+    map[row][col] = '@';
+    map.into_iter()
+        .map(|line| line.into_iter().chain(['\n']).collect::<String>())
+        .collect()
+}
+
+/// Pixels per cell in [`render_svg`], and between tanks.
+const SVG_CELL: usize = 24;
+/// Pixels above each tank for its label.
+const SVG_LABEL: usize = 20;
+
+/// Draws the aquarium as an SVG image: each tank labelled with its name and call letter, its
+/// cells colored by [`CellClass`] and the instruction pointer's cell outlined.
+pub fn render_svg<I: Io>(program: &Program<I>) -> String {
+    let aquarium = program.aquarium();
+    let tank_width = 4 * SVG_CELL;
+    let tank_height = SVG_LABEL + 5 * SVG_CELL;
+    let width = aquarium.cols() * (tank_width + SVG_CELL) + SVG_CELL;
+    let height = aquarium.rows() * (tank_height + SVG_CELL) + SVG_CELL;
+    let mut out = String::new();
+    writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+         font-family=\"monospace\" font-size=\"14\" text-anchor=\"middle\">"
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "<rect width=\"{width}\" height=\"{height}\" fill=\"#ffffff\"/>"
+    )
+    .unwrap();
+    for ((row, col), tank) in aquarium.indexed_iter() {
+        let x = SVG_CELL + col * (tank_width + SVG_CELL);
+        let y = SVG_CELL + row * (tank_height + SVG_CELL);
+        writeln!(
+            out,
+            "<text x=\"{}\" y=\"{}\">{} [{}]</text>",
+            x + tank_width / 2,
+            y + SVG_LABEL - 6,
+            tank.name(),
+            tank.call_letter()
+        )
+        .unwrap();
+        for ((r, c), cell) in tank.grid().indexed_iter() {
+            let (cx, cy) = (x + c * SVG_CELL, y + SVG_LABEL + r * SVG_CELL);
+            let here =
+                program.ftp() == (row, col) && (program.ip().row(), program.ip().col()) == (r, c);
+            let (stroke, stroke_width) = match (here, program.trampoline > 0) {
+                (true, false) => ("#000000", 3),
+                (true, true) => ("#d00000", 3),
+                (false, _) => ("#cccccc", 1),
+            };
+            writeln!(
+                out,
+                "<rect x=\"{cx}\" y=\"{cy}\" width=\"{SVG_CELL}\" height=\"{SVG_CELL}\" \
+                 fill=\"#ffffff\" stroke=\"{stroke}\" stroke-width=\"{stroke_width}\"/>"
+            )
+            .unwrap();
+            if cell % 10 != 0 {
+                writeln!(
+                    out,
+                    "<text x=\"{}\" y=\"{}\" fill=\"{}\">{}</text>",
+                    cx + SVG_CELL / 2,
+                    cy + SVG_CELL - 7,
+                    svg_color(CellClass::of(cell, program.extensions)),
+                    cell % 10
+                )
+                .unwrap();
+            }
+        }
+    }
+    writeln!(out, "</svg>").unwrap();
+    out
+}
+
+fn svg_color(class: CellClass) -> &'static str {
+    match class {
+        CellClass::Blank => "#bbbbbb",
+        CellClass::Move => "#1f5fbf",
+        CellClass::Stack => "#1f8f3f",
+        CellClass::Branch => "#a07000",
+        CellClass::Call => "#a0209f",
+        CellClass::Extension => "#108f9f",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::MockIo;
+
+    #[test]
+    fn test_render() {
+        let program = Program::with_io("cd ab", MockIo::default()).unwrap();
+        assert_eq!(
+            render(&program, &RenderOptions::default()),
+            "\
+ab 0,0 [a]   cd 0,1 [c]
+>1  .  .  .   .  .  .  1
+ 1  2  2  1   .  2  2  1
+ 2  .  .  2   2  .  .  1
+ 2  .  .  2   2  .  .  1
+ 1  2  2  1   .  2  2  1
+"
+        );
+        assert_eq!(
+            render(&program, &RenderOptions::plain()),
+            "\
+1 . . .  . . . 1
+1 2 2 1  . 2 2 1
+2 . . 2  2 . . 1
+2 . . 2  2 . . 1
+1 2 2 1  . 2 2 1
+"
+        );
+    }
+
+    #[test]
+    fn test_render_compact() {
+        let program = Program::with_io("ab cd", MockIo::default()).unwrap();
+        assert_eq!(
+            render_compact(&program, Compact::HalfBlock),
+            "\
+█▄▄▄  ▄▄█
+█  █ █  █
+▀▀▀▀  ▀▀▀
+"
+        );
+        assert_eq!(render_compact(&program, Compact::Braille), "⡗⢲⢠⠒⡇\n⠉⠉⠀⠉⠁\n");
+    }
+
+    #[test]
+    fn test_render_minimap() {
+        let program = Program::with_io("a b c d e f", MockIo::default()).unwrap();
+        assert_eq!(
+            render_minimap(&program, &[(1, 2), (0, 0), (1, 0), (0, 1), (1, 1)]),
+            "@░·\n▒·█\n"
+        );
+    }
+
+    #[test]
+    fn test_render_stack() {
+        // The first instruction is a cycle, whose first operation subtracts.
+        let mut program = Program::with_io("kkkkkk", MockIo::default()).unwrap();
+        assert_eq!(render_stack(&program), "");
+        program.stack_mut().extend([10, -1, 72]);
+        assert_eq!(
+            render_stack(&program),
+            "\
+> 2  72  0x48  'H'
+> 1  -1  -0x1  '\\xff'
+  0  10   0xa  '\\n'
+"
+        );
+    }
+
+    #[test]
+    fn test_render_svg() {
+        let program = Program::with_io("ab cd", MockIo::default()).unwrap();
+        let svg = render_svg(&program);
+        assert!(
+            svg.starts_with(
+                "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"264\" height=\"188\""
+            )
+        );
+        assert!(svg.contains(">cd [c]</text>"));
+        assert_eq!(svg.matches("<rect").count(), 1 + 40);
+        assert_eq!(svg.matches("stroke-width=\"3\"").count(), 1);
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+
+    #[test]
+    fn test_cell_classes() {
+        let classes = [0, 3, 15, 6, 17, 29].map(|cell| CellClass::of(cell, false));
+        assert_eq!(
+            classes,
+            [
+                CellClass::Blank,
+                CellClass::Move,
+                CellClass::Stack,
+                CellClass::Stack,
+                CellClass::Branch,
+                CellClass::Call
+            ]
+        );
+        assert_eq!(CellClass::of(15, true), CellClass::Extension);
+    }
+
+    #[test]
+    fn test_dead_code() {
+        // The pointer bounces between the first two rows of the first column.
+        let program = Program::with_io("ab", MockIo::default()).unwrap();
+        let options = RenderOptions {
+            dead_code: true,
+            ..RenderOptions::plain()
+        };
+        assert_eq!(
+            render(&program, &options),
+            "1 . . .\n1 - - -\n2 . . -\n- . . -\n- - - -\n"
+        );
+    }
+
+    #[cfg(feature = "color")]
+    #[test]
+    fn test_color() {
+        let program = Program::with_io("ab", MockIo::default()).unwrap();
+        let options = RenderOptions {
+            color: true,
+            ..RenderOptions::default()
+        };
+        let colored = render(&program, &options);
+        assert!(colored.contains("\x1b["));
+        let first = colored.lines().nth(1).unwrap();
+        // The pointer's cell is inverted, and blank cells are dimmed.
+        assert!(first.starts_with(">\x1b[7m\x1b[34m1\x1b[0m"), "{first:?}");
+        assert!(first.ends_with("\x1b[2m.\x1b[0m"), "{first:?}");
+        assert_eq!(colored.lines().count(), 6);
+    }
+}
@@ -0,0 +1,165 @@
+//! Replay bundles: everything needed to reproduce a run exactly, in one file.
+//!
+//! A bundle holds the program's code, the seed its `y` calls used, every byte of input it
+//! read, and the command-line options that shaped it. The format starts with text lines and
+//! stores each option, the code and the input as length-prefixed raw bytes, so none of them
+//! needs escaping:
+//!
+//! ```text
+//! pufferfish-replay 1
+//! seed 1234
+//! arg 7
+//! --edges
+//! arg 6
+//! bounce
+//! code 10
+//! ebbbbbbbbb
+//! input 3
+//! abc
+//! ```
+
+use thiserror::Error;
+
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bundle {
+    pub seed: u64,
+    /// Interpreter options, one shell word per entry, not including the program or its input.
+    pub args: Vec<String>,
+    pub code: String,
+    pub input: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum BundleError {
+    #[error("not a pufferfish replay bundle")]
+    NotABundle,
+    #[error("unsupported replay bundle version {0}")]
+    UnsupportedVersion(String),
+    #[error("malformed replay bundle: {0}")]
+    Malformed(&'static str),
+}
+
+impl Bundle {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = format!("pufferfish-replay {VERSION}\nseed {}\n", self.seed).into_bytes();
+        for arg in &self.args {
+            out.extend(format!("arg {}\n", arg.len()).bytes());
+            out.extend(arg.bytes());
+            out.push(b'\n');
+        }
+        out.extend(format!("code {}\n", self.code.len()).bytes());
+        out.extend(self.code.bytes());
+        out.extend(format!("\ninput {}\n", self.input.len()).bytes());
+        out.extend(&self.input);
+        out.push(b'\n');
+        out
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, BundleError> {
+        let mut reader = Reader { bytes };
+        match reader.line()?.split_once(' ') {
+            Some(("pufferfish-replay", version)) if version == VERSION.to_string() => {}
+            Some(("pufferfish-replay", version)) => {
+                return Err(BundleError::UnsupportedVersion(version.to_string()));
+            }
+            _ => return Err(BundleError::NotABundle),
+        }
+        let seed = reader
+            .line()?
+            .strip_prefix("seed ")
+            .and_then(|seed| seed.parse().ok())
+            .ok_or(BundleError::Malformed("expected a seed"))?;
+        let mut args = Vec::new();
+        let code = loop {
+            let line = reader.line()?;
+            if let Some(len) = line.strip_prefix("arg ") {
+                let arg = reader.block(len)?;
+                args.push(
+                    String::from_utf8(arg.to_vec())
+                        .map_err(|_| BundleError::Malformed("arg is not UTF-8"))?,
+                );
+            } else if let Some(len) = line.strip_prefix("code ") {
+                let code = reader.block(len)?;
+                break String::from_utf8(code.to_vec())
+                    .map_err(|_| BundleError::Malformed("code is not UTF-8"))?;
+            } else {
+                return Err(BundleError::Malformed("expected an arg or the code"));
+            }
+        };
+        let len = reader
+            .line()?
+            .strip_prefix("input ")
+            .ok_or(BundleError::Malformed("expected the input"))?;
+        let input = reader.block(len)?.to_vec();
+        Ok(Self {
+            seed,
+            args,
+            code,
+            input,
+        })
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn line(&mut self) -> Result<&'a str, BundleError> {
+        let end = self
+            .bytes
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or(BundleError::Malformed("unexpected end of bundle"))?;
+        let line = std::str::from_utf8(&self.bytes[..end])
+            .map_err(|_| BundleError::Malformed("header line is not UTF-8"))?;
+        self.bytes = &self.bytes[end + 1..];
+        Ok(line)
+    }
+
+    /// Reads `len` raw bytes and the newline after them.
+    fn block(&mut self, len: &str) -> Result<&'a [u8], BundleError> {
+        let len: usize = len
+            .parse()
+            .map_err(|_| BundleError::Malformed("invalid length"))?;
+        if self.bytes.len() <= len || self.bytes[len] != b'\n' {
+            return Err(BundleError::Malformed("block is shorter than its length"));
+        }
+        let block = &self.bytes[..len];
+        self.bytes = &self.bytes[len + 1..];
+        Ok(block)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let bundle = Bundle {
+            seed: 99,
+            args: vec![
+                String::from("--arg"),
+                String::from("two words"),
+                String::from("two\nlines"),
+                String::new(),
+            ],
+            code: String::from("#!edges hop\nab\ncd"),
+            input: vec![0, b'\n', 255],
+        };
+        assert_eq!(Bundle::parse(&bundle.to_bytes()), Ok(bundle.clone()));
+        let mut truncated = bundle.to_bytes();
+        truncated.truncate(truncated.len() - 2);
+        assert_eq!(
+            Bundle::parse(&truncated),
+            Err(BundleError::Malformed("block is shorter than its length"))
+        );
+        assert_eq!(
+            Bundle::parse(b"pufferfish-replay 2\n"),
+            Err(BundleError::UnsupportedVersion(String::from("2")))
+        );
+    }
+}
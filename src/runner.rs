@@ -0,0 +1,254 @@
+use std::{
+    panic::{AssertUnwindSafe, catch_unwind},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    builder::ProgramBuilder,
+    io::{Io, MockIo},
+    program::{HaltReason, RuntimeError, Status},
+};
+
+/// Resource limits for a captured run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Limits {
+    pub max_steps: Option<u64>,
+    /// Applied as the program's output limit, so reaching it halts with
+    /// [`HaltReason::OutputLimit`].
+    pub max_output: Option<u64>,
+}
+
+impl Limits {
+    pub fn steps(max_steps: u64) -> Self {
+        Self {
+            max_steps: Some(max_steps),
+            max_output: None,
+        }
+    }
+
+    /// Also limits output to `max_output` bytes.
+    pub fn output(self, max_output: u64) -> Self {
+        Self {
+            max_output: Some(max_output),
+            ..self
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self::steps(1_000_000)
+    }
+}
+
+/// Paces steps to a steady rate, for demos and screen recordings.
+///
+/// Each step is due at a fixed offset from the start rather than a fixed time after the last
+/// one, so time spent executing steps or oversleeping doesn't add up to drift. Pauses shorter
+/// than [`Pacer::MIN_SLEEP`] are skipped and made up later, so fast rates sleep once every few
+/// steps instead of jittering on sleeps too short for the OS to honor. If the program falls
+/// far behind, say while waiting for input, the pacer starts over from the current step rather
+/// than rushing to catch up.
+#[derive(Debug, Clone)]
+pub struct Pacer {
+    start: Instant,
+    interval: Duration,
+    steps: u32,
+}
+
+impl Pacer {
+    pub const MIN_SLEEP: Duration = Duration::from_millis(2);
+    const MAX_LAG: Duration = Duration::from_millis(500);
+
+    /// # Panics
+    ///
+    /// If `steps_per_second` isn't positive and finite.
+    pub fn new(steps_per_second: f64) -> Self {
+        assert!(
+            steps_per_second.is_finite() && steps_per_second > 0.0,
+            "invalid step rate {steps_per_second}"
+        );
+        Self {
+            start: Instant::now(),
+            interval: Duration::from_secs_f64(1.0 / steps_per_second),
+            steps: 0,
+        }
+    }
+
+    /// Counts a step and returns how long to wait before taking it, if long enough to sleep.
+    pub fn delay(&mut self) -> Option<Duration> {
+        self.delay_at(Instant::now())
+    }
+
+    fn delay_at(&mut self, now: Instant) -> Option<Duration> {
+        let due = self.start + self.interval * self.steps;
+        if now > due + Self::MAX_LAG {
+            self.start = now;
+            self.steps = 0;
+        }
+        self.steps = self.steps.saturating_add(1);
+        due.checked_duration_since(now)
+            .filter(|&delay| delay >= Self::MIN_SLEEP)
+    }
+}
+
+/// How a captured run ended.
+#[derive(Debug)]
+pub enum Ending {
+    Halted(HaltReason),
+    Error(RuntimeError),
+    /// The interpreter panicked; holds the panic message.
+    Panic(String),
+}
+
+impl Ending {
+    pub fn is_exit(&self) -> bool {
+        matches!(self, Ending::Halted(HaltReason::Exit))
+    }
+}
+
+/// The result of a captured run.
+#[derive(Debug)]
+pub struct Outcome {
+    pub output: Vec<u8>,
+    /// The lines written by `d` calls and tracepoints.
+    pub debug_lines: Vec<String>,
+    pub steps: u64,
+    pub ending: Ending,
+}
+
+/// Runs a program against fixed input, capturing its output instead of touching the terminal.
+///
+/// Any I/O set on `builder` is replaced. Panics inside the interpreter are caught and reported
+/// as [`Ending::Panic`], so callers can run untrusted or generated programs in bulk. Errors
+/// building the program are returned as `Err`.
+pub fn run_captured<I: Io>(
+    builder: ProgramBuilder<I>,
+    input: impl Into<Vec<u8>>,
+    limits: Limits,
+) -> Result<Outcome, crate::Error> {
+    let mut program = builder
+        .io(MockIo::new(input))
+        .step_limit(limits.max_steps)
+        .output_limit(limits.max_output)
+        .build()?;
+    let result = catch_unwind(AssertUnwindSafe(|| program.run()));
+    let ending = match result {
+        Ok(Ok(Status::Halted(reason))) => Ending::Halted(reason),
+        Ok(Ok(Status::Running | Status::OutOfFuel)) => {
+            unreachable!("run only returns once the program stops, and there is no fuel limit")
+        }
+        Ok(Err(err)) => Ending::Error(err),
+        Err(payload) => Ending::Panic(
+            payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| String::from("unknown panic")),
+        ),
+    };
+    let steps = program.steps();
+    let io = program.into_io();
+    Ok(Outcome {
+        steps,
+        debug_lines: io.debug_lines().to_vec(),
+        output: io.into_output(),
+        ending,
+    })
+}
+
+/// The result of [`eval`].
+#[derive(Debug)]
+pub struct EvalResult {
+    pub output: Vec<u8>,
+    /// The lines written by `d` calls and tracepoints.
+    pub debug_lines: Vec<String>,
+    /// How the run ended.
+    pub halt: Ending,
+    pub steps: u64,
+}
+
+impl EvalResult {
+    /// Whether the program ended through an `e` call.
+    pub fn is_exit(&self) -> bool {
+        self.halt.is_exit()
+    }
+}
+
+/// Runs `code` against `input` under `limits`, capturing what it writes: the one call an
+/// online judge or a test needs.
+///
+/// Debug calls and tracepoints are captured too, apart from the output, so they never reach
+/// the host's standard error. Otherwise it behaves like [`run_captured`] with the default
+/// builder.
+pub fn eval(
+    code: &str,
+    input: impl Into<Vec<u8>>,
+    limits: Limits,
+) -> Result<EvalResult, crate::Error> {
+    let outcome = run_captured(ProgramBuilder::new(code), input, limits)?;
+    Ok(EvalResult {
+        output: outcome.output,
+        debug_lines: outcome.debug_lines,
+        halt: outcome.ending,
+        steps: outcome.steps,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_run_captured_limits_steps() {
+        let outcome = run_captured(ProgramBuilder::new("ab"), "", Limits::steps(50)).unwrap();
+        assert!(matches!(
+            outcome.ending,
+            Ending::Halted(HaltReason::StepLimit)
+        ));
+        assert_eq!(outcome.steps, 50);
+        assert!(outcome.output.is_empty());
+    }
+
+    #[test]
+    fn test_eval() {
+        let result = eval("ab", "", Limits::steps(50)).unwrap();
+        assert!(matches!(result.halt, Ending::Halted(HaltReason::StepLimit)));
+        assert_eq!(result.steps, 50);
+        assert!(result.output.is_empty() && !result.is_exit());
+        assert!(eval("Y", "", Limits::default()).is_err());
+
+        // The first two cells this tank runs are both tracepoints.
+        const TRACER: &str = "xqpyqbdjgneeqljxmbgayughbkqhe";
+        let result = eval(TRACER, "", Limits::steps(3)).unwrap();
+        assert_eq!(
+            result.debug_lines,
+            [
+                format!("trace tank={TRACER} step=1 ftp=0,0 stack=[]"),
+                format!("trace tank={TRACER} step=2 ftp=0,0 stack=[]"),
+            ]
+        );
+        assert!(result.output.is_empty());
+    }
+
+    #[test]
+    fn test_pacer() {
+        let mut pacer = Pacer::new(100.0);
+        let start = pacer.start;
+        let delays: Vec<_> = (0..3).map(|_| pacer.delay_at(start)).collect();
+        let ms = Duration::from_millis;
+        assert_eq!(delays, [None, Some(ms(10)), Some(ms(20))]);
+        // Being a little late is made up for by the next delay.
+        assert_eq!(pacer.delay_at(start + ms(29)), None);
+        assert_eq!(pacer.delay_at(start + ms(29)), Some(ms(11)));
+        // Being far behind starts over.
+        assert_eq!(pacer.delay_at(start + ms(5000)), None);
+        assert_eq!(pacer.delay_at(start + ms(5000)), Some(ms(10)));
+
+        let mut fast = Pacer::new(10_000.0);
+        let start = fast.start;
+        let delays: Vec<_> = (0..21).map(|_| fast.delay_at(start)).collect();
+        assert_eq!(delays[19], None);
+        assert_eq!(delays[20], Some(ms(2)));
+    }
+}
@@ -0,0 +1,214 @@
+//! Restrictions on what a program may do, for running code you don't trust.
+
+use std::fmt;
+
+use crate::{program::CallKind, runner::Limits};
+
+/// Something a call can do to the world outside the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// Reading input with `i`, or checking for it with `p`.
+    Input,
+    /// Writing output with `o` or `w`.
+    Output,
+    /// Ending the program with `e`.
+    Exit,
+    /// Choosing a random direction with `y`.
+    Random,
+    /// Writing diagnostics with `d` or a tracepoint.
+    Debug,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 5] = [
+        Capability::Input,
+        Capability::Output,
+        Capability::Exit,
+        Capability::Random,
+        Capability::Debug,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::Input => "input",
+            Capability::Output => "output",
+            Capability::Exit => "exit",
+            Capability::Random => "random",
+            Capability::Debug => "debug",
+        }
+    }
+
+    pub fn by_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|cap| cap.name() == name)
+    }
+
+    /// The capability a call letter needs, if any. Calls that only touch the program's own
+    /// state need none.
+    pub fn of_call(letter: char) -> Option<Self> {
+        CallKind::of(letter).capability()
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// What happens when a program makes a call its sandbox forbids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Violation {
+    /// Stop with [`crate::program::RuntimeError::Forbidden`].
+    #[default]
+    Error,
+    /// Halt with [`crate::program::HaltReason::Forbidden`], as if the program had exited.
+    Halt,
+}
+
+/// How much of each capability a program may use over a whole run. A program that reaches
+/// a quota halts with the [`crate::program::HaltReason`] for it, rather than an error, so a
+/// host can tell which policy stopped it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Quotas {
+    /// Bytes `i` calls may read. The call that would read one more halts with
+    /// [`HaltReason::InputQuota`](crate::program::HaltReason::InputQuota) instead.
+    pub input_bytes: Option<u64>,
+    /// Bytes `o` and `w` calls may write. The write that would pass the quota is cut short
+    /// and halts with [`HaltReason::OutputQuota`](crate::program::HaltReason::OutputQuota).
+    ///
+    /// This caps the same count as the program's output limit, set on the builder or through
+    /// [`Limits::max_output`], and differs only in the halt reason. Use the output limit,
+    /// unless a host needs to tell its fair-use policy apart from a run's own limits.
+    pub output_bytes: Option<u64>,
+    /// `y` calls the program may make. The one past the quota halts with
+    /// [`HaltReason::RandomQuota`](crate::program::HaltReason::RandomQuota) instead.
+    pub random_calls: Option<u64>,
+    /// Hop instructions the program may run, not counting hops off the edge of a tank. The
+    /// one past the quota halts with [`HaltReason::HopQuota`](crate::program::HaltReason::HopQuota)
+    /// instead.
+    pub hops: Option<u64>,
+}
+
+impl Quotas {
+    /// No quotas at all.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn input_bytes(mut self, quota: u64) -> Self {
+        self.input_bytes = Some(quota);
+        self
+    }
+
+    pub fn output_bytes(mut self, quota: u64) -> Self {
+        self.output_bytes = Some(quota);
+        self
+    }
+
+    pub fn random_calls(mut self, quota: u64) -> Self {
+        self.random_calls = Some(quota);
+        self
+    }
+
+    pub fn hops(mut self, quota: u64) -> Self {
+        self.hops = Some(quota);
+        self
+    }
+}
+
+/// The calls a program may make, the limits it runs under, and what happens when it
+/// oversteps.
+///
+/// The default sandbox allows everything and sets no limits or quotas. Limits set here and on
+/// the builder both apply, so the tighter one wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Sandbox {
+    allowed: u8,
+    pub limits: Limits,
+    pub quotas: Quotas,
+    pub violation: Violation,
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Self {
+            allowed: u8::MAX,
+            limits: Limits {
+                max_steps: None,
+                max_output: None,
+            },
+            quotas: Quotas::default(),
+            violation: Violation::default(),
+        }
+    }
+}
+
+impl Sandbox {
+    /// A sandbox that allows every capability.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A sandbox that allows no capabilities, for programs that should only compute.
+    pub fn deny_all() -> Self {
+        Self {
+            allowed: 0,
+            ..Self::default()
+        }
+    }
+
+    pub fn allow(mut self, capability: Capability) -> Self {
+        self.allowed |= capability.bit();
+        self
+    }
+
+    pub fn deny(mut self, capability: Capability) -> Self {
+        self.allowed &= !capability.bit();
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn quotas(mut self, quotas: Quotas) -> Self {
+        self.quotas = quotas;
+        self
+    }
+
+    pub fn on_violation(mut self, violation: Violation) -> Self {
+        self.violation = violation;
+        self
+    }
+
+    pub fn allows(&self, capability: Capability) -> bool {
+        self.allowed & capability.bit() != 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_capabilities() {
+        let sandbox = Sandbox::new().deny(Capability::Input);
+        assert!(!sandbox.allows(Capability::Input));
+        assert!(sandbox.allows(Capability::Output));
+        let sandbox = Sandbox::deny_all().allow(Capability::Exit);
+        let allowed: Vec<_> = Capability::ALL
+            .into_iter()
+            .filter(|&cap| sandbox.allows(cap))
+            .collect();
+        assert_eq!(allowed, [Capability::Exit]);
+        for cap in Capability::ALL {
+            assert_eq!(Capability::by_name(cap.name()), Some(cap));
+        }
+        assert_eq!(Capability::of_call('a'), None);
+    }
+}
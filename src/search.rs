@@ -0,0 +1,356 @@
+use std::{collections::HashSet, thread};
+
+use rand::{prelude::*, rngs::StdRng};
+
+use crate::{
+    builder::ProgramBuilder,
+    parser::is_name,
+    program::seeded_rng,
+    runner::{Ending, Limits, run_captured},
+};
+
+/// How close a candidate came to the target. Lower is better.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Fitness {
+    /// Edit distance between the output (without NUL padding) and the target.
+    pub distance: usize,
+    /// Set when the run ended in an error, or without exiting when exiting is required.
+    pub failed: bool,
+    pub steps: u64,
+    pub len: usize,
+}
+
+impl Fitness {
+    pub fn is_exact(&self) -> bool {
+        self.distance == 0 && !self.failed
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scored {
+    pub program: String,
+    pub fitness: Fitness,
+}
+
+/// An experimental evolutionary search for programs producing a given output.
+///
+/// Each generation, candidates are run under [`run_captured`] and ranked by [`Fitness`]. The
+/// best few survive unchanged and the rest of the next generation is bred from tournament
+/// winners by crossing over their name sets and mutating the result. Unlike
+/// [`Golfer`](crate::golf::Golfer) this finds nothing guaranteed to be shortest, but it can
+/// reach programs far longer than an exhaustive search could.
+#[derive(Debug, Clone)]
+pub struct Evolver {
+    target: Vec<u8>,
+    input: Vec<u8>,
+    limits: Limits,
+    population: usize,
+    generations: usize,
+    elite: usize,
+    max_tanks: usize,
+    seed: Option<u64>,
+    jobs: usize,
+    require_exit: bool,
+    extensions: bool,
+    dictionary: Option<Vec<String>>,
+}
+
+impl Evolver {
+    pub fn new(target: impl Into<Vec<u8>>) -> Self {
+        Self {
+            target: target.into(),
+            input: Vec::new(),
+            limits: Limits::steps(1000),
+            population: 64,
+            generations: 200,
+            elite: 2,
+            max_tanks: 12,
+            seed: None,
+            jobs: thread::available_parallelism().map_or(1, |n| n.get()),
+            require_exit: false,
+            extensions: false,
+            dictionary: None,
+        }
+    }
+
+    pub fn input(mut self, input: impl Into<Vec<u8>>) -> Self {
+        self.input = input.into();
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn population(mut self, population: usize) -> Self {
+        self.population = population.max(2);
+        self
+    }
+
+    pub fn generations(mut self, generations: usize) -> Self {
+        self.generations = generations;
+        self
+    }
+
+    /// The most names a candidate may have.
+    pub fn max_tanks(mut self, max_tanks: usize) -> Self {
+        self.max_tanks = max_tanks.max(1);
+        self
+    }
+
+    /// Seeds the random number generator, making the search reproducible.
+    pub fn seed(mut self, seed: Option<u64>) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    pub fn require_exit(mut self, require_exit: bool) -> Self {
+        self.require_exit = require_exit;
+        self
+    }
+
+    pub fn extensions(mut self, enabled: bool) -> Self {
+        self.extensions = enabled;
+        self
+    }
+
+    /// Restricts names to the valid names among `words`, lowercased. Mutations then swap whole
+    /// words instead of editing letters.
+    pub fn dictionary<S: AsRef<str>>(mut self, words: impl IntoIterator<Item = S>) -> Self {
+        let mut words: Vec<String> = words
+            .into_iter()
+            .map(|w| w.as_ref().to_lowercase())
+            .filter(|w| is_name(w))
+            .collect();
+        words.sort();
+        words.dedup();
+        self.dictionary = Some(words).filter(|words| !words.is_empty());
+        self
+    }
+
+    /// Evolves programs until one matches exactly or the generations run out, reporting the
+    /// best candidate after each generation to `progress`. Returns the best candidate seen.
+    pub fn run(&self, mut progress: impl FnMut(usize, &Scored)) -> Scored {
+        let mut rng = seeded_rng(self.seed);
+        let mut population: Vec<Vec<String>> = (0..self.population)
+            .map(|_| self.random_genome(&mut rng))
+            .collect();
+        let mut best: Option<Scored> = None;
+        for generation in 0..self.generations.max(1) {
+            let mut scored: Vec<(Fitness, Vec<String>)> = self
+                .score_all(&population)
+                .into_iter()
+                .zip(population)
+                .collect();
+            scored.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+            let leader = Scored {
+                program: scored[0].1.join(" "),
+                fitness: scored[0].0,
+            };
+            if best.as_ref().is_none_or(|b| leader.fitness < b.fitness) {
+                best = Some(leader.clone());
+            }
+            progress(generation, &leader);
+            if leader.fitness.is_exact() {
+                break;
+            }
+            population = scored
+                .iter()
+                .take(self.elite)
+                .map(|(_, genome)| genome.clone())
+                .collect();
+            while population.len() < self.population {
+                let a = tournament(&scored, &mut rng);
+                let b = tournament(&scored, &mut rng);
+                let mut child = self.crossover(a, b, &mut rng);
+                self.mutate(&mut child, &mut rng);
+                population.push(child);
+            }
+        }
+        best.expect("at least one generation runs")
+    }
+
+    /// Scores a single program.
+    pub fn score(&self, code: &str) -> Fitness {
+        let builder = ProgramBuilder::new(code).extensions(self.extensions);
+        let worst = Fitness {
+            distance: usize::MAX,
+            failed: true,
+            steps: u64::MAX,
+            len: code.len(),
+        };
+        let Ok(outcome) = run_captured(builder, self.input.clone(), self.limits) else {
+            return worst;
+        };
+        if matches!(outcome.ending, Ending::Panic(_)) {
+            return worst;
+        }
+        let output: Vec<u8> = outcome.output.into_iter().filter(|&b| b != 0).collect();
+        Fitness {
+            distance: strsim::generic_levenshtein(&output, &self.target),
+            failed: match outcome.ending {
+                Ending::Halted(_) if !self.require_exit => false,
+                ref ending => !ending.is_exit(),
+            },
+            steps: outcome.steps,
+            len: code.len(),
+        }
+    }
+
+    fn score_all(&self, population: &[Vec<String>]) -> Vec<Fitness> {
+        let chunk = population.len().div_ceil(self.jobs);
+        thread::scope(|scope| {
+            let handles: Vec<_> = population
+                .chunks(chunk)
+                .map(|genomes| {
+                    scope.spawn(move || {
+                        genomes
+                            .iter()
+                            .map(|names| self.score(&names.join(" ")))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        })
+    }
+
+    fn random_genome(&self, rng: &mut StdRng) -> Vec<String> {
+        let mut names = Vec::new();
+        for _ in 0..rng.random_range(1..=self.max_tanks.min(4)) {
+            let name = self.random_name(rng);
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    fn random_name(&self, rng: &mut StdRng) -> String {
+        match &self.dictionary {
+            Some(words) => words.choose(rng).unwrap().clone(),
+            None => {
+                let len = rng.random_range(1..=8);
+                (0..len).map(|_| random_letter(rng)).collect()
+            }
+        }
+    }
+
+    /// A child with a random half of each parent's names.
+    fn crossover(&self, a: &[String], b: &[String], rng: &mut StdRng) -> Vec<String> {
+        let mut child: Vec<String> = Vec::new();
+        for name in a.iter().chain(b) {
+            if rng.random_bool(0.5) && !child.contains(name) {
+                child.push(name.clone());
+            }
+        }
+        if child.is_empty() {
+            child.push(a.choose(rng).unwrap().clone());
+        }
+        child.truncate(self.max_tanks);
+        child
+    }
+
+    fn mutate(&self, names: &mut Vec<String>, rng: &mut StdRng) {
+        let i = rng.random_range(0..names.len());
+        match rng.random_range(0..5) {
+            0 if names.len() < self.max_tanks => names.push(self.random_name(rng)),
+            1 if names.len() > 1 => {
+                names.swap_remove(i);
+            }
+            _ if self.dictionary.is_some() => names[i] = self.random_name(rng),
+            2 => {
+                let at = rng.random_range(0..=names[i].len());
+                names[i].insert(at, random_letter(rng));
+            }
+            3 if names[i].len() > 1 => {
+                let at = rng.random_range(0..names[i].len());
+                names[i].remove(at);
+            }
+            _ => {
+                let at = rng.random_range(0..names[i].len());
+                names[i].replace_range(at..=at, &random_letter(rng).to_string());
+            }
+        }
+        let mut seen = HashSet::new();
+        names.retain(|name| seen.insert(name.clone()));
+        if names.is_empty() {
+            names.push(self.random_name(rng));
+        }
+    }
+}
+
+fn random_letter(rng: &mut StdRng) -> char {
+    (b'a' + rng.random_range(0..26)) as char
+}
+
+/// The best of three random members of a sorted population.
+fn tournament<'a>(scored: &'a [(Fitness, Vec<String>)], rng: &mut StdRng) -> &'a [String] {
+    let best = (0..3)
+        .map(|_| rng.random_range(0..scored.len()))
+        .min()
+        .unwrap();
+    &scored[best].1
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_score() {
+        let evolver = Evolver::new("").require_exit(true);
+        let exact = evolver.score("ebbbbbbbbb");
+        assert!(exact.is_exact());
+        assert_eq!(exact.steps, 1);
+        let looping = evolver.score("ab");
+        assert!(looping.failed);
+        assert!(Evolver::new("").score("ab").is_exact());
+        assert!(exact < looping);
+    }
+
+    #[test]
+    fn test_seeded_runs_are_reproducible() {
+        let run = || {
+            let mut leaders = Vec::new();
+            // No `y` names, whose random directions would not be reproducible.
+            Evolver::new("a")
+                .dictionary(["ab", "ba", "cat", "dog", "fish", "on", "ox"])
+                .population(8)
+                .generations(5)
+                .seed(Some(7))
+                .jobs(3)
+                .run(|_, best| leaders.push(best.clone()));
+            leaders
+        };
+        let first = run();
+        assert!(!first.is_empty());
+        assert!(first.windows(2).all(|w| w[1].fitness <= w[0].fitness));
+        assert_eq!(first, run());
+    }
+
+    #[test]
+    fn test_mutations_keep_names_distinct() {
+        let evolver = Evolver::new("").max_tanks(3);
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut names = vec![String::from("a"), String::from("b")];
+        for _ in 0..1000 {
+            evolver.mutate(&mut names, &mut rng);
+            assert!((1..=3).contains(&names.len()));
+            assert!(names.iter().all(|name| is_name(name)));
+            let mut sorted = names.clone();
+            sorted.sort();
+            sorted.dedup();
+            assert_eq!(sorted.len(), names.len());
+        }
+    }
+}
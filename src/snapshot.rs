@@ -0,0 +1,229 @@
+//! Snapshot tests: running a program and comparing everything it did with a file.
+//!
+//! A [`Snapshot`] records a run's step count, how it ended, its final state as the debug
+//! server's `state` method reports it, and its output. [`check`] compares one with the
+//! snapshot file at a path, and [`assert_snapshot`] fails a test when they differ:
+//!
+//! ```text
+//! pufferfish-snapshot 1
+//! steps 42
+//! ending halted: exit
+//! state {"status":"halted: exit","steps":42,...}
+//! output 3
+//! abc
+//! ```
+//!
+//! New and changed snapshots aren't written over the old file. They go next to it with
+//! `.new` added to the name, for `pufferfish review` to show as a diff and accept or reject.
+//! Setting `PUFFERFISH_SNAPSHOTS=overwrite` writes them straight to the file instead.
+
+use std::{
+    fs::{read, read_dir, remove_file, rename, write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    builder::ProgramBuilder,
+    debug_server::program_state,
+    diff::unified,
+    io::{Io, MockIo},
+    program::Status,
+    runner::Limits,
+};
+
+pub const VERSION: u32 = 1;
+
+/// The environment variable that makes [`check`] overwrite snapshots instead of writing them
+/// for review.
+pub const MODE_VAR: &str = "PUFFERFISH_SNAPSHOTS";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub steps: u64,
+    /// How the run ended, like `halted: exit` or `error: stack underflow: ...`.
+    pub ending: String,
+    /// The final state, as JSON.
+    pub state: String,
+    pub output: Vec<u8>,
+}
+
+impl Snapshot {
+    /// Runs a program against `input` until it stops or reaches `limits`, replacing any I/O
+    /// set on `builder`. Errors building the program are returned as `Err`, but a runtime
+    /// error is part of the snapshot.
+    pub fn take<I: Io>(
+        builder: ProgramBuilder<I>,
+        input: impl Into<Vec<u8>>,
+        limits: Limits,
+    ) -> Result<Self, crate::Error> {
+        let mut program = builder
+            .io(MockIo::new(input))
+            .step_limit(limits.max_steps)
+            .output_limit(limits.max_output)
+            .build()?;
+        let ending = match program.run() {
+            Ok(Status::Halted(reason)) => format!("halted: {}", reason.name()),
+            Ok(status) => unreachable!("run returned while the program was {status:?}"),
+            Err(err) => format!("error: {err}"),
+        };
+        Ok(Self {
+            steps: program.steps(),
+            ending,
+            state: program_state(&program).to_string(),
+            output: program.into_io().into_output(),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = format!(
+            "pufferfish-snapshot {VERSION}\nsteps {}\nending {}\nstate {}\noutput {}\n",
+            self.steps,
+            self.ending,
+            self.state,
+            self.output.len()
+        )
+        .into_bytes();
+        out.extend(&self.output);
+        out.push(b'\n');
+        out
+    }
+}
+
+/// How a snapshot compared with its file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Check {
+    /// The file holds the same snapshot.
+    Matched,
+    /// There was no file, and the snapshot was written for review.
+    New(PathBuf),
+    /// The file holds a different snapshot, and the new one was written for review. Holds a
+    /// unified diff from the old snapshot to the new one.
+    Changed(PathBuf, String),
+    /// The snapshot was written straight to the file, as [`MODE_VAR`] asked.
+    Overwritten,
+}
+
+/// Where a new or changed snapshot for `path` waits for review.
+pub fn pending_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".new");
+    path.with_file_name(name)
+}
+
+/// Compares `snapshot` with the snapshot file at `path`, writing it for review if they
+/// differ. A matching snapshot removes any earlier one still waiting for review.
+pub fn check(path: &Path, snapshot: &Snapshot) -> Result<Check, crate::Error> {
+    let bytes = snapshot.to_bytes();
+    let old = read(path).ok();
+    let pending = pending_path(path);
+    if old.as_ref() == Some(&bytes) {
+        if pending.exists() {
+            remove_file(&pending)?;
+        }
+        return Ok(Check::Matched);
+    }
+    if std::env::var(MODE_VAR).is_ok_and(|mode| mode == "overwrite") {
+        write(path, &bytes)?;
+        return Ok(Check::Overwritten);
+    }
+    write(&pending, &bytes)?;
+    Ok(match old {
+        None => Check::New(pending),
+        Some(old) => {
+            let diff = unified(&old, &bytes, &path.display().to_string(), "new");
+            Check::Changed(pending, diff)
+        }
+    })
+}
+
+/// Panics unless `snapshot` matches the snapshot file at `path`, for use in tests.
+pub fn assert_snapshot(path: impl AsRef<Path>, snapshot: &Snapshot) {
+    let path = path.as_ref();
+    match check(path, snapshot) {
+        Ok(Check::Matched | Check::Overwritten) => {}
+        Ok(Check::New(pending)) => panic!(
+            "no snapshot at {}; wrote {} for review",
+            path.display(),
+            pending.display()
+        ),
+        Ok(Check::Changed(pending, diff)) => panic!(
+            "snapshot {} changed; wrote {} for review\n{diff}",
+            path.display(),
+            pending.display()
+        ),
+        Err(err) => panic!("couldn't check snapshot {}: {err}", path.display()),
+    }
+}
+
+/// Every snapshot waiting for review in `dir` and its subdirectories, sorted. Only snapshot
+/// files named with `.snap` at the end are found.
+pub fn pending(dir: &Path) -> Result<Vec<PathBuf>, crate::Error> {
+    let mut found = Vec::new();
+    for entry in read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            found.extend(pending(&path)?);
+        } else if path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.ends_with(".snap.new"))
+        {
+            found.push(path);
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
+/// The snapshot file a snapshot waiting for review at `pending` would replace.
+pub fn snapshot_path(pending: &Path) -> PathBuf {
+    let name = pending.file_name().unwrap_or_default().to_string_lossy();
+    pending.with_file_name(name.strip_suffix(".new").unwrap_or(&name))
+}
+
+/// Accepts a snapshot waiting for review, replacing the old snapshot.
+pub fn accept(pending: &Path) -> Result<(), crate::Error> {
+    rename(pending, snapshot_path(pending))?;
+    Ok(())
+}
+
+/// Rejects a snapshot waiting for review, keeping the old snapshot.
+pub fn reject(pending: &Path) -> Result<(), crate::Error> {
+    remove_file(pending)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let dir = std::env::temp_dir().join(format!("pufferfish-snapshot-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("loop.snap");
+        let take =
+            |steps| Snapshot::take(ProgramBuilder::new("ab"), "", Limits::steps(steps)).unwrap();
+        let snapshot = take(5);
+        assert_eq!(snapshot.ending, "halted: step-limit");
+        assert!(snapshot.state.contains(r#""steps":5"#));
+
+        let Check::New(new) = check(&path, &snapshot).unwrap() else {
+            panic!("expected a new snapshot");
+        };
+        assert_eq!(new, dir.join("loop.snap.new"));
+        assert_eq!(pending(&dir).unwrap(), std::slice::from_ref(&new));
+        accept(&new).unwrap();
+        assert_eq!(check(&path, &snapshot).unwrap(), Check::Matched);
+        assert_snapshot(&path, &snapshot);
+
+        let Check::Changed(new, diff) = check(&path, &take(6)).unwrap() else {
+            panic!("expected a changed snapshot");
+        };
+        assert!(diff.contains("-steps 5\n+steps 6\n"));
+        reject(&new).unwrap();
+        assert!(pending(&dir).unwrap().is_empty());
+        assert_eq!(read(&path).unwrap(), snapshot.to_bytes());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
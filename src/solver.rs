@@ -0,0 +1,336 @@
+use std::{collections::BinaryHeap, fmt};
+
+use thiserror::Error;
+
+use crate::{parser::is_name, program::Tank};
+
+const CELLS: usize = 20;
+
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum TargetError {
+    #[error("invalid target cell: {0:?}")]
+    InvalidCell(char),
+    #[error("target has {0} cells, expected 20")]
+    WrongLength(usize),
+}
+
+/// A digit grid to solve for, where `None` cells may hold any digit.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Target {
+    cells: [Option<u8>; CELLS],
+}
+
+impl Target {
+    /// Parses 20 cells in row-major order, each a digit or `?`/`.` for "don't care".
+    ///
+    /// Whitespace, `/` and `,` may be used freely to separate rows.
+    pub fn parse(s: &str) -> Result<Self, TargetError> {
+        let mut cells = Vec::with_capacity(CELLS);
+        for c in s.chars() {
+            match c {
+                '0'..='9' => cells.push(Some(c as u8 - b'0')),
+                '?' | '.' => cells.push(None),
+                c if c.is_whitespace() || c == '/' || c == ',' => {}
+                c => return Err(TargetError::InvalidCell(c)),
+            }
+        }
+        let cells = cells
+            .try_into()
+            .map_err(|cells: Vec<_>| TargetError::WrongLength(cells.len()))?;
+        Ok(Self { cells })
+    }
+
+    /// The target matching exactly the digits of `tank`.
+    pub fn from_tank(tank: &Tank) -> Self {
+        let mut cells = [None; CELLS];
+        for (cell, &digit) in cells.iter_mut().zip(tank.digits().as_array()) {
+            *cell = Some(digit as u8);
+        }
+        Self { cells }
+    }
+
+    pub fn cells(&self) -> &[Option<u8>; CELLS] {
+        &self.cells
+    }
+
+    /// The number of specified cells whose digit differs from `digits`.
+    fn distance(&self, digits: impl Iterator<Item = usize>) -> usize {
+        self.cells
+            .iter()
+            .zip(digits)
+            .filter(|&(cell, digit)| cell.is_some_and(|c| c as usize != digit % 10))
+            .count()
+    }
+
+    /// What would have to be added to each cell of `digits`, mod 10, to hit the target.
+    fn residual(&self, digits: impl Iterator<Item = usize>) -> Residual {
+        let mut cells = [None; CELLS];
+        for ((out, cell), digit) in cells.iter_mut().zip(&self.cells).zip(digits) {
+            *out = cell.map(|c| ((c as usize + 10 - digit % 10) % 10) as u8);
+        }
+        Residual { cells }
+    }
+}
+
+/// Per-cell differences between a candidate and the target it was ranked against.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Residual {
+    cells: [Option<u8>; CELLS],
+}
+
+impl Residual {
+    pub fn cells(&self) -> &[Option<u8>; CELLS] {
+        &self.cells
+    }
+}
+
+/// Renders five rows of four cells: `.` where the cell already matches, `?` where the target
+/// does not care, and otherwise the digit still to add.
+impl fmt::Display for Residual {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, row) in self.cells.chunks(4).enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            for cell in row {
+                match cell {
+                    None => write!(f, "?")?,
+                    Some(0) => write!(f, ".")?,
+                    Some(d) => write!(f, "{d}")?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub word: String,
+    /// Hamming distance between the word's digits and the target's specified cells.
+    pub distance: usize,
+    pub residual: Residual,
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.distance, self.word.len(), &self.word).cmp(&(
+            other.distance,
+            other.word.len(),
+            &other.word,
+        ))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Searches for names whose tanks decode to a target digit grid.
+///
+/// Without a dictionary, every multiset of up to `max_len` letters is tried (apostrophes are
+/// not generated), which grows quickly past six letters. With a dictionary, only its words are
+/// considered, so the search is linear in the dictionary size and any length is fine.
+#[derive(Debug, Clone)]
+pub struct Solver {
+    max_len: usize,
+    limit: usize,
+    call_letter: Option<char>,
+    dictionary: Option<Vec<String>>,
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Self {
+            max_len: 4,
+            limit: 10,
+            call_letter: None,
+            dictionary: None,
+        }
+    }
+}
+
+impl Solver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The longest generated name. Ignored when a dictionary is set.
+    pub fn max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// How many of the closest candidates to return.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Only return names starting with `letter`.
+    pub fn call_letter(mut self, letter: Option<char>) -> Self {
+        self.call_letter = letter;
+        self
+    }
+
+    /// Restricts candidates to the valid names among `words`, lowercased.
+    pub fn dictionary<S: AsRef<str>>(mut self, words: impl IntoIterator<Item = S>) -> Self {
+        let mut words: Vec<String> = words
+            .into_iter()
+            .map(|w| w.as_ref().to_lowercase())
+            .filter(|w| is_name(w))
+            .collect();
+        words.sort();
+        words.dedup();
+        self.dictionary = Some(words);
+        self
+    }
+
+    /// Returns up to `limit` candidates, closest first.
+    pub fn solve(&self, target: &Target) -> Vec<Candidate> {
+        if self.call_letter.is_some_and(|c| !c.is_ascii_lowercase()) {
+            return Vec::new();
+        }
+        let mut best = BinaryHeap::new();
+        let mut consider = |word: String, digits: &[usize]| {
+            let distance = target.distance(digits.iter().copied());
+            if best.len() == self.limit
+                && best.peek().is_some_and(|worst: &Candidate| {
+                    (distance, word.len(), &word) >= (worst.distance, worst.word.len(), &worst.word)
+                })
+            {
+                return;
+            }
+            let residual = target.residual(digits.iter().copied());
+            best.push(Candidate {
+                word,
+                distance,
+                residual,
+            });
+            if best.len() > self.limit {
+                best.pop();
+            }
+        };
+        if let Some(dictionary) = &self.dictionary {
+            for word in dictionary {
+                if self.call_letter.is_some_and(|c| !word.starts_with(c)) {
+                    continue;
+                }
+                let tank = Tank::from_name(word).expect("dictionary words are valid names");
+                let digits: Vec<usize> = tank.grid().iter().collect();
+                consider(word.clone(), &digits);
+            }
+        } else {
+            let glyphs = glyphs();
+            let mut letters = Vec::with_capacity(self.max_len);
+            let mut sums = [0; CELLS];
+            if let Some(c) = self.call_letter {
+                let i = (c as u8 - b'a') as usize;
+                letters.push(i);
+                add(&mut sums, &glyphs[i]);
+                consider(self.name(&letters), &sums);
+            }
+            self.multisets(&glyphs, 0, &mut letters, &mut sums, &mut consider);
+        }
+        best.into_sorted_vec()
+    }
+
+    fn multisets(
+        &self,
+        glyphs: &[[usize; CELLS]; 26],
+        start: usize,
+        letters: &mut Vec<usize>,
+        sums: &mut [usize; CELLS],
+        consider: &mut impl FnMut(String, &[usize]),
+    ) {
+        if letters.len() == self.max_len {
+            return;
+        }
+        for i in start..26 {
+            letters.push(i);
+            add(sums, &glyphs[i]);
+            consider(self.name(letters), &sums[..]);
+            self.multisets(glyphs, i, letters, sums, consider);
+            for (sum, g) in sums.iter_mut().zip(&glyphs[i]) {
+                *sum -= g;
+            }
+            letters.pop();
+        }
+    }
+
+    fn name(&self, letters: &[usize]) -> String {
+        letters.iter().map(|&i| (b'a' + i as u8) as char).collect()
+    }
+}
+
+fn add(sums: &mut [usize; CELLS], glyph: &[usize; CELLS]) {
+    for (sum, g) in sums.iter_mut().zip(glyph) {
+        *sum += g;
+    }
+}
+
+fn glyphs() -> [[usize; CELLS]; 26] {
+    std::array::from_fn(|i| {
+        let letter = ((b'a' + i as u8) as char).to_string();
+        let tank = Tank::from_name(&letter).expect("single letters are valid names");
+        *tank.grid().as_array()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_target() {
+        let target = Target::parse("0123/4567/89?./0000/1111").unwrap();
+        assert_eq!(target.cells()[3], Some(3));
+        assert_eq!(target.cells()[10], None);
+        assert_eq!(target.cells()[11], None);
+        assert_eq!(Target::parse("012"), Err(TargetError::WrongLength(3)));
+        assert_eq!(
+            Target::parse("x".repeat(20).as_str()),
+            Err(TargetError::InvalidCell('x'))
+        );
+    }
+
+    #[test]
+    fn test_solve_exact() {
+        let target = Target::from_tank(&Tank::from_name("cab").unwrap());
+        let candidates = Solver::new().max_len(3).limit(3).solve(&target);
+        assert_eq!(candidates[0].word, "abc");
+        assert_eq!(candidates[0].distance, 0);
+        assert_eq!(
+            candidates[0].residual.to_string(),
+            "....\n....\n....\n....\n...."
+        );
+
+        let candidates = Solver::new()
+            .max_len(3)
+            .call_letter(Some('c'))
+            .solve(&target);
+        assert_eq!(candidates[0].word, "cab");
+    }
+
+    #[test]
+    fn test_solve_with_dictionary() {
+        let target = Target::from_tank(&Tank::from_name("fish").unwrap());
+        let candidates = Solver::new()
+            .dictionary(["Fish", "shif", "cat", "dog", "o'clock"])
+            .solve(&target);
+        assert_eq!(candidates[0].word, "fish");
+        assert_eq!(candidates[1].word, "shif");
+        assert_eq!(candidates[1].distance, 0);
+        assert!(candidates[2].distance > 0);
+        assert_eq!(candidates.len(), 5);
+
+        let candidates = Solver::new()
+            .dictionary(["fish", "shif"])
+            .call_letter(Some('s'))
+            .solve(&target);
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].word, "shif");
+    }
+}
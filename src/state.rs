@@ -0,0 +1,380 @@
+//! Save states: a running program's complete state as text, so a run can be stopped and
+//! resumed later.
+//!
+//! The format follows [`crate::ir`], with `key=value` fields after a leading keyword:
+//!
+//! ```text
+//! pufferfish-state 1
+//! rules font=standard extensions=false
+//! dialect edges=wrap zero=noop skip=1 tunnel=peek cycle=sub,swap,dup,drop
+//! pointer ftp=0,1 ip=2,3 dir=right trampoline=0
+//! counters steps=1234 blanks=17 halted=none
+//! stack 1 -2 3
+//! registers 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0
+//! tank name=ab acc=0 cycle=0 sums=1,0,0,0/1,2,2,1/2,0,0,2/2,0,0,2/1,2,2,1
+//! ...
+//! ```
+//!
+//! The stack is listed bottom to top and tanks in row-major aquarium order. Tanks are saved
+//! with their glyph sums rather than rebuilt from the source, so edits made during the run
+//! survive. The sandbox, the step and output limits, the seed, the I/O and the random
+//! generator behind `y` calls are not part of the state, so a loaded program runs unrestricted
+//! until given them again with [`Program::set_sandbox`] and its neighbours. Neither are the
+//! counters quotas are checked against, which start again from zero.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+use itertools::Itertools;
+use thiserror::Error;
+
+use crate::{
+    cells::Cells,
+    dialect::{CycleOrder, Dialect, EdgeMode, SkipMode, TunnelMode, ZeroMode},
+    font::Font,
+    io::Io,
+    parser::is_name,
+    program::{Direction, HaltReason, InstructionPointer, Program, REGISTERS, Tank},
+};
+
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum StateError {
+    #[error("not a pufferfish save state")]
+    NotAState,
+    #[error("unsupported save state version {0}")]
+    UnsupportedVersion(String),
+    #[error("save state has no {0} line")]
+    MissingLine(&'static str),
+    #[error("line {line}: {message}")]
+    Invalid { line: usize, message: String },
+}
+
+pub fn save<I: Io>(program: &Program<I>) -> String {
+    let mut out = String::new();
+    writeln!(out, "pufferfish-state {VERSION}").unwrap();
+    writeln!(
+        out,
+        "rules font={} extensions={}",
+        program.font.name(),
+        program.extensions
+    )
+    .unwrap();
+    let dialect = program.dialect;
+    writeln!(
+        out,
+        "dialect edges={} zero={} skip={} tunnel={} cycle={}",
+        dialect.edges.name(),
+        dialect.zero,
+        dialect.skip,
+        dialect.tunnel.name(),
+        dialect.cycle
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "pointer ftp={},{} ip={},{} dir={} trampoline={}",
+        program.ftp.0,
+        program.ftp.1,
+        program.ip.row(),
+        program.ip.col(),
+        program.ip_dir.name(),
+        program.trampoline
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "counters steps={} blanks={} halted={}",
+        program.steps,
+        program.blanks,
+        program.halted.map_or("none", HaltReason::name)
+    )
+    .unwrap();
+    writeln!(out, "stack {}", program.stack.iter().join(" ")).unwrap();
+    writeln!(out, "registers {}", program.registers.iter().join(" ")).unwrap();
+    for tank in program.aquarium.iter() {
+        writeln!(
+            out,
+            "tank name={} acc={} cycle={} sums={}",
+            tank.name,
+            tank.acc,
+            tank.cycle,
+            tank.grid.iter_rows().map(|mut row| row.join(",")).join("/")
+        )
+        .unwrap();
+    }
+    out
+}
+
+/// A line split into its keyword and the rest.
+struct Line<'a> {
+    number: usize,
+    rest: &'a str,
+}
+
+impl<'a> Line<'a> {
+    fn error(&self, message: impl Into<String>) -> StateError {
+        StateError::Invalid {
+            line: self.number,
+            message: message.into(),
+        }
+    }
+
+    fn fields(&self) -> HashMap<&'a str, &'a str> {
+        self.rest
+            .split_whitespace()
+            .filter_map(|field| field.split_once('='))
+            .collect()
+    }
+
+    fn values(&self) -> Result<Vec<isize>, StateError> {
+        self.rest
+            .split_whitespace()
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| self.error(format!("invalid value {value:?}")))
+            })
+            .collect()
+    }
+}
+
+/// Looks up a field and converts it, reporting which field was missing or invalid.
+fn field<'a, T>(
+    line: &Line<'a>,
+    fields: &HashMap<&'a str, &'a str>,
+    key: &str,
+    parse: impl FnOnce(&'a str) -> Option<T>,
+) -> Result<T, StateError> {
+    let value = fields
+        .get(key)
+        .ok_or_else(|| line.error(format!("missing field {key}")))?;
+    parse(value).ok_or_else(|| line.error(format!("invalid {key} {value:?}")))
+}
+
+fn pair(s: &str) -> Option<(usize, usize)> {
+    let (a, b) = s.split_once(',')?;
+    Some((a.parse().ok()?, b.parse().ok()?))
+}
+
+/// Rebuilds a program from a save state, giving it `io`.
+pub fn load<I: Io>(text: &str, io: I) -> Result<Program<I>, StateError> {
+    let mut lines = text.lines().enumerate().map(|(i, line)| {
+        let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+        (
+            keyword,
+            Line {
+                number: i + 1,
+                rest,
+            },
+        )
+    });
+    match lines.next() {
+        Some(("pufferfish-state", line)) if line.rest == VERSION.to_string() => {}
+        Some(("pufferfish-state", line)) => {
+            return Err(StateError::UnsupportedVersion(line.rest.to_string()));
+        }
+        _ => return Err(StateError::NotAState),
+    }
+    let mut by_keyword: HashMap<&str, Line> = HashMap::new();
+    let mut tanks = Vec::new();
+    let mut cycles = Vec::new();
+    let mut names = HashSet::new();
+    for (keyword, line) in lines {
+        if keyword != "tank" {
+            by_keyword.insert(keyword, line);
+            continue;
+        }
+        let fields = line.fields();
+        let name = field(&line, &fields, "name", |s| {
+            is_name(s).then(|| s.to_string())
+        })?;
+        if !names.insert(name.clone()) {
+            return Err(line.error(format!("duplicate tank name {name:?}")));
+        }
+        let sums = field(&line, &fields, "sums", |s| {
+            crate::decompose::parse_sums(s)
+                .ok()
+                .and_then(|grid| Cells::from_grid(&grid))
+        })?;
+        let mut tank = Tank::new(name, sums);
+        tank.acc = field(&line, &fields, "acc", |s| s.parse().ok())?;
+        cycles.push((
+            line.number,
+            field(&line, &fields, "cycle", |s| s.parse().ok())?,
+        ));
+        tanks.push(tank);
+    }
+    if tanks.is_empty() {
+        return Err(StateError::MissingLine("tank"));
+    }
+    let mut take = |keyword| {
+        by_keyword
+            .remove(keyword)
+            .ok_or(StateError::MissingLine(keyword))
+    };
+
+    let rules = take("rules")?;
+    let fields = rules.fields();
+    let font: &'static Font = field(&rules, &fields, "font", Font::by_name)?;
+    let extensions = field(&rules, &fields, "extensions", |s| s.parse().ok())?;
+
+    let line = take("dialect")?;
+    let fields = line.fields();
+    let dialect = Dialect {
+        edges: field(&line, &fields, "edges", EdgeMode::by_name)?,
+        zero: field(&line, &fields, "zero", ZeroMode::parse)?,
+        skip: field(&line, &fields, "skip", SkipMode::parse)?,
+        tunnel: field(&line, &fields, "tunnel", TunnelMode::by_name)?,
+        cycle: field(&line, &fields, "cycle", CycleOrder::parse)?,
+    };
+    for (number, cycle) in cycles.iter().copied() {
+        if cycle >= dialect.cycle.ops().len() {
+            return Err(StateError::Invalid {
+                line: number,
+                message: format!("cycle {cycle} is past the end of the cycle order"),
+            });
+        }
+    }
+    for (tank, (_, cycle)) in tanks.iter_mut().zip(cycles) {
+        tank.cycle = cycle;
+    }
+
+    let mut program = Program::build_aquarium(tanks, io);
+    program.font = font;
+    program.extensions = extensions;
+    program.dialect = dialect;
+
+    let line = take("pointer")?;
+    let fields = line.fields();
+    let (rows, cols) = (program.aquarium.rows(), program.aquarium.cols());
+    program.ftp = field(&line, &fields, "ftp", |s| {
+        pair(s).filter(|&(row, col)| row < rows && col < cols)
+    })?;
+    program.ip = field(&line, &fields, "ip", |s| {
+        pair(s).and_then(|(row, col)| InstructionPointer::new(row, col))
+    })?;
+    program.ip_dir = field(&line, &fields, "dir", Direction::by_name)?;
+    program.trampoline = field(&line, &fields, "trampoline", |s| s.parse().ok())?;
+
+    let line = take("counters")?;
+    let fields = line.fields();
+    program.steps = field(&line, &fields, "steps", |s| s.parse().ok())?;
+    program.blanks = field(&line, &fields, "blanks", |s| s.parse().ok())?;
+    program.halted = field(&line, &fields, "halted", |s| match s {
+        "none" => Some(None),
+        _ => HaltReason::by_name(s).map(Some),
+    })?;
+
+    program.stack = take("stack")?.values()?;
+    let line = take("registers")?;
+    program.registers = line
+        .values()?
+        .try_into()
+        .map_err(|_| line.error(format!("expected {REGISTERS} registers")))?;
+    Ok(program)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{builder::ProgramBuilder, io::MockIo, runner::Limits, sandbox::Sandbox};
+
+    fn sums<I: Io>(program: &Program<I>) -> Vec<Cells> {
+        program.aquarium.iter().map(|tank| tank.grid).collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut program = ProgramBuilder::new("#!zero quota=50\n#!cycle dup,sub\nab cd ef")
+            .io(MockIo::default())
+            .extensions(true)
+            .stack([4, -1])
+            .build()
+            .unwrap();
+        for _ in 0..7 {
+            program.step().unwrap();
+        }
+        program.tank_mut(0, 2).unwrap().set_cell(4, 3, 12);
+        let text = save(&program);
+        let mut loaded = load(&text, MockIo::default()).unwrap();
+        assert_eq!(save(&loaded), text);
+        assert_eq!(sums(&loaded), sums(&program));
+        assert_eq!(loaded.dialect(), program.dialect());
+        for _ in 0..20 {
+            assert_eq!(loaded.step().unwrap(), program.step().unwrap());
+            assert_eq!(loaded.stack(), program.stack());
+            assert_eq!((loaded.ftp(), loaded.ip()), (program.ftp(), program.ip()));
+        }
+    }
+
+    #[test]
+    fn test_restrictions_not_saved() {
+        let sandbox = Sandbox::deny_all().limits(Limits::steps(100));
+        let program = ProgramBuilder::new("ab cd")
+            .io(MockIo::default())
+            .sandbox(sandbox)
+            .output_limit(Some(8))
+            .build()
+            .unwrap();
+        let mut loaded = load(&save(&program), MockIo::default()).unwrap();
+        assert_eq!(loaded.sandbox(), Sandbox::default());
+        assert_eq!((loaded.step_limit(), loaded.output_limit()), (None, None));
+        loaded.set_sandbox(sandbox);
+        assert_eq!(loaded.sandbox(), program.sandbox());
+        assert_eq!(loaded.step_limit(), Some(100));
+    }
+
+    #[test]
+    fn test_invalid_states() {
+        assert_eq!(
+            load("pufferfish-ir 3", MockIo::default()).err(),
+            Some(StateError::NotAState)
+        );
+        assert_eq!(
+            load("pufferfish-state 9", MockIo::default()).err(),
+            Some(StateError::UnsupportedVersion(String::from("9")))
+        );
+        let program = ProgramBuilder::new("ab cd")
+            .io(MockIo::default())
+            .build()
+            .unwrap();
+        let text = save(&program).replace("ftp=0,0", "ftp=3,0");
+        assert_eq!(
+            load(&text, MockIo::default()).err(),
+            Some(StateError::Invalid {
+                line: 4,
+                message: String::from("invalid ftp \"3,0\"")
+            })
+        );
+        let text = save(&program).replace("name=cd", "name=");
+        assert_eq!(
+            load(&text, MockIo::default()).err(),
+            Some(StateError::Invalid {
+                line: 9,
+                message: String::from("invalid name \"\"")
+            })
+        );
+        let text = save(&program).replace("name=cd", "name='cd");
+        assert!(matches!(
+            load(&text, MockIo::default()),
+            Err(StateError::Invalid { line: 9, .. })
+        ));
+        let text = save(&program).replace("name=cd", "name=ab");
+        assert_eq!(
+            load(&text, MockIo::default()).err(),
+            Some(StateError::Invalid {
+                line: 9,
+                message: String::from("duplicate tank name \"ab\"")
+            })
+        );
+        let text = save(&program).replace("registers 0 ", "registers ");
+        assert!(matches!(
+            load(&text, MockIo::default()),
+            Err(StateError::Invalid { line: 7, .. })
+        ));
+    }
+}
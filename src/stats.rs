@@ -0,0 +1,559 @@
+//! Counting where a run spends its steps, per tank and per instruction.
+//!
+//! [`Stats::run`] runs a program like [`Program::run`] while counting every step, and
+//! [`Stats::report`] hands the counters to a [`StatsSink`]: a [`TextReport`] for reading, or a
+//! [`CsvSink`] for aggregating many runs in a spreadsheet.
+//!
+//! Stats also follow the stack's depth, to help find programs that slowly leak stack entries:
+//! its deepest point, the steps where it last reached a new depth, how many steps grew it, and
+//! how much each tank's steps grew it in total.
+//!
+//! For runs too long to count every step, [`Stats::sampling`] records only every Nth step and
+//! counts it N times, which estimates the same counters for a fraction of the cost. The
+//! stack counters then only see the sampled steps, apart from the maximum depth.
+
+use std::io::{self, Write};
+
+use grid::Grid;
+
+use crate::{
+    backend::Backend,
+    cells,
+    io::Io,
+    program::{HaltReason, Program, RuntimeError, Status},
+};
+
+/// Instruction names, indexed by [`opcode`]. Instructions skipped by a failed tunnel count as
+/// `skipped` rather than as themselves.
+pub const OPCODES: [&str; 17] = [
+    "blank",
+    "down",
+    "up",
+    "right",
+    "left",
+    "push_acc",
+    "cycle",
+    "tunnel",
+    "hop",
+    "call",
+    "mirror_slash",
+    "mirror_backslash",
+    "up_right",
+    "down_right",
+    "down_left",
+    "up_left",
+    "skipped",
+];
+
+const SKIPPED: usize = OPCODES.len() - 1;
+
+/// How many of the latest [`Stats::high_water_marks`] are kept.
+pub const HIGH_WATER_MARKS: usize = 16;
+
+/// The instruction a cell decodes to, as an index into [`OPCODES`].
+pub fn opcode(cell: usize, extensions: bool) -> usize {
+    if extensions && (10..=15).contains(&cell) {
+        cell
+    } else {
+        cell % 10
+    }
+}
+
+/// The instruction the step `program` is about to take runs, as an index into [`OPCODES`],
+/// counting instructions a failed tunnel skips as `skipped`.
+pub(crate) fn step_opcode(program: &impl Backend) -> usize {
+    match opcode(program.current_cell(), program.extensions()) {
+        0 => 0,
+        _ if program.skipping() > 0 => SKIPPED,
+        opcode => opcode,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stats {
+    /// Steps taken in each tank, in aquarium positions.
+    tanks: Grid<u64>,
+    /// Steps taken on each cell, [`cells::LEN`] per tank in row-major aquarium order.
+    cells: Vec<u64>,
+    /// Record every this many steps.
+    every: u64,
+    names: Grid<String>,
+    opcodes: [u64; OPCODES.len()],
+    /// Net stack growth from each tank's steps.
+    growth: Grid<i64>,
+    growth_steps: u64,
+    /// The step being recorded's tank and the stack depth before it.
+    pending: Option<((usize, usize), usize)>,
+    max_depth: usize,
+    high_water: Vec<(u64, usize)>,
+}
+
+/// The stack depth counters of [`Stats`], for [`StatsSink::stack`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackSummary<'a> {
+    pub max_depth: usize,
+    /// The latest steps to reach a new depth, and the depth they reached, oldest first.
+    pub high_water: &'a [(u64, usize)],
+    /// How many steps left the stack deeper than they found it.
+    pub growth_steps: u64,
+    /// Each tank with nonzero net growth: its row and column, name, and growth.
+    pub tanks: Vec<(usize, usize, &'a str, i64)>,
+}
+
+impl Stats {
+    /// Empty counters for `program`'s aquarium.
+    pub fn new<I: Io>(program: &Program<I>) -> Self {
+        let aquarium = program.aquarium();
+        let names = aquarium
+            .iter()
+            .map(|tank| tank.name().to_string())
+            .collect();
+        Self {
+            tanks: Grid::new(aquarium.rows(), aquarium.cols()),
+            cells: vec![0; aquarium.rows() * aquarium.cols() * cells::LEN],
+            every: 1,
+            names: Grid::from_vec(names, aquarium.cols()),
+            opcodes: [0; OPCODES.len()],
+            growth: Grid::new(aquarium.rows(), aquarium.cols()),
+            growth_steps: 0,
+            pending: None,
+            max_depth: program.stack().len(),
+            high_water: Vec::new(),
+        }
+    }
+
+    /// Makes [`Stats::run`] record only the steps whose number is a multiple of `every`,
+    /// counting each as `every` steps. Panics if `every` is zero.
+    pub fn sampling(mut self, every: u64) -> Self {
+        assert!(every > 0, "can't sample every 0 steps");
+        self.every = every;
+        self
+    }
+
+    /// Counts the step `program` is about to take, as many times as [`Stats::sampling`] says.
+    /// Call [`Stats::settle`] after it to count how it changed the stack.
+    pub fn record(&mut self, program: &impl Backend) {
+        let (row, col) = program.ftp();
+        let ip = program.ip();
+        let tank = row * self.tanks.cols() + col;
+        self.tanks[(row, col)] += self.every;
+        self.cells[tank * cells::LEN + ip.row() * cells::COLS + ip.col()] += self.every;
+        self.opcodes[step_opcode(program)] += self.every;
+        self.pending = Some(((row, col), program.stack().len()));
+    }
+
+    /// Counts the stack growth of the step since [`Stats::record`].
+    pub fn settle(&mut self, program: &impl Backend) {
+        let Some((tank, before)) = self.pending.take() else {
+            return;
+        };
+        let depth = program.stack().len();
+        self.growth[tank] += depth as i64 - before as i64;
+        if depth > before {
+            self.growth_steps += 1;
+        }
+        if depth > self.max_depth {
+            self.max_depth = depth;
+            if self.high_water.len() == HIGH_WATER_MARKS {
+                self.high_water.remove(0);
+            }
+            self.high_water.push((program.steps(), depth));
+        }
+    }
+
+    /// Runs `program` until it stops, counting every step, or a sample of them.
+    pub fn run(&mut self, program: &mut impl Backend) -> Result<Status, RuntimeError> {
+        loop {
+            let status = program.status();
+            if status != Status::Running {
+                return Ok(status);
+            }
+            if program
+                .step_limit()
+                .is_some_and(|limit| program.steps() >= limit)
+            {
+                return Ok(Status::Halted(HaltReason::StepLimit));
+            }
+            if !program.steps().is_multiple_of(self.every) {
+                program.step()?;
+                self.max_depth = self.max_depth.max(program.stack().len());
+                continue;
+            }
+            self.record(program);
+            let result = program.step();
+            self.settle(program);
+            result?;
+        }
+    }
+
+    pub fn tank_steps(&self, row: usize, col: usize) -> u64 {
+        self.tanks[(row, col)]
+    }
+
+    /// How many steps ran on the cell at `cell` in the tank at `tank`.
+    pub fn cell_steps(&self, tank: (usize, usize), cell: (usize, usize)) -> u64 {
+        let index = tank.0 * self.tanks.cols() + tank.1;
+        self.cells[index * cells::LEN + cell.0 * cells::COLS + cell.1]
+    }
+
+    /// How many times the instruction named `name` in [`OPCODES`] ran.
+    pub fn opcode_count(&self, name: &str) -> Option<u64> {
+        let index = OPCODES.iter().position(|&op| op == name)?;
+        Some(self.opcodes[index])
+    }
+
+    /// The deepest the stack has been.
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// The latest steps, up to [`HIGH_WATER_MARKS`] of them, after which the stack was deeper
+    /// than ever before, with the depths they reached, oldest first. Step numbers count the
+    /// step itself, like [`Program::steps`] after it.
+    pub fn high_water_marks(&self) -> &[(u64, usize)] {
+        &self.high_water
+    }
+
+    /// How many steps left the stack deeper than they found it.
+    pub fn growth_steps(&self) -> u64 {
+        self.growth_steps
+    }
+
+    /// How much deeper the steps in a tank have left the stack, in total.
+    pub fn tank_growth(&self, row: usize, col: usize) -> i64 {
+        self.growth[(row, col)]
+    }
+
+    pub fn stack_summary(&self) -> StackSummary<'_> {
+        StackSummary {
+            max_depth: self.max_depth,
+            high_water: &self.high_water,
+            growth_steps: self.growth_steps,
+            tanks: self
+                .growth
+                .indexed_iter()
+                .filter(|&(_, &growth)| growth != 0)
+                .map(|((row, col), &growth)| (row, col, self.names[(row, col)].as_str(), growth))
+                .collect(),
+        }
+    }
+
+    /// Writes every tank's counter, in aquarium order, then every cell that ran, then every
+    /// instruction's counter, then the stack summary, then finishes the sink.
+    pub fn report(&self, sink: &mut dyn StatsSink) -> io::Result<()> {
+        for ((row, col), &steps) in self.tanks.indexed_iter() {
+            sink.tank(row, col, &self.names[(row, col)], steps)?;
+        }
+        for (i, &steps) in self.cells.iter().enumerate() {
+            if steps > 0 {
+                let (tank, cell) = (i / cells::LEN, i % cells::LEN);
+                let (row, col) = (tank / self.tanks.cols(), tank % self.tanks.cols());
+                let cell = (cell / cells::COLS, cell % cells::COLS);
+                sink.cell(row, col, &self.names[(row, col)], cell, steps)?;
+            }
+        }
+        for (name, &count) in OPCODES.iter().zip(&self.opcodes) {
+            sink.opcode(name, count)?;
+        }
+        sink.stack(&self.stack_summary())?;
+        sink.finish()
+    }
+}
+
+/// Somewhere to write [`Stats`] counters.
+pub trait StatsSink {
+    fn tank(&mut self, row: usize, col: usize, name: &str, steps: u64) -> io::Result<()>;
+
+    fn opcode(&mut self, name: &str, count: u64) -> io::Result<()>;
+
+    /// Called for each cell that ran, after the tank counters, with the cell's row and column
+    /// in its tank. Sinks that don't report cells can leave this out.
+    fn cell(
+        &mut self,
+        row: usize,
+        col: usize,
+        name: &str,
+        cell: (usize, usize),
+        steps: u64,
+    ) -> io::Result<()> {
+        let _ = (row, col, name, cell, steps);
+        Ok(())
+    }
+
+    /// Called after the instruction counters. Sinks that don't report the stack can leave
+    /// this out.
+    fn stack(&mut self, summary: &StackSummary) -> io::Result<()> {
+        let _ = summary;
+        Ok(())
+    }
+
+    /// Called after the last counter.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// How many of the busiest cells a [`TextReport`] lists.
+const BUSIEST_CELLS: usize = 10;
+
+/// A plain text report, listing the busiest tanks, cells and instructions first and leaving
+/// out instructions that never ran.
+pub struct TextReport<W> {
+    writer: W,
+    tanks: Vec<(String, u64)>,
+    cells: Vec<(String, u64)>,
+    opcodes: Vec<(String, u64)>,
+    stack: Vec<String>,
+}
+
+impl<W: Write> TextReport<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            tanks: Vec::new(),
+            cells: Vec::new(),
+            opcodes: Vec::new(),
+            stack: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write> StatsSink for TextReport<W> {
+    fn tank(&mut self, row: usize, col: usize, name: &str, steps: u64) -> io::Result<()> {
+        self.tanks.push((format!("{name} {row},{col}"), steps));
+        Ok(())
+    }
+
+    fn cell(
+        &mut self,
+        row: usize,
+        col: usize,
+        name: &str,
+        (r, c): (usize, usize),
+        steps: u64,
+    ) -> io::Result<()> {
+        self.cells
+            .push((format!("{name} {row},{col} cell {r},{c}"), steps));
+        Ok(())
+    }
+
+    fn opcode(&mut self, name: &str, count: u64) -> io::Result<()> {
+        if count > 0 {
+            self.opcodes.push((name.to_string(), count));
+        }
+        Ok(())
+    }
+
+    fn stack(&mut self, summary: &StackSummary) -> io::Result<()> {
+        self.stack.push(format!("max depth  {}", summary.max_depth));
+        self.stack
+            .push(format!("steps that grew it  {}", summary.growth_steps));
+        if !summary.high_water.is_empty() {
+            let marks = summary
+                .high_water
+                .iter()
+                .map(|(step, depth)| format!("{depth} at step {step}"))
+                .collect::<Vec<_>>();
+            self.stack
+                .push(format!("latest new depths  {}", marks.join(", ")));
+        }
+        for &(row, col, name, growth) in &summary.tanks {
+            self.stack
+                .push(format!("net growth in {name} {row},{col}  {growth:+}"));
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.cells
+            .sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        self.cells.truncate(BUSIEST_CELLS);
+        for (title, rows) in [
+            ("tank", &mut self.tanks),
+            ("cell", &mut self.cells),
+            ("instruction", &mut self.opcodes),
+        ] {
+            rows.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+            let width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+            writeln!(self.writer, "steps by {title}:")?;
+            for (label, count) in rows.iter() {
+                writeln!(self.writer, "  {label:width$}  {count}")?;
+            }
+        }
+        if !self.stack.is_empty() {
+            writeln!(self.writer, "stack:")?;
+            for line in &self.stack {
+                writeln!(self.writer, "  {line}")?;
+            }
+        }
+        self.writer.flush()
+    }
+}
+
+/// Comma-separated rows of `kind,row,col,name,count`, after a header row. Tank rows have kind
+/// `tank`; instruction rows have kind `opcode` and leave the position empty. Stack rows have
+/// kind `stack` and are named `max_depth` and `growth_steps`, followed by a `growth` row for
+/// each tank whose net growth isn't zero.
+pub struct CsvSink<W> {
+    writer: W,
+    header: bool,
+}
+
+impl<W: Write> CsvSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            header: true,
+        }
+    }
+
+    /// Leaves out the header row, for appending to a file that already has one.
+    pub fn without_header(mut self) -> Self {
+        self.header = false;
+        self
+    }
+
+    fn row(&mut self, row: std::fmt::Arguments) -> io::Result<()> {
+        if std::mem::take(&mut self.header) {
+            writeln!(self.writer, "kind,row,col,name,count")?;
+        }
+        writeln!(self.writer, "{row}")
+    }
+}
+
+impl<W: Write> StatsSink for CsvSink<W> {
+    fn tank(&mut self, row: usize, col: usize, name: &str, steps: u64) -> io::Result<()> {
+        // Names are letters and apostrophes, so they never need quoting.
+        self.row(format_args!("tank,{row},{col},{name},{steps}"))
+    }
+
+    fn opcode(&mut self, name: &str, count: u64) -> io::Result<()> {
+        self.row(format_args!("opcode,,,{name},{count}"))
+    }
+
+    fn stack(&mut self, summary: &StackSummary) -> io::Result<()> {
+        self.row(format_args!("stack,,,max_depth,{}", summary.max_depth))?;
+        self.row(format_args!(
+            "stack,,,growth_steps,{}",
+            summary.growth_steps
+        ))?;
+        for &(row, col, name, growth) in &summary.tanks {
+            self.row(format_args!("growth,{row},{col},{name},{growth}"))?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{builder::ProgramBuilder, io::MockIo};
+
+    #[test]
+    fn test_run() {
+        let mut program = ProgramBuilder::new("ab cd")
+            .io(MockIo::default())
+            .step_limit(Some(10))
+            .build()
+            .unwrap();
+        let mut stats = Stats::new(&program);
+        assert_eq!(
+            stats.run(&mut program).unwrap(),
+            Status::Halted(HaltReason::StepLimit)
+        );
+        assert_eq!(stats.tank_steps(0, 0) + stats.tank_steps(0, 1), 10);
+        let counted: u64 = OPCODES.iter().filter_map(|op| stats.opcode_count(op)).sum();
+        assert_eq!(counted, 10);
+        assert_eq!(stats.opcode_count("nope"), None);
+    }
+
+    #[test]
+    fn test_csv() {
+        let program = Program::with_io("ab cd", MockIo::default()).unwrap();
+        let mut stats = Stats::new(&program);
+        stats.record(&program);
+        let mut csv = CsvSink::new(Vec::new());
+        stats.report(&mut csv).unwrap();
+        let csv = String::from_utf8(csv.writer).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines.len(), 1 + 2 + OPCODES.len() + 2);
+        assert_eq!(
+            lines[..4],
+            [
+                "kind,row,col,name,count",
+                "tank,0,0,ab,1",
+                "tank,0,1,cd,0",
+                "opcode,,,blank,0"
+            ]
+        );
+        assert_eq!(lines[4], "opcode,,,down,1");
+        assert_eq!(
+            lines[lines.len() - 2..],
+            ["stack,,,max_depth,0", "stack,,,growth_steps,0"]
+        );
+
+        let mut text = TextReport::new(Vec::new());
+        stats.report(&mut text).unwrap();
+        assert_eq!(
+            String::from_utf8(text.writer).unwrap(),
+            "steps by tank:\n  ab 0,0  1\n  cd 0,1  0\nsteps by cell:\n  ab 0,0 cell 0,0  1\n\
+             steps by instruction:\n  down  1\n\
+             stack:\n  max depth  0\n  steps that grew it  0\n"
+        );
+    }
+
+    #[test]
+    fn test_sampling() {
+        let run = |every| {
+            let mut program = ProgramBuilder::new("ab cd")
+                .io(MockIo::default())
+                .step_limit(Some(1000))
+                .build()
+                .unwrap();
+            let mut stats = Stats::new(&program).sampling(every);
+            stats.run(&mut program).unwrap();
+            stats
+        };
+        let (full, sampled) = (run(1), run(10));
+        assert_eq!(sampled.tank_steps(0, 0), 1000);
+        assert_eq!(full.cell_steps((0, 0), (1, 0)), 500);
+        // After its first step the program alternates between two cells, and sampling every
+        // 10 steps only ever lands on the one it reaches on even steps.
+        assert_eq!(sampled.cell_steps((0, 0), (0, 0)), 10);
+        assert_eq!(sampled.cell_steps((0, 0), (1, 0)), 0);
+        assert_eq!(sampled.cell_steps((0, 0), (2, 0)), 990);
+        assert_eq!(full.cell_steps((0, 0), (2, 0)), 499);
+    }
+
+    #[test]
+    fn test_stack() {
+        let mut program = Program::with_io("ab cd", MockIo::default()).unwrap();
+        let mut stats = Stats::new(&program);
+        // Grow the stack by hand in place of the steps, leaking one value per two steps.
+        for i in 0..40 {
+            stats.record(&program);
+            program.step().unwrap();
+            if i % 2 == 0 {
+                program.stack_mut().extend([0, 0]);
+            } else {
+                program.stack_mut().pop();
+            }
+            stats.settle(&program);
+        }
+        assert_eq!(stats.max_depth(), 21);
+        assert_eq!(stats.growth_steps(), 20);
+        assert_eq!(stats.tank_growth(0, 0), 20);
+        let marks = stats.high_water_marks();
+        assert_eq!(marks.len(), HIGH_WATER_MARKS);
+        assert_eq!(marks.last(), Some(&(39, 21)));
+
+        let mut csv = CsvSink::new(Vec::new()).without_header();
+        stats.report(&mut csv).unwrap();
+        let csv = String::from_utf8(csv.writer).unwrap();
+        assert!(csv.ends_with("stack,,,max_depth,21\nstack,,,growth_steps,20\ngrowth,0,0,ab,20\n"));
+    }
+}
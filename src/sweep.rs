@@ -0,0 +1,214 @@
+//! Running one program under many seeds, to see everything its `y` calls can make it do.
+//!
+//! A [`Sweep`] runs a program once per seed in a range, in parallel, and tallies how each run
+//! ended and what it wrote. Runs that reach the step limit count as looping, since that's
+//! almost always why a run with a generous limit reaches it.
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    ops::Range,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+};
+
+use crate::{
+    builder::ProgramBuilder,
+    io::MockIo,
+    program::HaltReason,
+    runner::{Ending, Limits, run_captured},
+};
+
+/// Runs a program under a range of seeds in parallel.
+#[derive(Debug, Clone)]
+pub struct Sweep {
+    input: Vec<u8>,
+    limits: Limits,
+    jobs: usize,
+    extensions: bool,
+}
+
+impl Default for Sweep {
+    fn default() -> Self {
+        Self {
+            input: Vec::new(),
+            limits: Limits::default(),
+            jobs: thread::available_parallelism().map_or(1, |n| n.get()),
+            extensions: false,
+        }
+    }
+}
+
+/// Something several runs had in common: how many had it, and the lowest seed that did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Observed<T> {
+    pub value: T,
+    pub count: u64,
+    pub first_seed: u64,
+}
+
+/// What a sweep saw.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SweepReport {
+    pub runs: u64,
+    /// Runs that halted before the step limit, errors aside.
+    pub halted: u64,
+    /// Runs that reached the step limit.
+    pub looped: u64,
+    /// Runs that hit a runtime error or crashed the interpreter.
+    pub errored: u64,
+    /// Every distinct output, most common first.
+    pub outputs: Vec<Observed<Vec<u8>>>,
+    /// Every distinct error message, most common first.
+    pub errors: Vec<Observed<String>>,
+}
+
+/// Counts of distinct values, with the lowest seed that produced each.
+struct Tally<T>(HashMap<T, (u64, u64)>);
+
+impl<T: Hash + Eq> Tally<T> {
+    fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    fn add(&mut self, value: T, count: u64, seed: u64) {
+        let entry = self.0.entry(value).or_insert((0, seed));
+        entry.0 += count;
+        entry.1 = entry.1.min(seed);
+    }
+
+    fn merge(&mut self, other: Self) {
+        for (value, (count, seed)) in other.0 {
+            self.add(value, count, seed);
+        }
+    }
+
+    /// Most common first, then by first seed.
+    fn into_sorted(self) -> Vec<Observed<T>> {
+        let mut observed: Vec<_> = self
+            .0
+            .into_iter()
+            .map(|(value, (count, first_seed))| Observed {
+                value,
+                count,
+                first_seed,
+            })
+            .collect();
+        observed.sort_by_key(|o| (std::cmp::Reverse(o.count), o.first_seed));
+        observed
+    }
+}
+
+impl Sweep {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The input every run reads.
+    pub fn input(mut self, input: impl Into<Vec<u8>>) -> Self {
+        self.input = input.into();
+        self
+    }
+
+    /// Limits applied to each run separately.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    pub fn extensions(mut self, enabled: bool) -> Self {
+        self.extensions = enabled;
+        self
+    }
+
+    fn builder(&self, code: &str) -> ProgramBuilder {
+        ProgramBuilder::new(code).extensions(self.extensions)
+    }
+
+    /// Runs `code` once for every seed in `seeds`. Only fails if the program doesn't build.
+    pub fn run(&self, code: &str, seeds: Range<u64>) -> Result<SweepReport, crate::Error> {
+        self.builder(code).io(MockIo::default()).build()?;
+        let next = AtomicU64::new(seeds.start);
+        let report = Mutex::new((SweepReport::default(), Tally::new(), Tally::new()));
+        let workers = seeds.end.saturating_sub(seeds.start).min(self.jobs as u64);
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| {
+                    let mut counts = SweepReport::default();
+                    let (mut outputs, mut errors) = (Tally::new(), Tally::new());
+                    loop {
+                        let seed = next.fetch_add(1, Ordering::Relaxed);
+                        if seed >= seeds.end {
+                            break;
+                        }
+                        let builder = self.builder(code).seed(seed);
+                        let outcome = run_captured(builder, self.input.clone(), self.limits)
+                            .expect("the program built before");
+                        counts.runs += 1;
+                        match outcome.ending {
+                            Ending::Halted(HaltReason::StepLimit) => counts.looped += 1,
+                            Ending::Halted(_) => counts.halted += 1,
+                            Ending::Error(err) => {
+                                counts.errored += 1;
+                                errors.add(err.to_string(), 1, seed);
+                            }
+                            Ending::Panic(message) => {
+                                counts.errored += 1;
+                                errors.add(format!("panic: {message}"), 1, seed);
+                            }
+                        }
+                        outputs.add(outcome.output, 1, seed);
+                    }
+                    let mut report = report.lock().unwrap();
+                    report.0.runs += counts.runs;
+                    report.0.halted += counts.halted;
+                    report.0.looped += counts.looped;
+                    report.0.errored += counts.errored;
+                    report.1.merge(outputs);
+                    report.2.merge(errors);
+                });
+            }
+        });
+        let (mut report, outputs, errors) = report.into_inner().unwrap();
+        report.outputs = outputs.into_sorted();
+        report.errors = errors.into_sorted();
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sweep() {
+        // The first cell calls y, and whichever way it sends the pointer decides whether the
+        // program loops or pops an empty stack.
+        let sweep = Sweep::new().limits(Limits::steps(300)).jobs(3);
+        let report = sweep.run("ybbbbbbkk", 0..40).unwrap();
+        assert_eq!(report.runs, 40);
+        assert_eq!(report.halted, 0);
+        assert!(report.looped > 0 && report.errored > 0);
+        assert_eq!(report.looped + report.errored, 40);
+        assert_eq!(report.outputs.len(), 1);
+        assert_eq!(report.outputs[0].count, 40);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].count, report.errored);
+        assert!(report.errors[0].value.starts_with("stack underflow"));
+        // Runs are seeded, so the report doesn't depend on how the seeds were shared out.
+        assert_eq!(
+            sweep.clone().jobs(1).run("ybbbbbbkk", 0..40).unwrap(),
+            report
+        );
+        assert!(sweep.run("Y", 0..1).is_err());
+        assert_eq!(sweep.run("y", 5..5).unwrap().runs, 0);
+    }
+}
@@ -0,0 +1,627 @@
+//! Running a program on every input at once, looking for one that gets somewhere. Experimental.
+//!
+//! A [`SymbolicExecutor`] stands in a symbol for each byte an `i` call reads and keeps the
+//! stack as sums of symbols and constants. Where a tunnel tests a value that depends on the
+//! input, or an `i` call could find the input ended, it follows both ways, noting what the
+//! input must be like on each; where a `y` call picks a direction, it follows all four. Every
+//! path is run until it meets the [`Goal`], stops, or runs out of steps, and the first path to
+//! meet the goal that some input can really take gives a [`Witness`].
+//!
+//! Only what the stack needs for core programs is modelled. Extension calls, and tunnels
+//! taking their skip count from a value that depends on the input, end the search with
+//! [`Answer::Unknown`]. Arithmetic doesn't wrap as it does in a real run, and sandboxes and
+//! output limits are ignored.
+
+use std::{collections::VecDeque, fmt};
+
+use crate::{
+    debugger::Location,
+    dialect::{CycleOp, SkipMode, TunnelMode, ZeroMode},
+    io::Io,
+    program::{Direction, InstructionPointer, Program, advance, neighbour},
+};
+
+/// What the search is looking for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Goal {
+    /// An `e` call.
+    Exit,
+    /// Any runtime error.
+    Error,
+    /// The instruction pointer reaching a cell, whether or not it then runs it.
+    Location(Location),
+}
+
+/// Input that meets a goal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Witness {
+    pub input: Vec<u8>,
+    /// The direction each `y` call has to pick, in order.
+    pub choices: Vec<Direction>,
+    /// The steps the program takes before meeting the goal.
+    pub steps: u64,
+}
+
+/// Why a search couldn't look at every path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Cutoff {
+    /// Some path reached the step limit.
+    Steps,
+    /// There were more paths than the path limit.
+    Paths,
+    /// Working out which inputs some path needs took too long.
+    Solver,
+    /// Some path ran an instruction the search doesn't model.
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for Cutoff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cutoff::Steps => write!(f, "some paths reached the step limit"),
+            Cutoff::Paths => write!(f, "there were too many paths"),
+            Cutoff::Solver => write!(f, "some inputs were too hard to work out"),
+            Cutoff::Unsupported(what) => write!(f, "{what} isn't supported"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Answer {
+    Reachable(Witness),
+    /// No input meets the goal: every path was followed until it stopped.
+    Unreachable,
+    /// No path that was followed met the goal, but not every path could be.
+    Unknown(Cutoff),
+}
+
+/// A constant plus a sum of input bytes, each times a coefficient.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+struct Expr {
+    constant: i128,
+    /// Coefficients by symbol, sorted by symbol and never zero.
+    terms: Vec<(usize, i128)>,
+}
+
+impl Expr {
+    fn constant(value: i128) -> Self {
+        Self {
+            constant: value,
+            terms: Vec::new(),
+        }
+    }
+
+    fn symbol(symbol: usize) -> Self {
+        Self {
+            constant: 0,
+            terms: vec![(symbol, 1)],
+        }
+    }
+
+    fn as_constant(&self) -> Option<i128> {
+        self.terms.is_empty().then_some(self.constant)
+    }
+
+    fn sub(&self, other: &Expr) -> Expr {
+        let mut terms = self.terms.clone();
+        for &(symbol, coeff) in &other.terms {
+            match terms.binary_search_by_key(&symbol, |&(s, _)| s) {
+                Ok(i) if terms[i].1 == coeff => {
+                    terms.remove(i);
+                }
+                Ok(i) => terms[i].1 -= coeff,
+                Err(i) => terms.insert(i, (symbol, -coeff)),
+            }
+        }
+        Expr {
+            constant: self.constant - other.constant,
+            terms,
+        }
+    }
+
+    fn negate(&self) -> Expr {
+        Expr::constant(0).sub(self)
+    }
+}
+
+/// Input bytes making an expression at least zero.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct AtLeastZero(Expr);
+
+impl AtLeastZero {
+    /// `expr > 0` if `positive`, otherwise `expr <= 0`.
+    fn tunnel(expr: &Expr, positive: bool) -> Self {
+        if positive {
+            Self(expr.sub(&Expr::constant(1)))
+        } else {
+            Self(expr.negate())
+        }
+    }
+}
+
+fn floor_div(a: i128, b: i128) -> i128 {
+    let q = a / b;
+    if a % b != 0 && (a < 0) != (b < 0) {
+        q - 1
+    } else {
+        q
+    }
+}
+
+/// How many assignments the solver may try before giving up.
+const SOLVER_BUDGET: usize = 10_000;
+
+/// Finds byte values for `symbols` symbols that satisfy every constraint, by narrowing each
+/// symbol's range until the constraints allow nothing else and then trying values in turn.
+/// `Err` means the budget ran out.
+fn solve(constraints: &[AtLeastZero], symbols: usize) -> Result<Option<Vec<u8>>, ()> {
+    let mut budget = SOLVER_BUDGET;
+    let ranges = vec![(0, 255); symbols];
+    label(constraints, ranges, &mut budget)
+}
+
+/// Narrows `ranges` to the values the constraints allow, returning false if that leaves none.
+fn narrow(constraints: &[AtLeastZero], ranges: &mut [(i128, i128)]) -> bool {
+    loop {
+        let mut changed = false;
+        for AtLeastZero(expr) in constraints {
+            // The most each term can add, given the ranges so far.
+            let most = |ranges: &[(i128, i128)], (symbol, coeff): (usize, i128)| {
+                let (lo, hi) = ranges[symbol];
+                if coeff > 0 { coeff * hi } else { coeff * lo }
+            };
+            let total = expr
+                .terms
+                .iter()
+                .map(|&term| most(ranges, term))
+                .sum::<i128>()
+                + expr.constant;
+            if total < 0 {
+                return false;
+            }
+            for &(symbol, coeff) in &expr.terms {
+                // coeff * x must make up for whatever the other terms can't.
+                let needed = -(total - most(ranges, (symbol, coeff)));
+                let (lo, hi) = &mut ranges[symbol];
+                if coeff > 0 {
+                    let bound = -floor_div(-needed, coeff);
+                    if bound > *lo {
+                        *lo = bound;
+                        changed = true;
+                    }
+                } else {
+                    let bound = floor_div(needed, coeff);
+                    if bound < *hi {
+                        *hi = bound;
+                        changed = true;
+                    }
+                }
+                if lo > hi {
+                    return false;
+                }
+            }
+        }
+        if !changed {
+            return true;
+        }
+    }
+}
+
+fn label(
+    constraints: &[AtLeastZero],
+    mut ranges: Vec<(i128, i128)>,
+    budget: &mut usize,
+) -> Result<Option<Vec<u8>>, ()> {
+    if !narrow(constraints, &mut ranges) {
+        return Ok(None);
+    }
+    let Some(open) = ranges.iter().position(|(lo, hi)| lo < hi) else {
+        return Ok(Some(ranges.iter().map(|&(lo, _)| lo as u8).collect()));
+    };
+    let (lo, hi) = ranges[open];
+    for value in lo..=hi {
+        *budget = budget.checked_sub(1).ok_or(())?;
+        let mut ranges = ranges.clone();
+        ranges[open] = (value, value);
+        if let Some(found) = label(constraints, ranges, budget)? {
+            return Ok(Some(found));
+        }
+    }
+    Ok(None)
+}
+
+/// One way a run could go.
+#[derive(Debug, Clone)]
+struct Path {
+    ftp: (usize, usize),
+    ip: InstructionPointer,
+    dir: Direction,
+    trampoline: usize,
+    stack: Vec<Expr>,
+    /// Each tank's accumulator and cycle phase, in row-major order.
+    tanks: Vec<(usize, usize)>,
+    blanks: u64,
+    /// How many bytes have been read, each a symbol.
+    symbols: usize,
+    /// Whether an `i` call found the input ended.
+    ended: bool,
+    constraints: Vec<AtLeastZero>,
+    choices: Vec<Direction>,
+    steps: u64,
+}
+
+/// How a step went.
+enum Stepped {
+    /// The path goes on, or splits into several.
+    Next(Vec<Path>),
+    /// The path stopped without meeting the goal.
+    Stopped,
+    /// The path met the goal.
+    Goal(Path),
+    Unsupported(&'static str),
+}
+
+/// Explores the paths a program could take for every input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SymbolicExecutor {
+    max_steps: u64,
+    max_paths: usize,
+}
+
+impl Default for SymbolicExecutor {
+    fn default() -> Self {
+        Self {
+            max_steps: 1000,
+            max_paths: 10_000,
+        }
+    }
+}
+
+impl SymbolicExecutor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How many steps each path may take.
+    pub fn max_steps(mut self, steps: u64) -> Self {
+        self.max_steps = steps;
+        self
+    }
+
+    /// How many paths may be followed in all.
+    pub fn max_paths(mut self, paths: usize) -> Self {
+        self.max_paths = paths;
+        self
+    }
+
+    /// Looks for input that makes `program` meet `goal` from where it is now. Paths are
+    /// followed breadth first, so a witness takes as few steps as any.
+    pub fn search<I: Io>(&self, program: &Program<I>, goal: Goal) -> Answer {
+        let aquarium = program.aquarium();
+        let start = Path {
+            ftp: program.ftp(),
+            ip: program.ip(),
+            dir: program.direction(),
+            trampoline: program.trampoline,
+            stack: program
+                .stack()
+                .iter()
+                .map(|&value| Expr::constant(value as i128))
+                .collect(),
+            tanks: aquarium
+                .iter()
+                .map(|tank| (tank.acc(), tank.cycle))
+                .collect(),
+            blanks: program.blanks,
+            symbols: 0,
+            ended: false,
+            constraints: Vec::new(),
+            choices: Vec::new(),
+            steps: 0,
+        };
+        let mut pending = VecDeque::from([start]);
+        let mut paths = 1;
+        let mut cutoff = None;
+        while let Some(path) = pending.pop_front() {
+            let here = Location {
+                tank: path.ftp,
+                cell: (path.ip.row(), path.ip.col()),
+            };
+            if goal == Goal::Location(here) {
+                return Answer::Reachable(path.witness());
+            }
+            if path.steps >= self.max_steps {
+                cutoff.get_or_insert(Cutoff::Steps);
+                continue;
+            }
+            match self.step(program, path, goal) {
+                Stepped::Next(next) => {
+                    for path in next {
+                        // Only paths some input can take are worth following.
+                        match solve(&path.constraints, path.symbols) {
+                            Ok(Some(_)) => {}
+                            Ok(None) => continue,
+                            Err(()) => {
+                                cutoff.get_or_insert(Cutoff::Solver);
+                                continue;
+                            }
+                        }
+                        if paths == self.max_paths {
+                            cutoff.get_or_insert(Cutoff::Paths);
+                            continue;
+                        }
+                        paths += 1;
+                        pending.push_back(path);
+                    }
+                }
+                Stepped::Stopped => {}
+                Stepped::Goal(path) => return Answer::Reachable(path.witness()),
+                Stepped::Unsupported(what) => {
+                    cutoff.get_or_insert(Cutoff::Unsupported(what));
+                }
+            }
+        }
+        match cutoff {
+            Some(cutoff) => Answer::Unknown(cutoff),
+            None => Answer::Unreachable,
+        }
+    }
+
+    fn step<I: Io>(&self, program: &Program<I>, mut path: Path, goal: Goal) -> Stepped {
+        let aquarium = program.aquarium();
+        let dialect = program.dialect();
+        let index = path.ftp.0 * aquarium.cols() + path.ftp.1;
+        path.steps += 1;
+        let error = |path: Path| {
+            if goal == Goal::Error {
+                Stepped::Goal(path)
+            } else {
+                Stepped::Stopped
+            }
+        };
+        let cell = aquarium[path.ftp][path.ip];
+        let instr = if program.extensions && (10..=15).contains(&cell) {
+            cell
+        } else {
+            cell % 10
+        };
+        match instr {
+            0 => {
+                path.blanks += 1;
+                match dialect.zero {
+                    ZeroMode::Halt => return Stepped::Stopped,
+                    ZeroMode::Quota(quota) if path.blanks > quota => return Stepped::Stopped,
+                    _ => {}
+                }
+            }
+            _ if path.trampoline > 0 => path.trampoline -= 1,
+            1 => path.dir = Direction::Down,
+            2 => path.dir = Direction::Up,
+            3 => path.dir = Direction::Right,
+            4 => path.dir = Direction::Left,
+            5 => {
+                let (acc, _) = &mut path.tanks[index];
+                path.stack.push(Expr::constant(*acc as i128));
+                *acc += 1;
+            }
+            6 => {
+                let ops = dialect.cycle.ops();
+                let (_, cycle) = &mut path.tanks[index];
+                let op = ops[*cycle];
+                *cycle = (*cycle + 1) % ops.len();
+                let len = path.stack.len();
+                match op {
+                    CycleOp::Subtract | CycleOp::Swap if len < 2 => return error(path),
+                    CycleOp::Dup if len < 1 => return error(path),
+                    CycleOp::Subtract => {
+                        let b = path.stack.pop().unwrap();
+                        let a = path.stack.pop().unwrap();
+                        path.stack.push(a.sub(&b));
+                    }
+                    CycleOp::Swap => path.stack.swap(len - 1, len - 2),
+                    CycleOp::Dup => path.stack.push(path.stack[len - 1].clone()),
+                    CycleOp::Drop => {
+                        path.stack.pop();
+                    }
+                }
+            }
+            7 => {
+                let skip = match dialect.skip {
+                    SkipMode::Fixed(count) => count,
+                    SkipMode::Stack => match path.stack.pop() {
+                        None => return error(path),
+                        Some(count) => match count.as_constant() {
+                            Some(count) => count.max(0) as usize,
+                            None => return Stepped::Unsupported("a skip count from input"),
+                        },
+                    },
+                };
+                let condition = match dialect.tunnel {
+                    TunnelMode::Peek => path.stack.last().cloned(),
+                    TunnelMode::Pop => path.stack.pop(),
+                };
+                match condition.as_ref().map(|c| (c, c.as_constant())) {
+                    None | Some((_, Some(..=0))) => path.trampoline = skip,
+                    Some((_, Some(_))) => path.trampoline = 0,
+                    Some((condition, None)) => {
+                        let mut skipped = path.clone();
+                        skipped.trampoline = skip;
+                        skipped
+                            .constraints
+                            .push(AtLeastZero::tunnel(condition, false));
+                        path.trampoline = 0;
+                        path.constraints.push(AtLeastZero::tunnel(condition, true));
+                        return Stepped::Next(vec![
+                            self.moved(program, path),
+                            self.moved(program, skipped),
+                        ]);
+                    }
+                }
+            }
+            8 => {
+                path.ftp = neighbour(path.ftp, path.dir, aquarium);
+                return Stepped::Next(vec![path]);
+            }
+            9 => match aquarium[path.ftp].call_letter() {
+                'e' if goal == Goal::Exit => return Stepped::Goal(path),
+                'e' => return Stepped::Stopped,
+                'i' if path.ended => path.stack.push(Expr::constant(-1)),
+                'i' => {
+                    let mut ended = path.clone();
+                    ended.ended = true;
+                    ended.stack.push(Expr::constant(-1));
+                    path.stack.push(Expr::symbol(path.symbols));
+                    path.symbols += 1;
+                    return Stepped::Next(vec![
+                        self.moved(program, path),
+                        self.moved(program, ended),
+                    ]);
+                }
+                'o' => {
+                    if path.stack.pop().is_none() {
+                        return error(path);
+                    }
+                }
+                'y' => {
+                    let next = [
+                        Direction::Down,
+                        Direction::Left,
+                        Direction::Right,
+                        Direction::Up,
+                    ]
+                    .map(|dir| {
+                        let mut path = path.clone();
+                        path.dir = dir;
+                        path.choices.push(dir);
+                        self.moved(program, path)
+                    });
+                    return Stepped::Next(next.into());
+                }
+                'x' => {}
+                'p' | 'c' | 'g' | 'u' | 'b' | 'a' | 'm' | 'q' | 'r' | 'l' | 's' | 'd' | 't'
+                | 'w' | 'v' | 'j'
+                    if program.extensions =>
+                {
+                    return Stepped::Unsupported("extension calls");
+                }
+                _ => return error(path),
+            },
+            10 => path.dir = path.dir.mirror_slash(),
+            11 => path.dir = path.dir.mirror_backslash(),
+            12 => path.dir = Direction::UpRight,
+            13 => path.dir = Direction::DownRight,
+            14 => path.dir = Direction::DownLeft,
+            15 => path.dir = Direction::UpLeft,
+            _ => unreachable!(),
+        }
+        Stepped::Next(vec![self.moved(program, path)])
+    }
+
+    /// Moves the pointer on from its cell, as `update_ip` does.
+    fn moved<I: Io>(&self, program: &Program<I>, mut path: Path) -> Path {
+        let (ip, dir, hops) = advance(path.ip, path.dir, program.dialect().edges);
+        path.ip = ip;
+        path.dir = dir;
+        if hops {
+            path.ftp = neighbour(path.ftp, dir, program.aquarium());
+        }
+        path
+    }
+}
+
+impl Path {
+    /// The input and choices that lead down this path.
+    fn witness(&self) -> Witness {
+        let input = solve(&self.constraints, self.symbols)
+            .ok()
+            .flatten()
+            .expect("only paths some input can take are followed");
+        Witness {
+            input,
+            choices: self.choices.clone(),
+            steps: self.steps,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        cells::{Cells, LEN},
+        io::MockIo,
+        program::{HaltReason, Status, Tank},
+    };
+
+    fn aquarium(tanks: &[(&str, &[(usize, usize)])]) -> Program<MockIo> {
+        let tanks = tanks
+            .iter()
+            .map(|&(name, cells)| {
+                let mut grid = [0; LEN];
+                for &(i, cell) in cells {
+                    grid[i] = cell;
+                }
+                Tank::new(name.into(), Cells::new(grid))
+            })
+            .collect();
+        Program::build_aquarium(tanks, MockIo::default())
+    }
+
+    #[test]
+    fn test_solve() {
+        // x0 - x1 > 0 and x1 > 99.
+        let x = |symbol| Expr::symbol(symbol);
+        let constraints = [
+            AtLeastZero::tunnel(&x(0).sub(&x(1)), true),
+            AtLeastZero::tunnel(&x(1).sub(&Expr::constant(99)), true),
+        ];
+        let found = solve(&constraints, 2).unwrap().unwrap();
+        assert!(found[0] > found[1] && found[1] > 99, "{found:?}");
+        let impossible = AtLeastZero::tunnel(&x(0).sub(&Expr::constant(255)), true);
+        assert_eq!(solve(&[impossible], 1), Ok(None));
+    }
+
+    #[test]
+    fn test_search() {
+        // Reads two bytes and subtracts them, then tunnels. If the difference is positive the
+        // pointer turns right, wraps round to a hop, and lands on the other tank's exit call.
+        // Otherwise it skips the turn and comes back to the tunnel forever.
+        let program = aquarium(&[
+            (
+                "i",
+                &[(0, 9), (1, 9), (2, 6), (3, 1), (7, 7), (11, 3), (8, 8)],
+            ),
+            ("e", &[(8, 9)]),
+        ]);
+        let executor = SymbolicExecutor::new().max_steps(50);
+        let Answer::Reachable(witness) = executor.search(&program, Goal::Exit) else {
+            panic!("expected to find an exit");
+        };
+        let mut run = Program::build_aquarium(
+            program.aquarium().iter().cloned().collect(),
+            MockIo::new(witness.input.clone()),
+        );
+        run.set_step_limit(Some(witness.steps));
+        assert_eq!(run.run().unwrap(), Status::Halted(HaltReason::Exit));
+        assert_eq!(run.steps(), witness.steps);
+
+        // Nothing can go wrong, but the loop never stops.
+        assert_eq!(
+            executor.search(&program, Goal::Error),
+            Answer::Unknown(Cutoff::Steps)
+        );
+        let cell = Location {
+            tank: (0, 1),
+            cell: (2, 0),
+        };
+        assert!(matches!(
+            executor.search(&program, Goal::Location(cell)),
+            Answer::Reachable(_)
+        ));
+
+        // Reading and then halting on a blank never exits.
+        let mut program = aquarium(&[("i", &[(0, 9)])]);
+        program.dialect.zero = ZeroMode::Halt;
+        assert_eq!(executor.search(&program, Goal::Exit), Answer::Unreachable);
+    }
+}
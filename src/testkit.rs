@@ -0,0 +1,194 @@
+//! A harness for testing instructions one at a time.
+//!
+//! [`InstructionTest`] is a program of a single blank tank whose state tests set directly: the
+//! stack, the accumulator, where the instruction pointer is and which way it heads. Executing
+//! an instruction by name writes it into the cell under the pointer and takes one step, after
+//! which the test checks whatever state it cares about. This is meant for instruction and
+//! dialect tests; whole programs are better tested through [`crate::builder::ProgramBuilder`].
+
+use crate::{
+    cells::Cells,
+    dialect::Dialect,
+    io::MockIo,
+    parser::is_name,
+    program::{Direction, InstructionPointer, Program, RuntimeError, Status, Tank},
+    stats::OPCODES,
+};
+
+pub struct InstructionTest {
+    program: Program<MockIo>,
+}
+
+impl InstructionTest {
+    /// A single blank tank called `name`, whose first letter decides what calls do. The
+    /// pointer starts at the top left heading right, with an empty stack.
+    ///
+    /// Panics if `name` isn't a valid name.
+    pub fn new(name: &str) -> Self {
+        assert!(is_name(name), "{name:?} is not a valid name");
+        let tank = Tank::new(name.to_string(), Cells::default());
+        Self {
+            program: Program::build_aquarium(vec![tank], MockIo::default()),
+        }
+    }
+
+    pub fn extensions(mut self, enabled: bool) -> Self {
+        self.program.extensions = enabled;
+        self
+    }
+
+    pub fn dialect(mut self, dialect: Dialect) -> Self {
+        self.program.dialect = dialect;
+        self
+    }
+
+    /// The bytes `i` calls read.
+    pub fn input(mut self, input: impl Into<Vec<u8>>) -> Self {
+        *self.program.io_mut() = MockIo::new(input);
+        self
+    }
+
+    /// Sets the stack, listed bottom to top.
+    pub fn stack(mut self, stack: impl IntoIterator<Item = isize>) -> Self {
+        self.program.stack = stack.into_iter().collect();
+        self
+    }
+
+    pub fn acc(mut self, acc: usize) -> Self {
+        self.program.aquarium[(0, 0)].acc = acc;
+        self
+    }
+
+    /// Moves the instruction pointer. Panics if the cell is outside the tank.
+    pub fn at(mut self, row: usize, col: usize) -> Self {
+        self.program.ip = InstructionPointer::new(row, col)
+            .unwrap_or_else(|| panic!("cell {row},{col} is outside the tank"));
+        self
+    }
+
+    pub fn heading(mut self, direction: Direction) -> Self {
+        self.program.ip_dir = direction;
+        self
+    }
+
+    /// Sets one cell's glyph sum, for instructions like tunnels whose effect depends on the
+    /// cells after them. Panics if the cell is outside the tank.
+    pub fn cell(mut self, row: usize, col: usize, sum: usize) -> Self {
+        self.program.aquarium[(0, 0)]
+            .set_cell(row, col, sum)
+            .unwrap_or_else(|| panic!("can't set cell {row},{col} to {sum}"));
+        self
+    }
+
+    /// Writes the instruction named `instruction` in [`OPCODES`] under the pointer and takes
+    /// one step.
+    ///
+    /// Panics if there is no such instruction, or if it's an extension instruction and
+    /// extensions are off.
+    pub fn execute(&mut self, instruction: &str) -> Result<Status, RuntimeError> {
+        let opcode = OPCODES[..16]
+            .iter()
+            .position(|&name| name == instruction)
+            .unwrap_or_else(|| panic!("there is no {instruction} instruction"));
+        assert!(
+            opcode < 10 || self.program.extensions,
+            "{instruction} needs extensions enabled"
+        );
+        let ip = self.program.ip;
+        self.program.aquarium[(0, 0)].set_cell(ip.row(), ip.col(), opcode);
+        self.program.step()
+    }
+
+    pub fn program(&self) -> &Program<MockIo> {
+        &self.program
+    }
+
+    pub fn program_mut(&mut self) -> &mut Program<MockIo> {
+        &mut self.program
+    }
+
+    pub fn current_stack(&self) -> &[isize] {
+        self.program.stack()
+    }
+
+    pub fn current_acc(&self) -> usize {
+        self.program.aquarium[(0, 0)].acc
+    }
+
+    /// The row and column of the instruction pointer.
+    pub fn ip(&self) -> (usize, usize) {
+        (self.program.ip.row(), self.program.ip.col())
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.program.ip_dir
+    }
+
+    /// What `o` and `w` calls have written.
+    pub fn output(&self) -> &[u8] {
+        self.program.io().output()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dialect::{SkipMode, TunnelMode};
+
+    #[test]
+    fn test_cycle() {
+        let mut test = InstructionTest::new("a").stack([5, 3]);
+        test.execute("cycle").unwrap();
+        assert_eq!(test.current_stack(), [2]);
+        assert_eq!(test.ip(), (0, 1));
+        // The next cycle in this tank swaps.
+        test.program_mut().stack_mut().push(7);
+        test.execute("cycle").unwrap();
+        assert_eq!(test.current_stack(), [7, 2]);
+    }
+
+    #[test]
+    fn test_tunnel() {
+        let tunnel = |stack: Vec<isize>, tunnel| {
+            let mut test = InstructionTest::new("a")
+                .dialect(Dialect {
+                    skip: SkipMode::Fixed(2),
+                    tunnel,
+                    ..Dialect::default()
+                })
+                .stack(stack)
+                .cell(0, 1, 5)
+                .cell(0, 2, 5)
+                .cell(0, 3, 5);
+            test.execute("tunnel").unwrap();
+            for _ in 0..3 {
+                test.program_mut().step().unwrap();
+            }
+            (test.current_stack().to_vec(), test.current_acc())
+        };
+        assert_eq!(tunnel(vec![1], TunnelMode::Peek), (vec![1, 0, 1, 2], 3));
+        assert_eq!(tunnel(vec![0], TunnelMode::Peek), (vec![0, 0], 1));
+        assert_eq!(tunnel(vec![1], TunnelMode::Pop), (vec![0, 1, 2], 3));
+    }
+
+    #[test]
+    fn test_heading_and_extensions() {
+        let mut test = InstructionTest::new("a")
+            .extensions(true)
+            .at(2, 2)
+            .heading(Direction::Up);
+        test.execute("down_left").unwrap();
+        assert_eq!((test.ip(), test.direction()), ((3, 1), Direction::DownLeft));
+
+        let mut test = InstructionTest::new("a").at(4, 3).heading(Direction::Down);
+        test.execute("hop").unwrap();
+        // Hopping moves between tanks, and with only one there is nowhere to go.
+        assert_eq!((test.program().ftp(), test.ip()), ((0, 0), (4, 3)));
+    }
+
+    #[test]
+    #[should_panic(expected = "needs extensions enabled")]
+    fn test_extension_without_extensions() {
+        let _ = InstructionTest::new("a").execute("mirror_slash");
+    }
+}
@@ -0,0 +1,201 @@
+//! A record of the most recent steps, for looking back over how a program got where it is.
+//!
+//! A [`Timeline`] keeps one [`Entry`] per step up to a fixed capacity, dropping the oldest
+//! first, and [`Timeline::render`] draws a window of it as text: one line per step with its
+//! instruction and tank, and a sparkline column of stack depths scaled to the window. The
+//! window can end at any recorded step, so a frontend can scroll back through the record.
+
+use std::{collections::VecDeque, fmt::Write};
+
+use crate::{
+    backend::Backend,
+    stats::{OPCODES, step_opcode},
+};
+
+/// One recorded step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Entry {
+    /// How many steps the program had taken before this one.
+    pub step: u64,
+    /// The instruction the step ran, as an index into [`OPCODES`].
+    pub opcode: usize,
+    /// The tank the step ran in.
+    pub tank: (usize, usize),
+    /// The stack depth after the step.
+    pub depth: usize,
+}
+
+impl Entry {
+    pub fn opcode_name(&self) -> &'static str {
+        OPCODES[self.opcode]
+    }
+}
+
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timeline {
+    entries: VecDeque<Entry>,
+    capacity: usize,
+}
+
+impl Timeline {
+    /// An empty timeline that keeps the last `capacity` steps.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Notes the step `program` is about to take. Call [`Timeline::finish`] after it.
+    pub fn start(&mut self, program: &impl Backend) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(Entry {
+            step: program.steps(),
+            opcode: step_opcode(program),
+            tank: program.ftp(),
+            depth: program.stack().len(),
+        });
+    }
+
+    /// Records the stack depth the step left behind.
+    pub fn finish(&mut self, program: &impl Backend) {
+        if let Some(entry) = self.entries.back_mut() {
+            entry.depth = program.stack().len();
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Every recorded step, oldest first.
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = &Entry> + ExactSizeIterator {
+        self.entries.iter()
+    }
+
+    /// Where step `step` is in `entries`, if it's still recorded.
+    fn index(&self, step: u64) -> Option<usize> {
+        let first = self.entries.front()?.step;
+        let index = usize::try_from(step.checked_sub(first)?).ok()?;
+        (index < self.entries.len()).then_some(index)
+    }
+
+    /// The entry for step `step`, if it's still recorded.
+    pub fn get(&self, step: u64) -> Option<&Entry> {
+        self.entries.get(self.index(step)?)
+    }
+
+    /// Up to `rows` entries ending at step `end`, or at the latest step if `end` is `None`,
+    /// oldest first. Empty if `end` isn't recorded.
+    pub fn window(
+        &self,
+        end: Option<u64>,
+        rows: usize,
+    ) -> impl DoubleEndedIterator<Item = &Entry> + ExactSizeIterator {
+        let last = match end {
+            None => self.entries.len(),
+            Some(step) => self.index(step).map_or(0, |index| index + 1),
+        };
+        self.entries.range(last.saturating_sub(rows)..last)
+    }
+
+    /// The last `len` different tanks the recorded steps ran in, most recent first, not
+    /// counting the tank of the latest step.
+    pub fn trail(&self, len: usize) -> Vec<(usize, usize)> {
+        let mut entries = self.entries.iter().rev();
+        let Some(latest) = entries.next() else {
+            return Vec::new();
+        };
+        let mut trail = Vec::with_capacity(len);
+        for entry in entries {
+            if trail.len() == len {
+                break;
+            }
+            if entry.tank != latest.tank && !trail.contains(&entry.tank) {
+                trail.push(entry.tank);
+            }
+        }
+        trail
+    }
+
+    /// Draws [`Timeline::window`] as one line per step, such as `41  tank 0,1    ▃ 3    cycle`,
+    /// with the sparkline scaled to the deepest stack in the window.
+    pub fn render(&self, end: Option<u64>, rows: usize) -> String {
+        let window: Vec<_> = self.window(end, rows).collect();
+        let deepest = window.iter().map(|entry| entry.depth).max().unwrap_or(0);
+        let step_width = window
+            .last()
+            .map_or(1, |entry| entry.step.to_string().len());
+        let mut out = String::new();
+        for entry in window {
+            let level = (entry.depth * (SPARKS.len() - 1)).checked_div(deepest);
+            let spark = SPARKS[level.unwrap_or(0)];
+            let tank = format!("{},{}", entry.tank.0, entry.tank.1);
+            writeln!(
+                out,
+                "{:>step_width$}  tank {tank:<5}  {spark} {:<3}  {}",
+                entry.step,
+                entry.depth,
+                entry.opcode_name()
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{io::MockIo, program::Program};
+
+    #[test]
+    fn test_timeline() {
+        let mut program = Program::with_io("ab cd", MockIo::default()).unwrap();
+        let mut timeline = Timeline::new(4);
+        for _ in 0..6 {
+            timeline.start(&program);
+            program.step().unwrap();
+            timeline.finish(&program);
+        }
+        assert_eq!(timeline.len(), 4);
+        assert_eq!(timeline.entries().next().unwrap().step, 2);
+        assert_eq!(timeline.get(1), None);
+        assert_eq!(timeline.get(5).unwrap().opcode_name(), "down");
+        let steps = |end, rows| {
+            timeline
+                .window(end, rows)
+                .map(|entry| entry.step)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(steps(None, 2), [4, 5]);
+        assert_eq!(steps(Some(3), 9), [2, 3]);
+        assert!(steps(Some(9), 9).is_empty());
+        // Every step so far ran in the first tank.
+        assert!(timeline.trail(4).is_empty());
+
+        program.stack_mut().extend([1, 2, 3, 4]);
+        timeline.start(&program);
+        program.step().unwrap();
+        timeline.finish(&program);
+        assert_eq!(
+            timeline.render(None, 2),
+            "5  tank 0,0    ▁ 0    down\n6  tank 0,0    █ 4    up\n"
+        );
+    }
+}
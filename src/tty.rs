@@ -0,0 +1,84 @@
+use std::{io, mem::MaybeUninit, panic, sync::OnceLock};
+
+static ORIGINAL: OnceLock<libc::termios> = OnceLock::new();
+
+/// Puts the terminal on standard input into raw mode for as long as the guard lives.
+///
+/// Output post-processing is left enabled so that `\n` keeps moving the cursor to the start of
+/// the next line while the guard is held.
+pub struct RawMode {
+    original: libc::termios,
+}
+
+impl RawMode {
+    pub fn enable() -> io::Result<Self> {
+        let mut termios = MaybeUninit::uninit();
+        // SAFETY: `tcgetattr` fully initializes `termios` when it returns 0.
+        let original = unsafe {
+            if libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            termios.assume_init()
+        };
+        let mut raw = original;
+        raw.c_iflag &= !(libc::BRKINT | libc::ICRNL | libc::INPCK | libc::ISTRIP | libc::IXON);
+        raw.c_cflag |= libc::CS8;
+        raw.c_lflag &= !(libc::ECHO | libc::ICANON | libc::IEXTEN | libc::ISIG);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        set_attrs(&raw)?;
+        Ok(Self { original })
+    }
+
+    /// Restores the terminal settings in effect before the guard was created.
+    pub fn restore(&self) -> io::Result<()> {
+        set_attrs(&self.original)
+    }
+
+    /// Makes sure the terminal is restored even if the process exits or panics while the guard
+    /// is alive, since neither runs destructors.
+    pub fn install_exit_hooks(&self) {
+        if ORIGINAL.set(self.original).is_err() {
+            return;
+        }
+        // SAFETY: `restore_original` is an `extern "C"` function that never unwinds.
+        unsafe {
+            libc::atexit(restore_original);
+        }
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            restore_original();
+            previous(info);
+        }));
+    }
+}
+
+extern "C" fn restore_original() {
+    if let Some(original) = ORIGINAL.get() {
+        let _ = set_attrs(original);
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = self.restore();
+    }
+}
+
+fn set_attrs(termios: &libc::termios) -> io::Result<()> {
+    // SAFETY: `termios` is a valid, initialized struct obtained from `tcgetattr`.
+    if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, termios) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Re-raises the interrupt signal, for raw-mode readers that receive Ctrl-C as a byte.
+///
+/// Callers must restore the terminal before calling this.
+pub fn raise_interrupt() {
+    // SAFETY: raising a signal has no memory-safety preconditions.
+    unsafe {
+        libc::raise(libc::SIGINT);
+    }
+}